@@ -249,7 +249,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
                 .extend_from_slice(builder.block_params(loop_body));
 
             builder.switch_to_block(loop_body);
-            environ.translate_loop_header(builder.cursor())?;
+            environ.translate_loop_header(builder)?;
         }
         Operator::If { ty } => {
             let val = state.pop1();
@@ -1039,6 +1039,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             let timeout = state.pop1(); // 64 (fixed)
             let expected = state.pop1(); // 32 or 64 (per the `Ixx` in `IxxAtomicWait`)
             let addr = state.pop1(); // 32 (fixed)
+            let addr = atomic_wait_notify_addr(addr, memarg, builder);
             assert!(builder.func.dfg.value_type(expected) == implied_ty);
             // `fn translate_atomic_wait` can inspect the type of `expected` to figure out what
             // code it needs to generate, if it wants.
@@ -1057,6 +1058,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             let heap = state.get_heap(builder.func, memarg.memory, environ)?;
             let count = state.pop1(); // 32 (fixed)
             let addr = state.pop1(); // 32 (fixed)
+            let addr = atomic_wait_notify_addr(addr, memarg, builder);
             let res =
                 environ.translate_atomic_notify(builder.cursor(), heap_index, heap, addr, count)?;
             state.push1(res);
@@ -2088,6 +2090,21 @@ fn translate_icmp(cc: IntCC, builder: &mut FunctionBuilder, state: &mut FuncTran
 
 // For an atomic memory operation, emit an alignment check for the linear memory address,
 // and then compute the final effective address.
+/// Computes the effective address of an `atomic.wait` or `atomic.notify`,
+/// which is extended to 64 bits so that adding the offset can't overflow.
+fn atomic_wait_notify_addr(
+    addr: Value,
+    memarg: &MemoryImmediate,
+    builder: &mut FunctionBuilder,
+) -> Value {
+    let addr = builder.ins().uextend(I64, addr);
+    if memarg.offset == 0 {
+        addr
+    } else {
+        builder.ins().iadd_imm(addr, i64::from(memarg.offset))
+    }
+}
+
 fn finalise_atomic_mem_addr<FE: FuncEnvironment + ?Sized>(
     linear_mem_addr: Value,
     memarg: &MemoryImmediate,
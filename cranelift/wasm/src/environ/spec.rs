@@ -568,6 +568,9 @@ pub trait FuncEnvironment: TargetEnvironment {
     /// for the same index.  Whether the waited-on value is 32- or 64-bit can be
     /// determined by examining the type of `expected`, which must be only I32 or I64.
     ///
+    /// The `addr` is the I64 effective address of the value, which includes the
+    /// instruction's offset and hasn't been bounds or alignment checked.
+    ///
     /// Returns an i32, which is negative if the helper call failed.
     fn translate_atomic_wait(
         &mut self,
@@ -584,6 +587,9 @@ pub trait FuncEnvironment: TargetEnvironment {
     /// to wait on, and `heap` is the heap reference returned by `make_heap`
     /// for the same index.
     ///
+    /// The `addr` is the I64 effective address of the value, which includes the
+    /// instruction's offset and hasn't been bounds or alignment checked.
+    ///
     /// Returns an i64, which is negative if the helper call failed.
     fn translate_atomic_notify(
         &mut self,
@@ -598,7 +604,7 @@ pub trait FuncEnvironment: TargetEnvironment {
     ///
     /// This can be used to insert explicit interrupt or safepoint checking at
     /// the beginnings of loops.
-    fn translate_loop_header(&mut self, _pos: FuncCursor) -> WasmResult<()> {
+    fn translate_loop_header(&mut self, _builder: &mut FunctionBuilder) -> WasmResult<()> {
         // By default, don't emit anything.
         Ok(())
     }
@@ -624,6 +630,32 @@ pub trait FuncEnvironment: TargetEnvironment {
     ) -> WasmResult<()> {
         Ok(())
     }
+
+    /// Optional callback invoked once all of a function's locals have been
+    /// declared, with the number of variables declared for them. Any
+    /// variables the environment declares itself must use indices from this
+    /// number onwards.
+    fn after_locals(&mut self, _num_locals_defined: usize) {}
+
+    /// Optional callback invoked before the body of a function is translated,
+    /// with the builder positioned in the function's entry block.
+    fn before_translate_function(
+        &mut self,
+        _builder: &mut FunctionBuilder,
+        _state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        Ok(())
+    }
+
+    /// Optional callback invoked after the body of a function is translated,
+    /// just before the implicit return at the end of the function is emitted.
+    fn after_translate_function(
+        &mut self,
+        _builder: &mut FunctionBuilder,
+        _state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        Ok(())
+    }
 }
 
 /// An object satisfying the `ModuleEnvironment` trait can be passed as argument to the
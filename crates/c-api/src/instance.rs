@@ -2,7 +2,7 @@ use crate::{wasm_extern_t, wasm_extern_vec_t, wasm_module_t, wasm_trap_t};
 use crate::{wasm_store_t, wasmtime_error_t};
 use anyhow::Result;
 use std::ptr;
-use wasmtime::{Instance, Trap};
+use wasmtime::{Instance, InstantiationError};
 
 #[repr(C)]
 #[derive(Clone)]
@@ -112,11 +112,12 @@ pub fn handle_instantiate(
             write(instance_ptr, wasm_instance_t::new(instance));
             None
         }
-        Err(e) => match e.downcast::<Trap>() {
-            Ok(trap) => {
+        Err(e) => match e.downcast::<InstantiationError>() {
+            Ok(InstantiationError::Trap(trap)) => {
                 write(trap_ptr, wasm_trap_t::new(trap));
                 None
             }
+            Ok(e) => Some(Box::new(anyhow::Error::from(e).into())),
             Err(e) => Some(Box::new(e.into())),
         },
     }
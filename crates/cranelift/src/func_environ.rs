@@ -6,12 +6,14 @@ use cranelift_codegen::ir::types::*;
 use cranelift_codegen::ir::{AbiParam, ArgumentPurpose, Function, InstBuilder, Signature};
 use cranelift_codegen::isa::{self, TargetFrontendConfig};
 use cranelift_entity::EntityRef;
-use cranelift_frontend::FunctionBuilder;
+use cranelift_frontend::{FunctionBuilder, Variable};
+use cranelift_wasm::wasmparser::Operator;
 use cranelift_wasm::{
-    self, FuncIndex, GlobalIndex, GlobalVariable, MemoryIndex, SignatureIndex, TableIndex,
-    TargetEnvironment, WasmError, WasmResult, WasmType,
+    self, FuncIndex, FuncTranslationState, GlobalIndex, GlobalVariable, MemoryIndex,
+    SignatureIndex, TableIndex, TargetEnvironment, WasmError, WasmResult, WasmType,
 };
 use std::convert::TryFrom;
+use std::mem;
 use wasmtime_environ::{
     BuiltinFunctionIndex, MemoryPlan, MemoryStyle, Module, TableStyle, Tunables, VMOffsets,
     INTERRUPTED, WASM_PAGE_SIZE,
@@ -72,6 +74,10 @@ macro_rules! declare_function_signatures {
                 AbiParam::new(I32)
             }
 
+            fn i64(&self) -> AbiParam {
+                AbiParam::new(I64)
+            }
+
             $(
                 fn $name(&mut self, func: &mut Function) -> ir::SigRef {
                     let sig = self.$name.unwrap_or_else(|| {
@@ -109,6 +115,23 @@ pub struct FuncEnvironment<'module_environment> {
     pub(crate) offsets: VMOffsets,
 
     tunables: &'module_environment Tunables,
+
+    /// A function-local variable which stores the cached value of the amount
+    /// of fuel remaining to execute. If used this is modified frequently so
+    /// it's stored locally as a variable instead of always referenced from
+    /// the field in `*const VMInterrupts`
+    fuel_var: Variable,
+
+    /// A function-local variable which caches the value of `*const
+    /// VMInterrupts` for this function's vmctx argument. This pointer is stored
+    /// in the vmctx itself, but never changes for the lifetime of the
+    /// function, so if we load it up front we can continue to use it
+    /// throughout.
+    vminterrupts_ptr: Variable,
+
+    /// The amount of fuel consumed by the instructions translated since
+    /// `fuel_var` was last updated.
+    fuel_consumed: i64,
 }
 
 impl<'module_environment> FuncEnvironment<'module_environment> {
@@ -133,6 +156,11 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             builtin_function_signatures,
             offsets: VMOffsets::new(target_config.pointer_bytes(), module),
             tunables,
+            // Start with bogus variables, the real indices are assigned in
+            // `after_locals` once the function's own variables are known.
+            fuel_var: Variable::new(0),
+            vminterrupts_ptr: Variable::new(0),
+            fuel_consumed: 0,
         }
     }
 
@@ -348,6 +376,225 @@ impl<'module_environment> TargetEnvironment for FuncEnvironment<'module_environm
     }
 }
 
+/// Fuel metering and epoch interruption.
+///
+/// Fuel is stored in `VMInterrupts::fuel_consumed` as a negative number which
+/// counts up towards zero as instructions execute, and running out of fuel is
+/// detected by it no longer being negative. Within a function the value is
+/// cached in `fuel_var`, and the cost of straight-line code is accumulated in
+/// `fuel_consumed` at compile time and only added to `fuel_var` where control
+/// flow splits or joins.
+///
+/// Epochs are much cheaper: the engine's current epoch is compared against the
+/// store's deadline in `VMInterrupts` at function entries and loop headers.
+impl<'module_environment> FuncEnvironment<'module_environment> {
+    fn declare_vminterrupts_ptr(&mut self, builder: &mut FunctionBuilder<'_>) {
+        // The `*const VMInterrupts` never changes while this function runs,
+        // so load it once here instead of at every access to its fields.
+        let pointer_type = self.pointer_type();
+        builder.declare_var(self.vminterrupts_ptr, pointer_type);
+        let vmctx = self.vmctx(builder.func);
+        let base = builder.ins().global_value(pointer_type, vmctx);
+        let offset = i32::try_from(self.offsets.vmctx_interrupts()).unwrap();
+        let interrupts_ptr =
+            builder
+                .ins()
+                .load(pointer_type, ir::MemFlags::trusted(), base, offset);
+        builder.def_var(self.vminterrupts_ptr, interrupts_ptr);
+    }
+
+    /// Emits a check of the engine's epoch against this store's deadline,
+    /// calling the new-epoch libcall if it has been reached.
+    fn epoch_check(&mut self, builder: &mut FunctionBuilder<'_>) {
+        let pointer_type = self.pointer_type();
+        let interrupts_ptr = builder.use_var(self.vminterrupts_ptr);
+        let epoch_ptr = builder.ins().load(
+            pointer_type,
+            ir::MemFlags::trusted(),
+            interrupts_ptr,
+            i32::from(self.offsets.vminterrupts_epoch_ptr()),
+        );
+        let epoch = builder
+            .ins()
+            .load(I64, ir::MemFlags::trusted(), epoch_ptr, 0);
+        let deadline = builder.ins().load(
+            I64,
+            ir::MemFlags::trusted(),
+            interrupts_ptr,
+            i32::from(self.offsets.vminterrupts_epoch_deadline()),
+        );
+        let cmp = builder
+            .ins()
+            .icmp(IntCC::UnsignedGreaterThanOrEqual, epoch, deadline);
+
+        // The libcall either traps or returns once the deadline has been
+        // moved, which the next check will pick up.
+        let new_epoch_block = builder.create_block();
+        let continuation_block = builder.create_block();
+        builder.ins().brnz(cmp, new_epoch_block, &[]);
+        builder.ins().jump(continuation_block, &[]);
+        builder.seal_block(new_epoch_block);
+
+        builder.switch_to_block(new_epoch_block);
+        if self.tunables.consume_fuel {
+            self.fuel_save_from_var(builder);
+        }
+        let new_epoch_sig = self.builtin_function_signatures.new_epoch(builder.func);
+        let (vmctx, new_epoch) = self.translate_load_builtin_function_address(
+            &mut builder.cursor(),
+            BuiltinFunctionIndex::new_epoch(),
+        );
+        builder
+            .ins()
+            .call_indirect(new_epoch_sig, new_epoch, &[vmctx]);
+        if self.tunables.consume_fuel {
+            self.fuel_load_into_var(builder);
+        }
+        builder.ins().jump(continuation_block, &[]);
+        builder.seal_block(continuation_block);
+
+        builder.switch_to_block(continuation_block);
+    }
+
+    fn fuel_function_entry(&mut self, builder: &mut FunctionBuilder<'_>) {
+        builder.declare_var(self.fuel_var, I64);
+        self.fuel_load_into_var(builder);
+        self.fuel_check(builder);
+    }
+
+    fn fuel_function_exit(&mut self, builder: &mut FunctionBuilder<'_>) {
+        self.fuel_increment_var(builder);
+        self.fuel_save_from_var(builder);
+    }
+
+    fn fuel_before_op(&mut self, op: &Operator<'_>, builder: &mut FunctionBuilder<'_>) {
+        self.fuel_consumed += match op {
+            // These generate no code of their own, so they're free.
+            Operator::Nop
+            | Operator::Drop
+            | Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::Unreachable
+            | Operator::Return
+            | Operator::Else
+            | Operator::End => 0,
+
+            // Everything else costs one unit of fuel.
+            _ => 1,
+        };
+
+        match op {
+            // Control is about to leave this function, either for good or for
+            // a call, and the callee or the host may read or modify the fuel,
+            // so account for everything so far and write it back.
+            Operator::Unreachable
+            | Operator::Return
+            | Operator::Call { .. }
+            | Operator::CallIndirect { .. }
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallIndirect { .. } => {
+                self.fuel_increment_var(builder);
+                self.fuel_save_from_var(builder);
+            }
+
+            // These end the current basic block, so the fuel consumed so far
+            // has to be added to `fuel_var` before branching. Loops are
+            // included so that code before a loop is only counted once, and
+            // not again on every iteration.
+            Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::End
+            | Operator::Br { .. }
+            | Operator::BrIf { .. }
+            | Operator::BrTable { .. } => self.fuel_increment_var(builder),
+
+            // Straight-line code just accumulates in `fuel_consumed`. This
+            // means that a trap in the middle of a basic block may leave its
+            // fuel uncounted, which is fine since the trap ends execution.
+            _ => {}
+        }
+    }
+
+    fn fuel_after_op(&mut self, op: &Operator<'_>, builder: &mut FunctionBuilder<'_>) {
+        // Calls may have consumed or added fuel, so reload it afterwards.
+        match op {
+            Operator::Call { .. } | Operator::CallIndirect { .. } => {
+                self.fuel_load_into_var(builder);
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds `self.fuel_consumed` to `fuel_var`, resetting it to zero.
+    fn fuel_increment_var(&mut self, builder: &mut FunctionBuilder<'_>) {
+        let consumption = mem::replace(&mut self.fuel_consumed, 0);
+        if consumption == 0 {
+            return;
+        }
+        let fuel = builder.use_var(self.fuel_var);
+        let fuel = builder.ins().iadd_imm(fuel, consumption);
+        builder.def_var(self.fuel_var, fuel);
+    }
+
+    fn fuel_load_into_var(&mut self, builder: &mut FunctionBuilder<'_>) {
+        let (addr, offset) = self.fuel_addr_offset(builder);
+        let fuel = builder
+            .ins()
+            .load(I64, ir::MemFlags::trusted(), addr, offset);
+        builder.def_var(self.fuel_var, fuel);
+    }
+
+    fn fuel_save_from_var(&mut self, builder: &mut FunctionBuilder<'_>) {
+        let (addr, offset) = self.fuel_addr_offset(builder);
+        let fuel = builder.use_var(self.fuel_var);
+        builder
+            .ins()
+            .store(ir::MemFlags::trusted(), fuel, addr, offset);
+    }
+
+    fn fuel_addr_offset(&mut self, builder: &mut FunctionBuilder<'_>) -> (ir::Value, Offset32) {
+        (
+            builder.use_var(self.vminterrupts_ptr),
+            i32::from(self.offsets.vminterrupts_fuel_consumed()).into(),
+        )
+    }
+
+    /// Emits a check of the remaining fuel, calling the out-of-gas libcall if
+    /// it has run out.
+    fn fuel_check(&mut self, builder: &mut FunctionBuilder<'_>) {
+        self.fuel_increment_var(builder);
+        let out_of_gas_block = builder.create_block();
+        let continuation_block = builder.create_block();
+
+        let fuel = builder.use_var(self.fuel_var);
+        let cmp = builder
+            .ins()
+            .icmp_imm(IntCC::SignedGreaterThanOrEqual, fuel, 0);
+        builder.ins().brnz(cmp, out_of_gas_block, &[]);
+        builder.ins().jump(continuation_block, &[]);
+        builder.seal_block(out_of_gas_block);
+
+        // The libcall either traps or returns once more fuel has been added,
+        // so reload the fuel afterwards.
+        builder.switch_to_block(out_of_gas_block);
+        self.fuel_save_from_var(builder);
+        let out_of_gas_sig = self.builtin_function_signatures.out_of_gas(builder.func);
+        let (vmctx, out_of_gas) = self.translate_load_builtin_function_address(
+            &mut builder.cursor(),
+            BuiltinFunctionIndex::out_of_gas(),
+        );
+        builder
+            .ins()
+            .call_indirect(out_of_gas_sig, out_of_gas, &[vmctx]);
+        self.fuel_load_into_var(builder);
+        builder.ins().jump(continuation_block, &[]);
+        builder.seal_block(continuation_block);
+
+        builder.switch_to_block(continuation_block);
+    }
+}
+
 impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'module_environment> {
     fn is_wasm_parameter(&self, _signature: &ir::Signature, index: usize) -> bool {
         // The first two parameters are the vmctx and caller vmctx. The rest are
@@ -1350,32 +1597,69 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
 
     fn translate_atomic_wait(
         &mut self,
-        _pos: FuncCursor,
-        _index: MemoryIndex,
+        mut pos: FuncCursor,
+        index: MemoryIndex,
         _heap: ir::Heap,
-        _addr: ir::Value,
-        _expected: ir::Value,
-        _timeout: ir::Value,
+        addr: ir::Value,
+        expected: ir::Value,
+        timeout: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "wasm atomics (fn translate_atomic_wait)".to_string(),
-        ))
+        let (func_sig, func_idx) = if pos.func.dfg.value_type(expected) == I64 {
+            (
+                self.builtin_function_signatures
+                    .memory_atomic_wait64(&mut pos.func),
+                BuiltinFunctionIndex::memory_atomic_wait64(),
+            )
+        } else {
+            (
+                self.builtin_function_signatures
+                    .memory_atomic_wait32(&mut pos.func),
+                BuiltinFunctionIndex::memory_atomic_wait32(),
+            )
+        };
+        let memory_index = pos.ins().iconst(I32, i64::from(index.as_u32()));
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
+        let call_inst = pos.ins().call_indirect(
+            func_sig,
+            func_addr,
+            &[vmctx, memory_index, addr, expected, timeout],
+        );
+        Ok(*pos.func.dfg.inst_results(call_inst).first().unwrap())
     }
 
     fn translate_atomic_notify(
         &mut self,
-        _pos: FuncCursor,
-        _index: MemoryIndex,
+        mut pos: FuncCursor,
+        index: MemoryIndex,
         _heap: ir::Heap,
-        _addr: ir::Value,
-        _count: ir::Value,
+        addr: ir::Value,
+        count: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "wasm atomics (fn translate_atomic_notify)".to_string(),
-        ))
+        let func_sig = self
+            .builtin_function_signatures
+            .memory_atomic_notify(&mut pos.func);
+        let memory_index = pos.ins().iconst(I32, i64::from(index.as_u32()));
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(
+            &mut pos,
+            BuiltinFunctionIndex::memory_atomic_notify(),
+        );
+        let call_inst =
+            pos.ins()
+                .call_indirect(func_sig, func_addr, &[vmctx, memory_index, addr, count]);
+        Ok(*pos.func.dfg.inst_results(call_inst).first().unwrap())
     }
 
-    fn translate_loop_header(&mut self, mut pos: FuncCursor) -> WasmResult<()> {
+    fn translate_loop_header(&mut self, builder: &mut FunctionBuilder) -> WasmResult<()> {
+        // If fuel is enabled then each loop header checks for remaining fuel,
+        // since an infinite loop could otherwise run without ever reaching a
+        // function entry.
+        if self.tunables.consume_fuel {
+            self.fuel_check(builder);
+        }
+        if self.tunables.epoch_interruption {
+            self.epoch_check(builder);
+        }
+
         if !self.tunables.interruptable {
             return Ok(());
         }
@@ -1385,14 +1669,14 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         //
         // For more information about this see comments in
         // `crates/environ/src/cranelift.rs`
-        let vmctx = self.vmctx(&mut pos.func);
+        let vmctx = self.vmctx(builder.func);
         let pointer_type = self.pointer_type();
-        let base = pos.ins().global_value(pointer_type, vmctx);
+        let base = builder.ins().global_value(pointer_type, vmctx);
         let offset = i32::try_from(self.offsets.vmctx_interrupts()).unwrap();
-        let interrupt_ptr = pos
+        let interrupt_ptr = builder
             .ins()
             .load(pointer_type, ir::MemFlags::trusted(), base, offset);
-        let interrupt = pos.ins().load(
+        let interrupt = builder.ins().load(
             pointer_type,
             ir::MemFlags::trusted(),
             interrupt_ptr,
@@ -1400,11 +1684,74 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         );
         // Note that the cast to `isize` happens first to allow sign-extension,
         // if necessary, to `i64`.
-        let interrupted_sentinel = pos.ins().iconst(pointer_type, INTERRUPTED as isize as i64);
-        let cmp = pos
+        let interrupted_sentinel = builder
+            .ins()
+            .iconst(pointer_type, INTERRUPTED as isize as i64);
+        let cmp = builder
             .ins()
             .icmp(IntCC::Equal, interrupt, interrupted_sentinel);
-        pos.ins().trapnz(cmp, ir::TrapCode::Interrupt);
+        builder.ins().trapnz(cmp, ir::TrapCode::Interrupt);
+        Ok(())
+    }
+
+    fn after_locals(&mut self, num_locals: usize) {
+        self.vminterrupts_ptr = Variable::new(num_locals);
+        self.fuel_var = Variable::new(num_locals + 1);
+    }
+
+    fn before_translate_function(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        _state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        if self.tunables.consume_fuel || self.tunables.epoch_interruption {
+            self.declare_vminterrupts_ptr(builder);
+        }
+        if self.tunables.consume_fuel {
+            self.fuel_function_entry(builder);
+        }
+        if self.tunables.epoch_interruption {
+            self.epoch_check(builder);
+        }
+        Ok(())
+    }
+
+    fn before_translate_operator(
+        &mut self,
+        op: &Operator,
+        builder: &mut FunctionBuilder,
+        state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        // Unreachable code is never executed and has no fuel to account for,
+        // since whatever made it unreachable already flushed `fuel_consumed`.
+        if self.tunables.consume_fuel && state.reachable() {
+            self.fuel_before_op(op, builder);
+        }
+        Ok(())
+    }
+
+    fn after_translate_operator(
+        &mut self,
+        op: &Operator,
+        builder: &mut FunctionBuilder,
+        state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        if self.tunables.consume_fuel && state.reachable() {
+            self.fuel_after_op(op, builder);
+        }
+        Ok(())
+    }
+
+    fn after_translate_function(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        // The final `end` leaves us in the function's exit block, right before
+        // the implicit return, so write the cached fuel back for the caller.
+        if self.tunables.consume_fuel && state.reachable() {
+            self.fuel_function_exit(builder);
+        }
         Ok(())
     }
 }
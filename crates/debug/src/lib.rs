@@ -6,9 +6,11 @@ use anyhow::{bail, ensure, Error};
 use object::{RelocationEncoding, RelocationKind};
 use std::collections::HashMap;
 
+pub use crate::line_table::read_line_table;
 pub use crate::write_debuginfo::{emit_dwarf, DwarfSection, DwarfSectionRelocTarget};
 
 mod gc;
+mod line_table;
 mod transform;
 mod write_debuginfo;
 
@@ -32,6 +34,99 @@ pub fn create_gdbjit_image(
     Ok(bytes)
 }
 
+/// Removes the DWARF sections of the ELF image `bytes` and their relocations.
+///
+/// The headers of the removed sections are kept, with no contents, so that
+/// the indices of the other sections, which symbols refer to, don't change.
+pub fn strip_dwarf(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    use object::elf::*;
+    use object::endian::LittleEndian;
+    use std::mem::size_of;
+
+    let e = LittleEndian;
+    ensure!(
+        bytes.len() >= size_of::<FileHeader64<LittleEndian>>(),
+        "truncated ELF header"
+    );
+    let header: &FileHeader64<LittleEndian> =
+        unsafe { &*(bytes.as_ptr() as *const FileHeader64<_>) };
+    ensure!(
+        header.e_ident.class == ELFCLASS64 && header.e_ident.data == ELFDATA2LSB,
+        "bits and endianess in .ELF",
+    );
+    let e_shoff = header.e_shoff.get(e) as usize;
+    let e_shnum = header.e_shnum.get(e) as usize;
+    let e_shstrndx = header.e_shstrndx.get(e) as usize;
+    let shentsize = size_of::<SectionHeader64<LittleEndian>>();
+    ensure!(
+        e_shoff + e_shnum * shentsize <= bytes.len() && e_shstrndx < e_shnum,
+        "truncated section headers"
+    );
+
+    let mut sections = (0..e_shnum)
+        .map(|i| unsafe {
+            std::ptr::read_unaligned(
+                bytes.as_ptr().add(e_shoff + i * shentsize) as *const SectionHeader64<_>
+            )
+        })
+        .collect::<Vec<_>>();
+    fn section_data<'a>(
+        bytes: &'a [u8],
+        section: &SectionHeader64<LittleEndian>,
+    ) -> Result<&'a [u8], Error> {
+        let start = section.sh_offset.get(LittleEndian) as usize;
+        let end = start + section.sh_size.get(LittleEndian) as usize;
+        ensure!(end <= bytes.len(), "truncated section");
+        Ok(&bytes[start..end])
+    }
+    let shstrtab = section_data(bytes, &sections[e_shstrndx])?;
+    let is_dwarf = |section: &SectionHeader64<LittleEndian>| {
+        let name = &shstrtab[(section.sh_name.get(e) as usize).min(shstrtab.len())..];
+        name.starts_with(b".debug_")
+    };
+    let strip = sections
+        .iter()
+        .map(|section| match section.sh_type.get(e) {
+            SHT_REL | SHT_RELA => sections
+                .get(section.sh_info.get(e) as usize)
+                .map_or(false, is_dwarf),
+            _ => is_dwarf(section),
+        })
+        .collect::<Vec<_>>();
+
+    // Copy the contents of the sections which are kept in the order they
+    // were in, followed by the section headers.
+    let mut order = (1..e_shnum)
+        .filter(|i| sections[*i].sh_type.get(e) != SHT_NOBITS)
+        .collect::<Vec<_>>();
+    order.sort_by_key(|i| sections[*i].sh_offset.get(e));
+    let mut out = bytes[..size_of::<FileHeader64<LittleEndian>>()].to_vec();
+    for i in order {
+        let data = if strip[i] {
+            &[][..]
+        } else {
+            section_data(bytes, &sections[i])?
+        };
+        let align = sections[i].sh_addralign.get(e).max(1) as usize;
+        out.resize((out.len() + align - 1) / align * align, 0);
+        sections[i].sh_offset.set(e, out.len() as u64);
+        sections[i].sh_size.set(e, data.len() as u64);
+        out.extend_from_slice(data);
+    }
+    out.resize((out.len() + 7) & !7, 0);
+    let shoff = out.len();
+    for section in sections.iter() {
+        out.extend_from_slice(unsafe {
+            std::slice::from_raw_parts(section as *const _ as *const u8, shentsize)
+        });
+    }
+
+    let header: &mut FileHeader64<LittleEndian> =
+        unsafe { &mut *(out.as_mut_ptr() as *mut FileHeader64<_>) };
+    header.e_shoff.set(e, shoff as u64);
+    Ok(out)
+}
+
 fn relocate_dwarf_sections(
     bytes: &mut [u8],
     defined_funcs_offset: usize,
@@ -0,0 +1,101 @@
+//! Reading the source locations of a module's code from its DWARF, so that
+//! traps can show where in the guest source they happened.
+
+use anyhow::Error;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use wasmtime_environ::{DebugInfoData, LineRow, LineTable};
+
+/// Builds the table of source locations of the code described by the line
+/// programs of `di`, keyed by offsets in the wasm module.
+pub fn read_line_table(di: &DebugInfoData) -> Result<LineTable, Error> {
+    let dwarf = &di.dwarf;
+    let mut table = LineTable::default();
+    let mut paths = HashMap::new();
+
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let program = match unit.line_program.clone() {
+            Some(program) => program,
+            None => continue,
+        };
+        let mut files = HashMap::new();
+        let mut skip_sequence = false;
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            // Sequences starting at address 0 describe code which the linker
+            // removed, and would otherwise overlap with the module's real code.
+            if skip_sequence || row.address() == 0 {
+                skip_sequence = !row.end_sequence();
+                continue;
+            }
+            let offset = match u32::try_from(di.wasm_file.code_section_offset + row.address()) {
+                Ok(offset) => offset,
+                Err(_) => continue,
+            };
+            if row.end_sequence() {
+                table.rows.push(LineRow {
+                    offset,
+                    file: 0,
+                    line: 0,
+                    column: 0,
+                });
+                continue;
+            }
+
+            let file = match files.get(&row.file_index()) {
+                Some(file) => *file,
+                None => {
+                    let entry = match row.file(header) {
+                        Some(entry) => entry,
+                        None => continue,
+                    };
+                    let mut path = PathBuf::new();
+                    if let Some(dir) = entry.directory(header) {
+                        path.push(&*dwarf.attr_string(&unit, dir)?.to_string_lossy());
+                    }
+                    path.push(
+                        &*dwarf
+                            .attr_string(&unit, entry.path_name())?
+                            .to_string_lossy(),
+                    );
+                    let path = path.to_string_lossy().into_owned();
+                    let file = *paths.entry(path.clone()).or_insert_with(|| {
+                        table.files.push(path);
+                        table.files.len() as u32 - 1
+                    });
+                    files.insert(row.file_index(), file);
+                    file
+                }
+            };
+            table.rows.push(LineRow {
+                offset,
+                file,
+                line: saturate(row.line().unwrap_or(0)),
+                column: match row.column() {
+                    gimli::ColumnType::LeftEdge => 0,
+                    gimli::ColumnType::Column(column) => saturate(column),
+                },
+            });
+        }
+    }
+
+    // Where a sequence ends at the same offset as the next one starts, keep
+    // the start of the next one.
+    table.rows.sort_by_key(|row| (row.offset, row.line != 0));
+    let mut rows: Vec<LineRow> = Vec::with_capacity(table.rows.len());
+    for row in table.rows.drain(..) {
+        match rows.last_mut() {
+            Some(last) if last.offset == row.offset => *last = row,
+            _ => rows.push(row),
+        }
+    }
+    table.rows = rows;
+    Ok(table)
+}
+
+fn saturate(n: u64) -> u32 {
+    u32::try_from(n).unwrap_or(u32::max_value())
+}
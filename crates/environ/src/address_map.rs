@@ -47,3 +47,49 @@ pub enum ModuleMemoryOffset {
     /// Offset to the imported memory.
     Imported(u32),
 }
+
+/// The guest source locations of a module's wasm code, read from the line
+/// programs of its DWARF.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct LineTable {
+    /// Paths of the source files referred to by `rows`.
+    pub files: Vec<String>,
+
+    /// Rows of the table.
+    /// The array is sorted by the LineRow::offset field.
+    pub rows: Vec<LineRow>,
+}
+
+/// The source location of the wasm code from one offset up to the next row of
+/// a `LineTable`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRow {
+    /// Offset of the code in the wasm module.
+    pub offset: u32,
+
+    /// Index of the source file in `LineTable::files`.
+    pub file: u32,
+
+    /// Line in the source file, or 0 if the code has no source location.
+    pub line: u32,
+
+    /// Column in the line, or 0 if it's unknown.
+    pub column: u32,
+}
+
+impl LineTable {
+    /// Returns the source file, line and column of the wasm code at `offset`
+    /// in the module, if it has one.
+    pub fn lookup(&self, offset: u32) -> Option<(&str, u32, u32)> {
+        let i = match self.rows.binary_search_by_key(&offset, |row| row.offset) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let row = &self.rows[i];
+        if row.line == 0 {
+            return None;
+        }
+        Some((&self.files[row.file as usize], row.line, row.column))
+    }
+}
@@ -45,6 +45,16 @@ macro_rules! foreach_builtin_function {
             externref_global_get(vmctx, i32) -> (reference);
             /// Returns an index for Wasm's `global.get` instruction for `externref`s.
             externref_global_set(vmctx, i32, reference) -> ();
+            /// Returns an index for wasm's out-of-fuel libcall.
+            out_of_gas(vmctx) -> ();
+            /// Returns an index for wasm's epoch-deadline libcall.
+            new_epoch(vmctx) -> ();
+            /// Returns an index for wasm's `memory.atomic.notify` instruction.
+            memory_atomic_notify(vmctx, i32, i64, i32) -> (i32);
+            /// Returns an index for wasm's `memory.atomic.wait32` instruction.
+            memory_atomic_wait32(vmctx, i32, i64, i32, i64) -> (i32);
+            /// Returns an index for wasm's `memory.atomic.wait64` instruction.
+            memory_atomic_wait64(vmctx, i32, i64, i64, i64) -> (i32);
         }
     };
 }
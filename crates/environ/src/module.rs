@@ -1,7 +1,7 @@
 //! Data structures for representing decoded wasm modules.
 
 use crate::tunables::Tunables;
-use crate::WASM_MAX_PAGES;
+use crate::{LineTable, WASM_MAX_PAGES};
 use cranelift_codegen::ir;
 use cranelift_entity::{EntityRef, PrimaryMap};
 use cranelift_wasm::{
@@ -12,7 +12,8 @@ use cranelift_wasm::{
 use indexmap::IndexMap;
 use more_asserts::assert_ge;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::BTreeMap;
 use std::sync::{
     atomic::{AtomicUsize, Ordering::SeqCst},
     Arc,
@@ -64,6 +65,19 @@ impl MemoryStyle {
         //
         // If the module doesn't declare an explicit maximum treat it as 4GiB.
         let maximum = memory.maximum.unwrap_or(WASM_MAX_PAGES);
+
+        // Shared memories can't ever be relocated since other threads may be
+        // accessing them concurrently, so they are always static and reserve
+        // their entire maximum size up front.
+        if memory.shared {
+            return (
+                Self::Static {
+                    bound: cmp::max(maximum, tunables.static_memory_bound),
+                },
+                tunables.static_memory_offset_guard_size,
+            );
+        }
+
         if maximum <= tunables.static_memory_bound {
             assert_ge!(tunables.static_memory_bound, memory.minimum);
             return (
@@ -159,14 +173,18 @@ pub struct Module {
     pub table_elements: Vec<TableElements>,
 
     /// WebAssembly passive elements.
-    pub passive_elements: HashMap<ElemIndex, Box<[FuncIndex]>>,
+    pub passive_elements: BTreeMap<ElemIndex, Box<[FuncIndex]>>,
 
     /// WebAssembly passive data segments.
     #[serde(with = "passive_data_serde")]
-    pub passive_data: HashMap<DataIndex, Arc<[u8]>>,
+    pub passive_data: BTreeMap<DataIndex, Arc<[u8]>>,
 
     /// WebAssembly table initializers.
-    pub func_names: HashMap<FuncIndex, String>,
+    pub func_names: BTreeMap<FuncIndex, String>,
+
+    /// Source locations of the module's code, if it was compiled with debug
+    /// info and has DWARF.
+    pub line_table: LineTable,
 
     /// Unprocessed signatures exactly as provided by `declare_signature()`.
     pub signatures: PrimaryMap<SignatureIndex, (WasmFuncType, ir::Signature)>,
@@ -206,9 +224,10 @@ impl Module {
             exports: IndexMap::new(),
             start_func: None,
             table_elements: Vec::new(),
-            passive_elements: HashMap::new(),
-            passive_data: HashMap::new(),
-            func_names: HashMap::new(),
+            passive_elements: BTreeMap::new(),
+            passive_data: BTreeMap::new(),
+            func_names: BTreeMap::new(),
+            line_table: LineTable::default(),
             num_imported_funcs: 0,
             num_imported_tables: 0,
             num_imported_memories: 0,
@@ -333,12 +352,12 @@ impl Module {
 }
 
 mod passive_data_serde {
-    use super::{Arc, DataIndex, HashMap};
+    use super::{Arc, BTreeMap, DataIndex};
     use serde::{de::MapAccess, de::Visitor, ser::SerializeMap, Deserializer, Serializer};
     use std::fmt;
 
     pub(super) fn serialize<S>(
-        data: &HashMap<DataIndex, Arc<[u8]>>,
+        data: &BTreeMap<DataIndex, Arc<[u8]>>,
         ser: S,
     ) -> Result<S::Ok, S::Error>
     where
@@ -353,7 +372,7 @@ mod passive_data_serde {
 
     struct PassiveDataVisitor;
     impl<'de> Visitor<'de> for PassiveDataVisitor {
-        type Value = HashMap<DataIndex, Arc<[u8]>>;
+        type Value = BTreeMap<DataIndex, Arc<[u8]>>;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
             formatter.write_str("a passive_data map")
@@ -362,7 +381,7 @@ mod passive_data_serde {
         where
             M: MapAccess<'de>,
         {
-            let mut map = HashMap::with_capacity(access.size_hint().unwrap_or(0));
+            let mut map = BTreeMap::new();
             while let Some((key, value)) = access.next_entry::<_, Vec<u8>>()? {
                 map.insert(key, value.into());
             }
@@ -370,7 +389,7 @@ mod passive_data_serde {
         }
     }
 
-    pub(super) fn deserialize<'de, D>(de: D) -> Result<HashMap<DataIndex, Arc<[u8]>>, D::Error>
+    pub(super) fn deserialize<'de, D>(de: D) -> Result<BTreeMap<DataIndex, Arc<[u8]>>, D::Error>
     where
         D: Deserializer<'de>,
     {
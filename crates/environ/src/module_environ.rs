@@ -496,8 +496,9 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
         Ok(())
     }
 
-    fn reserve_passive_data(&mut self, count: u32) -> WasmResult<()> {
-        self.result.module.passive_data.reserve(count as usize);
+    fn reserve_passive_data(&mut self, _count: u32) -> WasmResult<()> {
+        // The passive data is kept in a `BTreeMap`, which has no use for
+        // reserving space up front.
         Ok(())
     }
 
@@ -515,17 +516,21 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
     }
 
     fn declare_module_name(&mut self, name: &'data str) {
-        self.result.module.name = Some(name.to_string());
+        if !self.result.tunables.strip_names {
+            self.result.module.name = Some(name.to_string());
+        }
         if let Some(info) = &mut self.result.debuginfo {
             info.name_section.module_name = Some(name);
         }
     }
 
     fn declare_func_name(&mut self, func_index: FuncIndex, name: &'data str) {
-        self.result
-            .module
-            .func_names
-            .insert(func_index, name.to_string());
+        if !self.result.tunables.strip_names {
+            self.result
+                .module
+                .func_names
+                .insert(func_index, name.to_string());
+        }
         if let Some(info) = &mut self.result.debuginfo {
             info.name_section
                 .func_names
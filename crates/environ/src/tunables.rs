@@ -20,6 +20,18 @@ pub struct Tunables {
     /// calls and interrupts are implemented through the `VMInterrupts`
     /// structure, or `InterruptHandle` in the `wasmtime` crate.
     pub interruptable: bool,
+
+    /// Whether or not fuel is enabled for generated code, meaning that fuel
+    /// will be consumed every time a wasm instruction is executed.
+    pub consume_fuel: bool,
+
+    /// Whether or not to check the engine's epoch against the store's epoch
+    /// deadline at function entries and loop headers.
+    pub epoch_interruption: bool,
+
+    /// Whether or not to drop the module and function names found in the
+    /// name section rather than keeping them in the `Module`.
+    pub strip_names: bool,
 }
 
 impl Default for Tunables {
@@ -53,6 +65,9 @@ impl Default for Tunables {
 
             debug_info: false,
             interruptable: false,
+            consume_fuel: false,
+            epoch_interruption: false,
+            strip_names: false,
         }
     }
 }
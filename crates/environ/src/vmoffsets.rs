@@ -258,6 +258,21 @@ impl VMOffsets {
     pub fn vminterrupts_stack_limit(&self) -> u8 {
         0
     }
+
+    /// Return the offset of the `fuel_consumed` field of `VMInterrupts`
+    pub fn vminterrupts_fuel_consumed(&self) -> u8 {
+        self.pointer_size
+    }
+
+    /// Return the offset of the `epoch_deadline` field of `VMInterrupts`
+    pub fn vminterrupts_epoch_deadline(&self) -> u8 {
+        self.vminterrupts_fuel_consumed() + 8
+    }
+
+    /// Return the offset of the `epoch_ptr` field of `VMInterrupts`
+    pub fn vminterrupts_epoch_ptr(&self) -> u8 {
+        self.vminterrupts_epoch_deadline() + 8
+    }
 }
 
 /// Offsets for `VMCallerCheckedAnyfunc`.
@@ -76,5 +76,5 @@ pub fn dummy_table(store: &Store, ty: TableType) -> Result<Table, Trap> {
 
 /// Construct a dummy memory for the given memory type.
 pub fn dummy_memory(store: &Store, ty: MemoryType) -> Memory {
-    Memory::new(store, ty)
+    Memory::new(store, ty).unwrap()
 }
@@ -5,6 +5,7 @@ use crate::object::{
     ObjectUnwindInfo,
 };
 use crate::unwind::UnwindRegistry;
+use more_asserts::assert_le;
 use object::read::{File as ObjectFile, Object, ObjectSection};
 use region;
 use std::collections::BTreeMap;
@@ -20,6 +21,7 @@ use wasmtime_runtime::{Mmap, VMFunctionBody};
 struct CodeMemoryEntry {
     mmap: ManuallyDrop<Mmap>,
     registry: ManuallyDrop<UnwindRegistry>,
+    start: usize,
     len: usize,
 }
 
@@ -30,12 +32,13 @@ impl CodeMemoryEntry {
         Ok(Self {
             mmap,
             registry,
+            start: 0,
             len: 0,
         })
     }
 
     fn range(&self) -> (usize, usize) {
-        let start = self.mmap.as_ptr() as usize;
+        let start = self.mmap.as_ptr() as usize + self.start;
         let end = start + self.len;
         (start, end)
     }
@@ -82,6 +85,11 @@ impl<'a> CodeMemoryObjectAllocation<'a> {
 }
 
 /// Memory manager for executable code.
+///
+/// Code is written into read-write memory, which is made read-only and
+/// executable by [`CodeMemory::publish`]. No memory managed here is ever both
+/// writable and executable, and only the pages holding code are ever
+/// executable.
 pub struct CodeMemory {
     current: Option<CodeMemoryEntry>,
     entries: Vec<CodeMemoryEntry>,
@@ -120,29 +128,52 @@ impl CodeMemory {
     }
 
     /// Make all allocated memory executable.
-    pub fn publish(&mut self, isa: &dyn TargetIsa) {
-        self.push_current(0)
-            .expect("failed to push current memory map");
-
+    ///
+    /// Code memory is only ever writable before it's published, and only
+    /// executable after: publishing makes it read-only and executable at
+    /// once, and memory allocated afterwards comes from new pages. The
+    /// instruction cache is flushed for the published code on architectures
+    /// where it isn't coherent with the data cache.
+    pub fn publish(&mut self, isa: &dyn TargetIsa) -> Result<(), String> {
+        self.push_current(0)?;
+
+        let page_size = region::page::size();
         for CodeMemoryEntry {
             mmap: m,
             registry: r,
-            ..
+            start,
+            len,
         } in &mut self.entries[self.published..]
         {
-            // Remove write access to the pages due to the relocation fixups.
             r.publish(isa)
-                .expect("failed to publish function unwind registry");
-
-            if !m.is_empty() {
+                .map_err(|e| format!("failed to publish unwind information: {}", e))?;
+
+            // Only the pages holding code are made executable, the rest of
+            // the mapping is made read-only.
+            let code_start = *start & !(page_size - 1);
+            let code_end = cmp::min((*start + *len + page_size - 1) & !(page_size - 1), m.len());
+            if !m.is_empty() && (*len == 0 || code_start > 0 || code_end < m.len()) {
+                unsafe { region::protect(m.as_mut_ptr(), m.len(), region::Protection::READ) }
+                    .map_err(|e| format!("unable to make memory readonly: {}", e))?;
+            }
+            if *len > 0 {
+                flush_icache(m.as_ptr().wrapping_add(*start), *len);
                 unsafe {
-                    region::protect(m.as_mut_ptr(), m.len(), region::Protection::READ_EXECUTE)
+                    region::protect(
+                        m.as_mut_ptr().add(code_start),
+                        code_end - code_start,
+                        region::Protection::READ_EXECUTE,
+                    )
                 }
-                .expect("unable to make memory readonly and executable");
+                .map_err(|e| format!("unable to make memory readonly and executable: {}", e))?;
             }
+
+            // Count entries as they're published so that, if publishing fails
+            // partway, `published_ranges` doesn't report writable memory.
+            self.published += 1;
         }
 
-        self.published = self.entries.len();
+        Ok(())
     }
 
     /// Allocate `size` bytes of memory which can be made executable later by
@@ -256,6 +287,15 @@ impl CodeMemory {
         Ok(())
     }
 
+    /// Returns the total size in bytes of the memory allocated for code.
+    pub fn allocated_size(&self) -> usize {
+        self.entries
+            .iter()
+            .chain(&self.current)
+            .map(|entry| entry.mmap.len())
+            .sum()
+    }
+
     /// Returns all published segment ranges.
     pub fn published_ranges<'a>(&'a self) -> impl Iterator<Item = (usize, usize)> + 'a {
         self.entries[..self.published]
@@ -289,6 +329,78 @@ impl CodeMemory {
                 .map_err(|_| "cannot read section data".to_string())?,
         );
 
+        Ok(Self::object_allocation(
+            buf,
+            registry,
+            start,
+            obj,
+            unwind_info,
+        ))
+    }
+
+    /// Uses the ELF image code section found at `text_start` in `mmap` in
+    /// place, rather than copying it into memory allocated here. The mapping
+    /// must be writable until `publish()` so relocations can be applied, and
+    /// is owned by this `CodeMemory` from now on.
+    ///
+    /// Only the code section is made executable by `publish()`, the rest of
+    /// the mapping is made read-only. Since protections apply to whole pages,
+    /// the bytes sharing a page with either end of the code section are
+    /// zeroed here so that nothing but code ends up executable.
+    pub(crate) fn map_object<'a>(
+        &'a mut self,
+        mut mmap: Mmap,
+        text_start: usize,
+        obj: &ObjectFile,
+        unwind_info: &[ObjectUnwindInfo],
+    ) -> Result<CodeMemoryObjectAllocation<'a>, String> {
+        let text_len = obj.section_by_name(".text").unwrap().size() as usize;
+        assert_le!(text_start + text_len, mmap.len());
+
+        let page_size = region::page::size();
+        let text_end = text_start + text_len;
+        let pages_start = text_start & !(page_size - 1);
+        let pages_end = cmp::min((text_end + page_size - 1) & !(page_size - 1), mmap.len());
+        let bytes = mmap.as_mut_slice();
+        for b in &mut bytes[pages_start..text_start] {
+            *b = 0;
+        }
+        for b in &mut bytes[text_end..pages_end] {
+            *b = 0;
+        }
+
+        self.push_current(0)?;
+        let registry = UnwindRegistry::new(mmap.as_ptr() as usize);
+        self.entries.push(CodeMemoryEntry {
+            mmap: ManuallyDrop::new(mmap),
+            registry: ManuallyDrop::new(registry),
+            start: text_start,
+            len: text_len,
+        });
+
+        let e = self.entries.last_mut().unwrap();
+        let buf = &mut e.mmap.as_mut_slice()[text_start..text_start + text_len];
+        Ok(Self::object_allocation(
+            buf,
+            &mut e.registry,
+            text_start,
+            obj,
+            unwind_info,
+        ))
+    }
+
+    /// Finds the functions and trampolines of the ELF image whose code
+    /// section is in `buf`, at `start` within the memory of `registry`.
+    ///
+    /// The locations of functions and trampolines are tracked relative to
+    /// `buf`, but registered relative to the memory of `registry`.
+    fn object_allocation<'a>(
+        buf: &'a mut [u8],
+        registry: &mut UnwindRegistry,
+        start: usize,
+        obj: &ObjectFile,
+        unwind_info: &[ObjectUnwindInfo],
+    ) -> CodeMemoryObjectAllocation<'a> {
         // Track locations of all defined functions and trampolines.
         let mut funcs = BTreeMap::new();
         let mut trampolines = BTreeMap::new();
@@ -298,14 +410,10 @@ impl CodeMemory {
                     if let Some(index) = try_parse_func_name(name) {
                         let is_import = sym.section_index().is_none();
                         if !is_import {
-                            funcs.insert(
-                                index,
-                                (start + sym.address() as usize, sym.size() as usize),
-                            );
+                            funcs.insert(index, (sym.address() as usize, sym.size() as usize));
                         }
                     } else if let Some(index) = try_parse_trampoline_name(name) {
-                        trampolines
-                            .insert(index, (start + sym.address() as usize, sym.size() as usize));
+                        trampolines.insert(index, (sym.address() as usize, sym.size() as usize));
                     }
                 }
                 None => (),
@@ -317,24 +425,46 @@ impl CodeMemory {
         for i in unwind_info {
             match i {
                 ObjectUnwindInfo::Func(func_index, info) => {
-                    let (start, len) = funcs.get(&func_index).unwrap();
+                    let (offset, len) = funcs.get(&func_index).unwrap();
                     registry
-                        .register(*start as u32, *len as u32, &info)
+                        .register((start + *offset) as u32, *len as u32, &info)
                         .expect("failed to register unwind information");
                 }
                 ObjectUnwindInfo::Trampoline(trampoline_index, info) => {
-                    let (start, len) = trampolines.get(&trampoline_index).unwrap();
+                    let (offset, len) = trampolines.get(&trampoline_index).unwrap();
                     registry
-                        .register(*start as u32, *len as u32, &info)
+                        .register((start + *offset) as u32, *len as u32, &info)
                         .expect("failed to register unwind information");
                 }
             }
         }
 
-        Ok(CodeMemoryObjectAllocation {
-            buf: &mut buf[..text_section.size() as usize],
+        CodeMemoryObjectAllocation {
+            buf,
             funcs,
             trampolines,
-        })
+        }
+    }
+}
+
+/// Makes sure the instruction cache doesn't hold stale contents of the `len`
+/// bytes of memory at `ptr`, into which code was just written.
+fn flush_icache(ptr: *const u8, len: usize) {
+    cfg_if::cfg_if! {
+        if #[cfg(all(target_arch = "aarch64", target_os = "macos"))] {
+            extern "C" {
+                fn sys_icache_invalidate(start: *mut std::ffi::c_void, len: usize);
+            }
+            unsafe { sys_icache_invalidate(ptr as *mut _, len) }
+        } else if #[cfg(target_arch = "aarch64")] {
+            extern "C" {
+                fn __clear_cache(start: *mut std::os::raw::c_char, end: *mut std::os::raw::c_char);
+            }
+            unsafe { __clear_cache(ptr as *mut _, ptr.add(len) as *mut _) }
+        } else {
+            // The instruction cache is coherent on x86, so there's nothing to
+            // do.
+            let _ = (ptr, len);
+        }
     }
 }
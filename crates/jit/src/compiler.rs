@@ -1,18 +1,20 @@
 //! JIT compilation.
 
+use crate::function_cache::FunctionCache;
 use crate::instantiate::SetupError;
 use crate::object::{build_object, ObjectUnwindInfo};
 use object::write::Object;
 use std::hash::{Hash, Hasher};
 use std::mem;
+use std::time::{Duration, Instant};
 use wasmparser::WasmFeatures;
 use wasmtime_debug::{emit_dwarf, DwarfSection};
-use wasmtime_environ::entity::EntityRef;
+use wasmtime_environ::entity::{EntityRef, PrimaryMap};
 use wasmtime_environ::isa::{TargetFrontendConfig, TargetIsa};
-use wasmtime_environ::wasm::{DefinedMemoryIndex, MemoryIndex};
+use wasmtime_environ::wasm::{DefinedFuncIndex, DefinedMemoryIndex, MemoryIndex};
 use wasmtime_environ::{
-    CompiledFunctions, Compiler as EnvCompiler, DebugInfoData, Module, ModuleMemoryOffset,
-    ModuleTranslation, Tunables, VMOffsets,
+    CompileError, CompiledFunction, CompiledFunctions, Compiler as EnvCompiler, DebugInfoData,
+    FunctionBodyData, Module, ModuleMemoryOffset, ModuleTranslation, Tunables, VMOffsets,
 };
 
 /// Select which kind of compilation to use.
@@ -43,6 +45,7 @@ pub struct Compiler {
     strategy: CompilationStrategy,
     tunables: Tunables,
     features: WasmFeatures,
+    function_cache: Option<FunctionCache>,
 }
 
 impl Compiler {
@@ -65,8 +68,19 @@ impl Compiler {
             },
             tunables,
             features,
+            function_cache: None,
         }
     }
+
+    /// Keeps the result of compiling each function in memory, to reuse it
+    /// when the same function is compiled again as part of another module.
+    ///
+    /// Functions are only reused if their body is identical, they have the
+    /// same index, and their modules declare the same types, functions,
+    /// tables, memories and globals. The cache is never evicted from.
+    pub fn enable_function_cache(&mut self) {
+        self.function_cache = Some(FunctionCache::default());
+    }
 }
 
 fn _assert_compiler_send_sync() {
@@ -98,6 +112,7 @@ pub struct Compilation {
     pub obj: Object,
     pub unwind_info: Vec<ObjectUnwindInfo>,
     pub funcs: CompiledFunctions,
+    pub compile_times: PrimaryMap<DefinedFuncIndex, Duration>,
 }
 
 impl Compiler {
@@ -138,14 +153,23 @@ impl Compiler {
                 let iter = functions.into_iter();
             }
         }
-        let funcs = iter
+        let module_key = self
+            .function_cache
+            .as_ref()
+            .map(|_| FunctionCache::module_key(&translation.module));
+        let compiled = iter
             .map(|(index, func)| {
-                self.compiler
-                    .compile_function(translation, index, func, &*self.isa)
+                let start = Instant::now();
+                let func = self.compile_function(translation, module_key.as_ref(), index, func)?;
+                Ok((func, start.elapsed()))
             })
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .collect::<CompiledFunctions>();
+            .collect::<Result<Vec<_>, SetupError>>()?;
+        let mut funcs = CompiledFunctions::new();
+        let mut compile_times = PrimaryMap::new();
+        for (func, time) in compiled {
+            funcs.push(func);
+            compile_times.push(time);
+        }
 
         let dwarf_sections = if translation.debuginfo.is_some() && !funcs.is_empty() {
             transform_dwarf_data(
@@ -165,10 +189,44 @@ impl Compiler {
             obj,
             unwind_info,
             funcs,
+            compile_times,
         })
     }
 }
 
+impl Compiler {
+    /// Compiles a single function, reusing the result of compiling it before
+    /// if it's in the function cache.
+    fn compile_function(
+        &self,
+        translation: &ModuleTranslation,
+        module_key: Option<&[u8; 32]>,
+        index: DefinedFuncIndex,
+        mut input: FunctionBodyData,
+    ) -> Result<CompiledFunction, CompileError> {
+        let (cache, module_key) = match (&self.function_cache, module_key) {
+            (Some(cache), Some(module_key)) => (cache, module_key),
+            _ => {
+                return self
+                    .compiler
+                    .compile_function(translation, index, input, &*self.isa)
+            }
+        };
+
+        let func_index = translation.module.func_index(index);
+        let key = FunctionCache::key(module_key, func_index, &input.body);
+        if let Some(func) = cache.get(&key, &input.body, &mut input.validator)? {
+            return Ok(func);
+        }
+        let body = input.body.clone();
+        let func = self
+            .compiler
+            .compile_function(translation, index, input, &*self.isa)?;
+        cache.insert(key, &body, &func);
+        Ok(func)
+    }
+}
+
 impl Hash for Compiler {
     fn hash<H: Hasher>(&self, hasher: &mut H) {
         let Compiler {
@@ -177,6 +235,7 @@ impl Hash for Compiler {
             isa,
             tunables,
             features,
+            function_cache: _,
         } = self;
 
         // Hash compiler's flags: compilation strategy, isa, frontend config,
@@ -0,0 +1,148 @@
+//! An in-memory cache of compiled functions, so that recompiling a module
+//! after changing a few of its functions only compiles those functions again.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use wasmparser::{FuncValidator, FunctionBody, ValidatorResources};
+use wasmtime_environ::entity::PrimaryMap;
+use wasmtime_environ::ir::SourceLoc;
+use wasmtime_environ::wasm::{
+    FuncIndex, Global, GlobalIndex, MemoryIndex, SignatureIndex, TableIndex, WasmFuncType,
+};
+use wasmtime_environ::{ir, CompileError, CompiledFunction, MemoryPlan, Module, TablePlan};
+
+/// Compiled functions keyed by a hash of their body and of everything in
+/// their module that their code depends on.
+///
+/// Source locations in the cached functions are relative to the beginning of
+/// their body, since unrelated changes to the module usually move the body
+/// around.
+#[derive(Default)]
+pub(crate) struct FunctionCache {
+    functions: Mutex<HashMap<[u8; 32], CompiledFunction>>,
+}
+
+/// The parts of a module which affect how its functions are compiled.
+///
+/// Validating a function depends on more than this, which is why
+/// `FunctionCache::get` always validates the function it's given.
+#[derive(Serialize)]
+struct ModuleContext<'a> {
+    signatures: &'a PrimaryMap<SignatureIndex, (WasmFuncType, ir::Signature)>,
+    functions: &'a PrimaryMap<FuncIndex, SignatureIndex>,
+    table_plans: &'a PrimaryMap<TableIndex, TablePlan>,
+    memory_plans: &'a PrimaryMap<MemoryIndex, MemoryPlan>,
+    globals: &'a PrimaryMap<GlobalIndex, Global>,
+    num_imported_funcs: usize,
+    num_imported_tables: usize,
+    num_imported_memories: usize,
+    num_imported_globals: usize,
+}
+
+impl FunctionCache {
+    /// Hashes the parts of `module` which are the same for all of its
+    /// functions, to be passed to `key`.
+    pub fn module_key(module: &Module) -> [u8; 32] {
+        let context = ModuleContext {
+            signatures: &module.signatures,
+            functions: &module.functions,
+            table_plans: &module.table_plans,
+            memory_plans: &module.memory_plans,
+            globals: &module.globals,
+            num_imported_funcs: module.num_imported_funcs,
+            num_imported_tables: module.num_imported_tables,
+            num_imported_memories: module.num_imported_memories,
+            num_imported_globals: module.num_imported_globals,
+        };
+        let bytes = bincode::serialize(&context).expect("module context is serializable");
+        Sha256::digest(&bytes).into()
+    }
+
+    /// Returns the key of the function `index` with the given `body`, in a
+    /// module whose `module_key` is `module`.
+    pub fn key(module: &[u8; 32], index: FuncIndex, body: &FunctionBody) -> [u8; 32] {
+        let (_, bytes) = body_bytes(body);
+        let mut hasher = Sha256::new();
+        hasher.input(module);
+        hasher.input(&index.as_u32().to_le_bytes());
+        hasher.input(bytes);
+        hasher.result().into()
+    }
+
+    /// Looks up the function with `key`, whose `body` is validated with
+    /// `validator` if it's found.
+    ///
+    /// Compiling a function validates it too, so the function has to be
+    /// validated here in its place.
+    pub fn get(
+        &self,
+        key: &[u8; 32],
+        body: &FunctionBody,
+        validator: &mut FuncValidator<ValidatorResources>,
+    ) -> Result<Option<CompiledFunction>, CompileError> {
+        let mut func = match self.functions.lock().unwrap().get(key) {
+            Some(func) => func.clone(),
+            None => return Ok(None),
+        };
+        validate(body, validator).map_err(|e| CompileError::Wasm(e.into()))?;
+        let (start, _) = body_bytes(body);
+        rebase(&mut func, |loc| loc.wrapping_add(start));
+        Ok(Some(func))
+    }
+
+    /// Caches `func`, the result of compiling `body`, under `key`.
+    pub fn insert(&self, key: [u8; 32], body: &FunctionBody, func: &CompiledFunction) {
+        let (start, _) = body_bytes(body);
+        let mut func = func.clone();
+        rebase(&mut func, |loc| loc.wrapping_sub(start));
+        self.functions.lock().unwrap().insert(key, func);
+    }
+}
+
+/// Returns the offset of `body` within its module and its bytes.
+fn body_bytes<'a>(body: &FunctionBody<'a>) -> (u32, &'a [u8]) {
+    let mut reader = body.get_binary_reader();
+    let start = reader.original_position() as u32;
+    let bytes = reader
+        .read_bytes(reader.bytes_remaining())
+        .expect("reading the remaining bytes can't fail");
+    (start, bytes)
+}
+
+/// Maps all of the source locations of `func` through `f`.
+fn rebase(func: &mut CompiledFunction, f: impl Fn(u32) -> u32) {
+    let map = |loc: &mut SourceLoc| {
+        if !loc.is_default() {
+            *loc = SourceLoc::new(f(loc.bits()));
+        }
+    };
+    for instruction in func.address_map.instructions.iter_mut() {
+        map(&mut instruction.srcloc);
+    }
+    map(&mut func.address_map.start_srcloc);
+    map(&mut func.address_map.end_srcloc);
+    for trap in func.traps.iter_mut() {
+        map(&mut trap.source_loc);
+    }
+}
+
+fn validate(
+    body: &FunctionBody,
+    validator: &mut FuncValidator<ValidatorResources>,
+) -> Result<(), wasmparser::BinaryReaderError> {
+    let mut reader = body.get_binary_reader();
+    for _ in 0..reader.read_var_u32()? {
+        let pos = reader.original_position();
+        let count = reader.read_var_u32()?;
+        let ty = reader.read_type()?;
+        validator.define_locals(pos, count, ty)?;
+    }
+    while !reader.eof() {
+        let pos = reader.original_position();
+        let op = reader.read_operator()?;
+        validator.op(pos, &op)?;
+    }
+    validator.finish(reader.original_position())
+}
@@ -6,26 +6,31 @@
 use crate::code_memory::CodeMemory;
 use crate::compiler::{Compilation, Compiler};
 use crate::link::link_module;
+use crate::object::utils::try_parse_func_name;
 use crate::object::ObjectUnwindInfo;
+use more_asserts::assert_le;
+use object::read::{Object, ObjectSection};
 use object::File as ObjectFile;
 use serde::{Deserialize, Serialize};
 use std::any::Any;
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
-use wasmtime_debug::create_gdbjit_image;
+use wasmtime_debug::{create_gdbjit_image, read_line_table, strip_dwarf};
 use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::isa::TargetIsa;
 use wasmtime_environ::wasm::{DefinedFuncIndex, SignatureIndex};
 use wasmtime_environ::{
-    CompileError, DataInitializer, DataInitializerLocation, FunctionAddressMap, Module,
+    CompileError, DataInitializer, DataInitializerLocation, FunctionAddressMap, LineTable, Module,
     ModuleEnvironment, ModuleTranslation, StackMapInformation, TrapInformation,
 };
 use wasmtime_profiling::ProfilingAgent;
 use wasmtime_runtime::{
-    GdbJitImageRegistration, Imports, InstanceHandle, InstantiationError, RuntimeMemoryCreator,
-    SignatureRegistry, StackMapRegistry, VMExternRefActivationsTable, VMFunctionBody, VMInterrupts,
-    VMTrampoline,
+    GdbJitImageRegistration, Imports, InstanceHandle, InstancePool, InstantiationError, Mmap,
+    ResourceLimiter, RuntimeMemoryCreator, SignatureRegistry, StackMapRegistry,
+    VMExternRefActivationsTable, VMFunctionBody, VMInterrupts, VMTrampoline,
 };
 
 /// An error condition while setting up a wasm instance, be it validation,
@@ -70,6 +75,27 @@ pub struct CompilationArtifacts {
 
     /// Debug info presence flags.
     debug_info: bool,
+
+    /// How long compiling each function took. This isn't deterministic, so
+    /// it's only known to the process which compiled the module.
+    #[serde(skip)]
+    compile_times: Option<PrimaryMap<DefinedFuncIndex, Duration>>,
+}
+
+/// `CompilationArtifacts` deserialized from a file mapped into memory, which
+/// its ELF image is borrowed from instead of being copied out.
+///
+/// This is read from the same serialized bytes as `CompilationArtifacts`, so
+/// the fields of both have to stay the same and in the same order.
+#[derive(Deserialize)]
+pub struct MappedCompilationArtifacts<'a> {
+    module: Module,
+    #[serde(borrow)]
+    obj: &'a [u8],
+    unwind_info: Box<[ObjectUnwindInfo]>,
+    data_initializers: Box<[OwnedDataInitializer]>,
+    funcs: PrimaryMap<DefinedFuncIndex, FunctionInfo>,
+    debug_info: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -96,14 +122,20 @@ impl CompilationArtifacts {
             obj,
             unwind_info,
             funcs,
+            compile_times,
         } = compiler.compile(&mut translation)?;
 
         let ModuleTranslation {
-            module,
+            mut module,
             data_initializers,
+            debuginfo,
             ..
         } = translation;
 
+        if let Some(debuginfo) = &debuginfo {
+            module.line_table = read_line_table(debuginfo).map_err(SetupError::DebugInfo)?;
+        }
+
         let data_initializers = data_initializers
             .into_iter()
             .map(OwnedDataInitializer::new)
@@ -130,8 +162,30 @@ impl CompilationArtifacts {
                 })
                 .collect(),
             debug_info: compiler.tunables().debug_info,
+            compile_times: Some(compile_times),
         })
     }
+
+    /// Drops the names of the module and its functions from its name
+    /// section.
+    pub fn strip_names(&mut self) {
+        self.module.name = None;
+        self.module.func_names.clear();
+    }
+
+    /// Drops the DWARF debug information describing the compiled code, and
+    /// the source locations read from the module's own DWARF, if the module
+    /// was compiled with any.
+    pub fn strip_debug_info(&mut self) -> Result<(), SetupError> {
+        if self.debug_info {
+            self.obj = strip_dwarf(&self.obj)
+                .map_err(SetupError::DebugInfo)?
+                .into_boxed_slice();
+            self.module.line_table = LineTable::default();
+            self.debug_info = false;
+        }
+        Ok(())
+    }
 }
 
 struct FinishedFunctions(PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>);
@@ -154,8 +208,31 @@ pub struct CompiledModule {
     trampolines: PrimaryMap<SignatureIndex, VMTrampoline>,
     data_initializers: Box<[OwnedDataInitializer]>,
     funcs: PrimaryMap<DefinedFuncIndex, FunctionInfo>,
-    obj: Box<[u8]>,
+    obj: ObjectImage,
     unwind_info: Box<[ObjectUnwindInfo]>,
+    compile_times: Option<PrimaryMap<DefinedFuncIndex, Duration>>,
+}
+
+/// The ELF image a module's code was loaded from, kept around to serialize
+/// the module again.
+enum ObjectImage {
+    Owned(Box<[u8]>),
+    #[cfg(unix)]
+    Mapped {
+        image: Arc<Mmap>,
+        start: usize,
+        len: usize,
+    },
+}
+
+impl ObjectImage {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            ObjectImage::Owned(obj) => &obj[..],
+            #[cfg(unix)]
+            ObjectImage::Mapped { image, start, len } => &image.as_slice()[*start..*start + *len],
+        }
+    }
 }
 
 impl CompiledModule {
@@ -182,21 +259,98 @@ impl CompiledModule {
             data_initializers,
             funcs,
             debug_info,
+            compile_times,
         } = artifacts;
 
         // Allocate all of the compiled functions into executable memory,
         // copying over their contents.
-        let (code_memory, code_range, finished_functions, trampolines) =
-            build_code_memory(isa, &obj, &module, &unwind_info).map_err(|message| {
-                SetupError::Instantiate(InstantiationError::Resource(format!(
-                    "failed to build code memory for functions: {}",
-                    message
-                )))
-            })?;
+        let code = build_code_memory(isa, &obj, None, &module, &unwind_info)?;
+        let mut compiled = Self::from_parts(
+            code,
+            module,
+            ObjectImage::Owned(obj),
+            unwind_info,
+            data_initializers,
+            funcs,
+            debug_info,
+            profiler,
+        )?;
+        compiled.compile_times = compile_times;
+        Ok(compiled)
+    }
+
+    /// Creates `CompiledModule` from `MappedCompilationArtifacts` deserialized
+    /// from `image`, a mapping of `file`.
+    ///
+    /// Rather than being copied, the compiled code is executed from a private
+    /// mapping of `file`, so that its pages are shared with the page cache
+    /// except for the ones relocations are applied to.
+    ///
+    /// # Unsafety
+    ///
+    /// The contents of `file` must not change for as long as the module is
+    /// alive.
+    #[cfg(unix)]
+    pub unsafe fn from_mapped_artifacts(
+        artifacts: MappedCompilationArtifacts<'_>,
+        image: &Arc<Mmap>,
+        file: &std::fs::File,
+        isa: &dyn TargetIsa,
+        profiler: &dyn ProfilingAgent,
+    ) -> Result<Self, SetupError> {
+        let MappedCompilationArtifacts {
+            module,
+            obj,
+            unwind_info,
+            data_initializers,
+            funcs,
+            debug_info,
+        } = artifacts;
+
+        let start = obj.as_ptr() as usize - image.as_ptr() as usize;
+        assert_le!(start + obj.len(), image.len());
+
+        let mut mmap = Mmap::with_at_least(image.len()).map_err(resource_error)?;
+        mmap.map_file(0, file, image.len())
+            .map_err(resource_error)?;
+        let code = build_code_memory(isa, obj, Some((mmap, start)), &module, &unwind_info)?;
+        let obj = ObjectImage::Mapped {
+            image: image.clone(),
+            start,
+            len: obj.len(),
+        };
+        Self::from_parts(
+            code,
+            module,
+            obj,
+            unwind_info,
+            data_initializers,
+            funcs,
+            debug_info,
+            profiler,
+        )
+    }
+
+    fn from_parts(
+        code: BuiltCodeMemory,
+        module: Module,
+        obj: ObjectImage,
+        unwind_info: Box<[ObjectUnwindInfo]>,
+        data_initializers: Box<[OwnedDataInitializer]>,
+        funcs: PrimaryMap<DefinedFuncIndex, FunctionInfo>,
+        debug_info: bool,
+        profiler: &dyn ProfilingAgent,
+    ) -> Result<Self, SetupError> {
+        let (code_memory, code_range, finished_functions, trampolines) = code;
 
         // Register GDB JIT images; initialize profiler and load the wasm module.
         let dbg_jit_registration = if debug_info {
-            let bytes = create_dbg_image(obj.to_vec(), code_range, &module, &finished_functions)?;
+            let bytes = create_dbg_image(
+                obj.as_slice().to_vec(),
+                code_range,
+                &module,
+                &finished_functions,
+            )?;
 
             profiler.module_load(&module, &finished_functions, Some(&bytes));
 
@@ -221,6 +375,7 @@ impl CompiledModule {
             funcs,
             obj,
             unwind_info,
+            compile_times: None,
         })
     }
 
@@ -228,11 +383,12 @@ impl CompiledModule {
     pub fn to_compilation_artifacts(&self) -> CompilationArtifacts {
         CompilationArtifacts {
             module: (*self.module).clone(),
-            obj: self.obj.clone(),
+            obj: self.obj.as_slice().into(),
             unwind_info: self.unwind_info.clone(),
             data_initializers: self.data_initializers.clone(),
             funcs: self.funcs.clone(),
             debug_info: self.code.dbg_jit_registration.is_some(),
+            compile_times: self.compile_times.clone(),
         }
     }
 
@@ -250,10 +406,12 @@ impl CompiledModule {
         imports: Imports<'_>,
         signature_registry: &mut SignatureRegistry,
         mem_creator: Option<&dyn RuntimeMemoryCreator>,
+        pool: Option<&InstancePool>,
         interrupts: *const VMInterrupts,
         host_state: Box<dyn Any>,
         externref_activations_table: *mut VMExternRefActivationsTable,
         stack_map_registry: *mut StackMapRegistry,
+        limiter: Option<Rc<dyn ResourceLimiter>>,
     ) -> Result<InstanceHandle, InstantiationError> {
         // Compute indices into the shared signature table.
         let signatures = {
@@ -278,11 +436,13 @@ impl CompiledModule {
             trampolines,
             imports,
             mem_creator,
+            pool,
             signatures.into_boxed_slice(),
             host_state,
             interrupts,
             externref_activations_table,
             stack_map_registry,
+            limiter,
         )
     }
 
@@ -344,11 +504,67 @@ impl CompiledModule {
             .map(|((i, alloc), func)| (i, *alloc, func.traps.as_slice(), &func.address_map))
     }
 
+    /// Returns the mapping of a function's machine code to the wasm
+    /// instructions it was generated from.
+    pub fn address_map(&self, index: DefinedFuncIndex) -> &FunctionAddressMap {
+        &self.funcs[index].address_map
+    }
+
     /// Returns all ranges convered by JIT code.
     pub fn jit_code_ranges<'a>(&'a self) -> impl Iterator<Item = (usize, usize)> + 'a {
         self.code.code_memory.published_ranges()
     }
 
+    /// Returns the total size in bytes of the memory allocated for this
+    /// module's code.
+    pub fn code_memory_size(&self) -> usize {
+        self.code.code_memory.allocated_size()
+    }
+
+    /// Returns how long compiling each function took, if this module was
+    /// compiled by this process rather than deserialized.
+    pub fn compile_times(&self) -> Option<&PrimaryMap<DefinedFuncIndex, Duration>> {
+        self.compile_times.as_ref()
+    }
+
+    /// Returns the number of relocations which were applied to the code of
+    /// each function when it was loaded.
+    pub fn relocation_counts(&self) -> PrimaryMap<DefinedFuncIndex, usize> {
+        let obj = ObjectFile::parse(self.obj.as_slice()).expect("object was parsed when loaded");
+
+        // Find the range of each function's code within the code section.
+        let mut ranges = Vec::new();
+        for (_id, sym) in obj.symbols() {
+            let index = sym
+                .name()
+                .and_then(try_parse_func_name)
+                .and_then(|index| self.module.defined_func_index(index));
+            if let Some(index) = index {
+                ranges.push((sym.address(), sym.address() + sym.size(), index));
+            }
+        }
+        ranges.sort_by_key(|(start, _, _)| *start);
+
+        let mut counts = self
+            .finished_functions()
+            .values()
+            .map(|_| 0)
+            .collect::<PrimaryMap<_, _>>();
+        let text_section = obj.section_by_name(".text").unwrap();
+        for (offset, _) in text_section.relocations() {
+            let i = match ranges.binary_search_by_key(&offset, |(start, _, _)| *start) {
+                Ok(i) => i,
+                Err(0) => continue,
+                Err(i) => i - 1,
+            };
+            let (_, end, index) = ranges[i];
+            if offset < end {
+                counts[index] += 1;
+            }
+        }
+        counts
+    }
+
     /// Returns module's JIT code.
     pub fn code(&self) -> &Arc<ModuleCode> {
         &self.code
@@ -389,25 +605,50 @@ fn create_dbg_image(
         .map_err(SetupError::DebugInfo)
 }
 
+fn resource_error(message: String) -> SetupError {
+    SetupError::Instantiate(InstantiationError::Resource(format!(
+        "failed to build code memory for functions: {}",
+        message
+    )))
+}
+
+type BuiltCodeMemory = (
+    CodeMemory,
+    (*const u8, usize),
+    PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+    PrimaryMap<SignatureIndex, VMTrampoline>,
+);
+
+/// Loads the code of the ELF image `obj` into executable memory.
+///
+/// If `mapped` is given, it's a writable mapping which has a copy of `obj` at
+/// the given offset, whose code is used in place.
 fn build_code_memory(
     isa: &dyn TargetIsa,
     obj: &[u8],
+    mapped: Option<(Mmap, usize)>,
     module: &Module,
     unwind_info: &Box<[ObjectUnwindInfo]>,
-) -> Result<
-    (
-        CodeMemory,
-        (*const u8, usize),
-        PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
-        PrimaryMap<SignatureIndex, VMTrampoline>,
-    ),
-    String,
-> {
-    let obj = ObjectFile::parse(obj).map_err(|_| "Unable to read obj".to_string())?;
+) -> Result<BuiltCodeMemory, SetupError> {
+    let obj_bytes = obj;
+    let obj =
+        ObjectFile::parse(obj).map_err(|_| resource_error("Unable to read obj".to_string()))?;
 
     let mut code_memory = CodeMemory::new();
 
-    let allocation = code_memory.allocate_for_object(&obj, unwind_info)?;
+    let allocation = match mapped {
+        Some((mmap, obj_start)) => {
+            let text = obj
+                .section_by_name(".text")
+                .unwrap()
+                .data()
+                .map_err(|_| resource_error("cannot read section data".to_string()))?;
+            let text_start = obj_start + (text.as_ptr() as usize - obj_bytes.as_ptr() as usize);
+            code_memory.map_object(mmap, text_start, &obj, unwind_info)
+        }
+        None => code_memory.allocate_for_object(&obj, unwind_info),
+    }
+    .map_err(resource_error)?;
 
     // Second, create a PrimaryMap from result vector of pointers.
     let mut finished_functions = PrimaryMap::new();
@@ -433,7 +674,7 @@ fn build_code_memory(
     let code_range = (code_range.as_ptr(), code_range.len());
 
     // Make all code compiled thus far executable.
-    code_memory.publish(isa);
+    code_memory.publish(isa).map_err(resource_error)?;
 
     Ok((code_memory, code_range, finished_functions, trampolines))
 }
@@ -23,6 +23,7 @@
 
 mod code_memory;
 mod compiler;
+mod function_cache;
 mod instantiate;
 mod link;
 mod object;
@@ -33,7 +34,9 @@ pub mod trampoline;
 
 pub use crate::code_memory::CodeMemory;
 pub use crate::compiler::{Compilation, CompilationStrategy, Compiler};
-pub use crate::instantiate::{CompilationArtifacts, CompiledModule, SetupError};
+pub use crate::instantiate::{
+    CompilationArtifacts, CompiledModule, MappedCompilationArtifacts, SetupError,
+};
 pub use crate::link::link_module;
 
 /// Version number of this crate.
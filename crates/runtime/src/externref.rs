@@ -848,6 +848,11 @@ impl StackMapRegistry {
         assert!(old.is_none());
     }
 
+    /// Unregister the stack maps of all modules.
+    pub fn clear(&self) {
+        self.inner.borrow_mut().ranges.clear();
+    }
+
     /// Lookup the stack map for the given PC, if any.
     pub fn lookup_stack_map(&self, pc: usize) -> Option<Rc<StackMap>> {
         let inner = self.inner.borrow();
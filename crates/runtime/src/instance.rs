@@ -6,6 +6,8 @@ use crate::export::Export;
 use crate::externref::{StackMapRegistry, VMExternRefActivationsTable};
 use crate::imports::Imports;
 use crate::memory::{DefaultMemoryCreator, RuntimeLinearMemory, RuntimeMemoryCreator};
+use crate::parking_spot;
+use crate::pooling::{self, InstancePool, InstanceSlot};
 use crate::table::{Table, TableElement};
 use crate::traphandlers::Trap;
 use crate::vmcontext::{
@@ -19,10 +21,13 @@ use more_asserts::assert_lt;
 use std::alloc::{self, Layout};
 use std::any::Any;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
 use std::ptr::NonNull;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering::SeqCst};
 use std::sync::Arc;
+use std::time::Duration;
 use std::{mem, ptr, slice};
 use thiserror::Error;
 use wasmtime_environ::entity::{packed_option::ReservedValue, BoxedSlice, EntityRef, PrimaryMap};
@@ -31,7 +36,69 @@ use wasmtime_environ::wasm::{
     ElemIndex, FuncIndex, GlobalIndex, GlobalInit, MemoryIndex, SignatureIndex, TableElementType,
     TableIndex, WasmType,
 };
-use wasmtime_environ::{ir, DataInitializer, EntityIndex, Module, TableElements, VMOffsets};
+use wasmtime_environ::{
+    ir, DataInitializer, EntityIndex, MemoryPlan, Module, TableElements, VMOffsets,
+};
+
+/// A policy deciding whether the memories and tables of an instance may grow,
+/// and how many instances, memories and tables a store may hold.
+///
+/// The limiter is consulted before any growth is attempted, whether it was
+/// requested by wasm with `memory.grow` or `table.grow`, or by the host. A
+/// vetoed growth fails the same way as growth beyond the declared maximum:
+/// wasm sees `-1`.
+///
+/// Limits apply per store: the callbacks are not told which instance is
+/// growing. A memory or table may be exported by one instance and grown
+/// through an import by another, or be created by the host and belong to no
+/// instance at all, so growth can't be attributed to a single instance.
+/// Embedders that need a budget per instance should give each instance its
+/// own store.
+pub trait ResourceLimiter {
+    /// Called when a linear memory is about to grow from `current` to
+    /// `desired` pages. `maximum` is the memory's declared maximum, if any.
+    ///
+    /// Returning `false` denies the growth.
+    fn memory_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool;
+
+    /// Called when a table is about to grow from `current` to `desired`
+    /// elements. `maximum` is the table's declared maximum, if any.
+    ///
+    /// Returning `false` denies the growth.
+    fn table_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool;
+
+    /// The maximum number of instances that may be created in a store.
+    ///
+    /// Defaults to [`DEFAULT_INSTANCE_LIMIT`].
+    fn instances(&self) -> usize {
+        DEFAULT_INSTANCE_LIMIT
+    }
+
+    /// The maximum number of linear memories that may be defined by the
+    /// instances of a store.
+    ///
+    /// Defaults to [`DEFAULT_MEMORY_LIMIT`].
+    fn memories(&self) -> usize {
+        DEFAULT_MEMORY_LIMIT
+    }
+
+    /// The maximum number of tables that may be defined by the instances of a
+    /// store.
+    ///
+    /// Defaults to [`DEFAULT_TABLE_LIMIT`].
+    fn tables(&self) -> usize {
+        DEFAULT_TABLE_LIMIT
+    }
+}
+
+/// The default value of [`ResourceLimiter::instances`].
+pub const DEFAULT_INSTANCE_LIMIT: usize = 10000;
+
+/// The default value of [`ResourceLimiter::memories`].
+pub const DEFAULT_MEMORY_LIMIT: usize = 10000;
+
+/// The default value of [`ResourceLimiter::tables`].
+pub const DEFAULT_TABLE_LIMIT: usize = 10000;
 
 /// A WebAssembly instance.
 ///
@@ -60,7 +127,7 @@ pub(crate) struct Instance {
 
     /// Passive data segments from our module. As `data.drop`s happen, entries
     /// get removed. A missing entry is considered equivalent to an empty slice.
-    passive_data: RefCell<HashMap<DataIndex, Arc<[u8]>>>,
+    passive_data: RefCell<BTreeMap<DataIndex, Arc<[u8]>>>,
 
     /// Pointers to trampoline functions used to enter particular signatures
     trampolines: HashMap<VMSharedSignatureIndex, VMTrampoline>,
@@ -68,6 +135,13 @@ pub(crate) struct Instance {
     /// Hosts can store arbitrary per-instance information here.
     host_state: Box<dyn Any>,
 
+    /// Consulted before growing any of `memories` or `tables`.
+    limiter: Option<Rc<dyn ResourceLimiter>>,
+
+    /// The slot of an `InstancePool` this instance lives in, if it wasn't
+    /// allocated on the heap.
+    pool_slot: Option<InstanceSlot>,
+
     /// Additional context used by compiled wasm code. This field is last, and
     /// represents a dynamically-sized array that extends beyond the nominal
     /// end of the struct (similar to a flexible array member).
@@ -173,12 +247,26 @@ impl Instance {
             self.memory(defined_index)
         } else {
             let import = self.imported_memory(index);
-            *unsafe { import.from.as_ref().unwrap() }
+            unsafe {
+                let foreign_instance = (&mut *import.vmctx).instance();
+                let foreign_index = foreign_instance.memory_index(&*import.from);
+                foreign_instance.memory(foreign_index)
+            }
         }
     }
 
     /// Return the indexed `VMMemoryDefinition`.
     fn memory(&self, index: DefinedMemoryIndex) -> VMMemoryDefinition {
+        // Shared memories can be grown through other instances, possibly in
+        // other stores, so their definition in this vmctx may be out of date.
+        if self.module.memory_plans[self.module.memory_index(index)]
+            .memory
+            .shared
+        {
+            let memory = self.memories[index].vmmemory();
+            self.set_memory(index, memory);
+            return memory;
+        }
         unsafe { *self.memory_ptr(index) }
     }
 
@@ -389,11 +477,23 @@ impl Instance {
     /// Returns `None` if memory can't be grown by the specified amount
     /// of pages.
     pub(crate) fn memory_grow(&self, memory_index: DefinedMemoryIndex, delta: u32) -> Option<u32> {
-        let result = self
+        let memory = self
             .memories
             .get(memory_index)
-            .unwrap_or_else(|| panic!("no memory for index {}", memory_index.index()))
-            .grow(delta);
+            .unwrap_or_else(|| panic!("no memory for index {}", memory_index.index()));
+
+        if let Some(limiter) = &self.limiter {
+            let current = memory.size();
+            let desired = current.checked_add(delta)?;
+            let maximum = self.module.memory_plans[self.module.memory_index(memory_index)]
+                .memory
+                .maximum;
+            if !limiter.memory_growing(current, desired, maximum) {
+                return None;
+            }
+        }
+
+        let result = memory.grow(delta);
 
         // Keep current the VMContext pointers used by compiled wasm code.
         self.set_memory(memory_index, self.memories[memory_index].vmmemory());
@@ -471,12 +571,24 @@ impl Instance {
         delta: u32,
         init_value: TableElement,
     ) -> Option<u32> {
+        let table = self
+            .tables
+            .get(table_index)
+            .unwrap_or_else(|| panic!("no table for index {}", table_index.index()));
+
+        if let Some(limiter) = &self.limiter {
+            let current = table.size();
+            let desired = current.checked_add(delta)?;
+            let maximum = self.module.table_plans[self.module.table_index(table_index)]
+                .table
+                .maximum;
+            if !limiter.table_growing(current, desired, maximum) {
+                return None;
+            }
+        }
+
         unsafe {
-            let orig_size = self
-                .tables
-                .get(table_index)
-                .unwrap_or_else(|| panic!("no table for index {}", table_index.index()))
-                .grow(delta, init_value)?;
+            let orig_size = table.grow(delta, init_value)?;
 
             // Keep the `VMContext` pointers used by compiled Wasm code up to
             // date.
@@ -563,6 +675,19 @@ impl Instance {
         // https://webassembly.github.io/bulk-memory-operations/core/exec/instructions.html#exec-table-init
 
         let table = self.get_table(table_index);
+        self.table_init_segment(table, elem_index, dst, src, len)
+    }
+
+    /// Same as `table_init`, but the destination `table` may be any table
+    /// rather than one in this instance's index space.
+    pub(crate) fn table_init_segment(
+        &self,
+        table: &Table,
+        elem_index: ElemIndex,
+        dst: u32,
+        src: u32,
+        len: u32,
+    ) -> Result<(), Trap> {
         let passive_elements = self.passive_elements.borrow();
         let elem = passive_elements
             .get(&elem_index)
@@ -745,6 +870,101 @@ impl Instance {
         passive_data.remove(&data_index);
     }
 
+    /// Perform the `memory.atomic.notify` operation, returning the number of
+    /// threads which were woken up.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Trap` error if `addr` is out of bounds or not 4-byte
+    /// aligned.
+    pub(crate) fn memory_atomic_notify(
+        &self,
+        memory_index: MemoryIndex,
+        addr: u64,
+        count: u32,
+    ) -> Result<u32, Trap> {
+        let ptr = self.atomic_addr(memory_index, addr, 4)?;
+        // No thread can wait on a non-shared memory.
+        if !self.module.memory_plans[memory_index].memory.shared {
+            return Ok(0);
+        }
+        Ok(parking_spot::unpark(ptr as usize, count))
+    }
+
+    /// Perform the `memory.atomic.wait32` operation, returning the
+    /// `WaitResult` as an integer. A negative `timeout` means waiting forever.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Trap` error if `addr` is out of bounds or not 4-byte
+    /// aligned, or if the memory isn't shared.
+    pub(crate) fn memory_atomic_wait32(
+        &self,
+        memory_index: MemoryIndex,
+        addr: u64,
+        expected: u32,
+        timeout: i64,
+    ) -> Result<u32, Trap> {
+        let ptr = self.atomic_wait_addr(memory_index, addr, 4)? as *const AtomicU32;
+        let validate = || unsafe { (*ptr).load(SeqCst) == expected };
+        Ok(parking_spot::park(ptr as usize, validate, wait_timeout(timeout)) as u32)
+    }
+
+    /// Perform the `memory.atomic.wait64` operation, returning the
+    /// `WaitResult` as an integer. A negative `timeout` means waiting forever.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Trap` error if `addr` is out of bounds or not 8-byte
+    /// aligned, or if the memory isn't shared.
+    pub(crate) fn memory_atomic_wait64(
+        &self,
+        memory_index: MemoryIndex,
+        addr: u64,
+        expected: u64,
+        timeout: i64,
+    ) -> Result<u32, Trap> {
+        let ptr = self.atomic_wait_addr(memory_index, addr, 8)? as *const AtomicU64;
+        let validate = || unsafe { (*ptr).load(SeqCst) == expected };
+        Ok(parking_spot::park(ptr as usize, validate, wait_timeout(timeout)) as u32)
+    }
+
+    fn atomic_wait_addr(
+        &self,
+        memory_index: MemoryIndex,
+        addr: u64,
+        size: u64,
+    ) -> Result<*mut u8, Trap> {
+        let ptr = self.atomic_addr(memory_index, addr, size)?;
+        if !self.module.memory_plans[memory_index].memory.shared {
+            return Err(Trap::User(
+                "atomic wait on a non-shared memory".to_string().into(),
+            ));
+        }
+        Ok(ptr)
+    }
+
+    /// Returns the host address of the `size`-byte value at `addr` in the
+    /// given memory, which is the key threads wait on it with.
+    fn atomic_addr(
+        &self,
+        memory_index: MemoryIndex,
+        addr: u64,
+        size: u64,
+    ) -> Result<*mut u8, Trap> {
+        let memory = self.get_memory(memory_index);
+        if addr
+            .checked_add(size)
+            .map_or(true, |end| end > memory.current_length as u64)
+        {
+            return Err(Trap::wasm(ir::TrapCode::HeapOutOfBounds));
+        }
+        if addr % size != 0 {
+            return Err(Trap::wasm(ir::TrapCode::HeapMisaligned));
+        }
+        Ok(unsafe { memory.base.add(addr as usize) })
+    }
+
     /// Get a table by index regardless of whether it is locally-defined or an
     /// imported, foreign table.
     pub(crate) fn get_table(&self, table_index: TableIndex) -> &Table {
@@ -785,6 +1005,16 @@ impl Instance {
     }
 }
 
+/// Converts the timeout of an atomic wait, in nanoseconds, where a negative
+/// timeout means waiting forever.
+fn wait_timeout(timeout: i64) -> Option<Duration> {
+    if timeout < 0 {
+        None
+    } else {
+        Some(Duration::from_nanos(timeout as u64))
+    }
+}
+
 /// A handle holding an `Instance` of a WebAssembly module.
 #[derive(Hash, PartialEq, Eq)]
 pub struct InstanceHandle {
@@ -811,6 +1041,12 @@ impl InstanceHandle {
     /// It is your responsibility to ensure that the given raw
     /// `externref_activations_table` and `stack_map_registry` outlive this
     /// instance.
+    ///
+    /// If a `limiter` is given it is consulted before any of the instance's
+    /// memories or tables grow.
+    ///
+    /// If a `pool` is given the instance and its tables are allocated from
+    /// it, and so are its memories unless a `mem_creator` is given as well.
     pub unsafe fn new(
         module: Arc<Module>,
         code: Arc<dyn Any>,
@@ -818,17 +1054,29 @@ impl InstanceHandle {
         trampolines: HashMap<VMSharedSignatureIndex, VMTrampoline>,
         imports: Imports,
         mem_creator: Option<&dyn RuntimeMemoryCreator>,
+        pool: Option<&InstancePool>,
         vmshared_signatures: BoxedSlice<SignatureIndex, VMSharedSignatureIndex>,
         host_state: Box<dyn Any>,
         interrupts: *const VMInterrupts,
         externref_activations_table: *mut VMExternRefActivationsTable,
         stack_map_registry: *mut StackMapRegistry,
+        limiter: Option<Rc<dyn ResourceLimiter>>,
     ) -> Result<Self, InstantiationError> {
         debug_assert!(!externref_activations_table.is_null());
         debug_assert!(!stack_map_registry.is_null());
 
-        let tables = create_tables(&module);
-        let memories = create_memories(&module, mem_creator.unwrap_or(&DefaultMemoryCreator {}))?;
+        let affinity = pooling::module_affinity(&module);
+        let tables = create_tables(&module, pool, affinity)?;
+        let memories = match (mem_creator, pool) {
+            (Some(mem_creator), _) => {
+                create_memories(&module, |plan| mem_creator.new_memory(plan))?
+            }
+            (None, Some(pool)) => create_memories(&module, |plan| {
+                pool.memory_creator()
+                    .new_memory_with_affinity(plan, Some(affinity))
+            })?,
+            (None, None) => create_memories(&module, |plan| DefaultMemoryCreator.new_memory(plan))?,
+        };
 
         let vmctx_tables = tables
             .values()
@@ -849,7 +1097,7 @@ impl InstanceHandle {
         let passive_data = RefCell::new(module.passive_data.clone());
 
         let handle = {
-            let instance = Instance {
+            let mut instance = Instance {
                 module,
                 code,
                 offsets,
@@ -859,13 +1107,26 @@ impl InstanceHandle {
                 passive_data,
                 trampolines,
                 host_state,
+                limiter,
+                pool_slot: None,
                 vmctx: VMContext {},
             };
             let layout = instance.alloc_layout();
-            let instance_ptr = alloc::alloc(layout) as *mut Instance;
-            if instance_ptr.is_null() {
-                alloc::handle_alloc_error(layout);
-            }
+            let instance_ptr = match pool {
+                Some(pool) => {
+                    let slot = pool.take_instance_slot(layout, affinity)?;
+                    let instance_ptr = slot.as_mut_ptr() as *mut Instance;
+                    instance.pool_slot = Some(slot);
+                    instance_ptr
+                }
+                None => {
+                    let instance_ptr = alloc::alloc(layout) as *mut Instance;
+                    if instance_ptr.is_null() {
+                        alloc::handle_alloc_error(layout);
+                    }
+                    instance_ptr
+                }
+            };
             ptr::write(instance_ptr, instance);
             InstanceHandle {
                 instance: instance_ptr,
@@ -1043,6 +1304,11 @@ impl InstanceHandle {
         self.instance().memory_index(memory)
     }
 
+    /// Return the number of wasm pages in the memory `memory_index`.
+    pub fn memory_size(&self, memory_index: DefinedMemoryIndex) -> u32 {
+        self.instance().memory_size(memory_index)
+    }
+
     /// Grow memory in this instance by the specified amount of pages.
     ///
     /// Returns `None` if memory can't be grown by the specified amount
@@ -1123,11 +1389,49 @@ impl InstanceHandle {
             .defined_table_fill(table_index, dst, val, len)
     }
 
+    /// Initialize a region of `table` with this instance's passive element
+    /// segment `elem_index`.
+    ///
+    /// Returns an error if either the region of the table or the region of
+    /// the element segment is out of bounds. Dropped segments are treated as
+    /// empty.
+    pub fn table_init_segment(
+        &self,
+        table: &Table,
+        elem_index: ElemIndex,
+        dst: u32,
+        src: u32,
+        len: u32,
+    ) -> Result<(), Trap> {
+        self.instance()
+            .table_init_segment(table, elem_index, dst, src, len)
+    }
+
     /// Get a table defined locally within this module.
     pub fn get_defined_table(&self, index: DefinedTableIndex) -> &Table {
         self.instance().get_defined_table(index)
     }
 
+    /// Returns the total size, in wasm pages, of the linear memories defined
+    /// within this instance.
+    pub fn defined_memory_pages(&self) -> u64 {
+        self.instance()
+            .memories
+            .values()
+            .map(|memory| u64::from(memory.size()))
+            .sum()
+    }
+
+    /// Returns the total number of elements of the tables defined within this
+    /// instance.
+    pub fn defined_table_elements(&self) -> u64 {
+        self.instance()
+            .tables
+            .values()
+            .map(|table| u64::from(table.size()))
+            .sum()
+    }
+
     /// Gets the trampoline pre-registered for a particular signature
     pub fn trampoline(&self, sig: VMSharedSignatureIndex) -> Option<VMTrampoline> {
         self.instance().trampolines.get(&sig).cloned()
@@ -1156,10 +1460,13 @@ impl InstanceHandle {
     /// `InstanceHandle` elsewhere, and there's nothing preventing usage of
     /// this handle after this function is called.
     pub unsafe fn dealloc(&self) {
-        let instance = self.instance();
-        let layout = instance.alloc_layout();
+        let layout = self.instance().alloc_layout();
+        let pool_slot = (*self.instance).pool_slot.take();
         ptr::drop_in_place(self.instance);
-        alloc::dealloc(self.instance.cast(), layout);
+        match pool_slot {
+            Some(slot) => slot.release(),
+            None => alloc::dealloc(self.instance.cast(), layout),
+        }
     }
 }
 
@@ -1237,14 +1544,22 @@ fn check_memory_init_bounds(
 }
 
 /// Allocate memory for just the tables of the current module.
-fn create_tables(module: &Module) -> BoxedSlice<DefinedTableIndex, Table> {
+fn create_tables(
+    module: &Module,
+    pool: Option<&InstancePool>,
+    affinity: usize,
+) -> Result<BoxedSlice<DefinedTableIndex, Table>, InstantiationError> {
     let num_imports = module.num_imported_tables;
     let mut tables: PrimaryMap<DefinedTableIndex, _> =
         PrimaryMap::with_capacity(module.table_plans.len() - num_imports);
     for table in &module.table_plans.values().as_slice()[num_imports..] {
-        tables.push(Table::new(table));
+        tables.push(match pool {
+            Some(pool) => Table::new_pooled(table, pool.tables(), affinity)
+                .map_err(InstantiationError::Resource)?,
+            None => Table::new(table),
+        });
     }
-    tables.into_boxed_slice()
+    Ok(tables.into_boxed_slice())
 }
 
 /// Compute the offset for a table element initializer.
@@ -1334,17 +1649,13 @@ fn initialize_passive_elements(instance: &Instance) {
 /// Allocate memory for just the memories of the current module.
 fn create_memories(
     module: &Module,
-    mem_creator: &dyn RuntimeMemoryCreator,
+    new_memory: impl Fn(&MemoryPlan) -> Result<Box<dyn RuntimeLinearMemory>, String>,
 ) -> Result<BoxedSlice<DefinedMemoryIndex, Box<dyn RuntimeLinearMemory>>, InstantiationError> {
     let num_imports = module.num_imported_memories;
     let mut memories: PrimaryMap<DefinedMemoryIndex, _> =
         PrimaryMap::with_capacity(module.memory_plans.len() - num_imports);
     for plan in &module.memory_plans.values().as_slice()[num_imports..] {
-        memories.push(
-            mem_creator
-                .new_memory(plan)
-                .map_err(InstantiationError::Resource)?,
-        );
+        memories.push(new_memory(plan).map_err(InstantiationError::Resource)?);
     }
     Ok(memories.into_boxed_slice())
 }
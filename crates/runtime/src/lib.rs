@@ -28,6 +28,8 @@ mod instance;
 mod jit_int;
 mod memory;
 mod mmap;
+mod parking_spot;
+mod pooling;
 mod sig_registry;
 mod table;
 mod traphandlers;
@@ -39,14 +41,22 @@ pub mod libcalls;
 pub use crate::export::*;
 pub use crate::externref::*;
 pub use crate::imports::Imports;
-pub use crate::instance::{InstanceHandle, InstantiationError, LinkError};
+pub use crate::instance::{
+    InstanceHandle, InstantiationError, LinkError, ResourceLimiter, DEFAULT_INSTANCE_LIMIT,
+    DEFAULT_MEMORY_LIMIT, DEFAULT_TABLE_LIMIT,
+};
 pub use crate::jit_int::GdbJitImageRegistration;
-pub use crate::memory::{RuntimeLinearMemory, RuntimeMemoryCreator};
+pub use crate::memory::{
+    MmapMemory, PoolingMemoryCreator, RuntimeLinearMemory, RuntimeMemoryCreator,
+};
 pub use crate::mmap::Mmap;
+pub use crate::parking_spot::{park, unpark, WaitResult};
+pub use crate::pooling::{InstancePool, PoolingLimits};
 pub use crate::sig_registry::SignatureRegistry;
 pub use crate::table::{Table, TableElement};
 pub use crate::traphandlers::{
-    catch_traps, init_traps, raise_lib_trap, raise_user_trap, resume_panic, SignalHandler, Trap,
+    capture_backtrace, catch_traps, init_traps, init_traps_without_memory_faults, raise_lib_trap,
+    raise_user_trap, resume_panic, Deadline, DeadlineHandler, SignalHandler, Trap,
 };
 pub use crate::vmcontext::{
     VMCallerCheckedAnyfunc, VMContext, VMFunctionBody, VMFunctionImport, VMGlobalDefinition,
@@ -55,13 +55,21 @@
 //!       // Wasm!)
 //!   }
 //!   ```
+//!
+//! * Rust panics can't unwind through the wasm frames below a library call,
+//!   so anything that may run host code, such as a resource limiter, a custom
+//!   memory, or the destructor of an `externref` released by a GC, must be
+//!   wrapped in `catch_host_panics`, which resumes the panic on the other side
+//!   of the wasm frames.
 
 use crate::externref::VMExternRef;
 use crate::table::Table;
-use crate::traphandlers::raise_lib_trap;
+use crate::traphandlers::{deadline_reached, raise_lib_trap, resume_panic, Deadline, Trap};
 use crate::vmcontext::{VMCallerCheckedAnyfunc, VMContext};
 use std::mem;
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr::{self, NonNull};
+use wasmtime_environ::ir;
 use wasmtime_environ::wasm::{
     DataIndex, DefinedMemoryIndex, ElemIndex, GlobalIndex, MemoryIndex, TableElementType,
     TableIndex,
@@ -185,6 +193,15 @@ pub extern "C" fn wasmtime_f64_nearest(x: f64) -> f64 {
     }
 }
 
+/// Runs `f`, resuming any panic it raises on the other side of the wasm frames
+/// which called this library function.
+unsafe fn catch_host_panics<R>(f: impl FnOnce() -> R) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => resume_panic(payload),
+    }
+}
+
 /// Implementation of memory.grow for locally-defined 32-bit memories.
 pub unsafe extern "C" fn wasmtime_memory32_grow(
     vmctx: *mut VMContext,
@@ -194,9 +211,7 @@ pub unsafe extern "C" fn wasmtime_memory32_grow(
     let instance = (&mut *vmctx).instance();
     let memory_index = DefinedMemoryIndex::from_u32(memory_index);
 
-    instance
-        .memory_grow(memory_index, delta)
-        .unwrap_or(u32::max_value())
+    catch_host_panics(|| instance.memory_grow(memory_index, delta)).unwrap_or(u32::max_value())
 }
 
 /// Implementation of memory.grow for imported 32-bit memories.
@@ -208,8 +223,7 @@ pub unsafe extern "C" fn wasmtime_imported_memory32_grow(
     let instance = (&mut *vmctx).instance();
     let memory_index = MemoryIndex::from_u32(memory_index);
 
-    instance
-        .imported_memory_grow(memory_index, delta)
+    catch_host_panics(|| instance.imported_memory_grow(memory_index, delta))
         .unwrap_or(u32::max_value())
 }
 
@@ -246,8 +260,7 @@ pub unsafe extern "C" fn wasmtime_table_grow(
     match instance.table_element_type(table_index) {
         TableElementType::Func => {
             let func = init_value as *mut VMCallerCheckedAnyfunc;
-            instance
-                .table_grow(table_index, delta, func.into())
+            catch_host_panics(|| instance.table_grow(table_index, delta, func.into()))
                 .unwrap_or(-1_i32 as u32)
         }
         TableElementType::Val(ty) => {
@@ -259,9 +272,17 @@ pub unsafe extern "C" fn wasmtime_table_grow(
                 Some(VMExternRef::clone_from_raw(init_value))
             };
 
-            instance
-                .table_grow(table_index, delta, init_value.into())
-                .unwrap_or(-1_i32 as u32)
+            // Growing an `externref` table is a good time to release the
+            // references that are no longer reachable, so that the memory
+            // they're holding on to doesn't keep accumulating alongside the
+            // table.
+            let activations_table = &**instance.externref_activations_table();
+            let registry = &**instance.stack_map_registry();
+            catch_host_panics(|| {
+                crate::externref::gc(registry, activations_table);
+                instance.table_grow(table_index, delta, init_value.into())
+            })
+            .unwrap_or(-1_i32 as u32)
         }
     }
 }
@@ -276,7 +297,7 @@ pub unsafe extern "C" fn wasmtime_table_fill(
     val: *mut u8,
     len: u32,
 ) {
-    let result = {
+    let result = catch_host_panics(|| {
         let instance = (&mut *vmctx).instance();
         let table_index = TableIndex::from_u32(table_index);
         let table = instance.get_table(table_index);
@@ -295,7 +316,7 @@ pub unsafe extern "C" fn wasmtime_table_fill(
                 table.fill(dst, val.into(), len)
             }
         }
-    };
+    });
     if let Err(trap) = result {
         raise_lib_trap(trap);
     }
@@ -310,14 +331,14 @@ pub unsafe extern "C" fn wasmtime_table_copy(
     src: u32,
     len: u32,
 ) {
-    let result = {
+    let result = catch_host_panics(|| {
         let dst_table_index = TableIndex::from_u32(dst_table_index);
         let src_table_index = TableIndex::from_u32(src_table_index);
         let instance = (&mut *vmctx).instance();
         let dst_table = instance.get_table(dst_table_index);
         let src_table = instance.get_table(src_table_index);
         Table::copy(dst_table, src_table, dst, src, len)
-    };
+    });
     if let Err(trap) = result {
         raise_lib_trap(trap);
     }
@@ -437,7 +458,7 @@ pub unsafe extern "C" fn wasmtime_data_drop(vmctx: *mut VMContext, data_index: u
 pub unsafe extern "C" fn wasmtime_drop_externref(externref: *mut u8) {
     let externref = externref as *mut crate::externref::VMExternData;
     let externref = NonNull::new(externref).unwrap();
-    crate::externref::VMExternData::drop_and_dealloc(externref);
+    catch_host_panics(|| crate::externref::VMExternData::drop_and_dealloc(externref));
 }
 
 /// Do a GC and insert the given `externref` into the
@@ -450,7 +471,7 @@ pub unsafe extern "C" fn wasmtime_activations_table_insert_with_gc(
     let instance = (&mut *vmctx).instance();
     let activations_table = &**instance.externref_activations_table();
     let registry = &**instance.stack_map_registry();
-    activations_table.insert_with_gc(externref, registry);
+    catch_host_panics(|| activations_table.insert_with_gc(externref, registry));
 }
 
 /// Perform a Wasm `global.get` for `externref` globals.
@@ -467,7 +488,7 @@ pub unsafe extern "C" fn wasmtime_externref_global_get(
             let raw = externref.as_raw();
             let activations_table = &**instance.externref_activations_table();
             let registry = &**instance.stack_map_registry();
-            activations_table.insert_with_gc(externref, registry);
+            catch_host_panics(|| activations_table.insert_with_gc(externref, registry));
             raw
         }
     }
@@ -494,5 +515,79 @@ pub unsafe extern "C" fn wasmtime_externref_global_set(
     // that calls back into Wasm and touches this global again (we want to avoid
     // it observing a halfway-deinitialized value).
     let old = mem::replace((*global).as_externref_mut(), externref);
-    drop(old);
+    catch_host_panics(|| drop(old));
+}
+
+/// Implementation of `memory.atomic.notify`.
+pub unsafe extern "C" fn wasmtime_memory_atomic_notify(
+    vmctx: *mut VMContext,
+    memory_index: u32,
+    addr: u64,
+    count: u32,
+) -> u32 {
+    let result = {
+        let memory_index = MemoryIndex::from_u32(memory_index);
+        let instance = (&mut *vmctx).instance();
+        instance.memory_atomic_notify(memory_index, addr, count)
+    };
+    match result {
+        Ok(woken) => woken,
+        Err(trap) => raise_lib_trap(trap),
+    }
+}
+
+/// Implementation of `memory.atomic.wait32`.
+pub unsafe extern "C" fn wasmtime_memory_atomic_wait32(
+    vmctx: *mut VMContext,
+    memory_index: u32,
+    addr: u64,
+    expected: u32,
+    timeout: i64,
+) -> u32 {
+    let result = {
+        let memory_index = MemoryIndex::from_u32(memory_index);
+        let instance = (&mut *vmctx).instance();
+        instance.memory_atomic_wait32(memory_index, addr, expected, timeout)
+    };
+    match result {
+        Ok(result) => result,
+        Err(trap) => raise_lib_trap(trap),
+    }
+}
+
+/// Implementation of `memory.atomic.wait64`.
+pub unsafe extern "C" fn wasmtime_memory_atomic_wait64(
+    vmctx: *mut VMContext,
+    memory_index: u32,
+    addr: u64,
+    expected: u64,
+    timeout: i64,
+) -> u32 {
+    let result = {
+        let memory_index = MemoryIndex::from_u32(memory_index);
+        let instance = (&mut *vmctx).instance();
+        instance.memory_atomic_wait64(memory_index, addr, expected, timeout)
+    };
+    match result {
+        Ok(result) => result,
+        Err(trap) => raise_lib_trap(trap),
+    }
+}
+
+/// Implementation for running out of fuel, called when compiled code has
+/// consumed all of the fuel given to its store. Returns if the deadline handler
+/// added more fuel.
+pub unsafe extern "C" fn wasmtime_out_of_gas(_vmctx: *mut VMContext) {
+    if !deadline_reached(Deadline::Fuel) {
+        raise_lib_trap(Trap::out_of_fuel())
+    }
+}
+
+/// Implementation for reaching the epoch deadline, called when compiled code
+/// observes that the engine's epoch has reached its store's deadline. Returns
+/// if the deadline handler moved the deadline.
+pub unsafe extern "C" fn wasmtime_new_epoch(_vmctx: *mut VMContext) {
+    if !deadline_reached(Deadline::Epoch) {
+        raise_lib_trap(Trap::wasm(ir::TrapCode::Interrupt))
+    }
 }
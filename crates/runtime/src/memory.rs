@@ -3,10 +3,13 @@
 //! `RuntimeLinearMemory` is to WebAssembly linear memories what `Table` is to WebAssembly tables.
 
 use crate::mmap::Mmap;
+use crate::pooling::FreeList;
 use crate::vmcontext::VMMemoryDefinition;
 use more_asserts::{assert_ge, assert_le};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::convert::TryFrom;
+use std::mem;
+use std::sync::{Arc, Mutex};
 use wasmtime_environ::{MemoryPlan, MemoryStyle, WASM_MAX_PAGES, WASM_PAGE_SIZE};
 
 /// A memory allocator
@@ -25,6 +28,180 @@ impl RuntimeMemoryCreator for DefaultMemoryCreator {
     }
 }
 
+/// A memory allocator handing out linear memories from a pool of reserved
+/// slots, which are kept and reused for new memories once the memories using
+/// them are dropped.
+///
+/// Reserving and releasing the address space of a linear memory, which for
+/// static memories spans several gigabytes, is a large part of the cost of
+/// instantiating a module. With a pool this is only done the first time each
+/// slot is used. The slot freed most recently is reused first, since it's the
+/// most likely to still be in the TLB and caches, unless there's a slot last
+/// used by an instance of the same module.
+pub struct PoolingMemoryCreator {
+    pool: Arc<MemoryPool>,
+}
+
+struct MemoryPool {
+    slots: Mutex<PoolSlots>,
+    max_slots: u32,
+    max_pages: u32,
+    offset_guard_size: usize,
+}
+
+struct PoolSlots {
+    /// Reserved slots which aren't in use, all of which are inaccessible.
+    free: FreeList<Mmap>,
+    /// The number of slots reserved so far.
+    reserved: u32,
+}
+
+impl PoolingMemoryCreator {
+    /// Creates a pool of at most `max_memories` linear memories, each of which
+    /// can grow up to `max_pages` wasm pages and is followed by a guard region
+    /// of `offset_guard_size` bytes.
+    pub fn new(max_memories: u32, max_pages: u32, offset_guard_size: usize) -> Self {
+        assert_le!(max_pages, WASM_MAX_PAGES);
+        Self {
+            pool: Arc::new(MemoryPool {
+                slots: Mutex::new(PoolSlots {
+                    free: FreeList::new(),
+                    reserved: 0,
+                }),
+                max_slots: max_memories,
+                max_pages,
+                offset_guard_size,
+            }),
+        }
+    }
+
+    /// Creates a memory like `new_memory`, preferring a slot last used by an
+    /// instance with the same `affinity` key.
+    pub(crate) fn new_memory_with_affinity(
+        &self,
+        plan: &MemoryPlan,
+        affinity: Option<usize>,
+    ) -> Result<Box<dyn RuntimeLinearMemory>, String> {
+        let pool = &self.pool;
+        let reserved_pages = match plan.style {
+            MemoryStyle::Dynamic => plan.memory.minimum,
+            MemoryStyle::Static { bound } => bound,
+        };
+        if reserved_pages > pool.max_pages
+            || plan.offset_guard_size as usize > pool.offset_guard_size
+        {
+            return Err(format!(
+                "memory doesn't fit in a pooled memory slot of {} pages",
+                pool.max_pages
+            ));
+        }
+
+        let memory = PooledMemory {
+            alloc: RefCell::new(pool.take_slot(affinity)?),
+            size: Cell::new(0),
+            maximum: plan
+                .memory
+                .maximum
+                .map_or(pool.max_pages, |maximum| maximum.min(pool.max_pages)),
+            pool: pool.clone(),
+            affinity,
+        };
+        memory
+            .grow(plan.memory.minimum)
+            .ok_or("failed to make the minimum size of a pooled memory accessible")?;
+        Ok(Box::new(memory))
+    }
+}
+
+impl RuntimeMemoryCreator for PoolingMemoryCreator {
+    fn new_memory(&self, plan: &MemoryPlan) -> Result<Box<dyn RuntimeLinearMemory>, String> {
+        self.new_memory_with_affinity(plan, None)
+    }
+}
+
+impl MemoryPool {
+    fn take_slot(&self, affinity: Option<usize>) -> Result<Mmap, String> {
+        let mut slots = self.slots.lock().unwrap();
+        if let Some(slot) = slots.free.take(affinity, |_| true) {
+            return Ok(slot);
+        }
+        if slots.reserved == self.max_slots {
+            return Err(format!(
+                "all {} pooled memory slots are in use",
+                self.max_slots
+            ));
+        }
+
+        // `Mmap::make_accessible` requires some memory past the accessible
+        // range, so reserve at least one page after the largest memory.
+        let guard_size = self.offset_guard_size.max(region::page::size());
+        let max_bytes = self.max_pages as usize * WASM_PAGE_SIZE as usize;
+        let slot = Mmap::accessible_reserved(0, max_bytes + guard_size)?;
+        slots.reserved += 1;
+        Ok(slot)
+    }
+
+    fn return_slot(&self, mut slot: Mmap, accessible_bytes: usize, affinity: Option<usize>) {
+        // A slot which can't be reset can't be handed out again, so drop it
+        // and allow a fresh one to be reserved in its place.
+        let reset = slot.decommit(0, accessible_bytes);
+        let mut slots = self.slots.lock().unwrap();
+        match reset {
+            Ok(()) => slots.free.push(slot, affinity),
+            Err(_) => slots.reserved -= 1,
+        }
+    }
+}
+
+/// A linear memory living in a slot of a `PoolingMemoryCreator`. Since the
+/// slot is reserved for the largest memory the pool allows, it's never
+/// relocated.
+struct PooledMemory {
+    alloc: RefCell<Mmap>,
+    size: Cell<u32>,
+    maximum: u32,
+    pool: Arc<MemoryPool>,
+    affinity: Option<usize>,
+}
+
+impl RuntimeLinearMemory for PooledMemory {
+    fn size(&self) -> u32 {
+        self.size.get()
+    }
+
+    fn grow(&self, delta: u32) -> Option<u32> {
+        let prev_pages = self.size.get();
+        let new_pages = prev_pages
+            .checked_add(delta)
+            .filter(|new_pages| *new_pages <= self.maximum && *new_pages < WASM_MAX_PAGES)?;
+        if delta > 0 {
+            let prev_bytes = prev_pages as usize * WASM_PAGE_SIZE as usize;
+            let delta_bytes = delta as usize * WASM_PAGE_SIZE as usize;
+            self.alloc
+                .borrow_mut()
+                .make_accessible(prev_bytes, delta_bytes)
+                .ok()?;
+        }
+        self.size.set(new_pages);
+        Some(prev_pages)
+    }
+
+    fn vmmemory(&self) -> VMMemoryDefinition {
+        VMMemoryDefinition {
+            base: self.alloc.borrow_mut().as_mut_ptr(),
+            current_length: self.size.get() as usize * WASM_PAGE_SIZE as usize,
+        }
+    }
+}
+
+impl Drop for PooledMemory {
+    fn drop(&mut self) {
+        let slot = mem::replace(self.alloc.get_mut(), Mmap::new());
+        let accessible_bytes = self.size.get() as usize * WASM_PAGE_SIZE as usize;
+        self.pool.return_slot(slot, accessible_bytes, self.affinity);
+    }
+}
+
 /// A linear memory
 pub trait RuntimeLinearMemory {
     /// Returns the number of allocated wasm pages.
@@ -108,6 +285,35 @@ impl MmapMemory {
             needs_signal_handlers,
         })
     }
+
+    /// Create a new linear memory instance like `new`, whose initial contents
+    /// are the contents of `file`.
+    ///
+    /// The file is mapped copy-on-write, so its pages are only read in as
+    /// they're accessed and are shared with other mappings of the same file
+    /// until they're written to. Memory past the end of the file is zeroed.
+    ///
+    /// This is `unsafe` since the contents of the memory are undefined if the
+    /// file is modified or truncated while the memory is alive.
+    #[cfg(unix)]
+    pub unsafe fn new_with_file(plan: &MemoryPlan, file: &std::fs::File) -> Result<Self, String> {
+        let len = file.metadata().map_err(|e| e.to_string())?.len();
+        let minimum_bytes = u64::from(plan.memory.minimum) * u64::from(WASM_PAGE_SIZE);
+        if len > minimum_bytes {
+            return Err(format!(
+                "file of {} bytes doesn't fit in the minimum memory size of {} bytes",
+                len, minimum_bytes
+            ));
+        }
+
+        let memory = Self::new(plan)?;
+        memory
+            .mmap
+            .borrow_mut()
+            .alloc
+            .map_file(0, file, len as usize)?;
+        Ok(memory)
+    }
 }
 
 impl RuntimeLinearMemory for MmapMemory {
@@ -218,6 +218,108 @@ impl Mmap {
         Ok(())
     }
 
+    /// Make the memory starting at `start` and extending for `len` bytes
+    /// inaccessible again, discarding its contents so that it reads as zeroes
+    /// once it's made accessible again. `start` and `len` must be native
+    /// page-size multiples and describe a range within `self`'s reserved
+    /// memory.
+    #[cfg(not(target_os = "windows"))]
+    pub fn decommit(&mut self, start: usize, len: usize) -> Result<(), String> {
+        let page_size = region::page::size();
+        assert_eq!(start & (page_size - 1), 0);
+        assert_eq!(len & (page_size - 1), 0);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
+
+        if len == 0 {
+            return Ok(());
+        }
+        // Mapping fresh pages over the range both drops its old pages and
+        // makes it inaccessible.
+        let ptr = unsafe {
+            libc::mmap(
+                (self.ptr + start) as *mut libc::c_void,
+                len,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANON | libc::MAP_FIXED,
+                -1,
+                0,
+            )
+        };
+        if ptr as isize == -1_isize {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(())
+    }
+
+    /// Make the memory starting at `start` and extending for `len` bytes
+    /// inaccessible again, discarding its contents so that it reads as zeroes
+    /// once it's made accessible again. `start` and `len` must be native
+    /// page-size multiples and describe a range within `self`'s reserved
+    /// memory.
+    #[cfg(target_os = "windows")]
+    pub fn decommit(&mut self, start: usize, len: usize) -> Result<(), String> {
+        use winapi::ctypes::c_void;
+        use winapi::um::memoryapi::VirtualFree;
+        use winapi::um::winnt::MEM_DECOMMIT;
+        let page_size = region::page::size();
+        assert_eq!(start & (page_size - 1), 0);
+        assert_eq!(len & (page_size - 1), 0);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
+
+        if len == 0 {
+            return Ok(());
+        }
+        let ptr = self.ptr as *const u8;
+        if unsafe { VirtualFree(ptr.add(start) as *mut c_void, len, MEM_DECOMMIT) } == 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(())
+    }
+
+    /// Map the first `len` bytes of `file` copy-on-write over the memory
+    /// starting at `start`, which must be a native page-size multiple. The
+    /// mapping is rounded up to a whole number of native pages and the range
+    /// must already be accessible.
+    ///
+    /// Pages of the file are read in lazily as they're touched and writes only
+    /// ever affect this mapping, never the file itself.
+    ///
+    /// This is `unsafe` since the contents of the mapping are undefined if the
+    /// file is modified or truncated while it's mapped.
+    #[cfg(unix)]
+    pub unsafe fn map_file(
+        &mut self,
+        start: usize,
+        file: &std::fs::File,
+        len: usize,
+    ) -> Result<(), String> {
+        use std::os::unix::io::AsRawFd;
+
+        let page_size = region::page::size();
+        let len = round_up_to_page_size(len, page_size);
+        assert_eq!(start & (page_size - 1), 0);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
+
+        if len == 0 {
+            return Ok(());
+        }
+        let ptr = libc::mmap(
+            (self.ptr + start) as *mut libc::c_void,
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_FIXED,
+            file.as_raw_fd(),
+            0,
+        );
+        if ptr as isize == -1_isize {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(())
+    }
+
     /// Return the allocated memory as a slice of u8.
     pub fn as_slice(&self) -> &[u8] {
         unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
@@ -286,4 +388,17 @@ mod tests {
         assert_eq!(round_up_to_page_size(4096, 4096), 4096);
         assert_eq!(round_up_to_page_size(4097, 4096), 8192);
     }
+
+    #[test]
+    fn decommit_zeroes_memory() {
+        let page_size = region::page::size();
+        let mut mmap = Mmap::accessible_reserved(2 * page_size, 3 * page_size).unwrap();
+        mmap.as_mut_slice()[0] = 1;
+        mmap.as_mut_slice()[page_size] = 2;
+
+        mmap.decommit(0, page_size).unwrap();
+        mmap.make_accessible(0, page_size).unwrap();
+        assert_eq!(mmap.as_slice()[0], 0);
+        assert_eq!(mmap.as_slice()[page_size], 2);
+    }
 }
@@ -0,0 +1,197 @@
+//! Implementation of the wait/notify primitives of the threads proposal.
+//!
+//! Threads waiting on an address of a shared linear memory are parked in a
+//! process-wide table keyed by the host address being waited on. Since shared
+//! memories are never relocated, the host address uniquely identifies a
+//! location in a particular memory for as long as any thread can wait on it.
+
+use lazy_static::lazy_static;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The result of waiting on an address, matching the values returned by the
+/// `memory.atomic.wait32` and `memory.atomic.wait64` instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitResult {
+    /// The thread was woken up by a call to notify. This corresponds to the
+    /// value `0`.
+    Ok = 0,
+    /// The value stored at the address did not match the expected value, so
+    /// the thread didn't wait at all. This corresponds to the value `1`.
+    Mismatch = 1,
+    /// The timeout expired before the thread was woken up. This corresponds
+    /// to the value `2`.
+    TimedOut = 2,
+}
+
+#[derive(Default)]
+struct Spot {
+    /// The ticket handed to the next thread parking on this address.
+    next_ticket: u64,
+    /// Tickets of the threads parked on this address, oldest first.
+    queued: VecDeque<u64>,
+    /// Tickets of the parked threads which have been notified but haven't
+    /// woken up yet.
+    notified: HashSet<u64>,
+}
+
+lazy_static! {
+    static ref SPOTS: Mutex<HashMap<usize, Spot>> = Mutex::new(HashMap::new());
+    static ref WAKEUP: Condvar = Condvar::new();
+}
+
+/// Parks the current thread on `addr` until it is notified through `unpark`,
+/// or until `timeout` expires.
+///
+/// The `validate` callback is invoked while the table of parked threads is
+/// locked, so no notification can be missed between it returning `true` and
+/// this thread being parked. If it returns `false` the thread doesn't park and
+/// `WaitResult::Mismatch` is returned.
+pub fn park(addr: usize, validate: impl FnOnce() -> bool, timeout: Option<Duration>) -> WaitResult {
+    let mut spots = SPOTS.lock().unwrap();
+    if !validate() {
+        return WaitResult::Mismatch;
+    }
+    // Notifications only wake up the threads which were already parked when
+    // they happened, so a thread parking afterwards can't take the place of
+    // one of them.
+    let ticket = {
+        let spot = spots.entry(addr).or_default();
+        let ticket = spot.next_ticket;
+        spot.next_ticket += 1;
+        spot.queued.push_back(ticket);
+        ticket
+    };
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let spot = spots.get_mut(&addr).unwrap();
+        if spot.notified.remove(&ticket) {
+            remove_if_empty(&mut spots, addr);
+            return WaitResult::Ok;
+        }
+
+        spots = match deadline {
+            None => WAKEUP.wait(spots).unwrap(),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    spot.queued.retain(|t| *t != ticket);
+                    remove_if_empty(&mut spots, addr);
+                    return WaitResult::TimedOut;
+                }
+                WAKEUP.wait_timeout(spots, deadline - now).unwrap().0
+            }
+        };
+    }
+}
+
+/// Wakes up at most `count` threads parked on `addr`, oldest first, returning
+/// the number of threads which were woken up.
+pub fn unpark(addr: usize, count: u32) -> u32 {
+    let mut spots = SPOTS.lock().unwrap();
+    let spot = match spots.get_mut(&addr) {
+        Some(spot) => spot,
+        None => return 0,
+    };
+    let mut woken = 0;
+    while woken < count {
+        match spot.queued.pop_front() {
+            Some(ticket) => {
+                spot.notified.insert(ticket);
+                woken += 1;
+            }
+            None => break,
+        }
+    }
+    if woken > 0 {
+        WAKEUP.notify_all();
+    }
+    woken
+}
+
+fn remove_if_empty(spots: &mut HashMap<usize, Spot>, addr: usize) {
+    let spot = &spots[&addr];
+    if spot.queued.is_empty() && spot.notified.is_empty() {
+        spots.remove(&addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn mismatch_and_timeout() {
+        assert_eq!(park(1, || false, None), WaitResult::Mismatch);
+        assert_eq!(
+            park(1, || true, Some(Duration::from_millis(1))),
+            WaitResult::TimedOut
+        );
+        assert_eq!(unpark(1, 1), 0);
+    }
+
+    #[test]
+    fn wake_waiters() {
+        let parked = Arc::new(AtomicU32::new(0));
+        let threads = (0..4)
+            .map(|_| {
+                let parked = parked.clone();
+                thread::spawn(move || {
+                    park(
+                        2,
+                        || {
+                            parked.fetch_add(1, SeqCst);
+                            true
+                        },
+                        None,
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        while parked.load(SeqCst) < 4 {
+            thread::yield_now();
+        }
+
+        let mut woken = 0;
+        while woken < 4 {
+            woken += unpark(2, 3);
+        }
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), WaitResult::Ok);
+        }
+        assert_eq!(unpark(2, 1), 0);
+    }
+
+    #[test]
+    fn later_waiters_dont_take_earlier_wakeups() {
+        let parked = Arc::new(AtomicU32::new(0));
+        let thread = {
+            let parked = parked.clone();
+            thread::spawn(move || {
+                park(
+                    3,
+                    || {
+                        parked.fetch_add(1, SeqCst);
+                        true
+                    },
+                    None,
+                )
+            })
+        };
+        while parked.load(SeqCst) < 1 {
+            thread::yield_now();
+        }
+
+        assert_eq!(unpark(3, 1), 1);
+        assert_eq!(
+            park(3, || true, Some(Duration::from_millis(10))),
+            WaitResult::TimedOut
+        );
+        assert_eq!(thread.join().unwrap(), WaitResult::Ok);
+    }
+}
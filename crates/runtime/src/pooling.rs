@@ -0,0 +1,229 @@
+//! Pooled allocation of instances along with their tables and linear
+//! memories.
+//!
+//! An `InstancePool` hands out the storage of instances, tables and linear
+//! memories from a bounded number of slots, which are kept when they're freed
+//! rather than returned to the system. Freed slots remember the module whose
+//! instance used them last, and are preferably handed out again to instances
+//! of that same module. Those instances have the same layout and touch the
+//! same parts of their slots, so that's where reuse is cheapest.
+
+use crate::instance::InstantiationError;
+use crate::memory::PoolingMemoryCreator;
+use crate::mmap::Mmap;
+use crate::table::TablePool;
+use std::alloc::Layout;
+use std::sync::{Arc, Mutex};
+use wasmtime_environ::Module;
+
+/// The limits of an `InstancePool`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolingLimits {
+    /// The maximum number of instances alive at any one time.
+    pub max_instances: u32,
+    /// The maximum size in bytes of an instance, including its `VMContext`.
+    pub max_instance_size: usize,
+    /// The maximum number of tables alive at any one time.
+    pub max_tables: u32,
+    /// The maximum number of elements of a table.
+    pub max_table_elements: u32,
+    /// The maximum number of linear memories alive at any one time.
+    pub max_memories: u32,
+    /// The maximum number of wasm pages of a linear memory.
+    pub max_memory_pages: u32,
+    /// The size in bytes of the guard region following each linear memory.
+    pub memory_offset_guard_size: usize,
+}
+
+/// An allocator handing out instances, tables and linear memories from pools
+/// of slots which are reused once what's using them is dropped.
+///
+/// All instance slots are reserved in one go by the first instantiation, while
+/// table buffers and memory slots are only reserved the first time they're
+/// needed. Once all slots of a kind are in use, instantiations needing more of
+/// them fail.
+pub struct InstancePool {
+    instances: Arc<InstanceSlots>,
+    tables: Arc<TablePool>,
+    memories: PoolingMemoryCreator,
+}
+
+impl InstancePool {
+    /// Creates a pool with the given `limits`.
+    pub fn new(limits: &PoolingLimits) -> Self {
+        let page_size = region::page::size();
+        Self {
+            instances: Arc::new(InstanceSlots {
+                state: Mutex::new(InstanceSlotsState {
+                    region: None,
+                    free: FreeList::new(),
+                    used: 0,
+                }),
+                max_instances: limits.max_instances,
+                slot_size: (limits.max_instance_size + page_size - 1) & !(page_size - 1),
+            }),
+            tables: Arc::new(TablePool::new(limits.max_tables, limits.max_table_elements)),
+            memories: PoolingMemoryCreator::new(
+                limits.max_memories,
+                limits.max_memory_pages,
+                limits.memory_offset_guard_size,
+            ),
+        }
+    }
+
+    /// Returns the pool of linear memories, which memories created outside of
+    /// an instantiation can be allocated from as well.
+    pub fn memory_creator(&self) -> &PoolingMemoryCreator {
+        &self.memories
+    }
+
+    pub(crate) fn tables(&self) -> &Arc<TablePool> {
+        &self.tables
+    }
+
+    /// Takes a slot for an instance with the given `layout`.
+    pub(crate) fn take_instance_slot(
+        &self,
+        layout: Layout,
+        affinity: usize,
+    ) -> Result<InstanceSlot, InstantiationError> {
+        let slots = &self.instances;
+        if layout.size() > slots.slot_size || layout.align() > region::page::size() {
+            return Err(InstantiationError::Resource(format!(
+                "instance of {} bytes doesn't fit in a pooled instance slot of {} bytes",
+                layout.size(),
+                slots.slot_size
+            )));
+        }
+
+        let mut state = slots.state.lock().unwrap();
+        if state.region.is_none() {
+            let region = match (slots.max_instances as usize).checked_mul(slots.slot_size) {
+                Some(size) => Mmap::accessible_reserved(size, size),
+                None => Err("instance pool is too large to be reserved".to_string()),
+            };
+            state.region = Some(region.map_err(InstantiationError::Resource)?);
+        }
+        let index = match state.free.take(Some(affinity), |_| true) {
+            Some(index) => index,
+            None if state.used < slots.max_instances => {
+                state.used += 1;
+                state.used - 1
+            }
+            None => {
+                return Err(InstantiationError::Resource(format!(
+                    "all {} pooled instance slots are in use",
+                    slots.max_instances
+                )))
+            }
+        };
+        let base = state.region.as_mut().unwrap().as_mut_ptr();
+        Ok(InstanceSlot {
+            ptr: unsafe { base.add(index as usize * slots.slot_size) },
+            index,
+            affinity,
+            slots: slots.clone(),
+        })
+    }
+}
+
+struct InstanceSlots {
+    state: Mutex<InstanceSlotsState>,
+    max_instances: u32,
+    slot_size: usize,
+}
+
+struct InstanceSlotsState {
+    /// The reservation of all slots, made when the first one is taken.
+    region: Option<Mmap>,
+    /// Slots which were used before and aren't in use anymore.
+    free: FreeList<u32>,
+    /// The number of slots used at least once. These are the first slots of
+    /// `region`, the ones after them have never been handed out.
+    used: u32,
+}
+
+/// The storage of an instance allocated from an `InstancePool`.
+pub(crate) struct InstanceSlot {
+    ptr: *mut u8,
+    index: u32,
+    affinity: usize,
+    slots: Arc<InstanceSlots>,
+}
+
+impl InstanceSlot {
+    pub(crate) fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    /// Returns this slot to its pool. Whatever was stored in it must have been
+    /// dropped already.
+    pub(crate) fn release(self) {
+        let mut state = self.slots.state.lock().unwrap();
+        state.free.push(self.index, Some(self.affinity));
+    }
+}
+
+/// Returns the affinity key of the slots used by instances of `module`.
+///
+/// A module freed while some slots are still tagged with it may have its
+/// address reused by another module, which then inherits those slots'
+/// affinity. That's harmless since affinity is only a preference.
+pub(crate) fn module_affinity(module: &Arc<Module>) -> usize {
+    Arc::as_ptr(module) as usize
+}
+
+/// The free slots of a pool, each tagged with the affinity key of the module
+/// whose instance used it last, if any.
+#[derive(Debug)]
+pub(crate) struct FreeList<T> {
+    free: Vec<(T, Option<usize>)>,
+}
+
+impl<T> FreeList<T> {
+    pub(crate) fn new() -> Self {
+        Self { free: Vec::new() }
+    }
+
+    /// Takes a free slot accepted by `filter`. The slot freed most recently
+    /// with the given `affinity` is preferred, followed by the slot freed most
+    /// recently regardless of its affinity.
+    pub(crate) fn take(
+        &mut self,
+        affinity: Option<usize>,
+        filter: impl Fn(&T) -> bool,
+    ) -> Option<T> {
+        let index = affinity
+            .and_then(|_| {
+                self.free
+                    .iter()
+                    .rposition(|(slot, a)| *a == affinity && filter(slot))
+            })
+            .or_else(|| self.free.iter().rposition(|(slot, _)| filter(slot)))?;
+        Some(self.free.remove(index).0)
+    }
+
+    pub(crate) fn push(&mut self, slot: T, affinity: Option<usize>) {
+        self.free.push((slot, affinity));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FreeList;
+
+    #[test]
+    fn free_list_prefers_affine_slots() {
+        let mut list = FreeList::new();
+        list.push(1, Some(10));
+        list.push(2, Some(20));
+        list.push(3, None);
+        list.push(4, Some(10));
+
+        assert_eq!(list.take(Some(20), |_| true), Some(2));
+        assert_eq!(list.take(Some(10), |_| true), Some(4));
+        assert_eq!(list.take(Some(10), |slot| *slot != 1), Some(3));
+        assert_eq!(list.take(Some(30), |_| true), Some(1));
+        assert_eq!(list.take(None, |_| true), None);
+    }
+}
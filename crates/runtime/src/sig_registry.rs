@@ -5,6 +5,7 @@ use crate::vmcontext::VMSharedSignatureIndex;
 use more_asserts::assert_lt;
 use std::collections::{hash_map, HashMap};
 use std::convert::TryFrom;
+use std::sync::Arc;
 use wasmtime_environ::{ir, wasm::WasmFuncType};
 
 /// WebAssembly requires that the caller and callee signatures in an indirect
@@ -16,7 +17,7 @@ pub struct SignatureRegistry {
     wasm2index: HashMap<WasmFuncType, VMSharedSignatureIndex>,
 
     // Maps the index to the original Wasm signature.
-    index2wasm: HashMap<VMSharedSignatureIndex, WasmFuncType>,
+    index2wasm: HashMap<VMSharedSignatureIndex, Arc<WasmFuncType>>,
 
     // Maps the index to the native signature.
     index2native: HashMap<VMSharedSignatureIndex, ir::Signature>,
@@ -43,7 +44,7 @@ impl SignatureRegistry {
                 );
                 let index = VMSharedSignatureIndex::new(u32::try_from(len).unwrap());
                 entry.insert(index);
-                self.index2wasm.insert(index, wasm);
+                self.index2wasm.insert(index, Arc::new(wasm));
                 self.index2native.insert(index, native);
                 index
             }
@@ -68,6 +69,12 @@ impl SignatureRegistry {
     /// Note that for this operation to be semantically correct the `idx` must
     /// have previously come from a call to `register` of this same object.
     pub fn lookup_wasm(&self, idx: VMSharedSignatureIndex) -> Option<WasmFuncType> {
+        self.index2wasm.get(&idx).map(|wasm| (**wasm).clone())
+    }
+
+    /// Same as `lookup_wasm`, but returns a shared reference to the signature
+    /// rather than a clone of it.
+    pub fn lookup_wasm_shared(&self, idx: VMSharedSignatureIndex) -> Option<Arc<WasmFuncType>> {
         self.index2wasm.get(&idx).cloned()
     }
 
@@ -2,11 +2,13 @@
 //!
 //! `Table` is to WebAssembly tables what `LinearMemory` is to WebAssembly linear memories.
 
+use crate::pooling::FreeList;
 use crate::vmcontext::{VMCallerCheckedAnyfunc, VMTableDefinition};
 use crate::{Trap, VMExternRef};
 use std::cell::RefCell;
 use std::convert::{TryFrom, TryInto};
-use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::{mem, ptr};
 use wasmtime_environ::wasm::TableElementType;
 use wasmtime_environ::{ir, TablePlan, TableStyle};
 
@@ -15,6 +17,9 @@ use wasmtime_environ::{ir, TablePlan, TableStyle};
 pub struct Table {
     elements: RefCell<TableElements>,
     maximum: Option<u32>,
+    /// The pool the elements buffer came from and goes back to when this
+    /// table is dropped, along with the affinity key it's returned with.
+    pool: Option<(Arc<TablePool>, usize)>,
 }
 
 /// An element going into or coming out of a table.
@@ -47,10 +52,47 @@ impl Table {
             TableStyle::CallerChecksSignature => Self {
                 elements,
                 maximum: plan.table.maximum,
+                pool: None,
             },
         }
     }
 
+    /// Create a new table instance like `new`, whose elements buffer is taken
+    /// from `pool`.
+    ///
+    /// The buffer already has room for as many elements as the pool allows,
+    /// so the table is limited to that many elements and is never relocated
+    /// when it grows.
+    pub(crate) fn new_pooled(
+        plan: &TablePlan,
+        pool: &Arc<TablePool>,
+        affinity: usize,
+    ) -> Result<Self, String> {
+        if plan.table.minimum > pool.max_elements {
+            return Err(format!(
+                "table of {} elements doesn't fit in a pooled table of {} elements",
+                plan.table.minimum, pool.max_elements
+            ));
+        }
+        let min = usize::try_from(plan.table.minimum).unwrap();
+        let mut elements = pool.take(plan.table.ty, affinity)?;
+        match &mut elements {
+            TableElements::FuncRefs(x) => x.resize(min, ptr::null_mut()),
+            TableElements::ExternRefs(x) => x.resize(min, None),
+        }
+        let maximum = plan
+            .table
+            .maximum
+            .map_or(pool.max_elements, |maximum| maximum.min(pool.max_elements));
+        match plan.style {
+            TableStyle::CallerChecksSignature => Ok(Self {
+                elements: RefCell::new(elements),
+                maximum: Some(maximum),
+                pool: Some((pool.clone(), affinity)),
+            }),
+        }
+    }
+
     /// Returns the type of the elements in this table.
     pub fn element_type(&self) -> TableElementType {
         match &*self.elements.borrow() {
@@ -221,6 +263,88 @@ impl Table {
     }
 }
 
+impl Drop for Table {
+    fn drop(&mut self) {
+        if let Some((pool, affinity)) = self.pool.take() {
+            let elements =
+                mem::replace(self.elements.get_mut(), TableElements::FuncRefs(Vec::new()));
+            pool.give_back(elements, affinity);
+        }
+    }
+}
+
+/// The elements buffers of the tables of an `InstancePool`.
+#[derive(Debug)]
+pub(crate) struct TablePool {
+    state: Mutex<TablePoolState>,
+    max_tables: u32,
+    max_elements: u32,
+}
+
+#[derive(Debug)]
+struct TablePoolState {
+    /// Empty buffers with room for `max_elements` elements, of either type.
+    free: FreeList<FreeBuffer>,
+    /// The number of tables using a buffer of this pool.
+    live: u32,
+}
+
+#[derive(Debug)]
+struct FreeBuffer(TableElements);
+
+// The buffers of the free list never hold any elements, so moving them to
+// another thread doesn't move any of the pointers or `VMExternRef`s which a
+// table holds.
+unsafe impl Send for FreeBuffer {}
+
+impl TablePool {
+    pub(crate) fn new(max_tables: u32, max_elements: u32) -> Self {
+        Self {
+            state: Mutex::new(TablePoolState {
+                free: FreeList::new(),
+                live: 0,
+            }),
+            max_tables,
+            max_elements,
+        }
+    }
+
+    fn take(&self, ty: TableElementType, affinity: usize) -> Result<TableElements, String> {
+        let mut state = self.state.lock().unwrap();
+        if state.live == self.max_tables {
+            return Err(format!("all {} pooled tables are in use", self.max_tables));
+        }
+        let is_func = |buffer: &FreeBuffer| matches!(buffer.0, TableElements::FuncRefs(_));
+        let want_func = matches!(ty, TableElementType::Func);
+        let elements = match state.free.take(Some(affinity), |b| is_func(b) == want_func) {
+            Some(FreeBuffer(elements)) => elements,
+            None => {
+                // Keep at most `max_tables` buffers around by discarding a
+                // free buffer of the other type, if there is one.
+                state.free.take(None, |_| true);
+                let max = usize::try_from(self.max_elements).unwrap();
+                if want_func {
+                    TableElements::FuncRefs(Vec::with_capacity(max))
+                } else {
+                    TableElements::ExternRefs(Vec::with_capacity(max))
+                }
+            }
+        };
+        state.live += 1;
+        Ok(elements)
+    }
+
+    fn give_back(&self, mut elements: TableElements, affinity: usize) {
+        match &mut elements {
+            TableElements::FuncRefs(x) => x.clear(),
+            TableElements::ExternRefs(x) => x.clear(),
+        }
+        let mut state = self.state.lock().unwrap();
+        state.free.push(FreeBuffer(elements), Some(affinity));
+        state.live -= 1;
+    }
+}
+
 impl TryFrom<TableElement> for *mut VMCallerCheckedAnyfunc {
     type Error = TableElement;
 
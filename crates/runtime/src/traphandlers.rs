@@ -5,8 +5,10 @@ use crate::VMContext;
 use backtrace::Backtrace;
 use std::any::Any;
 use std::cell::Cell;
+use std::cmp;
 use std::error::Error;
 use std::io;
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr;
 use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 use std::sync::Once;
@@ -33,36 +35,33 @@ cfg_if::cfg_if! {
         static mut PREV_SIGILL: MaybeUninit<libc::sigaction> = MaybeUninit::uninit();
         static mut PREV_SIGFPE: MaybeUninit<libc::sigaction> = MaybeUninit::uninit();
 
-        unsafe fn platform_init() {
-            let register = |slot: &mut MaybeUninit<libc::sigaction>, signal: i32| {
-                let mut handler: libc::sigaction = mem::zeroed();
-                // The flags here are relatively careful, and they are...
-                //
-                // SA_SIGINFO gives us access to information like the program
-                // counter from where the fault happened.
-                //
-                // SA_ONSTACK allows us to handle signals on an alternate stack,
-                // so that the handler can run in response to running out of
-                // stack space on the main stack. Rust installs an alternate
-                // stack with sigaltstack, so we rely on that.
-                //
-                // SA_NODEFER allows us to reenter the signal handler if we
-                // crash while handling the signal, and fall through to the
-                // Breakpad handler by testing handlingSegFault.
-                handler.sa_flags = libc::SA_SIGINFO | libc::SA_NODEFER | libc::SA_ONSTACK;
-                handler.sa_sigaction = trap_handler as usize;
-                libc::sigemptyset(&mut handler.sa_mask);
-                if libc::sigaction(signal, &handler, slot.as_mut_ptr()) != 0 {
-                    panic!(
-                        "unable to install signal handler: {}",
-                        io::Error::last_os_error(),
-                    );
-                }
-            };
-
-            // Allow handling OOB with signals on all architectures
-            register(&mut PREV_SIGSEGV, libc::SIGSEGV);
+        unsafe fn register(slot: &mut MaybeUninit<libc::sigaction>, signal: i32) {
+            let mut handler: libc::sigaction = mem::zeroed();
+            // The flags here are relatively careful, and they are...
+            //
+            // SA_SIGINFO gives us access to information like the program
+            // counter from where the fault happened.
+            //
+            // SA_ONSTACK allows us to handle signals on an alternate stack,
+            // so that the handler can run in response to running out of
+            // stack space on the main stack. Rust installs an alternate
+            // stack with sigaltstack, so we rely on that.
+            //
+            // SA_NODEFER allows us to reenter the signal handler if we
+            // crash while handling the signal, and fall through to the
+            // Breakpad handler by testing handlingSegFault.
+            handler.sa_flags = libc::SA_SIGINFO | libc::SA_NODEFER | libc::SA_ONSTACK;
+            handler.sa_sigaction = trap_handler as usize;
+            libc::sigemptyset(&mut handler.sa_mask);
+            if libc::sigaction(signal, &handler, slot.as_mut_ptr()) != 0 {
+                panic!(
+                    "unable to install signal handler: {}",
+                    io::Error::last_os_error(),
+                );
+            }
+        }
 
+        unsafe fn platform_init_trap_instructions() {
             // Handle `unreachable` instructions which execute `ud2` right now
             register(&mut PREV_SIGILL, libc::SIGILL);
 
@@ -70,6 +69,11 @@ cfg_if::cfg_if! {
             if cfg!(target_arch = "x86") || cfg!(target_arch = "x86_64") {
                 register(&mut PREV_SIGFPE, libc::SIGFPE);
             }
+        }
+
+        unsafe fn platform_init_memory_faults() {
+            // Allow handling OOB with signals on all architectures
+            register(&mut PREV_SIGSEGV, libc::SIGSEGV);
 
             // On ARM, handle Unaligned Accesses.
             // On Darwin, guard page accesses are raised as SIGBUS.
@@ -105,8 +109,13 @@ cfg_if::cfg_if! {
                 // Otherwise flag ourselves as handling a trap, do the trap
                 // handling, and reset our trap handling flag. Then we figure
                 // out what to do based on the result of the trap handling.
+                let faulting_addr = match signum {
+                    libc::SIGSEGV | libc::SIGBUS => Some(get_faulting_addr(siginfo)),
+                    _ => None,
+                };
                 let jmp_buf = info.handle_trap(
                     get_pc(context),
+                    faulting_addr,
                     |handler| handler(signum, siginfo, context),
                 );
 
@@ -153,6 +162,16 @@ cfg_if::cfg_if! {
             }
         }
 
+        unsafe fn get_faulting_addr(siginfo: *mut libc::siginfo_t) -> usize {
+            cfg_if::cfg_if! {
+                if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                    (*siginfo).si_addr() as usize
+                } else {
+                    (*siginfo).si_addr as usize
+                }
+            }
+        }
+
         unsafe fn get_pc(cx: *mut libc::c_void) -> *const u8 {
             cfg_if::cfg_if! {
                 if #[cfg(all(target_os = "linux", target_arch = "x86_64"))] {
@@ -184,7 +203,7 @@ cfg_if::cfg_if! {
         /// Function which may handle custom signals while processing traps.
         pub type SignalHandler<'a> = dyn Fn(winapi::um::winnt::PEXCEPTION_POINTERS) -> bool + 'a;
 
-        unsafe fn platform_init() {
+        unsafe fn platform_init_trap_instructions() {
             // our trap handler needs to go first, so that we can recover from
             // wasm faults and continue execution, so pass `1` as a true value
             // here.
@@ -193,6 +212,11 @@ cfg_if::cfg_if! {
             }
         }
 
+        // The vectored exception handler above defers all exceptions raised
+        // outside of wasm, so there's no need to keep it away from access
+        // violations.
+        unsafe fn platform_init_memory_faults() {}
+
         unsafe extern "system" fn exception_handler(
             exception_info: PEXCEPTION_POINTERS
         ) -> LONG {
@@ -236,7 +260,18 @@ cfg_if::cfg_if! {
                         compile_error!("unsupported platform");
                     }
                 }
-                let jmp_buf = info.handle_trap(ip, |handler| handler(exception_info));
+                // For access violations the second piece of information is
+                // the address which was accessed.
+                let faulting_addr = if record.ExceptionCode == EXCEPTION_ACCESS_VIOLATION {
+                    Some(record.ExceptionInformation[1])
+                } else {
+                    None
+                };
+                let jmp_buf = info.handle_trap(
+                    ip,
+                    faulting_addr,
+                    |handler| handler(exception_info),
+                );
                 if jmp_buf.is_null() {
                     EXCEPTION_CONTINUE_SEARCH
                 } else if jmp_buf as usize == 1 {
@@ -260,13 +295,19 @@ cfg_if::cfg_if! {
 /// times, having no effect after the first call.
 pub fn init_traps() {
     static INIT: Once = Once::new();
-    INIT.call_once(real_init);
+    init_traps_without_memory_faults();
+    INIT.call_once(|| unsafe { platform_init_memory_faults() });
 }
 
-fn real_init() {
-    unsafe {
-        platform_init();
-    }
+/// Same as `init_traps`, but only installs the handlers needed to catch traps
+/// raised by trap instructions, leaving the handling of memory faults (e.g.
+/// `SIGSEGV` and `SIGBUS`) to the embedder.
+///
+/// Only code which explicitly bounds checks all of its memory accesses may
+/// run if no other handlers were installed with `init_traps`.
+pub fn init_traps_without_memory_faults() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| unsafe { platform_init_trap_instructions() });
 }
 
 /// Raises a user-defined trap immediately.
@@ -310,6 +351,35 @@ pub unsafe fn resume_panic(payload: Box<dyn Any + Send>) -> ! {
     tls::with(|info| info.unwrap().unwind_with(UnwindReason::Panic(payload)))
 }
 
+/// A deadline which wasm execution can reach.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Deadline {
+    /// The engine's epoch reached the store's epoch deadline.
+    Epoch,
+    /// All of the fuel given to the store has been consumed.
+    Fuel,
+}
+
+/// Function deciding what happens when compiled code reaches a `Deadline`. It
+/// returns `true` if execution should resume, in which case it must have moved
+/// the deadline, and `false` if the deadline should trap.
+pub type DeadlineHandler<'a> = dyn Fn(Deadline) -> bool + 'a;
+
+/// Invokes the deadline handler of the innermost call to `catch_traps`.
+///
+/// A panic in the handler is resumed on the other side of the wasm frames, so
+/// this must only be called from libcalls.
+pub(crate) unsafe fn deadline_reached(deadline: Deadline) -> bool {
+    let result = tls::with(|info| {
+        let handler = info.unwrap().deadline_handler;
+        panic::catch_unwind(AssertUnwindSafe(|| handler(deadline)))
+    });
+    match result {
+        Ok(resume) => resume,
+        Err(payload) => resume_panic(payload),
+    }
+}
+
 /// Stores trace message with backtrace.
 #[derive(Debug)]
 pub enum Trap {
@@ -326,6 +396,9 @@ pub enum Trap {
         /// interrupt, used for switching what would otherwise be a stack
         /// overflow trap to be an interrupt trap.
         maybe_interrupted: bool,
+        /// The native address whose access caused this trap, if it was
+        /// caused by a memory fault.
+        faulting_addr: Option<usize>,
     },
 
     /// A trap raised from a wasm libcall
@@ -341,14 +414,21 @@ pub enum Trap {
         /// Native stack backtrace at the time the OOM occurred
         backtrace: Backtrace,
     },
+
+    /// A trap indicating that wasm code consumed all of the fuel it was given.
+    OutOfFuel {
+        /// Native stack backtrace at the time fuel ran out
+        backtrace: Backtrace,
+    },
 }
 
 impl Trap {
     /// Construct a new Wasm trap with the given source location and trap code.
     ///
-    /// Internally saves a backtrace when constructed.
+    /// Internally saves a backtrace when constructed, unless backtraces are
+    /// disabled for the current call into wasm.
     pub fn wasm(trap_code: ir::TrapCode) -> Self {
-        let backtrace = Backtrace::new_unresolved();
+        let backtrace = capture_backtrace();
         Trap::Wasm {
             trap_code,
             backtrace,
@@ -357,22 +437,47 @@ impl Trap {
 
     /// Construct a new OOM trap with the given source location and trap code.
     ///
-    /// Internally saves a backtrace when constructed.
+    /// Internally saves a backtrace when constructed, unless backtraces are
+    /// disabled for the current call into wasm.
     pub fn oom() -> Self {
-        let backtrace = Backtrace::new_unresolved();
+        let backtrace = capture_backtrace();
         Trap::OOM { backtrace }
     }
+
+    /// Construct a new trap indicating that wasm ran out of fuel.
+    ///
+    /// Internally saves a backtrace when constructed, unless backtraces are
+    /// disabled for the current call into wasm.
+    pub fn out_of_fuel() -> Self {
+        let backtrace = capture_backtrace();
+        Trap::OutOfFuel { backtrace }
+    }
+}
+
+/// Captures a backtrace of the native stack for a trap.
+///
+/// The backtrace is empty if the innermost active call to `catch_traps` was
+/// asked not to capture backtraces.
+pub fn capture_backtrace() -> Backtrace {
+    tls::with(|state| match state {
+        Some(state) => state.capture_backtrace(),
+        None => Backtrace::new_unresolved(),
+    })
 }
 
 /// Catches any wasm traps that happen within the execution of `closure`,
 /// returning them as a `Result`.
 ///
+/// Backtraces of the traps are only captured if `capture_backtrace` is set.
+///
 /// Highly unsafe since `closure` won't have any dtors run.
 pub unsafe fn catch_traps<F>(
     vmctx: *mut VMContext,
     max_wasm_stack: usize,
+    capture_backtrace: bool,
     is_wasm_code: impl Fn(usize) -> bool,
     signal_handler: Option<&SignalHandler>,
+    deadline_handler: &DeadlineHandler,
     mut closure: F,
 ) -> Result<(), Trap>
 where
@@ -382,7 +487,14 @@ where
     #[cfg(unix)]
     setup_unix_sigaltstack()?;
 
-    return CallThreadState::new(vmctx, &is_wasm_code, signal_handler).with(max_wasm_stack, |cx| {
+    let state = CallThreadState::new(
+        vmctx,
+        capture_backtrace,
+        &is_wasm_code,
+        signal_handler,
+        deadline_handler,
+    );
+    return state.with(max_wasm_stack, |cx| {
         RegisterSetjmp(
             cx.jmp_buf.as_ptr(),
             call_closure::<F>,
@@ -405,8 +517,10 @@ pub struct CallThreadState<'a> {
     jmp_buf: Cell<*const u8>,
     vmctx: *mut VMContext,
     handling_trap: Cell<bool>,
+    capture_backtrace: bool,
     is_wasm_code: &'a (dyn Fn(usize) -> bool + 'a),
     signal_handler: Option<&'a SignalHandler<'a>>,
+    deadline_handler: &'a DeadlineHandler<'a>,
 }
 
 enum UnwindReason {
@@ -414,22 +528,30 @@ enum UnwindReason {
     Panic(Box<dyn Any + Send>),
     UserTrap(Box<dyn Error + Send + Sync>),
     LibTrap(Trap),
-    JitTrap { backtrace: Backtrace, pc: usize },
+    JitTrap {
+        backtrace: Backtrace,
+        pc: usize,
+        faulting_addr: Option<usize>,
+    },
 }
 
 impl<'a> CallThreadState<'a> {
     fn new(
         vmctx: *mut VMContext,
+        capture_backtrace: bool,
         is_wasm_code: &'a (dyn Fn(usize) -> bool + 'a),
         signal_handler: Option<&'a SignalHandler<'a>>,
+        deadline_handler: &'a DeadlineHandler<'a>,
     ) -> CallThreadState<'a> {
         CallThreadState {
             unwind: Cell::new(UnwindReason::None),
             vmctx,
             jmp_buf: Cell::new(ptr::null()),
             handling_trap: Cell::new(false),
+            capture_backtrace,
             is_wasm_code,
             signal_handler,
+            deadline_handler,
         }
     }
 
@@ -450,7 +572,11 @@ impl<'a> CallThreadState<'a> {
                 Err(Trap::User(data))
             }
             UnwindReason::LibTrap(trap) => Err(trap),
-            UnwindReason::JitTrap { backtrace, pc } => {
+            UnwindReason::JitTrap {
+                backtrace,
+                pc,
+                faulting_addr,
+            } => {
                 debug_assert_eq!(ret, 0);
                 let maybe_interrupted = unsafe {
                     let interrupts = (*self.vmctx).instance().interrupts();
@@ -460,6 +586,7 @@ impl<'a> CallThreadState<'a> {
                     pc,
                     backtrace,
                     maybe_interrupted,
+                    faulting_addr,
                 })
             }
             UnwindReason::Panic(panic) => {
@@ -469,6 +596,14 @@ impl<'a> CallThreadState<'a> {
         }
     }
 
+    fn capture_backtrace(&self) -> Backtrace {
+        if self.capture_backtrace {
+            Backtrace::new_unresolved()
+        } else {
+            Backtrace::from(Vec::new())
+        }
+    }
+
     /// Checks and/or initializes the wasm native call stack limit.
     ///
     /// This function will inspect the current state of the stack and calling
@@ -478,7 +613,9 @@ impl<'a> CallThreadState<'a> {
     ///    set up a stack limit where beyond which if the native wasm stack
     ///    pointer goes beyond forces a trap. For now we simply reserve an
     ///    arbitrary chunk of bytes (1 MB from roughly the current native stack
-    ///    pointer). This logic will likely get tweaked over time.
+    ///    pointer), or however much of it fits in the thread's stack while
+    ///    leaving `HOST_STACK_RESERVE` bytes for the host. This logic will
+    ///    likely get tweaked over time.
     ///
     /// 2. We aren't the first wasm call on the stack. In this scenario the wasm
     ///    stack limit is already configured. This case of wasm -> host -> wasm
@@ -501,7 +638,15 @@ impl<'a> CallThreadState<'a> {
     fn update_stack_limit(&self, max_wasm_stack: usize) -> Result<impl Drop + '_, Trap> {
         // Make an "educated guess" to figure out where the wasm sp value should
         // start trapping if it drops below.
-        let wasm_stack_limit = self as *const _ as usize - max_wasm_stack;
+        let mut wasm_stack_limit = (self as *const _ as usize).saturating_sub(max_wasm_stack);
+
+        // The thread may not have `max_wasm_stack` bytes of stack left, in
+        // which case wasm would run into the end of the stack before hitting
+        // the limit. Move the limit up so that wasm always traps while there's
+        // still `HOST_STACK_RESERVE` bytes left for the host.
+        if let Some(start) = thread_stack_start() {
+            wasm_stack_limit = cmp::max(wasm_stack_limit, start + HOST_STACK_RESERVE);
+        }
 
         let interrupts = unsafe { &**(&*self.vmctx).instance().interrupts() };
         let reset_stack_limit = match interrupts.stack_limit.compare_exchange(
@@ -525,7 +670,7 @@ impl<'a> CallThreadState<'a> {
                 interrupts.stack_limit.store(usize::max_value(), SeqCst);
                 return Err(Trap::Wasm {
                     trap_code: ir::TrapCode::Interrupt,
-                    backtrace: Backtrace::new_unresolved(),
+                    backtrace: self.capture_backtrace(),
                 });
             }
             Err(_) => {
@@ -560,6 +705,8 @@ impl<'a> CallThreadState<'a> {
     /// Trap handler using our thread-local state.
     ///
     /// * `pc` - the program counter the trap happened at
+    /// * `faulting_addr` - the address being accessed, if the trap is a memory
+    ///   fault
     /// * `call_handler` - a closure used to invoke the platform-specific
     ///   signal handler for each instance, if available.
     ///
@@ -575,6 +722,7 @@ impl<'a> CallThreadState<'a> {
     fn handle_trap(
         &self,
         pc: *const u8,
+        faulting_addr: Option<usize>,
         call_handler: impl Fn(&SignalHandler) -> bool,
     ) -> *const u8 {
         // If we hit a fault while handling a previous trap, that's quite bad,
@@ -616,10 +764,11 @@ impl<'a> CallThreadState<'a> {
         if self.jmp_buf.get().is_null() {
             return ptr::null();
         }
-        let backtrace = Backtrace::new_unresolved();
+        let backtrace = self.capture_backtrace();
         self.unwind.replace(UnwindReason::JitTrap {
             backtrace,
             pc: pc as usize,
+            faulting_addr,
         });
         self.jmp_buf.get()
     }
@@ -680,6 +829,66 @@ mod tls {
     }
 }
 
+/// The number of bytes at the end of a thread's stack which wasm is never
+/// allowed to use, leaving room for host functions and libcalls called by
+/// wasm, as well as for the trap handler on platforms which run it on the
+/// faulting thread's stack.
+const HOST_STACK_RESERVE: usize = 128 * 1024;
+
+/// Returns the lowest address of the current thread's stack, if it's known on
+/// this platform.
+///
+/// This is only looked up once per thread since doing so can be expensive, for
+/// example for the main thread on Linux.
+fn thread_stack_start() -> Option<usize> {
+    thread_local! {
+        static START: Cell<Option<usize>> = Cell::new(None);
+    }
+
+    return START.with(|start| {
+        if start.get().is_none() {
+            start.set(unsafe { lookup() });
+        }
+        start.get()
+    });
+
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            unsafe fn lookup() -> Option<usize> {
+                let mut attr = std::mem::zeroed();
+                if libc::pthread_getattr_np(libc::pthread_self(), &mut attr) != 0 {
+                    return None;
+                }
+                let mut addr = ptr::null_mut();
+                let mut size = 0;
+                let r = libc::pthread_attr_getstack(&attr, &mut addr, &mut size);
+                libc::pthread_attr_destroy(&mut attr);
+                if r != 0 {
+                    return None;
+                }
+                Some(addr as usize)
+            }
+        } else if #[cfg(target_os = "macos")] {
+            unsafe fn lookup() -> Option<usize> {
+                let thread = libc::pthread_self();
+                let top = libc::pthread_get_stackaddr_np(thread) as usize;
+                Some(top - libc::pthread_get_stacksize_np(thread))
+            }
+        } else if #[cfg(target_os = "windows")] {
+            unsafe fn lookup() -> Option<usize> {
+                let mut low = 0;
+                let mut high = 0;
+                winapi::um::processthreadsapi::GetCurrentThreadStackLimits(&mut low, &mut high);
+                Some(low)
+            }
+        } else {
+            unsafe fn lookup() -> Option<usize> {
+                None
+            }
+        }
+    }
+}
+
 /// A module for registering a custom alternate signal stack (sigaltstack).
 ///
 /// Rust's libstd installs an alternate stack with size `SIGSTKSZ`, which is not
@@ -4,8 +4,9 @@
 use crate::externref::VMExternRef;
 use crate::instance::Instance;
 use std::any::Any;
-use std::ptr::NonNull;
-use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::cell::UnsafeCell;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::SeqCst};
 use std::u32;
 use wasmtime_environ::BuiltinFunctionIndex;
 
@@ -595,6 +596,14 @@ impl VMBuiltinFunctionsArray {
             wasmtime_table_fill as usize;
         ptrs[BuiltinFunctionIndex::table_fill_funcref().index() as usize] =
             wasmtime_table_fill as usize;
+        ptrs[BuiltinFunctionIndex::out_of_gas().index() as usize] = wasmtime_out_of_gas as usize;
+        ptrs[BuiltinFunctionIndex::new_epoch().index() as usize] = wasmtime_new_epoch as usize;
+        ptrs[BuiltinFunctionIndex::memory_atomic_notify().index() as usize] =
+            wasmtime_memory_atomic_notify as usize;
+        ptrs[BuiltinFunctionIndex::memory_atomic_wait32().index() as usize] =
+            wasmtime_memory_atomic_wait32 as usize;
+        ptrs[BuiltinFunctionIndex::memory_atomic_wait64().index() as usize] =
+            wasmtime_memory_atomic_wait64 as usize;
 
         if cfg!(debug_assertions) {
             for i in 0..ptrs.len() {
@@ -641,8 +650,8 @@ impl VMInvokeArgument {
     }
 }
 
-/// Structure used to control interrupting wasm code, currently with only one
-/// atomic flag internally used.
+/// Structure used to control interrupting wasm code and metering its
+/// execution.
 #[derive(Debug)]
 #[repr(C)]
 pub struct VMInterrupts {
@@ -651,8 +660,33 @@ pub struct VMInterrupts {
     /// This is used to control both stack overflow as well as interrupting wasm
     /// modules. For more information see `crates/environ/src/cranelift.rs`.
     pub stack_limit: AtomicUsize,
+
+    /// Indicator of how much fuel has been consumed and is remaining to
+    /// WebAssembly.
+    ///
+    /// This field is typically negative and increments towards positive. Upon
+    /// turning positive a wasm trap will be generated. This field is only
+    /// modified if wasm is configured to consume fuel.
+    pub fuel_consumed: UnsafeCell<i64>,
+
+    /// The epoch at which wasm will trap, if epoch interruption is enabled.
+    ///
+    /// Compiled code compares this against the value behind `epoch_ptr` at
+    /// function entries and loop headers.
+    pub epoch_deadline: UnsafeCell<u64>,
+
+    /// Pointer to the engine-wide epoch counter, which is incremented by the
+    /// embedder. This is null unless the store has set it up.
+    pub epoch_ptr: *const AtomicU64,
 }
 
+// The `VMInterrupts` type is a pod-type with no destructor, and we only access
+// `stack_limit` from other threads, so add in these trait impls which are
+// otherwise not available due to the `fuel_consumed`, `epoch_deadline` and
+// `epoch_ptr` fields in `VMInterrupts`.
+unsafe impl Send for VMInterrupts {}
+unsafe impl Sync for VMInterrupts {}
+
 impl VMInterrupts {
     /// Flag that an interrupt should occur
     pub fn interrupt(&self) {
@@ -665,6 +699,9 @@ impl Default for VMInterrupts {
     fn default() -> VMInterrupts {
         VMInterrupts {
             stack_limit: AtomicUsize::new(usize::max_value()),
+            fuel_consumed: UnsafeCell::new(0),
+            epoch_deadline: UnsafeCell::new(0),
+            epoch_ptr: ptr::null(),
         }
     }
 }
@@ -684,6 +721,18 @@ mod test_vminterrupts {
             offset_of!(VMInterrupts, stack_limit),
             usize::from(offsets.vminterrupts_stack_limit())
         );
+        assert_eq!(
+            offset_of!(VMInterrupts, fuel_consumed),
+            usize::from(offsets.vminterrupts_fuel_consumed())
+        );
+        assert_eq!(
+            offset_of!(VMInterrupts, epoch_deadline),
+            usize::from(offsets.vminterrupts_epoch_deadline())
+        );
+        assert_eq!(
+            offset_of!(VMInterrupts, epoch_ptr),
+            usize::from(offsets.vminterrupts_epoch_ptr())
+        );
     }
 }
 
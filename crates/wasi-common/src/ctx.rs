@@ -3,6 +3,7 @@ use crate::fdpool::FdPool;
 use crate::handle::Handle;
 use crate::string_array::{PendingString, StringArray, StringArrayError};
 use crate::sys::osdir::OsDir;
+use crate::sys::preopen_dir;
 use crate::sys::stdio::NullDevice;
 use crate::sys::stdio::{Stderr, StderrExt, Stdin, StdinExt, Stdout, StdoutExt};
 use crate::virtfs::{VirtualDir, VirtualDirEntry};
@@ -12,6 +13,7 @@ use std::borrow::Borrow;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::ffi::OsStr;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
@@ -164,9 +166,33 @@ impl WasiCtxBuilder {
     /// If any environment variables from the host process contain invalid Unicode (UTF-16 for
     /// Windows, UTF-8 for other platforms), `WasiCtxBuilder::build()` will fail.
     pub fn inherit_env(&mut self) -> &mut Self {
+        self.inherit_env_filtered(|_, _| true)
+    }
+
+    /// Inherit the environment variables from the host process for which `filter` returns
+    /// `true`, given their key and value.
+    ///
+    /// This replaces any entries previously added to the environment, so entries added afterwards
+    /// with `env` or `envs` can be used to override inherited ones. As with `inherit_env`,
+    /// `WasiCtxBuilder::build()` will fail if any inherited variable contains invalid Unicode.
+    ///
+    /// ```
+    /// # use wasi_common::WasiCtxBuilder;
+    /// let mut ctx = WasiCtxBuilder::new();
+    /// ctx.inherit_env_filtered(|key, _| key != "AWS_SECRET_ACCESS_KEY")
+    ///     .env("LANG", "C");
+    /// ```
+    pub fn inherit_env_filtered(
+        &mut self,
+        mut filter: impl FnMut(&OsStr, &OsStr) -> bool,
+    ) -> &mut Self {
         let env = self.env.as_mut().unwrap();
         env.clear();
-        env.extend(std::env::vars_os().map(|(k, v)| (k.into(), v.into())));
+        env.extend(
+            std::env::vars_os()
+                .filter(|(k, v)| filter(k, v))
+                .map(|(k, v)| (k.into(), v.into())),
+        );
         self
     }
 
@@ -198,18 +224,25 @@ impl WasiCtxBuilder {
     }
 
     /// Provide a `Handle` to use as stdin
+    ///
+    /// A `virtfs::pipe::ReadPipe` feeds the guest input from any `Read` type.
     pub fn stdin<T: Handle + 'static>(&mut self, handle: T) -> &mut Self {
         self.stdin = Some(PendingEntry::Handle(Box::new(handle)));
         self
     }
 
     /// Provide a `Handle` to use as stdout
+    ///
+    /// A `virtfs::pipe::WritePipe` captures the guest's output in any `Write` type, or in memory
+    /// with `WritePipe::new_in_memory`.
     pub fn stdout<T: Handle + 'static>(&mut self, handle: T) -> &mut Self {
         self.stdout = Some(PendingEntry::Handle(Box::new(handle)));
         self
     }
 
     /// Provide a `Handle` to use as stderr
+    ///
+    /// Output can be captured with a `virtfs::pipe::WritePipe`, as with `stdout`.
     pub fn stderr<T: Handle + 'static>(&mut self, handle: T) -> &mut Self {
         self.stderr = Some(PendingEntry::Handle(Box::new(handle)));
         self
@@ -228,6 +261,31 @@ impl WasiCtxBuilder {
         self
     }
 
+    /// Add a preopened directory, opening the directory at `host_path` on
+    /// the host when the context is built.
+    ///
+    /// Paths the guest resolves relative to the preopen are confined to it
+    /// by this crate: absolute paths, `..` components and symlinks which
+    /// would lead out of `host_path` all fail with `Notcapable`, regardless
+    /// of what the guest's libc does beforehand.
+    pub fn preopened_dir_path<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        host_path: P,
+        guest_path: Q,
+    ) -> &mut Self {
+        let host_path = host_path.as_ref().to_owned();
+        let preopen = PendingPreopen::new(move || {
+            let dir = preopen_dir(host_path)?;
+            let dir = OsDir::try_from(dir).map_err(WasiCtxBuilderError::from)?;
+            Ok(Box::new(dir))
+        });
+        self.preopens
+            .as_mut()
+            .unwrap()
+            .push((guest_path.as_ref().to_owned(), preopen));
+        self
+    }
+
     /// Add a preopened virtual directory.
     pub fn preopened_virt<P: AsRef<Path>>(
         &mut self,
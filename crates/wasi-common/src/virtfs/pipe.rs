@@ -283,6 +283,23 @@ impl WritePipe<io::Cursor<Vec<u8>>> {
     pub fn new_in_memory() -> Self {
         Self::new(io::Cursor::new(vec![]))
     }
+
+    /// Get a copy of everything written to this pipe so far.
+    ///
+    /// Unlike `try_into_inner`, this works while clones of the pipe are still alive, such as the
+    /// one held by a `WasiCtx` the pipe was given to.
+    ///
+    /// ```
+    /// # use wasi_common::WasiCtxBuilder;
+    /// # use wasi_common::virtfs::pipe::WritePipe;
+    /// let stdout = WritePipe::new_in_memory();
+    /// let ctx = WasiCtxBuilder::new().stdout(stdout.clone()).build().unwrap();
+    /// // ... run a guest with `ctx` ...
+    /// let output = stdout.contents();
+    /// ```
+    pub fn contents(&self) -> Vec<u8> {
+        self.writer.read().unwrap().get_ref().clone()
+    }
 }
 
 impl<W: Write + Any> Handle for WritePipe<W> {
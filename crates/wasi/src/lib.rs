@@ -1,4 +1,4 @@
-use wasmtime::Trap;
+use wasmtime::{Linker, Trap};
 
 pub mod old;
 
@@ -44,6 +44,39 @@ pub fn is_wasi_module(name: &str) -> bool {
     name.starts_with("wasi")
 }
 
+/// Defines all of the `wasi_snapshot_preview1` functions in `linker`, backed
+/// by `ctx`, so that command modules built with the WASI SDK can be
+/// instantiated with it.
+///
+/// This is a shorthand for creating a [`Wasi`] in the linker's store and
+/// adding it with [`Wasi::add_to_linker`].
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> anyhow::Result<()> {
+/// use wasmtime::{Linker, Module, Store};
+/// use wasmtime_wasi::{add_wasi_to_linker, WasiCtxBuilder};
+///
+/// let store = Store::default();
+/// let mut linker = Linker::new(&store);
+/// let ctx = WasiCtxBuilder::new()
+///     .arg("hello.wasm")
+///     .inherit_stdio()
+///     .build()?;
+/// add_wasi_to_linker(&mut linker, ctx)?;
+///
+/// let module = Module::from_file(store.engine(), "hello.wasm")?;
+/// linker.module("", &module)?;
+/// linker.get_default("")?.get0::<()>()?()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn add_wasi_to_linker(linker: &mut Linker, ctx: WasiCtx) -> anyhow::Result<()> {
+    let wasi = Wasi::new(linker.store(), ctx);
+    wasi.add_to_linker(linker)
+}
+
 /// Implement the WASI `proc_exit` function. This function is implemented here
 /// instead of in wasi-common so that we can use the runtime to perform an
 /// unwind rather than exiting the host process.
@@ -0,0 +1,500 @@
+use crate::func::wasm_entries;
+use crate::instance::is_module_instance;
+use crate::{FrameInfo, Store};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::{ptr, slice};
+use wasmtime_environ::entity::EntityRef;
+use wasmtime_environ::wasm::{EntityIndex, FuncIndex, GlobalIndex, MemoryIndex, WasmType};
+use wasmtime_environ::{Module, WASM_PAGE_SIZE};
+use wasmtime_runtime::{
+    Export, InstanceHandle, VMCallerCheckedAnyfunc, VMContext, VMGlobalDefinition,
+    VMMemoryDefinition,
+};
+
+/// A snapshot of a [`Store`] taken when wasm trapped in it, captured if
+/// [`Config::coredump_on_trap`](crate::Config::coredump_on_trap) is enabled.
+///
+/// The snapshot lists the wasm frames on the stack, from the one which
+/// trapped outwards, and every instance of a wasm module in the store along
+/// with its globals and, if
+/// [`Config::coredump_memories`](crate::Config::coredump_memories) is enabled,
+/// its memories. Memories and globals shared by several instances are only
+/// captured once. Instances holding host-defined functions, memories, etc.
+/// aren't included.
+///
+/// The values of locals and of the operand stack aren't captured, since
+/// compiled code doesn't keep track of where they live. Every frame is
+/// recorded with no locals and an empty operand stack.
+///
+/// The instance executing a frame isn't recorded by compiled code either. It's
+/// recovered from the functions the host called into wasm, and from the
+/// imports through which those called into other modules. Calls through a
+/// table into another instance of the caller's own module are attributed to
+/// the caller's instance. Frames whose instance can't be told apart from other
+/// instances of their module are left out.
+pub struct WasmCoreDump {
+    modules: Vec<String>,
+    instances: Vec<CoreDumpInstance>,
+    memories: Vec<CoreDumpMemory>,
+    globals: Vec<CoreDumpGlobal>,
+    frames: Vec<CoreDumpFrame>,
+}
+
+struct CoreDumpInstance {
+    module: u32,
+    memories: Vec<u32>,
+    globals: Vec<u32>,
+}
+
+struct CoreDumpMemory {
+    pages: u32,
+    maximum: Option<u32>,
+    shared: bool,
+    data: Vec<u8>,
+}
+
+struct CoreDumpGlobal {
+    mutable: bool,
+    value: GlobalValue,
+}
+
+enum GlobalValue {
+    I32(i32),
+    I64(i64),
+    F32(u32),
+    F64(u64),
+    V128([u8; 16]),
+    /// References can't be represented, so they're recorded as null.
+    NullRef(WasmType),
+}
+
+struct CoreDumpFrame {
+    instance: u32,
+    func: u32,
+    offset: u32,
+}
+
+impl WasmCoreDump {
+    pub(crate) fn new(store: &Store, trace: &[FrameInfo], entries: &[u32]) -> WasmCoreDump {
+        let config = store.engine().config();
+        let mut dump = WasmCoreDump {
+            modules: Vec::new(),
+            instances: Vec::new(),
+            memories: Vec::new(),
+            globals: Vec::new(),
+            frames: Vec::new(),
+        };
+        let mut modules: Vec<*const Module> = Vec::new();
+        let mut instances = HashMap::new();
+        let mut memories = HashMap::new();
+        let mut globals = HashMap::new();
+
+        for handle in store.instances().iter() {
+            if !is_module_instance(handle) {
+                continue;
+            }
+            let module = handle.module();
+            let module_index = match modules.iter().position(|m| *m == module as *const _) {
+                Some(i) => i,
+                None => {
+                    modules.push(module);
+                    dump.modules.push(
+                        module
+                            .name
+                            .clone()
+                            .unwrap_or_else(|| "<unknown>".to_string()),
+                    );
+                    modules.len() - 1
+                }
+            };
+            let mut instance = CoreDumpInstance {
+                module: module_index as u32,
+                memories: Vec::new(),
+                globals: Vec::new(),
+            };
+
+            if config.coredump_memories {
+                for i in 0..module.memory_plans.len() {
+                    let index = EntityIndex::Memory(MemoryIndex::new(i));
+                    let export = match handle.lookup_by_declaration(&index) {
+                        Export::Memory(m) => m,
+                        _ => unreachable!(),
+                    };
+                    let index = *memories.entry(export.definition).or_insert_with(|| {
+                        let plan = &export.memory.memory;
+                        let data = unsafe { memory_data(&*export.definition) };
+                        let pages = data.len() / WASM_PAGE_SIZE as usize;
+                        dump.memories.push(CoreDumpMemory {
+                            pages: pages as u32,
+                            maximum: plan.maximum,
+                            shared: plan.shared,
+                            data: trim_zeros(data).to_vec(),
+                        });
+                        dump.memories.len() as u32 - 1
+                    });
+                    instance.memories.push(index);
+                }
+            }
+
+            for i in 0..module.globals.len() {
+                let index = EntityIndex::Global(GlobalIndex::new(i));
+                let export = match handle.lookup_by_declaration(&index) {
+                    Export::Global(g) => g,
+                    _ => unreachable!(),
+                };
+                let index = *globals.entry(export.definition).or_insert_with(|| {
+                    let value = unsafe { global_value(&*export.definition, export.global.wasm_ty) };
+                    dump.globals.push(CoreDumpGlobal {
+                        mutable: export.global.mutability,
+                        value,
+                    });
+                    dump.globals.len() as u32 - 1
+                });
+                instance.globals.push(index);
+            }
+
+            instances.insert(handle.vmctx_ptr(), dump.instances.len() as u32);
+            dump.instances.push(instance);
+        }
+
+        let vmctxs = unsafe { frame_vmctxs(trace, entries) };
+        for (frame, vmctx) in trace.iter().zip(vmctxs) {
+            let instance = match vmctx.and_then(|vmctx| instances.get(&vmctx)) {
+                Some(i) => *i,
+                None => {
+                    // Fall back to the only instance of the frame's module, if
+                    // there is just one.
+                    let module = modules.iter().position(|m| ptr::eq(*m, &**frame.module()));
+                    let mut candidates = dump
+                        .instances
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, i)| Some(i.module as usize) == module);
+                    match (candidates.next(), candidates.next()) {
+                        (Some((i, _)), None) => i as u32,
+                        _ => continue,
+                    }
+                }
+            };
+            dump.frames.push(CoreDumpFrame {
+                instance,
+                func: frame.func_index(),
+                offset: u32::try_from(frame.func_offset()).unwrap_or(u32::max_value()),
+            });
+        }
+
+        dump
+    }
+
+    /// Returns the number of wasm frames which were on the stack.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns the number of instances captured.
+    pub fn instance_count(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Returns the number of linear memories captured.
+    pub fn memory_count(&self) -> usize {
+        self.memories.len()
+    }
+
+    /// Serializes this coredump to the [wasm coredump format], a wasm module
+    /// whose custom sections describe the stack and the instances and whose
+    /// globals and data segments hold the contents of the store's globals and
+    /// memories.
+    ///
+    /// `name` is recorded as the name of the program which trapped.
+    ///
+    /// [wasm coredump format]: https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md
+    pub fn serialize(&self, name: &str) -> Vec<u8> {
+        let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut core = vec![0x00];
+        write_name(&mut core, name);
+        write_custom_section(&mut bytes, "core", &core);
+
+        let mut modules = Vec::new();
+        write_u32(&mut modules, self.modules.len() as u32);
+        for module in self.modules.iter() {
+            modules.push(0x00);
+            write_name(&mut modules, module);
+        }
+        write_custom_section(&mut bytes, "coremodules", &modules);
+
+        let mut instances = Vec::new();
+        write_u32(&mut instances, self.instances.len() as u32);
+        for instance in self.instances.iter() {
+            instances.push(0x00);
+            write_u32(&mut instances, instance.module);
+            write_u32(&mut instances, instance.memories.len() as u32);
+            for memory in instance.memories.iter() {
+                write_u32(&mut instances, *memory);
+            }
+            write_u32(&mut instances, instance.globals.len() as u32);
+            for global in instance.globals.iter() {
+                write_u32(&mut instances, *global);
+            }
+        }
+        write_custom_section(&mut bytes, "coreinstances", &instances);
+
+        if !self.memories.is_empty() {
+            let mut section = Vec::new();
+            write_u32(&mut section, self.memories.len() as u32);
+            for memory in self.memories.iter() {
+                match memory.maximum {
+                    Some(max) => {
+                        section.push(if memory.shared { 0x03 } else { 0x01 });
+                        write_u32(&mut section, memory.pages);
+                        write_u32(&mut section, max);
+                    }
+                    None => {
+                        section.push(0x00);
+                        write_u32(&mut section, memory.pages);
+                    }
+                }
+            }
+            write_section(&mut bytes, 5, &section);
+        }
+
+        if !self.globals.is_empty() {
+            let mut section = Vec::new();
+            write_u32(&mut section, self.globals.len() as u32);
+            for global in self.globals.iter() {
+                section.push(global.value.val_type());
+                section.push(global.mutable as u8);
+                global.value.write_const_expr(&mut section);
+                section.push(0x0b);
+            }
+            write_section(&mut bytes, 6, &section);
+        }
+
+        if !self.memories.is_empty() {
+            let mut section = Vec::new();
+            write_u32(&mut section, self.memories.len() as u32);
+            for (i, memory) in self.memories.iter().enumerate() {
+                if i == 0 {
+                    section.push(0x00);
+                } else {
+                    section.push(0x02);
+                    write_u32(&mut section, i as u32);
+                }
+                section.extend_from_slice(&[0x41, 0x00, 0x0b]);
+                write_u32(&mut section, memory.data.len() as u32);
+                section.extend_from_slice(&memory.data);
+            }
+            write_section(&mut bytes, 11, &section);
+        }
+
+        let mut stack = vec![0x00];
+        write_name(&mut stack, "main");
+        write_u32(&mut stack, self.frames.len() as u32);
+        for frame in self.frames.iter() {
+            stack.push(0x00);
+            write_u32(&mut stack, frame.instance);
+            write_u32(&mut stack, frame.func);
+            write_u32(&mut stack, frame.offset);
+            // Locals and the operand stack aren't captured.
+            write_u32(&mut stack, 0);
+            write_u32(&mut stack, 0);
+        }
+        write_custom_section(&mut bytes, "corestack", &stack);
+
+        bytes
+    }
+}
+
+/// Returns the `VMContext` of the instance executing each frame of `trace`,
+/// where `entries` tells which call from the host into wasm each frame is part
+/// of, see `WasmCoreDump`.
+///
+/// This is unsafe since all of the calls into wasm must still be on the stack,
+/// so that their `VMContext`s are still alive.
+unsafe fn frame_vmctxs(trace: &[FrameInfo], entries: &[u32]) -> Vec<Option<*mut VMContext>> {
+    // Calls into host functions aren't part of the trace, so leave them out.
+    let entry_vmctxs = wasm_entries()
+        .into_iter()
+        .filter(|vmctx| is_module_instance(&InstanceHandle::from_vmctx(*vmctx)))
+        .collect::<Vec<_>>();
+
+    let mut vmctxs = vec![None; trace.len()];
+    let mut caller = None;
+    for i in (0..trace.len()).rev() {
+        let frame = &trace[i];
+        let vmctx = if i + 1 == trace.len() || entries[i + 1] != entries[i] {
+            entry_vmctxs
+                .len()
+                .checked_sub(entries[i] as usize + 1)
+                .map(|e| entry_vmctxs[e])
+        } else {
+            caller.and_then(|caller| callee_vmctx(caller, frame))
+        };
+        let vmctx = vmctx.filter(|vmctx| {
+            ptr::eq(
+                InstanceHandle::from_vmctx(*vmctx).module(),
+                &**frame.module(),
+            )
+        });
+        vmctxs[i] = vmctx;
+        caller = vmctx;
+    }
+    vmctxs
+}
+
+/// Returns the `VMContext` of the instance whose function `callee` the
+/// instance of `caller` calls, if it can be found.
+unsafe fn callee_vmctx(caller: *mut VMContext, callee: &FrameInfo) -> Option<*mut VMContext> {
+    let caller = InstanceHandle::from_vmctx(caller);
+    if ptr::eq(caller.module(), &**callee.module()) {
+        return Some(caller.vmctx_ptr());
+    }
+
+    let callee_index = FuncIndex::from_u32(callee.func_index());
+    (0..caller.module().num_imported_funcs)
+        .map(|i| func_ref(&caller, FuncIndex::new(i)))
+        .find(|import| {
+            let instance = InstanceHandle::from_vmctx(import.vmctx);
+            ptr::eq(instance.module(), &**callee.module())
+                && func_ref(&instance, callee_index).func_ptr == import.func_ptr
+        })
+        .map(|import| import.vmctx)
+}
+
+unsafe fn func_ref(handle: &InstanceHandle, index: FuncIndex) -> &VMCallerCheckedAnyfunc {
+    match handle.lookup_by_declaration(&EntityIndex::Function(index)) {
+        Export::Function(f) => &*f.anyfunc.as_ptr(),
+        _ => unreachable!(),
+    }
+}
+
+impl fmt::Debug for WasmCoreDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmCoreDump")
+            .field("modules", &self.modules)
+            .field("instances", &self.instances.len())
+            .field("memories", &self.memories.len())
+            .field("globals", &self.globals.len())
+            .field("frames", &self.frames.len())
+            .finish()
+    }
+}
+
+impl GlobalValue {
+    fn val_type(&self) -> u8 {
+        match self {
+            GlobalValue::I32(_) => 0x7f,
+            GlobalValue::I64(_) => 0x7e,
+            GlobalValue::F32(_) => 0x7d,
+            GlobalValue::F64(_) => 0x7c,
+            GlobalValue::V128(_) => 0x7b,
+            GlobalValue::NullRef(ty) => ref_type(*ty),
+        }
+    }
+
+    fn write_const_expr(&self, bytes: &mut Vec<u8>) {
+        match self {
+            GlobalValue::I32(x) => {
+                bytes.push(0x41);
+                write_i64(bytes, i64::from(*x));
+            }
+            GlobalValue::I64(x) => {
+                bytes.push(0x42);
+                write_i64(bytes, *x);
+            }
+            GlobalValue::F32(bits) => {
+                bytes.push(0x43);
+                bytes.extend_from_slice(&bits.to_le_bytes());
+            }
+            GlobalValue::F64(bits) => {
+                bytes.push(0x44);
+                bytes.extend_from_slice(&bits.to_le_bytes());
+            }
+            GlobalValue::V128(v) => {
+                bytes.extend_from_slice(&[0xfd, 0x0c]);
+                bytes.extend_from_slice(v);
+            }
+            GlobalValue::NullRef(ty) => {
+                bytes.push(0xd0);
+                bytes.push(ref_type(*ty));
+            }
+        }
+    }
+}
+
+fn ref_type(ty: WasmType) -> u8 {
+    match ty {
+        WasmType::FuncRef => 0x70,
+        _ => 0x6f,
+    }
+}
+
+unsafe fn memory_data(definition: &VMMemoryDefinition) -> &[u8] {
+    if definition.current_length == 0 {
+        return &[];
+    }
+    slice::from_raw_parts(definition.base, definition.current_length)
+}
+
+unsafe fn global_value(definition: &VMGlobalDefinition, ty: WasmType) -> GlobalValue {
+    match ty {
+        WasmType::I32 => GlobalValue::I32(*definition.as_i32()),
+        WasmType::I64 => GlobalValue::I64(*definition.as_i64()),
+        WasmType::F32 => GlobalValue::F32(*definition.as_f32_bits()),
+        WasmType::F64 => GlobalValue::F64(*definition.as_f64_bits()),
+        WasmType::V128 => GlobalValue::V128(*definition.as_u128_bits()),
+        WasmType::FuncRef | WasmType::ExternRef => GlobalValue::NullRef(ty),
+    }
+}
+
+/// Drops the trailing zeros of a memory, which are implied by its size.
+fn trim_zeros(data: &[u8]) -> &[u8] {
+    let len = data.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    &data[..len]
+}
+
+fn write_u32(bytes: &mut Vec<u8>, mut val: u32) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn write_i64(bytes: &mut Vec<u8>, mut val: i64) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        let done = (val == 0 && byte & 0x40 == 0) || (val == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    write_u32(bytes, name.len() as u32);
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+fn write_section(bytes: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    bytes.push(id);
+    write_u32(bytes, contents.len() as u32);
+    bytes.extend_from_slice(contents);
+}
+
+fn write_custom_section(bytes: &mut Vec<u8>, name: &str, contents: &[u8]) {
+    let mut section = Vec::new();
+    write_name(&mut section, name);
+    section.extend_from_slice(contents);
+    write_section(bytes, 0, &section);
+}
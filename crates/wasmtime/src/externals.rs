@@ -1,18 +1,30 @@
 use crate::trampoline::{
-    generate_global_export, generate_memory_export, generate_table_export, StoreInstanceHandle,
+    generate_global_export, generate_host_buffer_memory_export, generate_memory_export,
+    generate_shared_memory_export, generate_table_export, SharedLinearMemory, StoreInstanceHandle,
+    WeakStoreInstanceHandle,
 };
-use crate::values::{from_checked_anyfunc, into_checked_anyfunc, Val};
+use crate::values::{from_checked_anyfunc, Val};
 use crate::{
-    ExternRef, ExternType, Func, GlobalType, MemoryType, Mutability, Store, TableType, Trap,
-    ValType,
+    Engine, ExternRef, ExternType, Func, GlobalType, Instance, MemoryType, Mutability, Store,
+    TableType, Trap, ValType,
 };
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use std::convert::TryFrom;
 use std::mem;
+use std::ops::Range;
 use std::ptr;
 use std::slice;
+use std::sync::atomic::{
+    AtomicU32, AtomicU64, AtomicU8,
+    Ordering::{Relaxed, SeqCst},
+};
+use std::sync::Arc;
+use std::time::Duration;
 use wasmtime_environ::wasm;
 use wasmtime_runtime::{self as runtime, InstanceHandle};
 
+pub use wasmtime_runtime::WaitResult;
+
 // Externals
 
 /// An external item to a WebAssembly module, or a list of what can possibly be
@@ -76,6 +88,50 @@ impl Extern {
         }
     }
 
+    /// Returns a reference to the underlying `Func`, if this external is a
+    /// function.
+    ///
+    /// Returns `None` if this is not a function.
+    pub fn as_func(&self) -> Option<&Func> {
+        match self {
+            Extern::Func(func) => Some(func),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the underlying `Global`, if this external is a
+    /// global.
+    ///
+    /// Returns `None` if this is not a global.
+    pub fn as_global(&self) -> Option<&Global> {
+        match self {
+            Extern::Global(global) => Some(global),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the underlying `Table`, if this external is a
+    /// table.
+    ///
+    /// Returns `None` if this is not a table.
+    pub fn as_table(&self) -> Option<&Table> {
+        match self {
+            Extern::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the underlying `Memory`, if this external is a
+    /// memory.
+    ///
+    /// Returns `None` if this is not a memory.
+    pub fn as_memory(&self) -> Option<&Memory> {
+        match self {
+            Extern::Memory(memory) => Some(memory),
+            _ => None,
+        }
+    }
+
     /// Returns the type associated with this `Extern`.
     pub fn ty(&self) -> ExternType {
         match self {
@@ -150,6 +206,28 @@ impl From<Table> for Extern {
     }
 }
 
+macro_rules! extern_try_from {
+    ($(($ty:ident $variant:ident $desc:tt))*) => ($(
+        impl TryFrom<Extern> for $ty {
+            type Error = anyhow::Error;
+
+            fn try_from(e: Extern) -> Result<$ty> {
+                match e {
+                    Extern::$variant(x) => Ok(x),
+                    other => bail!(concat!("expected ", $desc, ", but found {}"), other.desc()),
+                }
+            }
+        }
+    )*)
+}
+
+extern_try_from! {
+    (Func Func "function")
+    (Global Global "global")
+    (Table Table "table")
+    (Memory Memory "memory")
+}
+
 /// A WebAssembly `global` value which can be read and written to.
 ///
 /// A `global` in WebAssembly is sort of like a global variable within an
@@ -227,6 +305,7 @@ impl Global {
                 ValType::I64 => Val::from(*definition.as_i64()),
                 ValType::F32 => Val::F32(*definition.as_u32()),
                 ValType::F64 => Val::F64(*definition.as_u64()),
+                ValType::V128 => Val::V128(*definition.as_u128()),
                 ValType::ExternRef => Val::ExternRef(
                     definition
                         .as_externref()
@@ -236,7 +315,6 @@ impl Global {
                 ValType::FuncRef => {
                     from_checked_anyfunc(definition.as_anyfunc() as *mut _, &self.instance.store)
                 }
-                ty => unimplemented!("Global::get for {:?}", ty),
             }
         }
     }
@@ -253,7 +331,7 @@ impl Global {
         }
         let ty = self.val_type();
         if val.ty() != ty {
-            bail!("global of type {:?} cannot be set to {:?}", ty, val.ty());
+            bail!("global of type {} cannot be set to {}", ty, val.ty());
         }
         if !val.comes_from_same_store(&self.instance.store) {
             bail!("cross-`Store` values are not supported");
@@ -265,6 +343,7 @@ impl Global {
                 Val::I64(i) => *definition.as_i64_mut() = i,
                 Val::F32(f) => *definition.as_u32_mut() = f,
                 Val::F64(f) => *definition.as_u64_mut() = f,
+                Val::V128(x) => *definition.as_u128_mut() = x,
                 Val::FuncRef(f) => {
                     *definition.as_anyfunc_mut() = f.map_or(ptr::null(), |f| {
                         f.caller_checked_anyfunc().as_ptr() as *const _
@@ -278,12 +357,20 @@ impl Global {
                     let old = mem::replace(definition.as_externref_mut(), x.map(|x| x.inner));
                     drop(old);
                 }
-                _ => unimplemented!("Global::set for {:?}", val.ty()),
             }
         }
         Ok(())
     }
 
+    /// Creates a [`WeakGlobal`] referring to this global without keeping its
+    /// [`Store`] alive.
+    pub fn downgrade(&self) -> WeakGlobal {
+        WeakGlobal {
+            instance: self.instance.downgrade(),
+            wasmtime_export: self.wasmtime_export.clone(),
+        }
+    }
+
     pub(crate) fn from_wasmtime_global(
         wasmtime_export: wasmtime_runtime::ExportGlobal,
         instance: StoreInstanceHandle,
@@ -308,6 +395,26 @@ impl Global {
     }
 }
 
+/// A reference to a [`Global`] which doesn't keep its [`Store`] alive, created
+/// with [`Global::downgrade`]. See [`WeakFunc`](crate::WeakFunc) for more
+/// information.
+#[derive(Clone)]
+pub struct WeakGlobal {
+    instance: WeakStoreInstanceHandle,
+    wasmtime_export: wasmtime_runtime::ExportGlobal,
+}
+
+impl WeakGlobal {
+    /// Returns the [`Global`] this refers to, or `None` if its [`Store`] has
+    /// been dropped.
+    pub fn upgrade(&self) -> Option<Global> {
+        Some(Global {
+            instance: self.instance.upgrade()?,
+            wasmtime_export: self.wasmtime_export.clone(),
+        })
+    }
+}
+
 /// A WebAssembly `table`, or an array of values.
 ///
 /// Like [`Memory`] a table is an indexed array of values, but unlike [`Memory`]
@@ -323,6 +430,15 @@ impl Global {
 /// Tables are internally reference counted so you can `clone` a `Table`. The
 /// cloning process only performs a shallow clone, so two cloned `Table`
 /// instances are equivalent in their functionality.
+///
+/// # Sharing tables between instances
+///
+/// A table, whether exported by an instance or created with [`Table::new`],
+/// can be imported by any number of instances in the same [`Store`], all of
+/// which then operate on the same elements. For example a function placed in a
+/// shared table by one instance can be called with `call_indirect` by another.
+/// A table satisfies an import if its current size, rather than the minimum
+/// size it was created with, is at least the import's minimum.
 #[derive(Clone)]
 pub struct Table {
     instance: StoreInstanceHandle,
@@ -340,6 +456,17 @@ fn set_table_item(
         .map_err(|()| anyhow!("table element index out of bounds"))
 }
 
+fn check_table_element_type(ty: &TableType, val: &Val) -> Result<()> {
+    if val.ty() != *ty.element() {
+        bail!(
+            "value of type {} does not match table element type {}",
+            val.ty(),
+            ty.element()
+        );
+    }
+    Ok(())
+}
+
 impl Table {
     /// Creates a new `Table` with the given parameters.
     ///
@@ -353,31 +480,29 @@ impl Table {
     ///
     /// Returns an error if `init` does not match the element type of the table.
     pub fn new(store: &Store, ty: TableType, init: Val) -> Result<Table> {
-        let (instance, wasmtime_export) = generate_table_export(store, &ty)?;
-
-        let init: runtime::TableElement = match ty.element() {
-            ValType::FuncRef => into_checked_anyfunc(init, store)?.into(),
-            ValType::ExternRef => init
-                .externref()
-                .ok_or_else(|| {
-                    anyhow!("table initialization value does not have expected type `externref`")
-                })?
-                .map(|x| x.inner)
-                .into(),
-            ty => bail!("unsupported table element type: {:?}", ty),
-        };
-
-        // Initialize entries with the init value.
-        let definition = unsafe { &*wasmtime_export.definition };
-        let index = instance.table_index(definition);
-        for i in 0..definition.current_elements {
-            set_table_item(&instance, index, i, init.clone())?;
+        if !init.comes_from_same_store(store) {
+            bail!("cross-`Store` values are not supported in tables");
         }
+        check_table_element_type(&ty, &init)?;
 
-        Ok(Table {
+        let (instance, wasmtime_export) = generate_table_export(store, &ty)?;
+        let table = Table {
             instance,
             wasmtime_export,
-        })
+        };
+
+        // Initialize entries with the init value.
+        table
+            .instance
+            .handle
+            .defined_table_fill(
+                table.wasmtime_table_index(),
+                0,
+                init.into_table_element()?,
+                table.size(),
+            )
+            .map_err(Trap::from_runtime)?;
+        Ok(table)
     }
 
     /// Returns the underlying type of this table, including its element type as
@@ -390,6 +515,10 @@ impl Table {
         unsafe { self.instance.table_index(&*self.wasmtime_export.definition) }
     }
 
+    fn check_element_type(&self, val: &Val) -> Result<()> {
+        check_table_element_type(&self.ty(), val)
+    }
+
     /// Returns the table element value at `index`.
     ///
     /// Returns `None` if `index` is out of bounds.
@@ -417,6 +546,7 @@ impl Table {
         if !val.comes_from_same_store(&self.instance.store) {
             bail!("cross-`Store` values are not supported in tables");
         }
+        self.check_element_type(&val)?;
         let table_index = self.wasmtime_table_index();
         set_table_item(
             &self.instance,
@@ -434,38 +564,52 @@ impl Table {
     /// Grows the size of this table by `delta` more elements, initialization
     /// all new elements to `init`.
     ///
-    /// Returns the previous size of this table if successful.
+    /// Returns the previous size of this table if successful, just like the
+    /// `table.grow` instruction.
     ///
     /// # Errors
     ///
-    /// Returns an error if the table cannot be grown by `delta`, for example
-    /// if it would cause the table to exceed its maximum size. Also returns an
-    /// error if `init` is not of the right type.
+    /// Returns an error if `init` is not of the right type, or if the table
+    /// cannot be grown by `delta` because it would exceed the table's maximum
+    /// size or the largest size representable by a `u32`. The error message
+    /// describes which of these happened.
+    ///
+    /// Growing an `externref` table first performs a garbage collection, as
+    /// with [`Store::gc`](crate::Store::gc).
     pub fn grow(&self, delta: u32, init: Val) -> Result<u32> {
-        let index = self.wasmtime_table_index();
-        let orig_size = match self.ty().element() {
-            ValType::FuncRef => {
-                let init = into_checked_anyfunc(init, &self.instance.store)?;
-                self.instance.defined_table_grow(index, delta, init.into())
-            }
-            ValType::ExternRef => {
-                let init = match init {
-                    Val::ExternRef(Some(x)) => Some(x.inner),
-                    Val::ExternRef(None) => None,
-                    _ => bail!("incorrect init value for growing table"),
-                };
-                self.instance.defined_table_grow(
-                    index,
+        if !init.comes_from_same_store(&self.instance.store) {
+            bail!("cross-`Store` values are not supported in tables");
+        }
+        self.check_element_type(&init)?;
+        let size = self.size();
+        let new_size = match size.checked_add(delta) {
+            Some(new_size) => new_size,
+            None => bail!(
+                "cannot grow table of {} elements by {}: size would overflow a u32",
+                size,
+                delta
+            ),
+        };
+        if let Some(max) = self.ty().limits().max() {
+            if new_size > max {
+                bail!(
+                    "cannot grow table of {} elements by {}: maximum of {} elements exceeded",
+                    size,
                     delta,
-                    runtime::TableElement::ExternRef(init),
-                )
+                    max
+                );
             }
-            _ => unreachable!("only `funcref` and `externref` tables are supported"),
-        };
-        if let Some(size) = orig_size {
-            Ok(size)
-        } else {
-            bail!("failed to grow table by `{}`", delta)
+        }
+
+        let init = init.into_table_element()?;
+        if let runtime::TableElement::ExternRef(_) = init {
+            self.instance.store.gc();
+        }
+
+        let index = self.wasmtime_table_index();
+        match self.instance.defined_table_grow(index, delta, init) {
+            Some(size) => Ok(size),
+            None => bail!("failed to grow table by `{}`", delta),
         }
     }
 
@@ -518,6 +662,7 @@ impl Table {
         if !val.comes_from_same_store(&self.instance.store) {
             bail!("cross-`Store` table fills are not supported");
         }
+        self.check_element_type(&val)?;
 
         let table_index = self.wasmtime_table_index();
         self.instance
@@ -528,6 +673,58 @@ impl Table {
         Ok(())
     }
 
+    /// Initialize `table[dst..(dst + len)]` with the elements
+    /// `elem[src..(src + len)]` of the passive element segment `elem_index`
+    /// from `instance`'s module.
+    ///
+    /// This mirrors the `table.init` instruction from the bulk memory
+    /// proposal. Element segments which are not passive, or which have already
+    /// been dropped with `elem.drop`, are treated as empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if
+    ///
+    /// * this table's element type is not `funcref`,
+    ///
+    /// * the region to be initialized is out of bounds of either this table or
+    ///   the element segment, or
+    ///
+    /// * `instance` comes from a different `Store` from this table.
+    pub fn init(
+        &self,
+        dst: u32,
+        instance: &Instance,
+        elem_index: u32,
+        src: u32,
+        len: u32,
+    ) -> Result<()> {
+        if !Store::same(&self.instance.store, instance.store()) {
+            bail!("cross-`Store` table initializations are not supported");
+        }
+        if *self.ty().element() != ValType::FuncRef {
+            bail!("element segments can only initialize `funcref` tables");
+        }
+
+        let table_index = self.wasmtime_table_index();
+        let table = self.instance.get_defined_table(table_index);
+        instance
+            .handle
+            .table_init_segment(table, wasm::ElemIndex::from_u32(elem_index), dst, src, len)
+            .map_err(Trap::from_runtime)?;
+
+        Ok(())
+    }
+
+    /// Creates a [`WeakTable`] referring to this table without keeping its
+    /// [`Store`] alive.
+    pub fn downgrade(&self) -> WeakTable {
+        WeakTable {
+            instance: self.instance.downgrade(),
+            wasmtime_export: self.wasmtime_export.clone(),
+        }
+    }
+
     pub(crate) fn from_wasmtime_table(
         wasmtime_export: wasmtime_runtime::ExportTable,
         instance: StoreInstanceHandle,
@@ -543,7 +740,7 @@ impl Table {
         let actual = &self.wasmtime_export.table.table;
         expected.wasm_ty == actual.wasm_ty
             && expected.ty == actual.ty
-            && expected.minimum <= actual.minimum
+            && expected.minimum <= self.size()
             && match expected.maximum {
                 Some(expected) => match actual.maximum {
                     Some(actual) => expected >= actual,
@@ -561,6 +758,26 @@ impl Table {
     }
 }
 
+/// A reference to a [`Table`] which doesn't keep its [`Store`] alive, created
+/// with [`Table::downgrade`]. See [`WeakFunc`](crate::WeakFunc) for more
+/// information.
+#[derive(Clone)]
+pub struct WeakTable {
+    instance: WeakStoreInstanceHandle,
+    wasmtime_export: wasmtime_runtime::ExportTable,
+}
+
+impl WeakTable {
+    /// Returns the [`Table`] this refers to, or `None` if its [`Store`] has
+    /// been dropped.
+    pub fn upgrade(&self) -> Option<Table> {
+        Some(Table {
+            instance: self.instance.upgrade()?,
+            wasmtime_export: self.wasmtime_export.clone(),
+        })
+    }
+}
+
 /// A WebAssembly linear memory.
 ///
 /// WebAssembly memories represent a contiguous array of bytes that have a size
@@ -576,6 +793,41 @@ impl Table {
 /// cloning process only performs a shallow clone, so two cloned `Memory`
 /// instances are equivalent in their functionality.
 ///
+/// # Sharing memories between instances
+///
+/// A memory, whether exported by an instance or created with [`Memory::new`],
+/// can be imported by any number of instances in the same [`Store`]. They all
+/// access the same bytes, and growth by any of them is visible to the others,
+/// so modules linked this way can share a single heap. A memory satisfies an
+/// import if its current size, rather than the minimum size it was created
+/// with, is at least the import's minimum, so a memory can be grown before
+/// it's handed to a module which expects a larger one.
+///
+/// ```
+/// # use wasmtime::*;
+/// # fn main() -> anyhow::Result<()> {
+/// let store = Store::default();
+/// let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)))?;
+/// memory.grow(1)?;
+///
+/// let module = Module::new(
+///     store.engine(),
+///     r#"(module
+///         (import "" "" (memory 2))
+///         (func (export "store") (param i32 i32)
+///             local.get 0
+///             local.get 1
+///             i32.store8))"#,
+/// )?;
+/// let a = Instance::new(&store, &module, &[memory.clone().into()])?;
+/// let b = Instance::new(&store, &module, &[memory.clone().into()])?;
+/// a.get_typed_func::<(i32, i32), ()>("store")?.call((70000, 1))?;
+/// b.get_typed_func::<(i32, i32), ()>("store")?.call((70001, 2))?;
+/// assert_eq!(unsafe { &memory.data_unchecked()[70000..70002] }, [1, 2]);
+/// # Ok(())
+/// # }
+/// ```
+///
 /// # `Memory` and threads
 ///
 /// It is intended that `Memory` is safe to share between threads. At this time
@@ -780,6 +1032,12 @@ impl Memory {
     /// otherwise the memory will immediately be allocated according to the
     /// type's configuration. All WebAssembly memory is initialized to zero.
     ///
+    /// # Errors
+    ///
+    /// Returns an error if the memory couldn't be allocated, for example
+    /// because reserving its address space failed or because the engine's
+    /// [`MemoryCreator`] returned an error.
+    ///
     /// # Examples
     ///
     /// ```
@@ -789,7 +1047,7 @@ impl Memory {
     /// let store = Store::new(&engine);
     ///
     /// let memory_ty = MemoryType::new(Limits::new(1, None));
-    /// let memory = Memory::new(&store, memory_ty);
+    /// let memory = Memory::new(&store, memory_ty)?;
     ///
     /// let module = Module::new(&engine, "(module (memory (import \"\" \"\") 1))")?;
     /// let instance = Instance::new(&store, &module, &[memory.into()])?;
@@ -797,13 +1055,178 @@ impl Memory {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn new(store: &Store, ty: MemoryType) -> Memory {
+    pub fn new(store: &Store, ty: MemoryType) -> Result<Memory> {
+        let (instance, wasmtime_export) = generate_memory_export(store, &ty)?;
+        Ok(Memory {
+            instance,
+            wasmtime_export,
+        })
+    }
+
+    /// Creates a new WebAssembly memory of type `ty`, initialized with a copy
+    /// of `data` at `offset` and zeroes everywhere else.
+    ///
+    /// This is a shorthand for [`Memory::new`] followed by [`Memory::write`],
+    /// which can also be used to initialize more than one region.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors returned by [`Memory::new`], this returns an
+    /// error if `data` doesn't fit at `offset` within the minimum size of
+    /// `ty`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let memory_ty = MemoryType::new(Limits::new(1, None));
+    /// let memory = Memory::new_with_data(&store, memory_ty, 8, b"hello")?;
+    ///
+    /// let mut buf = [0; 6];
+    /// memory.read(7, &mut buf)?;
+    /// assert_eq!(&buf, b"\0hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_with_data(
+        store: &Store,
+        ty: MemoryType,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<Memory> {
+        let memory = Memory::new(store, ty)?;
+        memory
+            .write(offset, data)
+            .context("initial data doesn't fit in the memory's minimum size")?;
+        Ok(memory)
+    }
+
+    /// Creates a new WebAssembly memory in `store` which refers to `shared`.
+    ///
+    /// The returned memory has the same contents as every other memory created
+    /// from `shared`, including ones in other stores on other threads, and can
+    /// be imported by instances in `store` which import a shared memory. See
+    /// [`SharedMemory`] for more information.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `store` doesn't belong to the engine `shared` was
+    /// created with.
+    pub fn new_with_shared(store: &Store, shared: &SharedMemory) -> Result<Memory> {
+        let (instance, wasmtime_export) = generate_shared_memory_export(store, shared)?;
+        Ok(Memory {
+            instance,
+            wasmtime_export,
+        })
+    }
+
+    /// Creates a new WebAssembly memory of type `ty` whose contents live in a
+    /// buffer provided by the embedder instead of one allocated by wasmtime.
+    ///
+    /// The memory starts out with `ty`'s minimum size and can grow up to
+    /// `capacity` bytes (rounded down to whole wasm pages), but it is never
+    /// relocated: its base pointer is always `base`. Unlike with
+    /// [`Memory::new`], the memory is not zeroed, so wasm will see whatever
+    /// the buffer contained.
+    ///
+    /// Compiled code must never be able to access the buffer out of bounds, so
+    /// this only works for memories which are bounds checked on every access
+    /// and which don't rely on guard pages. In practice that means the store's
+    /// [`Config`](crate::Config) must set
+    /// [`static_memory_maximum_size`](crate::Config::static_memory_maximum_size)
+    /// and
+    /// [`dynamic_memory_guard_size`](crate::Config::dynamic_memory_guard_size)
+    /// to 0. Shared memories cannot be backed by a host buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the engine's configuration doesn't allow this memory
+    /// to be bounds checked as described above, if `ty` is shared, or if
+    /// `capacity` is too small to hold `ty`'s minimum size.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be valid for reads and writes of `capacity` bytes for as
+    /// long as the returned memory or any instance importing it is alive, which
+    /// at most is as long as `store` is alive. The buffer must also not be
+    /// accessed other than through this memory in the meantime, with the same
+    /// caveats as [`Memory::data_ptr`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// // The buffer must outlive the store, so it's declared first.
+    /// let mut buffer = vec![0u8; 2 * 65536];
+    ///
+    /// let mut config = Config::new();
+    /// config.static_memory_maximum_size(0);
+    /// config.dynamic_memory_guard_size(0);
+    /// let engine = Engine::new(&config);
+    /// let store = Store::new(&engine);
+    ///
+    /// let memory_ty = MemoryType::new(Limits::new(1, None));
+    /// let memory = unsafe {
+    ///     Memory::new_with_host_buffer(&store, memory_ty, buffer.as_mut_ptr(), buffer.len())?
+    /// };
+    /// assert_eq!(memory.data_ptr(), buffer.as_mut_ptr());
+    /// assert!(memory.grow(1).is_ok());
+    /// assert!(memory.grow(1).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub unsafe fn new_with_host_buffer(
+        store: &Store,
+        ty: MemoryType,
+        base: *mut u8,
+        capacity: usize,
+    ) -> Result<Memory> {
         let (instance, wasmtime_export) =
-            generate_memory_export(store, &ty).expect("generated memory");
-        Memory {
+            generate_host_buffer_memory_export(store, &ty, base, capacity)?;
+        Ok(Memory {
             instance,
             wasmtime_export,
-        }
+        })
+    }
+
+    /// Creates a new WebAssembly memory of type `ty` whose initial contents
+    /// are the contents of `file`, with the rest of the memory zeroed.
+    ///
+    /// Rather than being read up front, the file is memory-mapped
+    /// copy-on-write: its pages are loaded lazily as wasm touches them and are
+    /// shared with every other memory created from the same file until they're
+    /// written to. Writes are private to this memory and never reach the file.
+    /// Note that growing a dynamically bounds-checked memory may relocate it,
+    /// at which point its contents are copied out of the mapping.
+    ///
+    /// The memory is always allocated by wasmtime, even if the engine was
+    /// configured with a custom [`MemoryCreator`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file doesn't fit in `ty`'s minimum size or if
+    /// it couldn't be mapped.
+    ///
+    /// # Safety
+    ///
+    /// The contents of the memory are undefined if `file` is modified or
+    /// truncated by anyone while the memory, or any instance importing it, is
+    /// alive.
+    #[cfg(unix)]
+    pub unsafe fn new_from_file(
+        store: &Store,
+        ty: MemoryType,
+        file: &std::fs::File,
+    ) -> Result<Memory> {
+        let (instance, wasmtime_export) =
+            crate::trampoline::generate_file_memory_export(store, &ty, file)?;
+        Ok(Memory {
+            instance,
+            wasmtime_export,
+        })
     }
 
     /// Returns the underlying type of this memory.
@@ -838,8 +1261,11 @@ impl Memory {
     /// * Data could be modified by calling into a wasm module.
     /// * Memory could be relocated through growth by calling into a wasm
     ///   module.
-    /// * When threads are supported, non-atomic reads will race with other
-    ///   writes.
+    /// * For shared memories, data could be modified at any time by other
+    ///   threads using the same [`SharedMemory`], whether through wasm or
+    ///   through the host. Reading such data through the returned slice while
+    ///   it's modified is a data race, and so undefined behavior, unless the
+    ///   embedder synchronizes with every other thread using the memory.
     ///
     /// Extreme care need be taken when the data of a `Memory` is read. The
     /// above invariants all need to be upheld at a bare minimum, and in
@@ -852,6 +1278,9 @@ impl Memory {
     /// of wasm instances, so calling any wasm code should be considered
     /// dangerous while you're holding a slice of memory.
     ///
+    /// If you only need to copy bytes in or out of memory, prefer the safe
+    /// [`Memory::read`] and [`Memory::write`] methods instead.
+    ///
     /// For more information and examples see the documentation on the
     /// [`Memory`] type.
     pub unsafe fn data_unchecked(&self) -> &[u8] {
@@ -871,6 +1300,10 @@ impl Memory {
     /// and in general you probably want to result to unsafe accessors and the
     /// `data` methods below.
     ///
+    /// For shared memories, other threads may also read or write the memory
+    /// at any time, so writing through the returned slice races with them
+    /// as well unless every thread using the memory is synchronized with.
+    ///
     /// For more information and examples see the documentation on the
     /// [`Memory`] type.
     pub unsafe fn data_unchecked_mut(&self) -> &mut [u8] {
@@ -878,6 +1311,99 @@ impl Memory {
         slice::from_raw_parts_mut(definition.base, definition.current_length)
     }
 
+    /// Safely reads memory contents at the given offset into a buffer.
+    ///
+    /// The entire buffer will be filled. Shared memories can be written by
+    /// other threads concurrently, so they're read one byte at a time with
+    /// atomic loads. The buffer isn't a consistent snapshot of the memory in
+    /// that case.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `offset + buffer.len()` doesn't fit within the
+    /// current size of this memory. Nothing is read in that case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::default();
+    /// let store = Store::new(&engine);
+    /// let module = Module::new(&engine, r#"(module (memory (export "mem") 1) (data (i32.const 4) "abc"))"#)?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// let memory = instance.get_memory("mem").unwrap();
+    ///
+    /// let mut buf = [0; 3];
+    /// memory.read(4, &mut buf)?;
+    /// assert_eq!(&buf, b"abc");
+    /// assert!(memory.read(65535, &mut buf).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<()> {
+        let src = self.checked_ptr(offset, buffer.len())?;
+        if self.wasmtime_export.memory.memory.shared {
+            for (i, byte) in buffer.iter_mut().enumerate() {
+                *byte = unsafe { (*(src.add(i) as *const AtomicU8)).load(Relaxed) };
+            }
+        } else {
+            // Safety: no wasm code can run to modify or grow a non-shared
+            // memory during this copy.
+            unsafe { ptr::copy_nonoverlapping(src, buffer.as_mut_ptr(), buffer.len()) }
+        }
+        Ok(())
+    }
+
+    /// Safely writes contents of a buffer to this memory at the given offset.
+    ///
+    /// Shared memories can be accessed by other threads concurrently, so
+    /// they're written one byte at a time with atomic stores.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `offset + buffer.len()` doesn't fit within the
+    /// current size of this memory. Nothing is written in that case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::default();
+    /// let store = Store::new(&engine);
+    /// let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)))?;
+    ///
+    /// memory.write(0x100, b"hello")?;
+    /// let mut buf = [0; 5];
+    /// memory.read(0x100, &mut buf)?;
+    /// assert_eq!(&buf, b"hello");
+    /// assert!(memory.write(memory.data_size() - 1, b"hello").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write(&self, offset: usize, buffer: &[u8]) -> Result<()> {
+        let dst = self.checked_ptr(offset, buffer.len())?;
+        if self.wasmtime_export.memory.memory.shared {
+            for (i, byte) in buffer.iter().enumerate() {
+                unsafe { (*(dst.add(i) as *const AtomicU8)).store(*byte, Relaxed) }
+            }
+        } else {
+            // Safety: see `Memory::read` above.
+            unsafe { ptr::copy_nonoverlapping(buffer.as_ptr(), dst, buffer.len()) }
+        }
+        Ok(())
+    }
+
+    /// Returns a pointer to the `len` bytes at `offset` in this memory, which
+    /// must be in bounds.
+    fn checked_ptr(&self, offset: usize, len: usize) -> Result<*mut u8> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.data_size() => Ok(unsafe { self.data_ptr().add(offset) }),
+            _ => bail!("out of bounds memory access"),
+        }
+    }
+
     /// Returns the base pointer, in the host's address space, that the memory
     /// is located at.
     ///
@@ -898,6 +1424,13 @@ impl Memory {
     /// For more information and examples see the documentation on the
     /// [`Memory`] type.
     pub fn data_size(&self) -> usize {
+        if self.wasmtime_export.memory.memory.shared {
+            // Shared memories can be grown through other stores, which doesn't
+            // update the definition this memory refers to until it's used.
+            let owner = self.owner();
+            let index = owner.memory_index(unsafe { &*self.wasmtime_export.definition });
+            return owner.memory_size(index) as usize * wasmtime_environ::WASM_PAGE_SIZE as usize;
+        }
         unsafe { (*self.wasmtime_export.definition).current_length }
     }
 
@@ -950,6 +1483,177 @@ impl Memory {
             .ok_or_else(|| anyhow!("failed to grow memory"))
     }
 
+    /// Changes the page protection of the bytes in `range` of this memory to
+    /// `protection`.
+    ///
+    /// Wasm loads and stores which aren't permitted by the new protection
+    /// raise an out-of-bounds memory access trap, which can be caught like any
+    /// other trap. This is intended for embeddings such as debuggers which
+    /// need to observe or restrict guest accesses to parts of memory.
+    ///
+    /// Protection is applied at the granularity of host pages, so both ends of
+    /// `range` must be multiples of the host page size. Protections persist
+    /// until they're changed again, including across [`Memory::grow`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `range` is out of bounds or not page-aligned, if
+    /// the engine uses
+    /// [`Config::explicit_bounds_checks`](crate::Config::explicit_bounds_checks),
+    /// or if this memory may be relocated when it grows. Only statically-allocated
+    /// memories, as determined by
+    /// [`Config::static_memory_maximum_size`](crate::Config::static_memory_maximum_size),
+    /// can be protected, since relocation would copy out of, and lose, the
+    /// protected pages.
+    ///
+    /// # Safety
+    ///
+    /// Only accesses from compiled wasm code are turned into traps. Any other
+    /// access to a page made inaccessible or read-only, including through
+    /// [`Memory::read`], [`Memory::write`], [`Memory::data_unchecked`] or
+    /// bulk memory instructions like `memory.copy` which are implemented by
+    /// the host, will crash the process. The caller must ensure no such
+    /// accesses happen while the protection is in effect.
+    pub unsafe fn protect(&self, range: Range<usize>, protection: MemoryProtection) -> Result<()> {
+        self.instance
+            .store
+            .engine()
+            .config()
+            .check_memory_faults_allowed("`Memory::protect`")?;
+        if let wasmtime_environ::MemoryStyle::Dynamic = self.wasmtime_export.memory.style {
+            bail!("only memories which are never relocated can be protected");
+        }
+        if range.start > range.end || range.end > self.data_size() {
+            bail!("out of bounds memory protection range {:?}", range);
+        }
+        let page_size = region::page::size();
+        if range.start % page_size != 0 || range.end % page_size != 0 {
+            bail!(
+                "memory protection range {:?} is not aligned to the host page size of {} bytes",
+                range,
+                page_size
+            );
+        }
+        if range.start == range.end {
+            return Ok(());
+        }
+
+        let protection = match protection {
+            MemoryProtection::ReadWrite => region::Protection::READ_WRITE,
+            MemoryProtection::ReadOnly => region::Protection::READ,
+            MemoryProtection::NoAccess => region::Protection::NONE,
+        };
+        region::protect(
+            self.data_ptr().add(range.start),
+            range.end - range.start,
+            protection,
+        )
+        .map_err(|e| anyhow!("failed to change memory protection: {}", e))
+    }
+
+    /// Wakes up at most `count` threads waiting on the address `addr` of this
+    /// memory, returning the number of threads which were woken up.
+    ///
+    /// This is the host equivalent of the `memory.atomic.notify` instruction
+    /// from the threads proposal. Since no thread can wait on a non-shared
+    /// memory this always returns 0 for non-shared memories.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `addr` is not 4-byte aligned or is out of bounds.
+    pub fn atomic_notify(&self, addr: usize, count: u32) -> Result<u32> {
+        let ptr = self.atomic_addr(addr, 4)?;
+        if !self.wasmtime_export.memory.memory.shared {
+            return Ok(0);
+        }
+        Ok(runtime::unpark(ptr as usize, count))
+    }
+
+    /// Blocks the current thread until it is woken up by
+    /// [`Memory::atomic_notify`] on `addr`, as long as the 32-bit value at
+    /// `addr` is equal to `expected`.
+    ///
+    /// This is the host equivalent of the `memory.atomic.wait32` instruction
+    /// from the threads proposal. If `timeout` is provided then the thread
+    /// will stop waiting once the timeout expires.
+    ///
+    /// Only memories created with [`Memory::new_with_shared`] can be waited
+    /// on, since no other thread could ever wake up a thread waiting on any
+    /// other memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this memory wasn't created from a [`SharedMemory`],
+    /// or if `addr` is not 4-byte aligned or is out of bounds.
+    pub fn atomic_wait32(
+        &self,
+        addr: usize,
+        expected: u32,
+        timeout: Option<Duration>,
+    ) -> Result<WaitResult> {
+        self.check_shared_for_wait()?;
+        let ptr = self.atomic_addr(addr, 4)? as *const AtomicU32;
+        let validate = || unsafe { (*ptr).load(SeqCst) == expected };
+        Ok(runtime::park(ptr as usize, validate, timeout))
+    }
+
+    /// Same as [`Memory::atomic_wait32`], except that it compares the 64-bit
+    /// value at `addr`, which must be 8-byte aligned.
+    ///
+    /// This is the host equivalent of the `memory.atomic.wait64` instruction
+    /// from the threads proposal.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this memory wasn't created from a [`SharedMemory`],
+    /// or if `addr` is not 8-byte aligned or is out of bounds.
+    pub fn atomic_wait64(
+        &self,
+        addr: usize,
+        expected: u64,
+        timeout: Option<Duration>,
+    ) -> Result<WaitResult> {
+        self.check_shared_for_wait()?;
+        let ptr = self.atomic_addr(addr, 8)? as *const AtomicU64;
+        let validate = || unsafe { (*ptr).load(SeqCst) == expected };
+        Ok(runtime::park(ptr as usize, validate, timeout))
+    }
+
+    fn check_shared_for_wait(&self) -> Result<()> {
+        // Only memories created from a `SharedMemory` can be reached from
+        // another thread, so waiting on any other memory would never end.
+        if !self.owner().host_state().is::<SharedMemory>() {
+            bail!("atomic wait is only supported on memories created from a `SharedMemory`");
+        }
+        Ok(())
+    }
+
+    /// Returns the instance which defines this memory, which is a different
+    /// one than `self.instance` if this memory was imported.
+    fn owner(&self) -> InstanceHandle {
+        unsafe { InstanceHandle::from_vmctx(self.wasmtime_export.vmctx) }
+    }
+
+    fn atomic_addr(&self, addr: usize, size: usize) -> Result<*mut u8> {
+        if addr % size != 0 {
+            bail!("unaligned atomic memory access");
+        }
+        match addr.checked_add(size) {
+            Some(end) if end <= self.data_size() => {}
+            _ => bail!("out of bounds memory access"),
+        }
+        Ok(unsafe { self.data_ptr().add(addr) })
+    }
+
+    /// Creates a [`WeakMemory`] referring to this memory without keeping its
+    /// [`Store`] alive.
+    pub fn downgrade(&self) -> WeakMemory {
+        WeakMemory {
+            instance: self.instance.downgrade(),
+            wasmtime_export: self.wasmtime_export.clone(),
+        }
+    }
+
     pub(crate) fn from_wasmtime_memory(
         wasmtime_export: wasmtime_runtime::ExportMemory,
         instance: StoreInstanceHandle,
@@ -964,7 +1668,7 @@ impl Memory {
         let expected = &ty.memory;
         let actual = &self.wasmtime_export.memory.memory;
         expected.shared == actual.shared
-            && expected.minimum <= actual.minimum
+            && expected.minimum <= self.size()
             && match expected.maximum {
                 Some(expected) => match actual.maximum {
                     Some(actual) => expected >= actual,
@@ -982,6 +1686,135 @@ impl Memory {
     }
 }
 
+/// A reference to a [`Memory`] which doesn't keep its [`Store`] alive, created
+/// with [`Memory::downgrade`]. See [`WeakFunc`](crate::WeakFunc) for more
+/// information.
+#[derive(Clone)]
+pub struct WeakMemory {
+    instance: WeakStoreInstanceHandle,
+    wasmtime_export: wasmtime_runtime::ExportMemory,
+}
+
+impl WeakMemory {
+    /// Returns the [`Memory`] this refers to, or `None` if its [`Store`] has
+    /// been dropped.
+    pub fn upgrade(&self) -> Option<Memory> {
+        Some(Memory {
+            instance: self.instance.upgrade()?,
+            wasmtime_export: self.wasmtime_export.clone(),
+        })
+    }
+}
+
+/// A shared WebAssembly linear memory which can be used from several
+/// [`Store`]s, and so from several threads, at once.
+///
+/// Unlike [`Memory`], a `SharedMemory` is `Send` and `Sync`. Each thread
+/// running wasm which uses it creates its own [`Memory`] from it with
+/// [`Memory::new_with_shared`], in its own store, and imports that into its
+/// instances. All of these memories have the same contents, and threads
+/// blocked in [`Memory::atomic_wait32`] or [`Memory::atomic_wait64`] on one of
+/// them, or in the `memory.atomic.wait32` and `memory.atomic.wait64`
+/// instructions, are woken up by [`Memory::atomic_notify`] or
+/// `memory.atomic.notify` on any of them, just like threads spawned by wasm
+/// compiled with LLVM's `-pthread` expect.
+///
+/// # Examples
+///
+/// ```
+/// # use wasmtime::*;
+/// # fn main() -> anyhow::Result<()> {
+/// let engine = Engine::default();
+/// let shared = SharedMemory::new(&engine, MemoryType::shared(1, 2))?;
+///
+/// let thread = std::thread::spawn({
+///     let shared = shared.clone();
+///     move || -> anyhow::Result<()> {
+///         let store = Store::new(shared.engine());
+///         let memory = Memory::new_with_shared(&store, &shared)?;
+///         memory.write(0, b"hello")?;
+///         Ok(())
+///     }
+/// });
+/// thread.join().unwrap()?;
+///
+/// let store = Store::new(&engine);
+/// let memory = Memory::new_with_shared(&store, &shared)?;
+/// let mut buf = [0; 5];
+/// memory.read(0, &mut buf)?;
+/// assert_eq!(&buf, b"hello");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SharedMemory {
+    engine: Engine,
+    ty: MemoryType,
+    memory: Arc<SharedLinearMemory>,
+}
+
+impl SharedMemory {
+    /// Creates a new shared memory of type `ty`, which can be used in any
+    /// store of `engine`. All of its contents are initialized to zero.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `ty` is not shared, if the memory couldn't be
+    /// allocated, or if `engine` is configured in a way which doesn't allow
+    /// memories to be allocated up front to their maximum size, such as with
+    /// [`Config::explicit_bounds_checks`](crate::Config::explicit_bounds_checks).
+    pub fn new(engine: &Engine, ty: MemoryType) -> Result<SharedMemory> {
+        if !ty.is_shared() {
+            bail!("shared memories must have a shared memory type");
+        }
+        engine
+            .config()
+            .check_memory_faults_allowed("shared memories")?;
+        let memory = wasm::Memory {
+            minimum: ty.limits().min(),
+            maximum: ty.limits().max(),
+            shared: true,
+        };
+        let plan = wasmtime_environ::MemoryPlan::for_memory(memory, &engine.config().tunables);
+        Ok(SharedMemory {
+            engine: engine.clone(),
+            ty,
+            memory: Arc::new(SharedLinearMemory::new(&plan)?),
+        })
+    }
+
+    /// Returns the type of this memory.
+    pub fn ty(&self) -> &MemoryType {
+        &self.ty
+    }
+
+    /// Returns the engine this memory can be used with.
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// Returns the current size, in pages, of this memory.
+    pub fn size(&self) -> u32 {
+        self.memory.size()
+    }
+
+    pub(crate) fn linear_memory(&self) -> &Arc<SharedLinearMemory> {
+        &self.memory
+    }
+}
+
+/// The page protections which can be applied to a region of a [`Memory`]
+/// with [`Memory::protect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryProtection {
+    /// Wasm can both read and write the region. This is the default.
+    ReadWrite,
+    /// Wasm can read the region, but writes trap.
+    ReadOnly,
+    /// Both reads and writes to the region trap.
+    NoAccess,
+}
+
 /// A linear memory. This trait provides an interface for raw memory buffers which are used
 /// by wasmtime, e.g. inside ['Memory']. Such buffers are in principle not thread safe.
 /// By implementing this trait together with MemoryCreator,
@@ -1067,7 +1900,7 @@ mod tests {
             .dynamic_memory_guard_size(0);
         let store = Store::new(&Engine::new(&cfg));
         let ty = MemoryType::new(Limits::new(1, None));
-        let mem = Memory::new(&store, ty);
+        let mem = Memory::new(&store, ty).unwrap();
         assert_eq!(mem.wasmtime_export.memory.offset_guard_size, 0);
         match mem.wasmtime_export.memory.style {
             wasmtime_environ::MemoryStyle::Dynamic => {}
@@ -1,5 +1,6 @@
 use std::cmp;
 use std::collections::BTreeMap;
+use std::fmt;
 use std::sync::{Arc, RwLock};
 use wasmtime_environ::entity::EntityRef;
 use wasmtime_environ::ir;
@@ -103,6 +104,7 @@ impl GlobalFrameInfo {
             None => func.instr_map.start_srcloc,
         };
         Some(FrameInfo {
+            module: module.module.clone(),
             module_name: module.module.name.clone(),
             func_index: func.index.index() as u32,
             func_name: module.module.func_names.get(&func.index).cloned(),
@@ -203,8 +205,8 @@ pub fn register(module: &CompiledModule) -> Option<GlobalFrameInfoRegistration>
 /// each frame is described by this structure.
 ///
 /// [`Trap`]: crate::Trap
-#[derive(Debug)]
 pub struct FrameInfo {
+    module: Arc<Module>,
     module_name: Option<String>,
     func_index: u32,
     func_name: Option<String>,
@@ -212,6 +214,18 @@ pub struct FrameInfo {
     instr: ir::SourceLoc,
 }
 
+impl fmt::Debug for FrameInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameInfo")
+            .field("module_name", &self.module_name)
+            .field("func_index", &self.func_index)
+            .field("func_name", &self.func_name)
+            .field("func_start", &self.func_start)
+            .field("instr", &self.instr)
+            .finish()
+    }
+}
+
 impl FrameInfo {
     /// Returns the WebAssembly function index for this frame.
     ///
@@ -270,4 +284,26 @@ impl FrameInfo {
     pub fn func_offset(&self) -> usize {
         (self.instr.bits() - self.func_start.bits()) as usize
     }
+
+    /// Returns the location in the guest's source code of the instruction
+    /// this frame points to, as its file, line and column, if known.
+    ///
+    /// Source locations are read from the DWARF of the wasm module, so this
+    /// returns `None` unless the module has DWARF and was compiled with
+    /// [`Config::debug_info`] enabled. The column is 0 if only the line is
+    /// known.
+    ///
+    /// [`Config::debug_info`]: crate::Config::debug_info
+    pub fn source_location(&self) -> Option<(&str, u32, u32)> {
+        if self.instr.is_default() {
+            return None;
+        }
+        self.module.line_table.lookup(self.instr.bits())
+    }
+
+    /// Returns the module this frame's function belongs to, which is the same
+    /// one its instances were created from.
+    pub(crate) fn module(&self) -> &Arc<Module> {
+        &self.module
+    }
 }
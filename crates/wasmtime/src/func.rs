@@ -1,19 +1,24 @@
 use crate::runtime::StoreInner;
-use crate::trampoline::StoreInstanceHandle;
-use crate::{Extern, ExternRef, FuncType, Memory, Store, Trap, Val, ValType};
+use crate::trampoline::{StoreInstanceHandle, WeakStoreInstanceHandle};
+use crate::{CallHook, Extern, ExternRef, FuncType, Store, Trap, Val, ValType};
 use anyhow::{bail, ensure, Context as _, Result};
 use smallvec::{smallvec, SmallVec};
+use std::cell::RefCell;
 use std::cmp::max;
 use std::fmt;
 use std::mem;
 use std::panic::{self, AssertUnwindSafe};
 use std::ptr::{self, NonNull};
 use std::rc::Weak;
+use wasmtime_environ::wasm;
 use wasmtime_runtime::{
-    raise_user_trap, Export, InstanceHandle, VMContext, VMFunctionBody, VMSharedSignatureIndex,
+    raise_user_trap, InstanceHandle, VMContext, VMFunctionBody, VMSharedSignatureIndex,
     VMTrampoline,
 };
 
+mod typed;
+pub use typed::*;
+
 /// A WebAssembly function which can be called.
 ///
 /// This type can represent a number of callable items, such as:
@@ -284,14 +289,17 @@ impl Func {
             let mut returns: SmallVec<[Val; STACK_RETURNS]> =
                 smallvec![Val::null(); ty_clone.results().len()];
 
-            func(
+            store.invoke_call_hook(CallHook::CallingHost)?;
+            let result = func(
                 Caller {
                     store: &store_weak,
                     caller_vmctx,
                 },
                 &args,
                 &mut returns,
-            )?;
+            );
+            store.invoke_call_hook(CallHook::ReturningFromHost)?;
+            result?;
 
             // Unlike our arguments we need to dynamically check that the return
             // values produced are correct. There could be a bug in `func` that
@@ -299,9 +307,14 @@ impl Func {
             // to catch that here.
             for (i, (ret, ty)) in returns.into_iter().zip(ty_clone.results()).enumerate() {
                 if ret.ty() != *ty {
-                    return Err(Trap::new(
-                        "function attempted to return an incompatible value",
-                    ));
+                    return Err(Trap::new(format!(
+                        "function attempted to return an incompatible value: \
+                         found {} but expected {} for result {} of `{}`",
+                        ret.ty(),
+                        ty,
+                        i,
+                        ty_clone,
+                    )));
                 }
                 unsafe {
                     ret.write_value_to(&store, values_vec.add(i));
@@ -360,16 +373,17 @@ impl Func {
     /// |-------------------|-------------------------|-------------------|
     /// | `()`              | nothing                 | no return value   |
     /// | `Result<T, Trap>` | `T`                     | function may trap |
+    /// | `(T1, T2, ...)`   | `T1 T2 ...`             | multiple returns  |
     ///
-    /// At this time multi-value returns are not supported, and supporting this
-    /// is the subject of [#1178].
-    ///
-    /// [#1178]: https://github.com/bytecodealliance/wasmtime/issues/1178
+    /// Tuples of return types, also wrapped in `Result<_, Trap>`, return
+    /// multiple values. Note that these functions are called through an
+    /// intermediate stub, much like functions created with [`Func::new`], so
+    /// they are somewhat slower to call than functions with a single result.
     ///
     /// Finally you can also optionally take [`Caller`] as the first argument of
     /// your closure. If inserted then you're able to inspect the caller's
-    /// state, for example the [`Memory`] it has exported so you can read what
-    /// pointers point to.
+    /// state, for example the [`Memory`](crate::Memory) it has exported so you
+    /// can read what pointers point to.
     ///
     /// Note that when using this API, the intention is to create as thin of a
     /// layer as possible for when WebAssembly calls the function provided. With
@@ -398,8 +412,8 @@ impl Func {
     ///     "#,
     /// )?;
     /// let instance = Instance::new(&store, &module, &[add.into()])?;
-    /// let foo = instance.get_func("foo").unwrap().get2::<i32, i32, i32>()?;
-    /// assert_eq!(foo(1, 2)?, 3);
+    /// let foo = instance.get_func("foo").unwrap().typed::<(i32, i32), i32>()?;
+    /// assert_eq!(foo.call((1, 2))?, 3);
     /// # Ok(())
     /// # }
     /// ```
@@ -429,9 +443,9 @@ impl Func {
     ///     "#,
     /// )?;
     /// let instance = Instance::new(&store, &module, &[add.into()])?;
-    /// let foo = instance.get_func("foo").unwrap().get2::<i32, i32, i32>()?;
-    /// assert_eq!(foo(1, 2)?, 3);
-    /// assert!(foo(i32::max_value(), 1).is_err());
+    /// let foo = instance.get_func("foo").unwrap().typed::<(i32, i32), i32>()?;
+    /// assert_eq!(foo.call((1, 2))?, 3);
+    /// assert!(foo.call((i32::max_value(), 1)).is_err());
     /// # Ok(())
     /// # }
     /// ```
@@ -467,8 +481,8 @@ impl Func {
     ///     "#,
     /// )?;
     /// let instance = Instance::new(&store, &module, &[debug.into()])?;
-    /// let foo = instance.get_func("foo").unwrap().get0::<()>()?;
-    /// foo()?;
+    /// let foo = instance.get_func("foo").unwrap().typed::<(), ()>()?;
+    /// foo.call(())?;
     /// # Ok(())
     /// # }
     /// ```
@@ -523,8 +537,8 @@ impl Func {
     ///     "#,
     /// )?;
     /// let instance = Instance::new(&store, &module, &[log_str.into()])?;
-    /// let foo = instance.get_func("foo").unwrap().get0::<()>()?;
-    /// foo()?;
+    /// let foo = instance.get_func("foo").unwrap().typed::<(), ()>()?;
+    /// foo.call(())?;
     /// # Ok(())
     /// # }
     /// ```
@@ -550,20 +564,16 @@ impl Func {
 
     /// Returns the number of parameters that this function takes.
     pub fn param_arity(&self) -> usize {
-        let sig = self
-            .instance
+        self.instance
             .store
-            .lookup_signature(unsafe { self.export.anyfunc.as_ref().type_index });
-        sig.params.len()
+            .with_signature(self.sig_index(), |sig| sig.params.len())
     }
 
     /// Returns the number of results this function produces.
     pub fn result_arity(&self) -> usize {
-        let sig = self
-            .instance
+        self.instance
             .store
-            .lookup_signature(unsafe { self.export.anyfunc.as_ref().type_index });
-        sig.returns.len()
+            .with_signature(self.sig_index(), |sig| sig.returns.len())
     }
 
     /// Invokes this function with the `params` given, returning the results and
@@ -576,63 +586,216 @@ impl Func {
     /// This function should not panic unless the underlying function itself
     /// initiates a panic.
     pub fn call(&self, params: &[Val]) -> Result<Box<[Val]>> {
+        let mut results = vec![Val::null(); self.result_arity()];
+        self.call_into(params, &mut results)?;
+        Ok(results.into())
+    }
+
+    /// Invokes this function with the `params` given, writing the results of
+    /// the call into `results`.
+    ///
+    /// This behaves the same as [`Func::call`], except that the caller
+    /// provides the storage for the results. Functions with a small number of
+    /// parameters and results are called without any heap allocation, so this
+    /// is suitable for calling into wasm from hot paths where the signature
+    /// isn't statically known (otherwise see [`Func::typed`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `params` doesn't match this function's parameters,
+    /// if `results` doesn't have exactly as many slots as this function has
+    /// results, or if a trap happens while executing the function.
+    pub fn call_into(&self, params: &[Val], results: &mut [Val]) -> Result<()> {
+        const STACK_VALUES: usize = 8;
+        let store = &self.instance.store;
+
         // We need to perform a dynamic check that the arguments given to us
         // match the signature of this function and are appropriate to pass to
         // this function. This involves checking to make sure we have the right
         // number and types of arguments as well as making sure everything is
         // from the same `Store`.
-        let my_ty = self.ty();
-        if my_ty.params().len() != params.len() {
-            bail!(
-                "expected {} arguments, got {}",
-                my_ty.params().len(),
-                params.len()
-            );
-        }
-
-        let mut values_vec = vec![0; max(params.len(), my_ty.results().len())];
-
-        // Store the argument values into `values_vec`.
-        let param_tys = my_ty.params().iter();
-        for ((arg, slot), ty) in params.iter().cloned().zip(&mut values_vec).zip(param_tys) {
-            if arg.ty() != *ty {
+        let result_tys = store.with_signature(self.sig_index(), |sig| {
+            if sig.params.len() != params.len() {
+                bail!(
+                    "expected {} arguments, got {} ({})",
+                    sig.params.len(),
+                    params.len(),
+                    describe_mismatch(sig, params),
+                );
+            }
+            if sig.returns.len() != results.len() {
                 bail!(
-                    "argument type mismatch: found {} but expected {}",
-                    arg.ty(),
-                    ty
+                    "expected {} results, got {} ({})",
+                    sig.returns.len(),
+                    results.len(),
+                    describe_results_mismatch(sig, results.len()),
                 );
             }
-            if !arg.comes_from_same_store(&self.instance.store) {
+            for (arg, ty) in params.iter().zip(sig.params.iter()) {
+                let ty = ValType::from_wasm_type(ty);
+                if arg.ty() != ty {
+                    bail!(
+                        "argument type mismatch: found {} but expected {} ({})",
+                        arg.ty(),
+                        ty,
+                        describe_mismatch(sig, params),
+                    );
+                }
+            }
+            Ok(sig
+                .returns
+                .iter()
+                .map(ValType::from_wasm_type)
+                .collect::<SmallVec<[ValType; STACK_VALUES]>>())
+        })?;
+
+        let mut values_vec: SmallVec<[u128; STACK_VALUES]> =
+            smallvec![0; max(params.len(), results.len())];
+
+        // Store the argument values into `values_vec`.
+        for (arg, slot) in params.iter().cloned().zip(&mut values_vec) {
+            if !arg.comes_from_same_store(store) {
                 bail!("cross-`Store` values are not currently supported");
             }
             unsafe {
-                arg.write_value_to(&self.instance.store, slot);
+                arg.write_value_to(store, slot);
             }
         }
 
-        // Call the trampoline.
+        // Call the trampoline. The arguments were type-checked above, so this
+        // is safe.
         unsafe {
-            let anyfunc = self.export.anyfunc.as_ref();
-            invoke_wasm_and_catch_traps(anyfunc.vmctx, &self.instance.store, || {
-                (self.trampoline)(
-                    anyfunc.vmctx,
-                    ptr::null_mut(),
-                    anyfunc.func_ptr.as_ptr(),
-                    values_vec.as_mut_ptr(),
-                )
-            })?;
+            self.call_unchecked(&mut values_vec)?;
         }
 
         // Load the return values out of `values_vec`.
-        let mut results = Vec::with_capacity(my_ty.results().len());
-        for (index, ty) in my_ty.results().iter().enumerate() {
+        for (index, (slot, ty)) in results.iter_mut().zip(&result_tys).enumerate() {
             unsafe {
                 let ptr = values_vec.as_ptr().add(index);
-                results.push(Val::read_value_from(&self.instance.store, ptr, ty));
+                *slot = Val::read_value_from(store, ptr, ty);
             }
         }
 
-        Ok(results.into())
+        Ok(())
+    }
+
+    /// Invokes this function with a raw buffer of arguments and results,
+    /// without performing any type-checking or conversions.
+    ///
+    /// The `values_vec` buffer maps directly onto the trampoline ABI used to
+    /// call wasm functions: on entry the `i`th element holds the `i`th
+    /// argument, and when the call returns successfully the `i`th element
+    /// holds the `i`th result. Each value occupies one `u128` slot and is
+    /// stored in its native representation in the low bytes of the slot, the
+    /// same way [`Val`]s are laid out when passed to [`Func::call`].
+    ///
+    /// This is intended for embedders which already validate the types of
+    /// arguments themselves, such as bridges from dynamically typed languages,
+    /// and for which converting to and from [`Val`] is pure overhead.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Trap`] if one happens while executing the function.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because nothing is checked about `values_vec`.
+    /// Callers must ensure that:
+    ///
+    /// * `values_vec` is at least as long as the larger of this function's
+    ///   parameter count and result count.
+    /// * Each argument has the type this function expects in that position.
+    /// * Any `funcref` arguments are null or valid pointers coming from this
+    ///   function's [`Store`].
+    /// * Any `externref` arguments are null or pointers returned by
+    ///   [`ExternRef::to_raw`] for this function's [`Store`], which roots them
+    ///   in the store the way [`Func::call`] does with its arguments. Garbage
+    ///   collections happening during the call only know about references
+    ///   rooted in the store or on the wasm stack, so any other pointer may be
+    ///   freed while the callee is still using it.
+    ///
+    /// Likewise, `externref` results are only rooted by the store, so they
+    /// must be converted with [`ExternRef::from_raw`] before the [`Store`]'s
+    /// next garbage collection if they're to be used after it.
+    ///
+    /// Violating any of these is undefined behavior. If in doubt, use
+    /// [`Func::call`] or [`Func::typed`] instead.
+    pub unsafe fn call_unchecked(&self, values_vec: &mut [u128]) -> Result<(), Trap> {
+        let anyfunc = self.export.anyfunc.as_ref();
+        let values_vec = values_vec.as_mut_ptr();
+        invoke_wasm_and_catch_traps(anyfunc.vmctx, &self.instance.store, || {
+            (self.trampoline)(
+                anyfunc.vmctx,
+                ptr::null_mut(),
+                anyfunc.func_ptr.as_ptr(),
+                values_vec,
+            )
+        })
+    }
+
+    /// Attempts to extract a typed object from this `Func` through which the
+    /// function can be called.
+    ///
+    /// This function serves as an alternative to [`Func::call`] when the type
+    /// signature of a function is statically known to the program. Calls made
+    /// through the returned [`TypedFunc`] are faster than `call` because:
+    ///
+    /// * Runtime type-checking only happens once, when this method is called.
+    /// * Arguments and results are native Rust values and are never boxed into
+    ///   a [`Val`] or a vector.
+    ///
+    /// The `Params` type parameter is either a single type or a tuple of types
+    /// for the function's parameters, and similarly `Results` is `()`, a single
+    /// type, or a tuple of types for its results. For more information about
+    /// which Rust types match up to which wasm types, see the documentation on
+    /// [`Func::wrap`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `Params` or `Results` does not
+    /// match the native type of this WebAssembly function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let module = Module::new(
+    ///     store.engine(),
+    ///     r#"
+    ///         (module
+    ///             (func (export "add") (param i32 i64) (result i64)
+    ///                 local.get 1
+    ///                 local.get 0
+    ///                 i64.extend_i32_s
+    ///                 i64.add))
+    ///     "#,
+    /// )?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// let add = instance
+    ///     .get_func("add")
+    ///     .expect("export wasn't a function")
+    ///     .typed::<(i32, i64), i64>()?;
+    /// assert_eq!(add.call((1, 2))?, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn typed<Params, Results>(&self) -> Result<TypedFunc<Params, Results>>
+    where
+        Params: WasmParams,
+        Results: WasmResults,
+    {
+        // First type-check that the params/results are all valid...
+        let ty = self.ty();
+        Params::typecheck(ty.params().iter().cloned())
+            .with_context(|| format!("type mismatch with parameters of `{}`", ty))?;
+        Results::typecheck(ty.results().iter().cloned())
+            .with_context(|| format!("type mismatch with results of `{}`", ty))?;
+
+        // ... then we can construct the typed version of this function
+        // (unsafely), which should be safe since we just did the type check above.
+        unsafe { Ok(TypedFunc::new_unchecked(self.clone())) }
     }
 
     pub(crate) fn caller_checked_anyfunc(
@@ -786,6 +949,16 @@ impl Func {
         &self.instance.store
     }
 
+    /// Creates a [`WeakFunc`] referring to this function without keeping its
+    /// [`Store`] alive.
+    pub fn downgrade(&self) -> WeakFunc {
+        WeakFunc {
+            instance: self.instance.downgrade(),
+            trampoline: self.trampoline,
+            export: self.export.clone(),
+        }
+    }
+
     pub(crate) fn matches_expected(&self, expected: VMSharedSignatureIndex) -> bool {
         self.sig_index() == expected
     }
@@ -801,19 +974,105 @@ impl Func {
     }
 }
 
+/// Describes the expected signature versus the provided arguments, for use in
+/// error messages when `Func::call` is given the wrong arguments.
+#[cold]
+fn describe_mismatch(sig: &wasm::WasmFuncType, params: &[Val]) -> String {
+    let provided = params
+        .iter()
+        .map(|p| p.ty().to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "function type is `{}` but was called with `({})`",
+        FuncType::from_wasm_func_type(sig),
+        provided
+    )
+}
+
+/// Describes the expected signature versus the number of result slots
+/// provided, for use in error messages when `Func::call_into` is given the
+/// wrong number of them.
+#[cold]
+fn describe_results_mismatch(sig: &wasm::WasmFuncType, results: usize) -> String {
+    format!(
+        "function type is `{}` but room for {} results was provided",
+        FuncType::from_wasm_func_type(sig),
+        results
+    )
+}
+
 impl fmt::Debug for Func {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Func")
     }
 }
 
+/// A reference to a [`Func`] which doesn't keep its [`Store`] alive, created
+/// with [`Func::downgrade`].
+///
+/// A `Func` keeps all of the instances of its store alive, so host state
+/// caching functions may leak stores which are otherwise unused. Caching a
+/// `WeakFunc` instead lets the store be dropped, after which the `WeakFunc`
+/// can no longer be upgraded. The same goes for a store which has been
+/// [reset](Store::reset).
+#[derive(Clone)]
+pub struct WeakFunc {
+    instance: WeakStoreInstanceHandle,
+    trampoline: VMTrampoline,
+    export: wasmtime_runtime::ExportFunction,
+}
+
+impl WeakFunc {
+    /// Returns the [`Func`] this refers to, or `None` if its [`Store`] has
+    /// been dropped or reset.
+    pub fn upgrade(&self) -> Option<Func> {
+        Some(Func {
+            instance: self.instance.upgrade()?,
+            trampoline: self.trampoline,
+            export: self.export.clone(),
+        })
+    }
+}
+
+impl fmt::Debug for WeakFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WeakFunc")
+    }
+}
+
+thread_local! {
+    /// The `VMContext`s of the functions called by each call from the host
+    /// into wasm which is on this thread's stack, innermost last.
+    static WASM_ENTRIES: RefCell<Vec<*mut VMContext>> = RefCell::new(Vec::new());
+}
+
+/// Returns the `VMContext`s of the functions called by each call from the host
+/// into wasm which is on this thread's stack, innermost last.
+pub(crate) fn wasm_entries() -> Vec<*mut VMContext> {
+    WASM_ENTRIES.with(|entries| entries.borrow().clone())
+}
+
+/// Calls `closure`, which is expected to call the function whose `VMContext`
+/// is `vmctx`, catching the traps raised by wasm.
 pub(crate) fn invoke_wasm_and_catch_traps(
     vmctx: *mut VMContext,
     store: &Store,
     closure: impl FnMut(),
 ) -> Result<(), Trap> {
+    store.invoke_call_hook(CallHook::CallingWasm)?;
     let signalhandler = store.signal_handler();
-    unsafe {
+
+    struct PopEntry;
+    impl Drop for PopEntry {
+        fn drop(&mut self) {
+            WASM_ENTRIES.with(|entries| entries.borrow_mut().pop());
+        }
+    }
+    WASM_ENTRIES.with(|entries| entries.borrow_mut().push(vmctx));
+    let _pop_entry = PopEntry;
+
+    let result = unsafe {
         let canary = 0;
         let _auto_reset_canary = store
             .externref_activations_table()
@@ -822,12 +1081,24 @@ pub(crate) fn invoke_wasm_and_catch_traps(
         wasmtime_runtime::catch_traps(
             vmctx,
             store.engine().config().max_wasm_stack,
+            store.engine().config().wasm_backtrace,
             |addr| store.is_in_jit_code(addr),
             signalhandler.as_deref(),
+            &|deadline| store.deadline_reached(deadline),
             closure,
         )
         .map_err(Trap::from_runtime)
-    }
+    };
+    let result = result.map_err(|mut trap| {
+        trap.resolve_memory_access(store);
+        if store.engine().config().coredump_on_trap {
+            trap.capture_coredump(store);
+        }
+        store.invoke_trap_hook(&trap);
+        trap
+    });
+    store.invoke_call_hook(CallHook::ReturningFromWasm)?;
+    result
 }
 
 // Public (but hidden) wrapper around a `Weak<StoreInner>` so that we can use it
@@ -929,6 +1200,23 @@ pub unsafe trait WasmRet {
     // Same as `WasmTy::store_to_args`.
     #[doc(hidden)]
     unsafe fn store_to_args(abi: Self::Abi, ptr: *mut u128);
+
+    // Whether this type is returned from host functions through a values
+    // vector, as for functions created with `Func::new`, instead of natively
+    // as `Self::Abi`. This is the case for multiple return values, which have
+    // no native representation that matches what Cranelift expects.
+    #[doc(hidden)]
+    fn returns_via_values_vec() -> bool;
+
+    // Stores this return value into the values vector `ptr`. Unlike
+    // `into_abi_for_ret` this never raises a trap itself, but instead returns
+    // it so it can be raised once the host function has returned.
+    #[doc(hidden)]
+    unsafe fn store_to_values_vec<'a>(
+        self,
+        store: WeakStore<'a>,
+        ptr: *mut u128,
+    ) -> Result<(), Trap>;
 }
 
 unsafe impl WasmTy for () {
@@ -1365,6 +1653,19 @@ where
     unsafe fn store_to_args(abi: Self::Abi, ptr: *mut u128) {
         <Self as WasmTy>::store_to_args(abi, ptr)
     }
+
+    fn returns_via_values_vec() -> bool {
+        false
+    }
+
+    unsafe fn store_to_values_vec<'a>(
+        self,
+        store: WeakStore<'a>,
+        ptr: *mut u128,
+    ) -> Result<(), Trap> {
+        <Self as WasmTy>::store_to_args(<Self as WasmTy>::into_abi_for_arg(self, store), ptr);
+        Ok(())
+    }
 }
 
 unsafe impl<T> WasmRet for Result<T, Trap>
@@ -1415,8 +1716,147 @@ where
     unsafe fn store_to_args(abi: Self::Abi, ptr: *mut u128) {
         <T as WasmTy>::store_to_args(abi, ptr);
     }
+
+    fn returns_via_values_vec() -> bool {
+        false
+    }
+
+    unsafe fn store_to_values_vec<'a>(
+        self,
+        store: WeakStore<'a>,
+        ptr: *mut u128,
+    ) -> Result<(), Trap> {
+        <T as WasmTy>::store_to_args(<T as WasmTy>::into_abi_for_arg(self?, store), ptr);
+        Ok(())
+    }
 }
 
+macro_rules! impl_wasm_ret_tuple {
+    ($($t:ident)*) => {
+        // Tuples are always returned through a values vector, so their native
+        // ABI methods are never called.
+        #[allow(non_snake_case)]
+        unsafe impl<$($t: WasmTy,)*> WasmRet for ($($t,)*) {
+            type Abi = ();
+
+            fn compatible_with_store<'a>(&self, store: WeakStore<'a>) -> bool {
+                let ($($t,)*) = self;
+                $( <$t as WasmTy>::compatible_with_store($t, store) && )* true
+            }
+
+            unsafe fn into_abi_for_ret<'a>(self, _store: WeakStore<'a>) -> Self::Abi {
+                unreachable!("tuples are returned through a values vector")
+            }
+
+            unsafe fn from_abi<'a>(_abi: Self::Abi, _store: WeakStore<'a>) -> Self {
+                unreachable!("tuples are returned through a values vector")
+            }
+
+            fn push(dst: &mut Vec<ValType>) {
+                $( <$t as WasmTy>::push(dst); )*
+            }
+
+            fn matches(mut tys: impl Iterator<Item = ValType>) -> anyhow::Result<()> {
+                $( <$t as WasmTy>::matches(&mut tys)?; )*
+                Ok(())
+            }
+
+            unsafe fn load_from_args(_ptr: &mut *const u128) -> Self::Abi {
+                unreachable!("tuples are returned through a values vector")
+            }
+
+            unsafe fn store_to_args(_abi: Self::Abi, _ptr: *mut u128) {
+                unreachable!("tuples are returned through a values vector")
+            }
+
+            fn returns_via_values_vec() -> bool {
+                true
+            }
+
+            unsafe fn store_to_values_vec<'a>(
+                self,
+                store: WeakStore<'a>,
+                ptr: *mut u128,
+            ) -> Result<(), Trap> {
+                let ($($t,)*) = self;
+                let mut _ptr = ptr;
+                $(
+                    <$t as WasmTy>::store_to_args(
+                        <$t as WasmTy>::into_abi_for_arg($t, store),
+                        _ptr,
+                    );
+                    _ptr = _ptr.add(1);
+                )*
+                Ok(())
+            }
+        }
+
+        unsafe impl<$($t: WasmTy,)*> WasmRet for Result<($($t,)*), Trap> {
+            type Abi = ();
+
+            fn compatible_with_store<'a>(&self, store: WeakStore<'a>) -> bool {
+                match self {
+                    Ok(x) => <($($t,)*) as WasmRet>::compatible_with_store(x, store),
+                    Err(_) => true,
+                }
+            }
+
+            unsafe fn into_abi_for_ret<'a>(self, _store: WeakStore<'a>) -> Self::Abi {
+                unreachable!("tuples are returned through a values vector")
+            }
+
+            unsafe fn from_abi<'a>(_abi: Self::Abi, _store: WeakStore<'a>) -> Self {
+                unreachable!("tuples are returned through a values vector")
+            }
+
+            fn push(dst: &mut Vec<ValType>) {
+                <($($t,)*) as WasmRet>::push(dst)
+            }
+
+            fn matches(tys: impl Iterator<Item = ValType>) -> anyhow::Result<()> {
+                <($($t,)*) as WasmRet>::matches(tys)
+            }
+
+            unsafe fn load_from_args(_ptr: &mut *const u128) -> Self::Abi {
+                unreachable!("tuples are returned through a values vector")
+            }
+
+            unsafe fn store_to_args(_abi: Self::Abi, _ptr: *mut u128) {
+                unreachable!("tuples are returned through a values vector")
+            }
+
+            fn returns_via_values_vec() -> bool {
+                true
+            }
+
+            unsafe fn store_to_values_vec<'a>(
+                self,
+                store: WeakStore<'a>,
+                ptr: *mut u128,
+            ) -> Result<(), Trap> {
+                <($($t,)*) as WasmRet>::store_to_values_vec(self?, store, ptr)
+            }
+        }
+    };
+}
+
+impl_wasm_ret_tuple!(A1);
+impl_wasm_ret_tuple!(A1 A2);
+impl_wasm_ret_tuple!(A1 A2 A3);
+impl_wasm_ret_tuple!(A1 A2 A3 A4);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5 A6);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5 A6 A7);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5 A6 A7 A8);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5 A6 A7 A8 A9);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14 A15);
+impl_wasm_ret_tuple!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14 A15 A16);
+
 /// Internal trait implemented for all arguments that can be passed to
 /// [`Func::wrap`].
 ///
@@ -1470,22 +1910,21 @@ pub struct Caller<'a> {
 impl Caller<'_> {
     /// Looks up an export from the caller's module by the `name` given.
     ///
-    /// Note that this function is only implemented for the `Extern::Memory`
-    /// and the `Extern::Func` types currently. No other exported structures
-    /// can be acquired through this just yet, but this may be implemented
-    /// in the future!
+    /// Any kind of export can be acquired through this method: functions,
+    /// globals, tables and memories. The most common use is to reach the
+    /// caller's exported `Memory` so that pointer arguments can be read and
+    /// written.
     ///
     /// Note that when accessing and calling exported functions, one should adhere
     /// to the guidlines of the interface types proposal.
     ///
     /// # Return
     ///
-    /// If a memory or function export with the `name` provided was found, then it is
-    /// returned as a `Memory`. There are a number of situations, however, where
-    /// the memory or function may not be available:
+    /// If an export with the `name` provided was found, then it is returned as
+    /// an `Extern`. There are a number of situations, however, where the export
+    /// may not be available:
     ///
     /// * The caller instance may not have an export named `name`
-    /// * The export named `name` may not be an exported memory
     /// * There may not be a caller available, for example if `Func` was called
     ///   directly from host code.
     ///
@@ -1505,11 +1944,7 @@ impl Caller<'_> {
             debug_assert!(self.store.upgrade().is_some());
             let handle =
                 Store::from_inner(self.store.upgrade()?).existing_instance_handle(instance);
-            match export {
-                Export::Memory(m) => Some(Extern::Memory(Memory::from_wasmtime_memory(m, handle))),
-                Export::Function(f) => Some(Extern::Func(Func::from_wasmtime_function(f, handle))),
-                _ => None,
-            }
+            Some(Extern::from_wasmtime_export(export, handle))
         }
     }
 
@@ -1520,6 +1955,15 @@ impl Caller<'_> {
     }
 }
 
+/// Invokes the call hook of the store behind `store`, used by host functions
+/// which only hold on to a weak reference to their store.
+fn invoke_host_call_hook(store: &Weak<StoreInner>, kind: CallHook) -> Result<(), Trap> {
+    match store.upgrade() {
+        Some(store) => store.invoke_call_hook(kind),
+        None => Ok(()),
+    }
+}
+
 #[inline(never)]
 #[cold]
 unsafe fn raise_cross_store_trap() -> ! {
@@ -1596,16 +2040,20 @@ macro_rules! impl_into_func {
                     let weak_store = WeakStore(store);
 
                     let ret = {
-                        panic::catch_unwind(AssertUnwindSafe(|| {
-                            func(
+                        panic::catch_unwind(AssertUnwindSafe(|| -> Result<R, Trap> {
+                            invoke_host_call_hook(store, CallHook::CallingHost)?;
+                            let ret = func(
                                 Caller { store, caller_vmctx },
                                 $( $args::from_abi($args, weak_store), )*
-                            )
+                            );
+                            invoke_host_call_hook(store, CallHook::ReturningFromHost)?;
+                            Ok(ret)
                         }))
                     };
                     match ret {
                         Err(panic) => wasmtime_runtime::resume_panic(panic),
-                        Ok(ret) => {
+                        Ok(Err(trap)) => raise_user_trap(Box::new(trap)),
+                        Ok(Ok(ret)) => {
                             // Because the wrapped function is not `unsafe`, we
                             // can't assume it returned a value that is
                             // compatible with this store.
@@ -1657,6 +2105,43 @@ macro_rules! impl_into_func {
                 let ty = FuncType::new(_args.into(), ret.into());
 
                 let store_weak = store.weak();
+
+                // Multiple return values can't be returned natively from
+                // `wasm_to_host_shim`, so instead go through the same stub as
+                // `Func::new`, which passes arguments and results in memory.
+                if R::returns_via_values_vec() {
+                    let func = move |caller_vmctx: *mut VMContext, values_vec: *mut u128| unsafe {
+                        let weak_store = WeakStore(&store_weak);
+                        let mut _next = values_vec as *const u128;
+                        $(
+                            let $args = $args::from_abi(
+                                $args::load_from_args(&mut _next),
+                                weak_store,
+                            );
+                        )*
+                        invoke_host_call_hook(&store_weak, CallHook::CallingHost)?;
+                        let ret = self(
+                            Caller { store: &store_weak, caller_vmctx },
+                            $( $args, )*
+                        );
+                        invoke_host_call_hook(&store_weak, CallHook::ReturningFromHost)?;
+                        if !ret.compatible_with_store(weak_store) {
+                            return Err(Trap::new(
+                                "host function attempted to return cross-`Store` value to Wasm",
+                            ));
+                        }
+                        ret.store_to_values_vec(weak_store, values_vec)
+                    };
+                    let (instance, export, trampoline) =
+                        crate::trampoline::generate_func_export(&ty, Box::new(func), store)
+                            .expect("generated func");
+                    return Func {
+                        instance,
+                        export,
+                        trampoline,
+                    };
+                }
+
                 let trampoline = host_trampoline::<$($args,)* R>;
                 let (instance, export) = unsafe {
                     crate::trampoline::generate_raw_func_export(
@@ -0,0 +1,271 @@
+use super::{invoke_wasm_and_catch_traps, WasmTy, WeakStore};
+use crate::{Func, Trap, ValType};
+use anyhow::{bail, Result};
+use smallvec::{smallvec, SmallVec};
+use std::cmp;
+use std::marker;
+use std::ptr;
+
+/// A statically typed WebAssembly function.
+///
+/// Values of this type represent statically type-checked WebAssembly functions.
+/// The function within a [`TypedFunc`] is statically known to have `Params` as
+/// its parameters and `Results` as its results.
+///
+/// This structure is created via [`Func::typed`] or [`TypedFunc::new_unchecked`].
+/// For more documentation about this see those methods.
+pub struct TypedFunc<Params, Results> {
+    _a: marker::PhantomData<fn(Params) -> Results>,
+    func: Func,
+    values_len: usize,
+}
+
+impl<Params, Results> Clone for TypedFunc<Params, Results> {
+    fn clone(&self) -> TypedFunc<Params, Results> {
+        TypedFunc {
+            _a: marker::PhantomData,
+            func: self.func.clone(),
+            values_len: self.values_len,
+        }
+    }
+}
+
+impl<Params, Results> TypedFunc<Params, Results>
+where
+    Params: WasmParams,
+    Results: WasmResults,
+{
+    /// Creates a new [`TypedFunc`] from an untyped [`Func`] without performing
+    /// any type-checks.
+    ///
+    /// # Unsafety
+    ///
+    /// This is an unsafe function because it does not check that the `func`
+    /// provided actually has the `Params` and `Results` signature. If the
+    /// signatures don't match then calling [`TypedFunc::call`] is undefined
+    /// behavior. Prefer [`Func::typed`] which performs the type-check once up
+    /// front.
+    pub unsafe fn new_unchecked(func: Func) -> TypedFunc<Params, Results> {
+        TypedFunc {
+            _a: marker::PhantomData,
+            func,
+            values_len: cmp::max(Params::len(), Results::len()),
+        }
+    }
+
+    /// Returns the underlying [`Func`] that this is wrapping, losing the static
+    /// type information in the process.
+    pub fn func(&self) -> &Func {
+        &self.func
+    }
+
+    /// Invokes this WebAssembly function with the specified parameters.
+    ///
+    /// Returns either the results of the call, or a [`Trap`] if one happened.
+    ///
+    /// Unlike [`Func::call`] no values are boxed into [`Val`](crate::Val)s
+    /// here and no type-checking happens at call time; the signature was
+    /// already checked when this `TypedFunc` was created. Arguments and
+    /// results are transferred through a small on-stack buffer, so calls with
+    /// a handful of parameters and results don't allocate.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Trap`] if the function traps while executing, or if any of
+    /// the `params` come from a different [`Store`](crate::Store) than this
+    /// function.
+    pub fn call(&self, params: Params) -> Result<Results, Trap> {
+        let store = &self.func.instance.store;
+        let weak_store = store.weak();
+        let weak_store = WeakStore(&weak_store);
+
+        // Because this method is not `unsafe` we have to check that incoming
+        // values are compatible with our store.
+        if !params.compatible_with_store(weak_store) {
+            return Err(Trap::new(
+                "attempt to pass cross-`Store` value to Wasm as function argument",
+            ));
+        }
+
+        const STACK_VALUES: usize = 8;
+        let mut values_vec: SmallVec<[u128; STACK_VALUES]> = smallvec![0; self.values_len];
+
+        unsafe {
+            params.store_to_args(weak_store, values_vec.as_mut_ptr());
+
+            let anyfunc = self.func.export.anyfunc.as_ref();
+            let trampoline = self.func.trampoline;
+            let values_vec_ptr = values_vec.as_mut_ptr();
+            invoke_wasm_and_catch_traps(anyfunc.vmctx, store, || {
+                trampoline(
+                    anyfunc.vmctx,
+                    ptr::null_mut(),
+                    anyfunc.func_ptr.as_ptr(),
+                    values_vec_ptr,
+                )
+            })?;
+
+            Ok(Results::load_from_args(weak_store, values_vec.as_ptr()))
+        }
+    }
+}
+
+/// A trait used for [`Func::typed`] and with [`TypedFunc`] to represent the set of
+/// parameters for wasm functions.
+///
+/// This is implemented for bare types that can be passed to wasm as well as
+/// tuples of those types.
+///
+/// This trait should not be implemented by user types. This trait may change at
+/// any time internally. The types which implement this trait, however, are
+/// stable over time.
+pub unsafe trait WasmParams {
+    // The number of wasm values this list of parameters represents.
+    #[doc(hidden)]
+    fn len() -> usize;
+
+    // Type-check that `params` exactly matches this list of types.
+    #[doc(hidden)]
+    fn typecheck(params: impl ExactSizeIterator<Item = ValType>) -> Result<()>;
+
+    // Are all of these values compatible with the given store?
+    #[doc(hidden)]
+    fn compatible_with_store<'a>(&self, store: WeakStore<'a>) -> bool;
+
+    // Write all of these values, in order, into the `args` array.
+    #[doc(hidden)]
+    unsafe fn store_to_args<'a>(self, store: WeakStore<'a>, args: *mut u128);
+}
+
+/// A trait used for [`Func::typed`] and with [`TypedFunc`] to represent the set of
+/// results for wasm functions.
+///
+/// This is implemented for `()`, bare types that can be returned from wasm, as
+/// well as tuples of those types.
+///
+/// This trait should not be implemented by user types. This trait may change at
+/// any time internally. The types which implement this trait, however, are
+/// stable over time.
+pub unsafe trait WasmResults: WasmParams {
+    // Read all of these values, in order, out of the `args` array.
+    #[doc(hidden)]
+    unsafe fn load_from_args<'a>(store: WeakStore<'a>, args: *const u128) -> Self;
+}
+
+// Note that bare types (including `()`) are handled here rather than through
+// the tuple implementations below, which is why `()` doesn't get a tuple impl.
+unsafe impl<T> WasmParams for T
+where
+    T: WasmTy,
+{
+    fn len() -> usize {
+        let mut tys = Vec::new();
+        T::push(&mut tys);
+        tys.len()
+    }
+
+    fn typecheck(mut params: impl ExactSizeIterator<Item = ValType>) -> Result<()> {
+        let expected = <T as WasmParams>::len();
+        let supplied = params.len();
+        if supplied != expected {
+            bail!("expected {} types, found {}", expected, supplied);
+        }
+        T::matches(&mut params)
+    }
+
+    #[inline]
+    fn compatible_with_store<'a>(&self, store: WeakStore<'a>) -> bool {
+        <T as WasmTy>::compatible_with_store(self, store)
+    }
+
+    #[inline]
+    unsafe fn store_to_args<'a>(self, store: WeakStore<'a>, args: *mut u128) {
+        let abi = <T as WasmTy>::into_abi_for_arg(self, store);
+        <T as WasmTy>::store_to_args(abi, args)
+    }
+}
+
+unsafe impl<T> WasmResults for T
+where
+    T: WasmTy,
+{
+    #[inline]
+    unsafe fn load_from_args<'a>(store: WeakStore<'a>, args: *const u128) -> Self {
+        let mut args = args;
+        let abi = <T as WasmTy>::load_from_args(&mut args);
+        <T as WasmTy>::from_abi(abi, store)
+    }
+}
+
+macro_rules! impl_wasm_params {
+    ($n:tt $($t:ident)*) => {
+        #[allow(non_snake_case)]
+        unsafe impl<$($t: WasmTy,)*> WasmParams for ($($t,)*) {
+            fn len() -> usize {
+                $n
+            }
+
+            fn typecheck(mut params: impl ExactSizeIterator<Item = ValType>) -> Result<()> {
+                let supplied = params.len();
+                if supplied != $n {
+                    bail!("expected {} types, found {}", $n, supplied);
+                }
+                let _n = 0;
+                $(
+                    let _n = _n + 1;
+                    if let Err(e) = <$t as WasmTy>::matches(&mut params) {
+                        bail!("type mismatch at index {}: {}", _n - 1, e);
+                    }
+                )*
+                Ok(())
+            }
+
+            #[inline]
+            fn compatible_with_store<'a>(&self, _store: WeakStore<'a>) -> bool {
+                let ($($t,)*) = self;
+                $(<$t as WasmTy>::compatible_with_store($t, _store)&&)* true
+            }
+
+            #[inline]
+            unsafe fn store_to_args<'a>(self, _store: WeakStore<'a>, _args: *mut u128) {
+                let ($($t,)*) = self;
+                let mut _next = _args;
+                $(
+                    let abi = <$t as WasmTy>::into_abi_for_arg($t, _store);
+                    <$t as WasmTy>::store_to_args(abi, _next);
+                    _next = _next.add(1);
+                )*
+            }
+        }
+
+        #[allow(non_snake_case)]
+        unsafe impl<$($t: WasmTy,)*> WasmResults for ($($t,)*) {
+            #[inline]
+            unsafe fn load_from_args<'a>(_store: WeakStore<'a>, _args: *const u128) -> Self {
+                let mut _next = _args;
+                $(
+                    let $t = <$t as WasmTy>::load_from_args(&mut _next);
+                    let $t = <$t as WasmTy>::from_abi($t, _store);
+                )*
+                ($($t,)*)
+            }
+        }
+    };
+}
+
+impl_wasm_params!(1 A1);
+impl_wasm_params!(2 A1 A2);
+impl_wasm_params!(3 A1 A2 A3);
+impl_wasm_params!(4 A1 A2 A3 A4);
+impl_wasm_params!(5 A1 A2 A3 A4 A5);
+impl_wasm_params!(6 A1 A2 A3 A4 A5 A6);
+impl_wasm_params!(7 A1 A2 A3 A4 A5 A6 A7);
+impl_wasm_params!(8 A1 A2 A3 A4 A5 A6 A7 A8);
+impl_wasm_params!(9 A1 A2 A3 A4 A5 A6 A7 A8 A9);
+impl_wasm_params!(10 A1 A2 A3 A4 A5 A6 A7 A8 A9 A10);
+impl_wasm_params!(11 A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11);
+impl_wasm_params!(12 A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12);
+impl_wasm_params!(13 A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13);
+impl_wasm_params!(14 A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14);
+impl_wasm_params!(15 A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14 A15);
+impl_wasm_params!(16 A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14 A15 A16);
@@ -1,13 +1,22 @@
+use crate::frame_info::GlobalFrameInfoRegistration;
 use crate::trampoline::StoreInstanceHandle;
-use crate::{Engine, Export, Extern, Func, Global, Memory, Module, Store, Table, Trap};
+use crate::types::EntityType;
+use crate::{
+    Engine, Export, Extern, Func, FuncType, Global, Memory, Module, Store, Table, Trap, TypedFunc,
+    WasmParams, WasmResults,
+};
 use anyhow::{anyhow, bail, Context, Error, Result};
 use std::any::Any;
+use std::fmt;
 use std::mem;
+use std::rc::Rc;
+use std::sync::Arc;
 use wasmtime_environ::EntityIndex;
 use wasmtime_jit::CompiledModule;
 use wasmtime_runtime::{
-    Imports, InstantiationError, StackMapRegistry, VMContext, VMExternRefActivationsTable,
-    VMFunctionBody,
+    Imports, InstanceHandle, RuntimeMemoryCreator, StackMapRegistry, VMContext,
+    VMExternRefActivationsTable, VMFunctionBody, VMFunctionImport, VMGlobalImport, VMMemoryImport,
+    VMTableImport,
 };
 
 fn instantiate(
@@ -15,18 +24,31 @@ fn instantiate(
     compiled_module: &CompiledModule,
     imports: Imports<'_>,
     host: Box<dyn Any>,
-) -> Result<StoreInstanceHandle, Error> {
+) -> Result<StoreInstanceHandle, InstantiationError> {
     let config = store.engine().config();
+    store
+        .bump_resource_counts(compiled_module.module())
+        .map_err(InstantiationError::Resource)?;
     let instance = unsafe {
-        let instance = compiled_module.instantiate(
-            imports,
-            &mut store.signatures_mut(),
-            config.memory_creator.as_ref().map(|a| a as _),
-            store.interrupts(),
-            host,
-            store.externref_activations_table() as *const VMExternRefActivationsTable as *mut _,
-            store.stack_map_registry() as *const StackMapRegistry as *mut _,
-        )?;
+        let instance = compiled_module
+            .instantiate(
+                imports,
+                &mut store.engine().signatures_mut(),
+                config
+                    .memory_creator
+                    .as_ref()
+                    .map(|c| c as &dyn RuntimeMemoryCreator),
+                store.instance_pool(),
+                store.interrupts(),
+                host,
+                store.externref_activations_table() as *const VMExternRefActivationsTable as *mut _,
+                store.stack_map_registry() as *const StackMapRegistry as *mut _,
+                store.limiter(),
+            )
+            .map_err(|e| {
+                store.release_resource_counts(compiled_module.module());
+                InstantiationError::from_runtime(e)
+            })?;
 
         // After we've created the `InstanceHandle` we still need to run
         // initialization to set up data/elements/etc. We do this after adding
@@ -41,12 +63,7 @@ fn instantiate(
                 config.features.bulk_memory,
                 &compiled_module.data_initializers(),
             )
-            .map_err(|e| -> Error {
-                match e {
-                    InstantiationError::Trap(trap) => Trap::from_runtime(trap).into(),
-                    other => other.into(),
-                }
-            })?;
+            .map_err(InstantiationError::from_runtime)?;
 
         instance
     };
@@ -65,20 +82,33 @@ fn instantiate(
         };
         let vmctx_ptr = instance.handle.vmctx_ptr();
         unsafe {
-            super::func::invoke_wasm_and_catch_traps(vmctx_ptr, store, || {
+            super::func::invoke_wasm_and_catch_traps(f.anyfunc.as_ref().vmctx, store, || {
                 mem::transmute::<
                     *const VMFunctionBody,
                     unsafe extern "C" fn(*mut VMContext, *mut VMContext),
                 >(f.anyfunc.as_ref().func_ptr.as_ptr())(
                     f.anyfunc.as_ref().vmctx, vmctx_ptr
                 )
-            })?;
+            })
+            .map_err(InstantiationError::Trap)?;
         }
     }
 
     Ok(instance)
 }
 
+/// The host state of the instances of wasm modules, which tells them apart
+/// from the instances created to hold host-defined functions, memories, etc.
+struct ModuleInstanceInfo {
+    _frame_info_registration: Option<Arc<GlobalFrameInfoRegistration>>,
+}
+
+/// Returns whether `handle` is an instance of a wasm module rather than an
+/// instance created for host-defined items.
+pub(crate) fn is_module_instance(handle: &InstanceHandle) -> bool {
+    handle.host_state().is::<ModuleInstanceInfo>()
+}
+
 /// An instantiated WebAssembly module.
 ///
 /// This type represents the instantiation of a [`Module`]. Once instantiated
@@ -150,27 +180,44 @@ impl Instance {
     /// * Module/instance resource limits are exceeded.
     ///
     /// When instantiation fails it's recommended to inspect the return value to
-    /// see why it failed, or bubble it upwards. If you'd like to specifically
-    /// check for trap errors, you can use `error.downcast::<Trap>()`.
+    /// see why it failed, or bubble it upwards. The error is an
+    /// [`InstantiationError`], which tells these cases apart and carries the
+    /// [`Trap`] if the `start` function trapped.
+    ///
+    /// Note that this means a trap in the `start` function can't be
+    /// downcast to a [`Trap`] directly, as `err.downcast::<Trap>()` fails.
+    /// Downcast to [`InstantiationError`] instead and match on
+    /// [`InstantiationError::Trap`] to get at the trap, for example to read
+    /// its [`Trap::i32_exit_status`].
     ///
     /// [inst]: https://webassembly.github.io/spec/core/exec/modules.html#exec-instantiation
     /// [issue]: https://github.com/bytecodealliance/wasmtime/issues/727
     /// [`ExternType`]: crate::ExternType
     pub fn new(store: &Store, module: &Module, imports: &[Extern]) -> Result<Instance, Error> {
-        if !Engine::same(store.engine(), module.engine()) {
-            bail!("cross-`Engine` instantiation is not currently supported");
-        }
+        let imports = resolve_imports(store, module, imports)?;
+        Instance::new_resolved(store, module, &imports)
+    }
 
+    fn new_resolved(
+        store: &Store,
+        module: &Module,
+        imports: &OwnedImports,
+    ) -> Result<Instance, Error> {
         let host_info = Box::new({
             let frame_info_registration = module.register_frame_info();
             store.register_jit_code(&module);
             store.register_stack_maps(&module);
-            frame_info_registration
+            ModuleInstanceInfo {
+                _frame_info_registration: frame_info_registration,
+            }
         });
 
-        let handle = with_imports(store, module.compiled_module(), imports, |imports| {
-            instantiate(store, module.compiled_module(), imports, host_info)
-        })?;
+        let handle = instantiate(
+            store,
+            module.compiled_module(),
+            imports.as_imports(),
+            host_info,
+        )?;
 
         Ok(Instance {
             handle,
@@ -217,6 +264,90 @@ impl Instance {
         self.get_export(name)?.into_func()
     }
 
+    /// Looks up an exported function by name and checks that its type is
+    /// `Params` and `Results`, see [`Func::typed`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was no export named `name`, or if there was
+    /// but it wasn't a function of the expected type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let module = Module::new(
+    ///     store.engine(),
+    ///     r#"(module (func (export "double") (param i32) (result i32)
+    ///         local.get 0
+    ///         local.get 0
+    ///         i32.add))"#,
+    /// )?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// let double = instance.get_typed_func::<i32, i32>("double")?;
+    /// assert_eq!(double.call(21)?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_typed_func<Params, Results>(&self, name: &str) -> Result<TypedFunc<Params, Results>>
+    where
+        Params: WasmParams,
+        Results: WasmResults,
+    {
+        let func = match self.get_export(name) {
+            Some(Extern::Func(func)) => func,
+            Some(other) => bail!("export `{}` is a {}, not a function", name, other.desc()),
+            None => bail!("failed to find function export `{}`", name),
+        };
+        func.typed()
+            .with_context(|| format!("failed to convert export `{}` to a typed function", name))
+    }
+
+    /// Returns the "default export" of this instance.
+    ///
+    /// An export with an empty name is considered to be the default export.
+    /// For compatibility with toolchains which produce commands, `_start` is
+    /// used if there's no such export, and otherwise a function which does
+    /// nothing is returned. This is the same convention as
+    /// [`Linker::get_default`] uses.
+    ///
+    /// Use [`Instance::get_default_with_entry`] for modules whose entry point
+    /// has another name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the default export isn't a function.
+    ///
+    /// [`Linker::get_default`]: crate::Linker::get_default
+    pub fn get_default(&self) -> Result<Func> {
+        self.get_default_with_entry("_start")
+    }
+
+    /// Returns the "default export" of this instance, falling back to the
+    /// export named `entry` rather than `_start`.
+    ///
+    /// This is otherwise the same as [`Instance::get_default`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the default export isn't a function.
+    pub fn get_default_with_entry(&self, entry: &str) -> Result<Func> {
+        for name in ["", entry].iter() {
+            if let Some(export) = self.get_export(name) {
+                return export
+                    .into_func()
+                    .ok_or_else(|| anyhow!("default export `{}` is not a function", name));
+            }
+        }
+        Ok(Func::new(
+            &self.store,
+            FuncType::new(Vec::new().into_boxed_slice(), Vec::new().into_boxed_slice()),
+            |_, _, _| Ok(()),
+        ))
+    }
+
     /// Looks up an exported [`Table`] value by name.
     ///
     /// Returns `None` if there was no export named `name`, or if there was but
@@ -242,12 +373,173 @@ impl Instance {
     }
 }
 
-fn with_imports<R>(
+/// A [`Module`] whose imports have already been resolved and type-checked,
+/// which can be instantiated any number of times.
+///
+/// Instantiating an `InstancePre` skips looking up and checking the imports
+/// of the module, which makes it cheaper than [`Linker::instantiate`] when
+/// the same module is instantiated with the same imports over and over, as is
+/// common with one instance per request. It's created with
+/// [`Linker::instantiate_pre`].
+///
+/// [`Linker::instantiate`]: crate::Linker::instantiate
+/// [`Linker::instantiate_pre`]: crate::Linker::instantiate_pre
+#[derive(Clone)]
+pub struct InstancePre {
+    store: Store,
+    module: Module,
+    imports: Rc<OwnedImports>,
+    /// The items `imports` refer to, which have to be kept alive.
+    _items: Rc<[Extern]>,
+}
+
+impl InstancePre {
+    pub(crate) fn new(store: &Store, module: &Module, items: Vec<Extern>) -> Result<InstancePre> {
+        let imports = resolve_imports(store, module, &items)?;
+        Ok(InstancePre {
+            store: store.clone(),
+            module: module.clone(),
+            imports: Rc::new(imports),
+            _items: items.into(),
+        })
+    }
+
+    /// Returns the module that this will instantiate.
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// Instantiates the module with the imports resolved when this
+    /// `InstancePre` was created.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`InstantiationError`] if the module's `start` function
+    /// traps or if resource limits are exceeded, see [`Instance::new`].
+    pub fn instantiate(&self) -> Result<Instance> {
+        Instance::new_resolved(&self.store, &self.module, &self.imports)
+    }
+}
+
+/// The reason instantiating a module failed, returned by [`Instance::new`],
+/// [`InstancePre::instantiate`] and [`Linker::instantiate`].
+///
+/// Instantiation returns an [`anyhow::Error`] like the rest of this crate,
+/// which can be downcast to this type to handle the different failures:
+///
+/// ```
+/// # use wasmtime::*;
+/// # fn main() -> anyhow::Result<()> {
+/// let store = Store::default();
+/// let module = Module::new(store.engine(), "(module (func unreachable) (start 0))")?;
+/// let err = Instance::new(&store, &module, &[]).unwrap_err();
+/// match err.downcast_ref::<InstantiationError>() {
+///     Some(InstantiationError::Trap(trap)) => {
+///         assert!(trap.to_string().contains("unreachable"));
+///     }
+///     _ => panic!("unexpected error: {:?}", err),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Linker::instantiate`]: crate::Linker::instantiate
+#[derive(Debug)]
+pub enum InstantiationError {
+    /// The imports don't match the ones the module expects. An import is
+    /// missing or has the wrong type, or an item comes from another store.
+    Link(Error),
+
+    /// The instance couldn't be allocated, because the resources or the
+    /// limits of its [`Store`] were exhausted.
+    Resource(Error),
+
+    /// A trap occurred while initializing the instance, either in its `start`
+    /// function or while copying in a segment that doesn't fit.
+    Trap(Trap),
+}
+
+impl InstantiationError {
+    fn from_runtime(error: wasmtime_runtime::InstantiationError) -> InstantiationError {
+        match error {
+            wasmtime_runtime::InstantiationError::Resource(msg) => {
+                InstantiationError::Resource(anyhow!(msg))
+            }
+            wasmtime_runtime::InstantiationError::Link(e) => InstantiationError::Link(e.into()),
+            wasmtime_runtime::InstantiationError::Trap(trap) => {
+                InstantiationError::Trap(Trap::from_runtime(trap))
+            }
+        }
+    }
+
+    /// Returns the trap which caused instantiation to fail, if any.
+    pub fn trap(&self) -> Option<&Trap> {
+        match self {
+            InstantiationError::Trap(trap) => Some(trap),
+            InstantiationError::Link(_) | InstantiationError::Resource(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for InstantiationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstantiationError::Link(e) | InstantiationError::Resource(e) => {
+                fmt::Display::fmt(e, f)
+            }
+            InstantiationError::Trap(trap) => fmt::Display::fmt(trap, f),
+        }
+    }
+}
+
+impl std::error::Error for InstantiationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InstantiationError::Link(e) | InstantiationError::Resource(e) => e.source(),
+            InstantiationError::Trap(trap) => std::error::Error::source(trap),
+        }
+    }
+}
+
+/// The imports of an instance, type-checked against the module and in the
+/// form that `InstanceHandle`s store them.
+#[derive(Clone, Default)]
+struct OwnedImports {
+    tables: Vec<VMTableImport>,
+    functions: Vec<VMFunctionImport>,
+    globals: Vec<VMGlobalImport>,
+    memories: Vec<VMMemoryImport>,
+}
+
+impl OwnedImports {
+    fn as_imports(&self) -> Imports<'_> {
+        Imports {
+            tables: &self.tables,
+            functions: &self.functions,
+            globals: &self.globals,
+            memories: &self.memories,
+        }
+    }
+}
+
+fn resolve_imports(
+    store: &Store,
+    module: &Module,
+    externs: &[Extern],
+) -> Result<OwnedImports, InstantiationError> {
+    if !Engine::same(store.engine(), module.engine()) {
+        return Err(InstantiationError::Link(anyhow!(
+            "cross-`Engine` instantiation is not currently supported"
+        )));
+    }
+    check_imports(store, module.compiled_module(), externs).map_err(InstantiationError::Link)
+}
+
+fn check_imports(
     store: &Store,
     module: &CompiledModule,
     externs: &[Extern],
-    f: impl FnOnce(Imports<'_>) -> Result<R>,
-) -> Result<R> {
+) -> Result<OwnedImports> {
     let m = module.module();
     if externs.len() != m.imports.len() {
         bail!(
@@ -257,66 +549,72 @@ fn with_imports<R>(
         );
     }
 
-    let mut tables = Vec::new();
-    let mut functions = Vec::new();
-    let mut globals = Vec::new();
-    let mut memories = Vec::new();
-
-    let mut process = |expected: &EntityIndex, actual: &Extern| {
-        // For now we have a restriction that the `Store` that we're working
-        // with is the same for everything involved here.
-        if !actual.comes_from_same_store(store) {
-            bail!("cross-`Store` instantiation is not currently supported");
-        }
+    let mut imports = OwnedImports::default();
+    let OwnedImports {
+        tables,
+        functions,
+        globals,
+        memories,
+    } = &mut imports;
 
+    let mut process = |expected: &EntityIndex, actual: &Extern| -> bool {
         match *expected {
-            EntityIndex::Table(i) => tables.push(match actual {
-                Extern::Table(e) if e.matches_expected(&m.table_plans[i]) => e.vmimport(),
-                Extern::Table(_) => bail!("table types incompatible"),
-                _ => bail!("expected table, but found {}", actual.desc()),
-            }),
-            EntityIndex::Memory(i) => memories.push(match actual {
-                Extern::Memory(e) if e.matches_expected(&m.memory_plans[i]) => e.vmimport(),
-                Extern::Memory(_) => bail!("memory types incompatible"),
-                _ => bail!("expected memory, but found {}", actual.desc()),
-            }),
-            EntityIndex::Global(i) => globals.push(match actual {
-                Extern::Global(e) if e.matches_expected(&m.globals[i]) => e.vmimport(),
-                Extern::Global(_) => bail!("global types incompatible"),
-                _ => bail!("expected global, but found {}", actual.desc()),
-            }),
+            EntityIndex::Table(i) => match actual {
+                Extern::Table(e) if e.matches_expected(&m.table_plans[i]) => {
+                    tables.push(e.vmimport())
+                }
+                _ => return false,
+            },
+            EntityIndex::Memory(i) => match actual {
+                Extern::Memory(e) if e.matches_expected(&m.memory_plans[i]) => {
+                    memories.push(e.vmimport())
+                }
+                _ => return false,
+            },
+            EntityIndex::Global(i) => match actual {
+                Extern::Global(e) if e.matches_expected(&m.globals[i]) => {
+                    globals.push(e.vmimport())
+                }
+                _ => return false,
+            },
             EntityIndex::Function(i) => {
                 let func = match actual {
                     Extern::Func(e) => e,
-                    _ => bail!("expected function, but found {}", actual.desc()),
+                    _ => return false,
                 };
                 // Look up the `i`th function's type from the module in our
                 // signature registry. If it's not present then we have no
                 // functions registered with that type, so `func` is guaranteed
                 // to not match.
                 let ty = store
-                    .signatures_mut()
-                    .lookup(&m.signatures[m.functions[i]].0)
-                    .ok_or_else(|| anyhow!("function types incompatible"))?;
-                if !func.matches_expected(ty) {
-                    bail!("function types incompatible");
+                    .engine()
+                    .signatures()
+                    .lookup(&m.signatures[m.functions[i]].0);
+                match ty {
+                    Some(ty) if func.matches_expected(ty) => functions.push(func.vmimport()),
+                    _ => return false,
                 }
-                functions.push(func.vmimport());
             }
         }
-        Ok(())
+        true
     };
 
-    for (expected, actual) in m.imports.iter().zip(externs) {
-        process(&expected.2, actual).with_context(|| {
-            format!("incompatible import type for {}/{}", expected.0, expected.1)
-        })?;
+    for ((module, name, expected), actual) in m.imports.iter().zip(externs) {
+        // For now we have a restriction that the `Store` that we're working
+        // with is the same for everything involved here.
+        if !actual.comes_from_same_store(store) {
+            bail!("cross-`Store` instantiation is not currently supported");
+        }
+        if !process(expected, actual) {
+            bail!(
+                "incompatible import type for `{}::{}`: expected {}, found {}",
+                module,
+                name,
+                EntityType::new(expected, m).extern_type(),
+                actual.ty(),
+            );
+        }
     }
 
-    return f(Imports {
-        tables: &tables,
-        functions: &functions,
-        globals: &globals,
-        memories: &memories,
-    });
+    Ok(imports)
 }
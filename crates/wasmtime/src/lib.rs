@@ -133,6 +133,16 @@
 //! module. The [`Linker`] type also transparently handles Commands and Reactors
 //! as defined by WASI.
 //!
+//! ## Executable memory
+//!
+//! Memory holding machine code, whether compiled for a [`Module`] or generated
+//! for a host function, is never writable and executable at the same time.
+//! Code is written and relocated in read-write memory, which is then made
+//! read-only and executable before any of it runs, and is never made writable
+//! again. Code compiled later is written to different pages. On AArch64,
+//! where the instruction cache isn't coherent with the data cache, it's
+//! flushed for the new code before the code is made executable.
+//!
 //! ## WASI
 //!
 //! The `wasmtime` crate does not natively provide support for WASI, but you can
@@ -234,10 +244,12 @@
 #![doc(test(attr(deny(warnings))))]
 #![doc(test(attr(allow(dead_code, unused_variables, unused_mut))))]
 
+mod coredump;
 mod externals;
 mod frame_info;
 mod func;
 mod instance;
+mod limits;
 mod linker;
 mod module;
 mod r#ref;
@@ -247,15 +259,21 @@ mod trap;
 mod types;
 mod values;
 
+pub use crate::coredump::WasmCoreDump;
 pub use crate::externals::*;
 pub use crate::frame_info::FrameInfo;
 pub use crate::func::*;
-pub use crate::instance::Instance;
+pub use crate::instance::{Instance, InstancePre, InstantiationError};
+pub use crate::limits::*;
 pub use crate::linker::*;
-pub use crate::module::Module;
+#[cfg(feature = "disas")]
+pub use crate::module::FunctionCode;
+pub use crate::module::{
+    DataSegment, ElementSegment, FunctionStats, Module, ModuleStats, SegmentMode, SerializeOptions,
+};
 pub use crate::r#ref::ExternRef;
 pub use crate::runtime::*;
-pub use crate::trap::Trap;
+pub use crate::trap::{MemoryAccess, Trap, TrapCode};
 pub use crate::types::*;
 pub use crate::values::*;
 
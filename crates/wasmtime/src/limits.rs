@@ -0,0 +1,139 @@
+use crate::{ResourceLimiter, DEFAULT_INSTANCE_LIMIT, DEFAULT_MEMORY_LIMIT, DEFAULT_TABLE_LIMIT};
+
+/// Used to build [`StoreLimits`].
+pub struct StoreLimitsBuilder(StoreLimits);
+
+impl StoreLimitsBuilder {
+    /// Creates a new `StoreLimitsBuilder`.
+    ///
+    /// Memories and tables may grow up to their declared maximum, and the
+    /// counts of instances, memories and tables default to
+    /// [`DEFAULT_INSTANCE_LIMIT`], [`DEFAULT_MEMORY_LIMIT`] and
+    /// [`DEFAULT_TABLE_LIMIT`] respectively.
+    pub fn new() -> Self {
+        Self(StoreLimits::default())
+    }
+
+    /// The maximum number of wasm pages any one linear memory may grow to.
+    ///
+    /// By default memories are only limited by their declared maximum.
+    pub fn memory_pages(mut self, limit: u32) -> Self {
+        self.0.memory_pages = Some(limit);
+        self
+    }
+
+    /// The maximum number of elements any one table may grow to.
+    ///
+    /// By default tables are only limited by their declared maximum.
+    pub fn table_elements(mut self, limit: u32) -> Self {
+        self.0.table_elements = Some(limit);
+        self
+    }
+
+    /// The maximum number of instances that can be created in a store.
+    ///
+    /// Only instances of modules count towards this limit. Functions,
+    /// globals, memories and tables created by the host do not.
+    pub fn instances(mut self, limit: usize) -> Self {
+        self.0.instances = limit;
+        self
+    }
+
+    /// The maximum number of linear memories that can be defined by the
+    /// instances of a store.
+    ///
+    /// Imported memories and memories created by the host with
+    /// [`Memory::new`](crate::Memory::new) do not count towards this limit.
+    pub fn memories(mut self, limit: usize) -> Self {
+        self.0.memories = limit;
+        self
+    }
+
+    /// The maximum number of tables that can be defined by the instances of a
+    /// store.
+    ///
+    /// Imported tables and tables created by the host with
+    /// [`Table::new`](crate::Table::new) do not count towards this limit.
+    pub fn tables(mut self, limit: usize) -> Self {
+        self.0.tables = limit;
+        self
+    }
+
+    /// Consumes this builder and returns the [`StoreLimits`].
+    pub fn build(self) -> StoreLimits {
+        self.0
+    }
+}
+
+impl Default for StoreLimitsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`ResourceLimiter`] placing fixed limits on the resources of a store,
+/// built with [`StoreLimitsBuilder`].
+///
+/// # Examples
+///
+/// ```
+/// # use wasmtime::*;
+/// # fn main() -> anyhow::Result<()> {
+/// let engine = Engine::default();
+/// let limits = StoreLimitsBuilder::new().instances(1).memory_pages(16).build();
+/// let store = Store::new_with_limits(&engine, limits);
+///
+/// let module = Module::new(&engine, "(module (memory 1))")?;
+/// Instance::new(&store, &module, &[])?;
+/// assert!(Instance::new(&store, &module, &[]).is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct StoreLimits {
+    memory_pages: Option<u32>,
+    table_elements: Option<u32>,
+    instances: usize,
+    memories: usize,
+    tables: usize,
+}
+
+impl Default for StoreLimits {
+    fn default() -> Self {
+        Self {
+            memory_pages: None,
+            table_elements: None,
+            instances: DEFAULT_INSTANCE_LIMIT,
+            memories: DEFAULT_MEMORY_LIMIT,
+            tables: DEFAULT_TABLE_LIMIT,
+        }
+    }
+}
+
+impl ResourceLimiter for StoreLimits {
+    fn memory_growing(&self, _current: u32, desired: u32, _maximum: Option<u32>) -> bool {
+        match self.memory_pages {
+            Some(limit) => desired <= limit,
+            None => true,
+        }
+    }
+
+    fn table_growing(&self, _current: u32, desired: u32, _maximum: Option<u32>) -> bool {
+        match self.table_elements {
+            Some(limit) => desired <= limit,
+            None => true,
+        }
+    }
+
+    fn instances(&self) -> usize {
+        self.instances
+    }
+
+    fn memories(&self) -> usize {
+        self.memories
+    }
+
+    fn tables(&self) -> usize {
+        self.tables
+    }
+}
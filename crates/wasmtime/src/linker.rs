@@ -1,6 +1,6 @@
 use crate::{
-    Extern, ExternType, Func, FuncType, GlobalType, ImportType, Instance, IntoFunc, Module, Store,
-    Trap,
+    Extern, ExternType, Func, FuncType, GlobalType, ImportType, Instance, InstancePre,
+    InstantiationError, IntoFunc, Module, Store, Trap,
 };
 use anyhow::{anyhow, bail, Context, Error, Result};
 use log::warn;
@@ -217,6 +217,54 @@ impl Linker {
         self._define(module, name, Func::wrap(&self.store, func).into())
     }
 
+    /// Defines every function import of `module` which isn't defined in this
+    /// linker yet as a function which traps when called.
+    ///
+    /// This allows instantiating modules whose imports can only be partially
+    /// provided, for example to analyze or test the parts of them which don't
+    /// call the missing functions. The trap message names the import that was
+    /// called. Imports other than functions aren't defined.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let wat = r#"
+    ///     (module
+    ///         (import "host" "missing" (func))
+    ///         (func (export "run") (result i32) i32.const 42)
+    ///     )
+    /// "#;
+    /// let module = Module::new(store.engine(), wat)?;
+    /// let mut linker = Linker::new(&store);
+    /// linker.define_unknown_imports_as_traps(&module)?;
+    /// let instance = linker.instantiate(&module)?;
+    /// assert_eq!(instance.get_typed_func::<(), i32>("run")?.call(())?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn define_unknown_imports_as_traps(&mut self, module: &Module) -> Result<&mut Self> {
+        for import in module.imports() {
+            if self.get(&import).is_some() {
+                continue;
+            }
+            if let ExternType::Func(ty) = import.ty() {
+                let message = format!(
+                    "unknown import: `{}::{}` has not been defined",
+                    import.module(),
+                    import.name()
+                );
+                let func = Func::new(&self.store, ty, move |_, _, _| {
+                    Err(Trap::new(message.clone()))
+                });
+                self._define(import.module(), import.name(), func.into())?;
+            }
+        }
+        Ok(self)
+    }
+
     /// Convenience wrapper to define an entire [`Instance`] in this linker.
     ///
     /// This function is a convenience wrapper around [`Linker::define`] which
@@ -399,13 +447,20 @@ impl Linker {
     fn command(&mut self, module_name: &str, module: &Module) -> Result<&mut Self> {
         for export in module.exports() {
             if let Some(func_ty) = export.ty().func() {
-                let imports = self.compute_imports(module)?;
-                let store = self.store.clone();
-                let module = module.clone();
+                let pre = self.instantiate_pre(module)?;
                 let export_name = export.name().to_owned();
                 let func = Func::new(&self.store, func_ty.clone(), move |_, params, results| {
-                    // Create a new instance for this command execution.
-                    let instance = Instance::new(&store, &module, &imports)?;
+                    // Create a new instance for this command execution. A trap
+                    // in its `start` function is returned as is rather than
+                    // wrapped in an `InstantiationError`, so that callers can
+                    // still get at e.g. its exit status.
+                    let instance = pre.instantiate().map_err(|error| {
+                        match error.downcast::<InstantiationError>() {
+                            Ok(InstantiationError::Trap(trap)) => trap,
+                            Ok(error) => Error::from(error).into(),
+                            Err(error) => error.into(),
+                        }
+                    })?;
 
                     // `unwrap()` everything here because we know the instance contains a
                     // function export with the given name and signature because we're
@@ -549,8 +604,8 @@ impl Linker {
     /// # Errors
     ///
     /// This method can fail because an import may not be found, or because
-    /// instantiation itself may fail. For information on instantiation
-    /// failures see [`Instance::new`].
+    /// instantiation itself may fail. Both are reported as an
+    /// [`InstantiationError`], for more information see [`Instance::new`].
     ///
     /// # Examples
     ///
@@ -572,11 +627,54 @@ impl Linker {
     /// # }
     /// ```
     pub fn instantiate(&self, module: &Module) -> Result<Instance> {
-        let imports = self.compute_imports(module)?;
+        let imports = self
+            .compute_imports(module)
+            .map_err(InstantiationError::Link)?;
 
         Instance::new(&self.store, module, &imports)
     }
 
+    /// Resolves the imports of `module` with the items defined in this linker
+    /// once, returning an [`InstancePre`] which instantiates it without
+    /// resolving them again.
+    ///
+    /// Items defined in this linker afterwards don't affect the returned
+    /// [`InstancePre`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an import of `module` isn't defined in this linker
+    /// or is defined with the wrong type, like [`Linker::instantiate`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let mut linker = Linker::new(&store);
+    /// linker.func("host", "double", |x: i32| x * 2)?;
+    ///
+    /// let wat = r#"
+    ///     (module
+    ///         (import "host" "double" (func (param i32) (result i32)))
+    ///     )
+    /// "#;
+    /// let module = Module::new(store.engine(), wat)?;
+    /// let pre = linker.instantiate_pre(&module)?;
+    /// for _ in 0..10 {
+    ///     pre.instantiate()?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn instantiate_pre(&self, module: &Module) -> Result<InstancePre> {
+        let imports = self
+            .compute_imports(module)
+            .map_err(InstantiationError::Link)?;
+        InstancePre::new(&self.store, module, imports)
+    }
+
     fn compute_imports(&self, module: &Module) -> Result<Vec<Extern>> {
         module
             .imports()
@@ -586,19 +684,21 @@ impl Linker {
 
     fn link_error(&self, import: &ImportType) -> Error {
         let mut options = Vec::new();
-        for i in self.map.keys() {
-            if &*self.strings[i.module] != import.module()
-                || &*self.strings[i.name] != import.name()
+        for (key, item) in self.map.iter() {
+            if &*self.strings[key.module] != import.module()
+                || &*self.strings[key.name] != import.name()
             {
                 continue;
             }
-            options.push(format!("  * {:?}\n", i.kind));
+            options.push(format!("  * {}\n", item.ty()));
         }
         if options.is_empty() {
             return anyhow!(
-                "unknown import: `{}::{}` has not been defined",
+                "unknown import: `{}::{}` has not been defined\n\
+                     desired type was: {}",
                 import.module(),
-                import.name()
+                import.name(),
+                import.ty(),
             );
         }
 
@@ -606,8 +706,8 @@ impl Linker {
 
         anyhow!(
             "incompatible import type for `{}::{}` specified\n\
-                 desired signature was: {:?}\n\
-                 signatures available:\n\n{}",
+                 desired type was: {}\n\
+                 types available:\n\n{}",
             import.module(),
             import.name(),
             import.ty(),
@@ -641,8 +741,27 @@ impl Linker {
     /// Looks up a value in this `Linker` which matches the `import` type
     /// provided.
     ///
+    /// Function imports which aren't defined in this linker are looked up in
+    /// the host functions of the store's [`Config`] too, see
+    /// [`Store::get_host_func`].
+    ///
     /// Returns `None` if no match was found.
+    ///
+    /// [`Config`]: crate::Config
     pub fn get(&self, import: &ImportType) -> Option<Extern> {
+        if let Some(item) = self._get(import) {
+            return Some(item);
+        }
+        match import.ty() {
+            ExternType::Func(_) => self
+                .store
+                .get_host_func(import.module(), import.name())
+                .map(Extern::Func),
+            _ => None,
+        }
+    }
+
+    fn _get(&self, import: &ImportType) -> Option<Extern> {
         let key = ImportKey {
             module: *self.string2idx.get(import.module())?,
             name: *self.string2idx.get(import.name())?,
@@ -1,13 +1,23 @@
 use crate::frame_info::GlobalFrameInfoRegistration;
-use crate::runtime::{Config, Engine};
+use crate::runtime::Engine;
 use crate::types::{EntityType, ExportType, ExternType, ImportType};
 use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use target_lexicon::Triple;
 use wasmparser::Validator;
 #[cfg(feature = "cache")]
 use wasmtime_cache::ModuleCacheEntry;
+use wasmtime_environ::wasm::FuncIndex;
+#[cfg(feature = "disas")]
+use wasmtime_environ::FunctionAddressMap;
+#[cfg(unix)]
+use wasmtime_jit::MappedCompilationArtifacts;
 use wasmtime_jit::{CompilationArtifacts, CompiledModule};
+#[cfg(unix)]
+use wasmtime_runtime::Mmap;
 
 /// A compiled WebAssembly module, ready to be instantiated.
 ///
@@ -239,6 +249,8 @@ impl Module {
     /// # }
     /// ```
     pub fn from_binary(engine: &Engine, binary: &[u8]) -> Result<Module> {
+        check_target_is_host(engine)?;
+
         #[cfg(feature = "cache")]
         let artifacts = ModuleCacheEntry::new("wasmtime", engine.cache_config())
             .get_data((engine.compiler(), binary), |(compiler, binary)| {
@@ -252,6 +264,7 @@ impl Module {
             engine.compiler().isa(),
             &*engine.config().profiler,
         )?;
+        check_shared_memories(engine, &compiled)?;
 
         Ok(Module {
             engine: engine.clone(),
@@ -286,43 +299,89 @@ impl Module {
         Ok(())
     }
 
-    /// Serialize compilation artifacts to the buffer. See also `deseriaize`.
+    /// Serializes this module's compiled code into a buffer which can be
+    /// loaded with [`Module::deserialize`], skipping compilation.
+    ///
+    /// Besides the compiled code and everything needed to instantiate it, the
+    /// buffer describes the Wasmtime version and the compilation settings it
+    /// was compiled with, so that loading it with an incompatible engine fails
+    /// with an explanation. Like with [`Engine::precompile_module`], the
+    /// buffer is the same every time a module is compiled from the same bytes
+    /// with the same configuration.
     pub fn serialize(&self) -> Result<Vec<u8>> {
-        let artifacts = (
-            compiler_fingerprint(self.engine.config()),
-            self.compiled.to_compilation_artifacts(),
-        );
+        self.serialize_with(&SerializeOptions::default())
+    }
 
-        let mut buffer = Vec::new();
-        bincode::serialize_into(&mut buffer, &artifacts)?;
+    /// Like [`Module::serialize`], but allows leaving out the parts of the
+    /// module which aren't needed to run it, and compressing the buffer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let engine = Engine::default();
+    /// let module = Module::new(&engine, "(module (func $f (export \"f\")))")?;
+    /// let serialized = module.serialize_with(
+    ///     SerializeOptions::new()
+    ///         .strip_names(true)
+    ///         .compress(true),
+    /// )?;
+    /// let module = Module::deserialize(&engine, &serialized)?;
+    /// assert_eq!(module.func_name(0), None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serialize_with(&self, options: &SerializeOptions) -> Result<Vec<u8>> {
+        serialize_artifacts(
+            &self.engine,
+            self.compiled.to_compilation_artifacts(),
+            options,
+        )
+    }
 
-        Ok(buffer)
+    /// Implementation of `Engine::precompile_module_with`.
+    pub(crate) fn precompile(
+        engine: &Engine,
+        bytes: &[u8],
+        options: &SerializeOptions,
+    ) -> Result<Vec<u8>> {
+        #[cfg(feature = "wat")]
+        let bytes = wat::parse_bytes(bytes)?;
+        let artifacts = CompilationArtifacts::build(engine.compiler(), &bytes)?;
+        serialize_artifacts(engine, artifacts, options)
     }
 
-    /// Deserializes and creates a module from the compilation artifacts.
-    /// The `serialize` saves the compilation artifacts along with the host
-    /// fingerprint, which consists of target, compiler flags, and wasmtime
-    /// package version.
+    /// Creates a module from a buffer produced by [`Module::serialize`] or
+    /// [`Engine::precompile_module`], without compiling anything.
+    ///
+    /// This fails if the module was serialized by another version of
+    /// Wasmtime, or compiled with settings other than the ones `engine` uses:
+    /// its target, Cranelift settings, enabled wasm features, and memory and
+    /// instrumentation settings all have to match.
     ///
-    /// The method will fail if fingerprints of current host and serialized
-    /// one are different. The method does not verify the serialized artifacts
-    /// for modifications or curruptions. All responsibily of signing and its
-    /// verification falls on the embedder.
+    /// Note that the compiled code itself isn't verified in any way, so
+    /// loading a corrupted or maliciously crafted buffer can do anything. It's
+    /// up to the embedder to make sure that the buffer comes from a trusted
+    /// source, for example by signing it.
     pub fn deserialize(engine: &Engine, serialized: &[u8]) -> Result<Module> {
-        let expected_fingerprint = compiler_fingerprint(engine.config());
-
-        let (fingerprint, artifacts) =
-            bincode::deserialize_from::<_, (u64, CompilationArtifacts)>(serialized)
-                .context("Deserialize compilation artifacts")?;
-        if fingerprint != expected_fingerprint {
-            bail!("Incompatible compilation artifact");
-        }
+        let (header, artifacts) = read_serialized_header(engine, serialized)?;
+        let decompressed;
+        let artifacts = if header.compressed {
+            decompressed = decompress(artifacts)?;
+            &decompressed[..]
+        } else {
+            artifacts
+        };
+        let artifacts = bincode::deserialize::<CompilationArtifacts>(artifacts)
+            .context("failed to read the compiled code of the serialized module")?;
 
         let compiled = CompiledModule::from_artifacts(
             artifacts,
             engine.compiler().isa(),
             &*engine.config().profiler,
         )?;
+        check_shared_memories(engine, &compiled)?;
 
         Ok(Module {
             engine: engine.clone(),
@@ -331,6 +390,91 @@ impl Module {
         })
     }
 
+    /// Creates a module from a file containing a module serialized with
+    /// [`Module::serialize`] or [`Engine::precompile_module`].
+    ///
+    /// This is like [`Module::deserialize`], except that the file is mapped
+    /// into memory and its code executed from there instead of being copied,
+    /// so that loading large modules is cheap and that their code can be
+    /// shared with other processes through the page cache. Within a process,
+    /// all engines loading the same file share a single copy of the module
+    /// for as long as any of them is using it. None of this applies to
+    /// modules serialized with [`SerializeOptions::compress`], which are
+    /// decompressed into memory like with [`Module::deserialize`].
+    ///
+    /// # Unsafety
+    ///
+    /// The file must not be modified or truncated for as long as the module is
+    /// alive, otherwise the code executed when calling into it is undefined.
+    #[cfg(unix)]
+    pub unsafe fn deserialize_file(engine: &Engine, path: impl AsRef<Path>) -> Result<Module> {
+        use std::os::unix::fs::MetadataExt;
+
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("failed to open: {}", path.display()))?;
+        let metadata = file.metadata()?;
+        let len = metadata.len() as usize;
+        let mut image = Mmap::with_at_least(len).map_err(anyhow::Error::msg)?;
+        image.map_file(0, &file, len).map_err(anyhow::Error::msg)?;
+        let image = Arc::new(image);
+        let (header, artifacts) = read_serialized_header(engine, &image.as_slice()[..len])?;
+
+        // The code of compressed modules can't be used in place.
+        if header.compressed {
+            return Module::deserialize(engine, &image.as_slice()[..len]);
+        }
+
+        // Engines only get this far if they have the settings the module was
+        // compiled with, so they can all share it.
+        let key = MappedFileKey {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            len: metadata.len(),
+            mtime: (metadata.mtime(), metadata.mtime_nsec()),
+        };
+        let mut modules = MAPPED_MODULES.lock().unwrap();
+        if let Some((compiled, frame_info_registration)) = modules.get(&key) {
+            if let (Some(compiled), Some(frame_info_registration)) =
+                (compiled.upgrade(), frame_info_registration.upgrade())
+            {
+                // The module may have been loaded by an engine with other
+                // settings than this one's.
+                check_shared_memories(engine, &compiled)?;
+                return Ok(Module {
+                    engine: engine.clone(),
+                    compiled,
+                    frame_info_registration,
+                });
+            }
+        }
+
+        let artifacts = bincode::deserialize::<MappedCompilationArtifacts>(artifacts)
+            .context("failed to read the compiled code of the serialized module")?;
+        let compiled = CompiledModule::from_mapped_artifacts(
+            artifacts,
+            &image,
+            &file,
+            engine.compiler().isa(),
+            &*engine.config().profiler,
+        )?;
+        check_shared_memories(engine, &compiled)?;
+        let module = Module {
+            engine: engine.clone(),
+            compiled: Arc::new(compiled),
+            frame_info_registration: Arc::new(Mutex::new(None)),
+        };
+        modules.retain(|_, (compiled, _)| compiled.strong_count() > 0);
+        modules.insert(
+            key,
+            (
+                Arc::downgrade(&module.compiled),
+                Arc::downgrade(&module.frame_info_registration),
+            ),
+        );
+        Ok(module)
+    }
+
     pub(crate) fn compiled_module(&self) -> &CompiledModule {
         &self.compiled
     }
@@ -363,6 +507,36 @@ impl Module {
         self.compiled.module().name.as_deref()
     }
 
+    /// Returns the name of the function at `index` in this module's function
+    /// index space, as given by its name section, if any.
+    ///
+    /// Names are not available if the module was compiled with
+    /// [`Config::strip_names`](crate::Config::strip_names) enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let engine = Engine::default();
+    /// let module = Module::new(
+    ///     &engine,
+    ///     r#"(module (func $log (import "" "log")) (func $run (export "run")))"#,
+    /// )?;
+    /// assert_eq!(module.func_name(0), Some("log"));
+    /// assert_eq!(module.func_name(1), Some("run"));
+    /// assert_eq!(module.func_name(2), None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn func_name(&self, index: u32) -> Option<&str> {
+        self.compiled
+            .module()
+            .func_names
+            .get(&FuncIndex::from_u32(index))
+            .map(|name| name.as_str())
+    }
+
     /// Returns the list of imports that this [`Module`] has and must be
     /// satisfied.
     ///
@@ -538,6 +712,170 @@ impl Module {
         Some(EntityType::new(entity_index, module).extern_type())
     }
 
+    /// Returns the data segments of this [`Module`].
+    ///
+    /// Active segments, which are copied into a memory when the module is
+    /// instantiated, are returned first in the order they're declared in the
+    /// module. Passive segments, which are only used through `memory.init`,
+    /// follow in order of their segment index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let engine = Engine::default();
+    /// let wat = r#"
+    ///     (module
+    ///         (memory 1)
+    ///         (data (i32.const 8) "hello"))
+    /// "#;
+    /// let module = Module::new(&engine, wat)?;
+    /// let segment = module.data_segments().next().unwrap();
+    /// assert_eq!(segment.data(), b"hello");
+    /// match segment.mode() {
+    ///     SegmentMode::Active { index, base, offset } => {
+    ///         assert_eq!((index, base, offset), (0, None, 8));
+    ///     }
+    ///     SegmentMode::Passive { .. } => unreachable!(),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn data_segments<'module>(
+        &'module self,
+    ) -> impl ExactSizeIterator<Item = DataSegment<'module>> + 'module {
+        let active = self
+            .compiled
+            .data_initializers()
+            .into_iter()
+            .map(|init| DataSegment {
+                mode: SegmentMode::Active {
+                    index: init.location.memory_index.as_u32(),
+                    base: init.location.base.map(|g| g.as_u32()),
+                    offset: init.location.offset,
+                },
+                data: init.data,
+            });
+        let passive = self
+            .compiled
+            .module()
+            .passive_data
+            .iter()
+            .map(|(index, data)| DataSegment {
+                mode: SegmentMode::Passive {
+                    index: index.as_u32(),
+                },
+                data,
+            });
+        active.chain(passive).collect::<Vec<_>>().into_iter()
+    }
+
+    /// Returns the element segments of this [`Module`].
+    ///
+    /// Active segments, which are copied into a table when the module is
+    /// instantiated, are returned first in the order they're declared in the
+    /// module. Passive segments, which are only used through `table.init`,
+    /// follow in order of their segment index. Declared segments are not
+    /// returned since they have no effect on instances.
+    pub fn element_segments<'module>(
+        &'module self,
+    ) -> impl ExactSizeIterator<Item = ElementSegment<'module>> + 'module {
+        let module = self.compiled.module();
+        let active = module.table_elements.iter().map(|init| ElementSegment {
+            mode: SegmentMode::Active {
+                index: init.table_index.as_u32(),
+                base: init.base.map(|g| g.as_u32()),
+                offset: init.offset,
+            },
+            functions: &init.elements,
+        });
+        let passive = module
+            .passive_elements
+            .iter()
+            .map(|(index, functions)| ElementSegment {
+                mode: SegmentMode::Passive {
+                    index: index.as_u32(),
+                },
+                functions,
+            });
+        active.chain(passive).collect::<Vec<_>>().into_iter()
+    }
+
+    /// Returns statistics about the compiled code of this [`Module`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let engine = Engine::default();
+    /// let module = Module::new(
+    ///     &engine,
+    ///     r#"(module (func $f (import "" "f")) (func $g call $f) (func $h))"#,
+    /// )?;
+    /// let stats = module.stats();
+    /// assert_eq!(stats.functions.len(), 2);
+    /// assert_eq!(stats.functions[0].index, 1);
+    /// assert!(stats.functions[0].code_size > 0);
+    /// assert!(stats.code_memory_size >= stats.functions[0].code_size);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stats(&self) -> ModuleStats {
+        let module = self.compiled.module();
+        let compile_times = self.compiled.compile_times();
+        let relocations = self.compiled.relocation_counts();
+        let functions = self
+            .compiled
+            .finished_functions()
+            .iter()
+            .map(|(index, body)| FunctionStats {
+                index: module.func_index(index).as_u32(),
+                code_size: unsafe { (**body).len() },
+                relocations: relocations[index],
+                compile_time: compile_times.map(|times| times[index]),
+            })
+            .collect();
+        ModuleStats {
+            functions,
+            code_memory_size: self.compiled.code_memory_size(),
+        }
+    }
+
+    /// Returns the machine code of the function at `index` in the module's
+    /// function index space.
+    ///
+    /// Returns `None` if `index` is out of bounds or refers to an imported
+    /// function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let engine = Engine::default();
+    /// let module = Module::new(&engine, "(module (func (result i32) i32.const 42))")?;
+    /// let code = module.function_code(0).unwrap();
+    /// assert!(!code.bytes().is_empty());
+    /// println!("{}", code.disassemble()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "disas")]
+    pub fn function_code(&self, index: u32) -> Option<FunctionCode<'_>> {
+        let index = self
+            .compiled
+            .module()
+            .defined_func_index(FuncIndex::from_u32(index))?;
+        let body = *self.compiled.finished_functions().get(index)?;
+        Some(FunctionCode {
+            bytes: unsafe { &*(body as *const [u8]) },
+            address_map: self.compiled.address_map(index),
+            triple: self.engine.config().target_triple(),
+        })
+    }
+
     /// Returns the [`Engine`] that this [`Module`] was compiled by.
     pub fn engine(&self) -> &Engine {
         &self.engine
@@ -557,11 +895,513 @@ impl Module {
     }
 }
 
-fn compiler_fingerprint(config: &Config) -> u64 {
-    use std::hash::Hasher;
-    let mut hasher = std::collections::hash_map::DefaultHasher::new();
-    config.compiler_fingerprint(&mut hasher);
-    hasher.finish()
+/// How a data or element segment is used by a [`Module`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentMode {
+    /// The segment is copied into the memory or table `index` when the module
+    /// is instantiated.
+    ///
+    /// It's copied to `offset`, plus the value of the global `base` at
+    /// instantiation time if `base` is present.
+    Active {
+        /// The index of the memory or table the segment initializes.
+        index: u32,
+        /// The index of the global the segment's offset is relative to, if any.
+        base: Option<u32>,
+        /// The constant offset the segment is copied to.
+        offset: usize,
+    },
+    /// The segment is only used through `memory.init` or `table.init`, which
+    /// refer to it by `index`.
+    Passive {
+        /// The index of this segment in the module's data or element section.
+        index: u32,
+    },
+}
+
+/// Statistics about the compiled code of a [`Module`], as returned by
+/// [`Module::stats`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ModuleStats {
+    /// Statistics about each function defined by the module, in order of
+    /// their index. Imported functions are not included.
+    pub functions: Vec<FunctionStats>,
+    /// The size in bytes of the executable memory allocated for the module's
+    /// code, which includes trampolines and padding besides the functions.
+    pub code_memory_size: usize,
+}
+
+/// Statistics about a compiled function, see [`ModuleStats`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct FunctionStats {
+    /// The index of the function in the module's function index space.
+    pub index: u32,
+    /// The size in bytes of the function's machine code.
+    pub code_size: usize,
+    /// The number of relocations which were applied to the function's
+    /// machine code when it was loaded, such as calls to other functions or
+    /// runtime functions.
+    pub relocations: usize,
+    /// How long compiling the function took, or `None` if the module wasn't
+    /// compiled by this process, for example if it was deserialized or loaded
+    /// from the compilation cache.
+    pub compile_time: Option<Duration>,
+}
+
+/// The machine code of a compiled function, as returned by
+/// [`Module::function_code`].
+#[cfg(feature = "disas")]
+#[derive(Debug, Clone)]
+pub struct FunctionCode<'module> {
+    bytes: &'module [u8],
+    address_map: &'module FunctionAddressMap,
+    triple: Triple,
+}
+
+#[cfg(feature = "disas")]
+impl<'module> FunctionCode<'module> {
+    /// Returns the function's machine code, as it was loaded into memory.
+    pub fn bytes(&self) -> &'module [u8] {
+        self.bytes
+    }
+
+    /// Returns the address of the function's machine code in memory.
+    pub fn address(&self) -> usize {
+        self.bytes.as_ptr() as usize
+    }
+
+    /// Returns the mapping of the function's machine code to the wasm
+    /// instructions it was generated from.
+    ///
+    /// Each item is the offset of some machine code within [`bytes`], the
+    /// length of that code, and the offset from the beginning of the original
+    /// wasm module of the instruction it implements. The items are sorted by
+    /// their code offset, and code which isn't attributed to any instruction,
+    /// such as the function's prologue, isn't included.
+    ///
+    /// [`bytes`]: FunctionCode::bytes
+    pub fn address_map(&self) -> impl Iterator<Item = (usize, usize, usize)> + 'module {
+        self.address_map
+            .instructions
+            .iter()
+            .filter(|i| !i.srcloc.is_default())
+            .map(|i| (i.code_offset, i.code_len, i.srcloc.bits() as usize))
+    }
+
+    /// Disassembles the function's machine code, one instruction per line,
+    /// each prefixed with its offset within [`bytes`].
+    ///
+    /// The code is decoded for the architecture of the target the module's
+    /// [`Engine`] compiles for, which isn't necessarily the host's.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there's no disassembler for that architecture.
+    ///
+    /// [`bytes`]: FunctionCode::bytes
+    pub fn disassemble(&self) -> Result<String> {
+        use capstone::prelude::*;
+        use std::fmt::Write;
+
+        let cs = match self.triple.architecture {
+            target_lexicon::Architecture::X86_64 => Capstone::new()
+                .x86()
+                .mode(arch::x86::ArchMode::Mode64)
+                .build()?,
+            target_lexicon::Architecture::Aarch64(_) => {
+                let mut cs = Capstone::new()
+                    .arm64()
+                    .mode(arch::arm64::ArchMode::Arm)
+                    .build()?;
+                // Constants are inlined into the code on AArch64, which would
+                // otherwise stop the disassembly at the first one.
+                cs.set_skipdata(true)?;
+                cs
+            }
+            arch => bail!("no disassembler available for {}", arch),
+        };
+
+        let mut ret = String::new();
+        for insn in cs.disasm_all(self.bytes, 0)?.iter() {
+            write!(
+                ret,
+                "{:6x}:  {}",
+                insn.address(),
+                insn.mnemonic().unwrap_or("")
+            )?;
+            if let Some(operands) = insn.op_str() {
+                write!(ret, " {}", operands)?;
+            }
+            ret.push('\n');
+        }
+        Ok(ret)
+    }
+}
+
+/// Options for serializing modules with [`Module::serialize_with`] and
+/// [`Engine::precompile_module_with`].
+///
+/// [`Engine::precompile_module_with`]: crate::Engine::precompile_module_with
+#[derive(Debug, Clone, Default)]
+pub struct SerializeOptions {
+    strip_names: bool,
+    strip_debug_info: bool,
+    compress: bool,
+}
+
+impl SerializeOptions {
+    /// Creates options which serialize modules in full and uncompressed, like
+    /// [`Module::serialize`].
+    pub fn new() -> SerializeOptions {
+        SerializeOptions::default()
+    }
+
+    /// Leaves out the names of the module and its functions from its name
+    /// section, which are only used to describe [`Trap`] backtraces and for
+    /// [`Module::name`] and [`Module::func_name`].
+    ///
+    /// By default names are kept.
+    ///
+    /// [`Trap`]: crate::Trap
+    pub fn strip_names(&mut self, strip: bool) -> &mut Self {
+        self.strip_names = strip;
+        self
+    }
+
+    /// Leaves out the DWARF debug information of modules compiled with
+    /// [`Config::debug_info`] enabled. The serialized module can then only
+    /// be loaded by engines with it disabled.
+    ///
+    /// By default debug information is kept.
+    ///
+    /// [`Config::debug_info`]: crate::Config::debug_info
+    pub fn strip_debug_info(&mut self, strip: bool) -> &mut Self {
+        self.strip_debug_info = strip;
+        self
+    }
+
+    /// Compresses the compiled code and metadata of the module with zstd.
+    /// The compression level is high, which makes compressing slow, but the
+    /// buffer is decompressed quickly when deserialized.
+    ///
+    /// Serializing and deserializing compressed modules requires the `zstd`
+    /// feature of this crate, which is enabled by default. By default modules
+    /// aren't compressed.
+    pub fn compress(&mut self, compress: bool) -> &mut Self {
+        self.compress = compress;
+        self
+    }
+}
+
+/// A data segment of a [`Module`], as returned by [`Module::data_segments`].
+#[derive(Debug, Clone)]
+pub struct DataSegment<'module> {
+    mode: SegmentMode,
+    data: &'module [u8],
+}
+
+impl<'module> DataSegment<'module> {
+    /// Returns how this segment is used by its module.
+    pub fn mode(&self) -> SegmentMode {
+        self.mode
+    }
+
+    /// Returns the bytes of this segment.
+    pub fn data(&self) -> &'module [u8] {
+        self.data
+    }
+}
+
+/// An element segment of a [`Module`], as returned by
+/// [`Module::element_segments`].
+#[derive(Debug, Clone)]
+pub struct ElementSegment<'module> {
+    mode: SegmentMode,
+    functions: &'module [FuncIndex],
+}
+
+impl<'module> ElementSegment<'module> {
+    /// Returns how this segment is used by its module.
+    pub fn mode(&self) -> SegmentMode {
+        self.mode
+    }
+
+    /// Returns the indices of the functions in this segment.
+    pub fn functions(&self) -> impl ExactSizeIterator<Item = u32> + 'module {
+        self.functions.iter().map(|f| f.as_u32())
+    }
+}
+
+/// The prefix of all serialized modules.
+const SERIALIZED_MODULE_MAGIC: &[u8] = b"\0wasmtime-module";
+
+/// Describes the compilation settings the code of a serialized module was
+/// compiled with, all of which an engine has to match to load the module.
+///
+/// Settings are recorded as names and values rather than as a hash so that a
+/// mismatch can be reported precisely.
+#[derive(Serialize, Deserialize)]
+struct SerializedModuleHeader {
+    target: String,
+    cranelift_settings: Vec<(String, String)>,
+    wasm_features: Vec<(String, String)>,
+    tunables: Vec<(String, String)>,
+    /// Whether the `CompilationArtifacts` following the header are
+    /// compressed with zstd.
+    compressed: bool,
+}
+
+impl SerializedModuleHeader {
+    fn new(engine: &Engine) -> SerializedModuleHeader {
+        // Settings are only exposed through their `Display` impls, which list
+        // them grouped by sections such as `[shared]` and `[x86]`, one
+        // `name = value` per line. The old backends display all of the ISA's
+        // settings that way, but the new ones don't, in which case only the
+        // shared settings can be recorded.
+        let isa = engine.compiler().isa();
+        let mut settings = isa.to_string();
+        if !settings.starts_with("[shared]") {
+            settings = isa.flags().to_string();
+        }
+        let mut cranelift_settings = Vec::new();
+        let mut section = String::new();
+        for line in settings.lines() {
+            let line = line.trim();
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].to_string();
+            } else if let Some(eq) = line.find(" = ") {
+                let name = &line[..eq];
+                let name = if section == "shared" {
+                    name.to_string()
+                } else {
+                    format!("{}.{}", section, name)
+                };
+                cranelift_settings.push((name, line[eq + 3..].to_string()));
+            }
+        }
+
+        let config = engine.config();
+        let features = &config.features;
+        let tunables = &config.tunables;
+        let settings = |settings: &[(&str, &dyn ToString)]| {
+            settings
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        SerializedModuleHeader {
+            target: config.target_triple().to_string(),
+            cranelift_settings,
+            wasm_features: settings(&[
+                ("threads", &features.threads),
+                ("reference_types", &features.reference_types),
+                ("simd", &features.simd),
+                ("bulk_memory", &features.bulk_memory),
+                ("multi_value", &features.multi_value),
+            ]),
+            tunables: settings(&[
+                ("static_memory_bound", &tunables.static_memory_bound),
+                (
+                    "static_memory_offset_guard_size",
+                    &tunables.static_memory_offset_guard_size,
+                ),
+                (
+                    "dynamic_memory_offset_guard_size",
+                    &tunables.dynamic_memory_offset_guard_size,
+                ),
+                ("debug_info", &tunables.debug_info),
+                ("interruptable", &tunables.interruptable),
+                ("consume_fuel", &tunables.consume_fuel),
+                ("epoch_interruption", &tunables.epoch_interruption),
+                ("strip_names", &tunables.strip_names),
+            ]),
+            compressed: false,
+        }
+    }
+
+    /// Checks that a module compiled with `self` can be loaded by an engine
+    /// described by `engine`.
+    fn check_compatible(&self, engine: &SerializedModuleHeader) -> Result<()> {
+        if self.target != engine.target {
+            bail!(
+                "module was compiled for target `{}`, but the engine targets `{}`",
+                self.target,
+                engine.target
+            );
+        }
+        // Some wasm features imply Cranelift settings, so check them first to
+        // report the setting the embedder actually configured.
+        check_settings("wasm feature", &self.wasm_features, &engine.wasm_features)?;
+        check_settings(
+            "Cranelift setting",
+            &self.cranelift_settings,
+            &engine.cranelift_settings,
+        )?;
+        check_settings("setting", &self.tunables, &engine.tunables)?;
+        Ok(())
+    }
+}
+
+fn check_settings(
+    kind: &str,
+    module: &[(String, String)],
+    engine: &[(String, String)],
+) -> Result<()> {
+    for (name, value) in engine {
+        match module.iter().find(|(n, _)| n == name) {
+            Some((_, v)) if v == value => {}
+            Some((_, v)) => bail!(
+                "module was compiled with {} `{}` set to {}, but the engine has it set to {}",
+                kind,
+                name,
+                v,
+                value
+            ),
+            None => bail!("module was compiled without {} `{}`", kind, name),
+        }
+    }
+    if let Some((name, _)) = module
+        .iter()
+        .find(|(n, _)| !engine.iter().any(|(m, _)| m == n))
+    {
+        bail!("module was compiled with unknown {} `{}`", kind, name);
+    }
+    Ok(())
+}
+
+/// Checks that `serialized` is a module serialized by this version of
+/// Wasmtime with settings that `engine` can load, returning its header and
+/// the rest of the buffer, where its `CompilationArtifacts` are.
+fn read_serialized_header<'a>(
+    engine: &Engine,
+    serialized: &'a [u8],
+) -> Result<(SerializedModuleHeader, &'a [u8])> {
+    check_target_is_host(engine)?;
+    if !serialized.starts_with(SERIALIZED_MODULE_MAGIC) {
+        bail!("not a serialized Wasmtime module");
+    }
+    let mut reader = &serialized[SERIALIZED_MODULE_MAGIC.len()..];
+
+    // The version comes first, on its own, so that it can always be checked
+    // even if the layout of the rest changes.
+    let version = bincode::deserialize_from::<_, String>(&mut reader)
+        .context("failed to read the version of the serialized module")?;
+    if version != env!("CARGO_PKG_VERSION") {
+        bail!(
+            "module was serialized by Wasmtime {}, but this is Wasmtime {}",
+            version,
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+    let header = bincode::deserialize_from::<_, SerializedModuleHeader>(&mut reader)
+        .context("failed to read the header of the serialized module")?;
+    header.check_compatible(&SerializedModuleHeader::new(engine))?;
+    Ok((header, reader))
+}
+
+/// Identifies a file loaded by `Module::deserialize_file`, as long as it
+/// isn't modified.
+#[cfg(unix)]
+#[derive(PartialEq, Eq, Hash)]
+struct MappedFileKey {
+    dev: u64,
+    ino: u64,
+    len: u64,
+    mtime: (i64, i64),
+}
+
+#[cfg(unix)]
+type MappedModule = (
+    std::sync::Weak<CompiledModule>,
+    std::sync::Weak<Mutex<Option<Option<Arc<GlobalFrameInfoRegistration>>>>>,
+);
+
+#[cfg(unix)]
+lazy_static::lazy_static! {
+    /// Modules loaded by `Module::deserialize_file` which are still alive,
+    /// to share them between engines.
+    static ref MAPPED_MODULES: Mutex<std::collections::HashMap<MappedFileKey, MappedModule>> =
+        Default::default();
+}
+
+fn serialize_artifacts(
+    engine: &Engine,
+    mut artifacts: CompilationArtifacts,
+    options: &SerializeOptions,
+) -> Result<Vec<u8>> {
+    let mut header = SerializedModuleHeader::new(engine);
+    if options.strip_names {
+        artifacts.strip_names();
+    }
+    if options.strip_debug_info {
+        artifacts.strip_debug_info()?;
+        // The code itself doesn't depend on debug information being emitted,
+        // so without it the module can be loaded by engines which don't emit
+        // any.
+        for (name, value) in header.tunables.iter_mut() {
+            if name == "debug_info" {
+                *value = false.to_string();
+            }
+        }
+    }
+    header.compressed = options.compress;
+
+    let mut buffer = SERIALIZED_MODULE_MAGIC.to_vec();
+    bincode::serialize_into(&mut buffer, env!("CARGO_PKG_VERSION"))?;
+    bincode::serialize_into(&mut buffer, &header)?;
+    if options.compress {
+        buffer.extend(compress(&bincode::serialize(&artifacts)?)?);
+    } else {
+        bincode::serialize_into(&mut buffer, &artifacts)?;
+    }
+    Ok(buffer)
+}
+
+#[cfg(feature = "zstd")]
+fn compress(bytes: &[u8]) -> Result<Vec<u8>> {
+    Ok(zstd::encode_all(bytes, 19)?)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress(_bytes: &[u8]) -> Result<Vec<u8>> {
+    bail!("compressing modules requires the `zstd` feature of wasmtime")
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>> {
+    zstd::decode_all(bytes).context("failed to decompress the serialized module")
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress(_bytes: &[u8]) -> Result<Vec<u8>> {
+    bail!("module is compressed, which requires the `zstd` feature of wasmtime to load")
+}
+
+/// Code compiled for another target can't be loaded for execution, only
+/// serialized by `Engine::precompile_module`.
+fn check_target_is_host(engine: &Engine) -> Result<()> {
+    let target = engine.config().target_triple();
+    if target != Triple::host() {
+        bail!(
+            "cannot load code compiled for target `{}` on host `{}`, use \
+             `Engine::precompile_module` to compile it ahead of time instead",
+            target,
+            Triple::host()
+        );
+    }
+    Ok(())
+}
+
+fn check_shared_memories(engine: &Engine, compiled: &CompiledModule) -> Result<()> {
+    let module = compiled.module();
+    if module.memory_plans.values().any(|plan| plan.memory.shared) {
+        engine
+            .config()
+            .check_memory_faults_allowed("shared memories")?;
+    }
+    Ok(())
 }
 
 fn _assert_send_sync() {
@@ -1,9 +1,52 @@
 #![allow(missing_docs)]
 
+use crate::Store;
 use std::any::Any;
 use wasmtime_runtime::VMExternRef;
 
 /// Represents an opaque reference to any data within WebAssembly.
+///
+/// An `ExternRef` wraps an arbitrary `'static` host value and can be passed to
+/// and from wasm as an `externref`, stored in `externref` tables and globals,
+/// and held in wasm locals and on the wasm stack. Wasm can't inspect the value,
+/// only pass the reference around.
+///
+/// `ExternRef`s are reference counted, and cloning one only clones the
+/// reference. The wrapped value is dropped, running its destructor, once the
+/// last reference to it goes away. References held by wasm frames are only
+/// released when the store performs a garbage collection, which happens
+/// periodically while passing references into wasm, when an `externref` table
+/// grows, or explicitly through [`Store::gc`](crate::Store::gc).
+///
+/// # Example
+///
+/// ```
+/// # use wasmtime::*;
+/// # use std::cell::Cell;
+/// # use std::rc::Rc;
+/// # fn main() -> anyhow::Result<()> {
+/// struct SetOnDrop(Rc<Cell<bool>>);
+///
+/// impl Drop for SetOnDrop {
+///     fn drop(&mut self) {
+///         self.0.set(true);
+///     }
+/// }
+///
+/// let store = Store::default();
+/// let dropped = Rc::new(Cell::new(false));
+/// let r = ExternRef::new(SetOnDrop(dropped.clone()));
+/// assert!(r.data().is::<SetOnDrop>());
+///
+/// let ty = GlobalType::new(ValType::ExternRef, Mutability::Var);
+/// let global = Global::new(&store, ty, Val::ExternRef(Some(r)))?;
+/// assert!(!dropped.get());
+///
+/// global.set(Val::ExternRef(None))?;
+/// assert!(dropped.get());
+/// # Ok(())
+/// # }
+/// ```
 #[derive(Clone, Debug)]
 pub struct ExternRef {
     pub(crate) inner: VMExternRef,
@@ -29,11 +72,54 @@ impl ExternRef {
         self.inner.strong_count()
     }
 
-    /// Does this `ExternRef` point to the same inner value as `other`?0
+    /// Does this `ExternRef` point to the same inner value as `other`?
     ///
     /// This is *only* pointer equality, and does *not* run any inner value's
     /// `Eq` implementation.
     pub fn ptr_eq(&self, other: &ExternRef) -> bool {
         VMExternRef::eq(&self.inner, &other.inner)
     }
+
+    /// Converts this reference into the raw pointer representation used by
+    /// [`Func::call_unchecked`], rooting it in `store`.
+    ///
+    /// The reference is inserted into the table of references `store` keeps
+    /// alive on behalf of running wasm, the same way [`Func::call`] does with
+    /// its arguments, so that it stays alive until a garbage collection of
+    /// `store` finds that wasm isn't using it anymore. That includes the
+    /// garbage collection which inserting it may perform, so `self`, or a
+    /// clone of it, must be kept alive until the pointer is passed to wasm.
+    ///
+    /// [`Func::call_unchecked`]: crate::Func::call_unchecked
+    /// [`Func::call`]: crate::Func::call
+    pub fn to_raw(&self, store: &Store) -> *mut u8 {
+        let raw = self.inner.as_raw();
+        unsafe {
+            store
+                .externref_activations_table()
+                .insert_with_gc(self.inner.clone(), store.stack_map_registry());
+        }
+        raw
+    }
+
+    /// Creates an `ExternRef` from its raw pointer representation, such as an
+    /// `externref` result of [`Func::call_unchecked`]. Returns `None` for the
+    /// null reference.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be null or a pointer to a live reference. References
+    /// returned by wasm are only kept alive by the store they come from until
+    /// its next garbage collection, so they must be converted before then.
+    ///
+    /// [`Func::call_unchecked`]: crate::Func::call_unchecked
+    pub unsafe fn from_raw(raw: *mut u8) -> Option<ExternRef> {
+        if raw.is_null() {
+            None
+        } else {
+            Some(ExternRef {
+                inner: VMExternRef::clone_from_raw(raw),
+            })
+        }
+    }
 }
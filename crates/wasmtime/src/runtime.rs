@@ -1,28 +1,37 @@
 use crate::externals::MemoryCreator;
-use crate::trampoline::{MemoryCreatorProxy, StoreInstanceHandle};
-use crate::Module;
-use anyhow::{bail, Result};
-use std::cell::RefCell;
+use crate::trampoline::{HostTrampolines, MemoryCreatorProxy, StoreInstanceHandle};
+use crate::{Caller, Func, FuncType, IntoFunc, Module, SerializeOptions, Trap, Val, WeakFunc};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
 use std::cmp;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::mem;
 #[cfg(feature = "cache")]
 use std::path::Path;
 use std::rc::{Rc, Weak};
-use std::sync::Arc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering::SeqCst};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use target_lexicon::Triple;
 use wasmparser::WasmFeatures;
 #[cfg(feature = "cache")]
 use wasmtime_cache::CacheConfig;
 use wasmtime_environ::settings::{self, Configurable, SetError};
-use wasmtime_environ::{ir, isa, isa::TargetIsa, wasm, Tunables};
+use wasmtime_environ::{ir, isa, isa::TargetIsa, wasm, Tunables, WASM_PAGE_SIZE};
 use wasmtime_jit::{native, CompilationStrategy, Compiler};
 use wasmtime_profiling::{JitDumpAgent, NullProfilerAgent, ProfilingAgent, VTuneAgent};
 use wasmtime_runtime::{
-    debug_builtins, InstanceHandle, RuntimeMemoryCreator, SignalHandler, SignatureRegistry,
-    StackMapRegistry, VMExternRef, VMExternRefActivationsTable, VMInterrupts,
-    VMSharedSignatureIndex,
+    debug_builtins, InstanceHandle, InstancePool, PoolingLimits, RuntimeMemoryCreator,
+    SignalHandler, SignatureRegistry, StackMapRegistry, VMExternRef, VMExternRefActivationsTable,
+    VMInterrupts, VMSharedSignatureIndex,
+};
+
+pub use wasmtime_runtime::{
+    Deadline, ResourceLimiter, DEFAULT_INSTANCE_LIMIT, DEFAULT_MEMORY_LIMIT, DEFAULT_TABLE_LIMIT,
 };
 
 // Runtime Environment
@@ -38,16 +47,27 @@ use wasmtime_runtime::{
 pub struct Config {
     pub(crate) flags: settings::Builder,
     pub(crate) isa_flags: isa::Builder,
+    pub(crate) target: Option<Triple>,
     pub(crate) tunables: Tunables,
     pub(crate) strategy: CompilationStrategy,
     #[cfg(feature = "cache")]
     pub(crate) cache_config: CacheConfig,
     pub(crate) profiler: Arc<dyn ProfilingAgent>,
     pub(crate) memory_creator: Option<MemoryCreatorProxy>,
+    pub(crate) allocation_strategy: InstanceAllocationStrategy,
     pub(crate) max_wasm_stack: usize,
+    pub(crate) wasm_backtrace: bool,
+    pub(crate) explicit_bounds_checks: bool,
+    pub(crate) function_cache: bool,
+    pub(crate) coredump_on_trap: bool,
+    pub(crate) coredump_memories: bool,
     pub(crate) features: WasmFeatures,
+    pub(crate) host_funcs: HashMap<(String, String), Arc<HostFunc>>,
 }
 
+/// Binds a host function defined in a [`Config`] to a [`Store`].
+type HostFunc = dyn Fn(&Store) -> Func + Send + Sync;
+
 impl Config {
     /// Creates a new configuration object with the default configuration
     /// specified.
@@ -88,25 +108,109 @@ impl Config {
             tunables,
             flags,
             isa_flags: native::builder(),
+            target: None,
             strategy: CompilationStrategy::Auto,
             #[cfg(feature = "cache")]
             cache_config: CacheConfig::new_cache_disabled(),
             profiler: Arc::new(NullProfilerAgent),
             memory_creator: None,
+            allocation_strategy: InstanceAllocationStrategy::OnDemand,
             max_wasm_stack: 1 << 20,
+            wasm_backtrace: true,
+            explicit_bounds_checks: false,
+            function_cache: false,
+            coredump_on_trap: false,
+            coredump_memories: false,
             features: WasmFeatures::default(),
+            host_funcs: HashMap::new(),
+        }
+    }
+
+    /// Configures the target for which code is compiled, given as a target
+    /// triple such as `aarch64-unknown-linux-gnu`.
+    ///
+    /// By default code is compiled for the host, making use of all the CPU
+    /// features it supports. An engine configured with another target can
+    /// only compile modules ahead of time, with
+    /// [`Engine::precompile_module`], and [`Module::new`] and
+    /// [`Module::deserialize`] fail with it. The serialized modules can then
+    /// be loaded with [`Module::deserialize`] on a matching host, by an engine
+    /// whose other compilation settings are the same.
+    ///
+    /// Code compiled for an explicit target only relies on the baseline CPU
+    /// features of its architecture. Since this resets any target-specific
+    /// flags, this should be called before
+    /// [`Config::cranelift_other_flag`].
+    ///
+    /// Support for targets other than the host's architecture requires the
+    /// `all-arch` feature of this crate.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `target` isn't a valid target triple, or if code
+    /// generation for it isn't supported.
+    pub fn target(&mut self, target: &str) -> Result<&mut Self> {
+        let triple = Triple::from_str(target)
+            .map_err(|e| anyhow!("failed to parse target `{}`: {}", target, e))?;
+        self.isa_flags = native::lookup(triple.clone())
+            .with_context(|| format!("cannot compile for target `{}`", target))?;
+        self.target = Some(triple);
+        Ok(self)
+    }
+
+    /// Returns the triple of the target code is compiled for.
+    pub(crate) fn target_triple(&self) -> Triple {
+        self.target.clone().unwrap_or_else(Triple::host)
+    }
+
+    /// Returns an error if memories relying on guard regions can't be used,
+    /// which shared memories always do since they can never be relocated.
+    pub(crate) fn check_memory_faults_allowed(&self, what: &str) -> Result<()> {
+        if self.explicit_bounds_checks {
+            bail!(
+                "{} cannot be used with `Config::explicit_bounds_checks`, which \
+                 doesn't handle memory faults",
+                what
+            );
         }
+        Ok(())
     }
 
     /// Configures whether DWARF debug information will be emitted during
     /// compilation.
     ///
+    /// This also reads the source locations of modules which carry their own
+    /// DWARF, so that the frames of traps can be resolved to the guest's
+    /// source code, see [`FrameInfo::source_location`].
+    ///
     /// By default this option is `false`.
+    ///
+    /// [`FrameInfo::source_location`]: crate::FrameInfo::source_location
     pub fn debug_info(&mut self, enable: bool) -> &mut Self {
         self.tunables.debug_info = enable;
         self
     }
 
+    /// Configures whether the names in the name section of modules are
+    /// dropped when they're compiled.
+    ///
+    /// These names are otherwise kept in memory for as long as the module is
+    /// alive, to describe the functions in [`Trap`] backtraces and profiles
+    /// and for [`Module::name`] and [`Module::func_name`]. Stripping them
+    /// saves that memory for modules with lots of functions, at the cost of
+    /// only referring to functions by index. DWARF debug information, if
+    /// enabled, still uses them.
+    ///
+    /// By default this option is `false`.
+    ///
+    /// [`Trap`]: crate::Trap
+    /// [`Module::name`]: crate::Module::name
+    /// [`Module::func_name`]: crate::Module::func_name
+    pub fn strip_names(&mut self, strip: bool) -> &mut Self {
+        self.tunables.strip_names = strip;
+        self
+    }
+
     /// Configures whether functions and loops will be interruptable via the
     /// [`Store::interrupt_handle`] method.
     ///
@@ -119,6 +223,55 @@ impl Config {
         self
     }
 
+    /// Configures whether execution of WebAssembly will "consume fuel" to
+    /// either halt or yield execution as desired.
+    ///
+    /// This option is similar in purpose to [`Config::interruptable`] where
+    /// you can prevent infinitely-executing WebAssembly code. The difference
+    /// is that this option allows deterministic execution of WebAssembly code
+    /// by instrumenting generated code to consume fuel as it executes. When fuel
+    /// runs out the wasm code traps, and [`Trap::is_out_of_fuel`] returns
+    /// `true` for the resulting trap.
+    ///
+    /// Every store starts out with no fuel, so [`Store::add_fuel`] must be
+    /// called before any wasm can run. Roughly one unit of fuel is consumed
+    /// per executed wasm instruction, although some instructions like `nop`,
+    /// `drop` and `block` are free.
+    ///
+    /// Note that this is only supported with the Cranelift code generator.
+    ///
+    /// By default this option is `false`.
+    ///
+    /// [`Trap::is_out_of_fuel`]: crate::Trap::is_out_of_fuel
+    pub fn consume_fuel(&mut self, enable: bool) -> &mut Self {
+        self.tunables.consume_fuel = enable;
+        self
+    }
+
+    /// Enables epoch-based interruption.
+    ///
+    /// When enabled, compiled code checks the [`Engine`]'s current epoch
+    /// against the [`Store`]'s epoch deadline at function entries and loop
+    /// headers, and traps with an interrupt once the deadline is reached. The
+    /// epoch is advanced with [`Engine::increment_epoch`], typically from a
+    /// separate timer thread, and the deadline is set per store with
+    /// [`Store::set_epoch_deadline`].
+    ///
+    /// This is cheaper than [`Config::consume_fuel`], since it doesn't count
+    /// individual instructions, but isn't deterministic. Unlike
+    /// [`Config::interruptable`] a single epoch increment can bound the
+    /// execution time of every store using the engine.
+    ///
+    /// Note that every store's deadline starts out at the current epoch, so
+    /// wasm traps on its first check until [`Store::set_epoch_deadline`] is
+    /// called. This is only supported with the Cranelift code generator.
+    ///
+    /// By default this option is `false`.
+    pub fn epoch_interruption(&mut self, enable: bool) -> &mut Self {
+        self.tunables.epoch_interruption = enable;
+        self
+    }
+
     /// Configures the maximum amount of native stack space available to
     /// executing WebAssembly code.
     ///
@@ -132,12 +285,43 @@ impl Config {
     /// If a wasm call (or series of nested wasm calls) take more stack space
     /// than the `size` specified then a stack overflow trap will be raised.
     ///
+    /// Wasm is never allowed to use the last 128 KB of the thread's stack,
+    /// which are left for host functions called by wasm. If the thread calling
+    /// into wasm has less than `size` bytes of stack left on top of that, the
+    /// limit shrinks to fit, so running out of stack in wasm raises a
+    /// [`TrapCode::StackOverflow`] trap rather than crashing the process even
+    /// when wasm is called from a thread with a small stack. This relies on
+    /// looking up the bounds of the thread's stack, which is supported on
+    /// Linux, Android, macOS and Windows. Host functions which themselves use
+    /// more than the reserved space are not protected.
+    ///
     /// By default this option is 1 MB.
+    ///
+    /// [`TrapCode::StackOverflow`]: crate::TrapCode::StackOverflow
     pub fn max_wasm_stack(&mut self, size: usize) -> &mut Self {
         self.max_wasm_stack = size;
         self
     }
 
+    /// Configures whether backtraces are captured when wasm traps.
+    ///
+    /// A trap normally records the native stack at the point it happened and
+    /// resolves it to the wasm frames returned by [`Trap::trace`]. Walking the
+    /// stack is by far the most expensive part of raising a trap, which
+    /// matters for workloads such as fuzzing which trap very often. When this
+    /// is disabled traps still carry their message and [`Trap::code`], but
+    /// [`Trap::trace`] is always empty, and since a coredump is made of the
+    /// trap's frames, [`Config::coredump_on_trap`] has no effect either.
+    ///
+    /// By default this option is `true`.
+    ///
+    /// [`Trap::trace`]: crate::Trap::trace
+    /// [`Trap::code`]: crate::Trap::code
+    pub fn wasm_backtrace(&mut self, enable: bool) -> &mut Self {
+        self.wasm_backtrace = enable;
+        self
+    }
+
     /// Configures whether the WebAssembly threads proposal will be enabled for
     /// compilation.
     ///
@@ -328,7 +512,7 @@ impl Config {
     /// optimization level used for generated code in a few various ways. For
     /// more information see the documentation of [`OptLevel`].
     ///
-    /// The default value for this is `OptLevel::None`.
+    /// The default value for this is `OptLevel::Speed`.
     pub fn cranelift_opt_level(&mut self, level: OptLevel) -> &mut Self {
         let val = match level {
             OptLevel::None => "none",
@@ -357,6 +541,60 @@ impl Config {
         self
     }
 
+    /// Sets one of the Cranelift flags which only trade compilation time or
+    /// code quality, and can't affect the correctness of generated code.
+    ///
+    /// The flags which can be set this way are:
+    ///
+    /// * `opt_level` - see [`Config::cranelift_opt_level`].
+    /// * `regalloc` - the register allocation algorithm, one of `backtracking`,
+    ///   `backtracking_checked`, `experimental_linear_scan` or
+    ///   `experimental_linear_scan_checked`.
+    /// * `enable_verifier` - see [`Config::cranelift_debug_verifier`].
+    /// * `enable_nan_canonicalization` - see
+    ///   [`Config::cranelift_nan_canonicalization`].
+    /// * `enable_jump_tables` - whether `br_table` may be compiled to a jump
+    ///   table.
+    /// * `enable_heap_access_spectre_mitigation` - whether bounds checks of
+    ///   dynamic memories are hardened against speculative execution.
+    ///
+    /// Any other flag can be set with [`Config::cranelift_other_flag`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` isn't one of the flags above or if `value`
+    /// isn't valid for it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut config = Config::new();
+    /// config.cranelift_flag("enable_jump_tables", "false")?;
+    /// assert!(config.cranelift_flag("enable_probestack", "true").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn cranelift_flag(&mut self, name: &str, value: &str) -> Result<&mut Self> {
+        const SAFE_FLAGS: &[&str] = &[
+            "opt_level",
+            "regalloc",
+            "enable_verifier",
+            "enable_nan_canonicalization",
+            "enable_jump_tables",
+            "enable_heap_access_spectre_mitigation",
+        ];
+        if !SAFE_FLAGS.contains(&name) {
+            bail!(
+                "cannot set Cranelift flag `{}` safely, see `Config::cranelift_other_flag`",
+                name
+            );
+        }
+        self.flags.set(name, value)?;
+        Ok(self)
+    }
+
     /// Allows settings another Cranelift flag defined by a flag name and value. This allows
     /// fine-tuning of Cranelift settings.
     ///
@@ -434,12 +672,92 @@ impl Config {
         Ok(self)
     }
 
+    /// Configures whether engines created with this configuration keep the
+    /// compiled code of every function in memory, to reuse it when an
+    /// identical function is compiled again.
+    ///
+    /// This is meant to speed up recompiling a module after editing it, where
+    /// most functions are unchanged. A function is reused if its body and its
+    /// index are the same as before, and its module declares the same types,
+    /// functions, tables, memories and globals, imported or not. The function
+    /// is still validated against the new module. Functions stay cached for
+    /// as long as the [`Engine`] lives, so its memory use grows with every
+    /// distinct function compiled.
+    ///
+    /// Unlike the cache configured with `Config::cache_config_load`, this
+    /// cache lives in memory only and is specific to each [`Engine`].
+    ///
+    /// By default this option is `false`.
+    pub fn function_cache(&mut self, enable: bool) -> &mut Self {
+        self.function_cache = enable;
+        self
+    }
+
+    /// Configures whether a coredump is captured whenever wasm traps.
+    ///
+    /// The coredump describes the wasm frames on the stack when the trap
+    /// happened, and the instances in the store along with the values of their
+    /// globals. It's available from [`Trap::coredump`] and can be serialized
+    /// to the [wasm coredump format] for post-mortem debugging with tools
+    /// which understand it. Linear memories are only included if
+    /// [`Config::coredump_memories`] is enabled too.
+    ///
+    /// Capturing a coredump takes time proportional to the size of the
+    /// store, so this is meant for hosts which rarely expect their wasm to
+    /// trap.
+    ///
+    /// By default this option is `false`.
+    ///
+    /// [`Trap::coredump`]: crate::Trap::coredump
+    /// [wasm coredump format]: https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md
+    pub fn coredump_on_trap(&mut self, enable: bool) -> &mut Self {
+        self.coredump_on_trap = enable;
+        self
+    }
+
+    /// Configures whether coredumps captured because of
+    /// [`Config::coredump_on_trap`] include a copy of every linear memory of
+    /// the store.
+    ///
+    /// By default this option is `false`.
+    pub fn coredump_memories(&mut self, enable: bool) -> &mut Self {
+        self.coredump_memories = enable;
+        self
+    }
+
     /// Sets a custom memory creator
     pub fn with_host_memory(&mut self, mem_creator: Arc<dyn MemoryCreator>) -> &mut Self {
         self.memory_creator = Some(MemoryCreatorProxy { mem_creator });
         self
     }
 
+    /// Configures how instances, along with their tables and linear memories,
+    /// are allocated.
+    ///
+    /// With [`InstanceAllocationStrategy::Pooling`] every engine created
+    /// with this configuration keeps pools of instances, tables and linear
+    /// memories. Instead of being freed, the storage of a dropped instance,
+    /// table or memory is kept and reused for the next one created, which
+    /// makes instantiating modules at a high rate considerably cheaper. Freed
+    /// slots are preferably reused by instances of the same module as before.
+    /// Instantiations needing more than what's left in the pools fail, as do
+    /// instantiations of modules exceeding the pool's per-instance limits.
+    ///
+    /// Each pooled memory is large enough for a static memory as configured
+    /// with [`Config::static_memory_maximum_size`] and
+    /// [`Config::static_memory_guard_size`], so those should be configured
+    /// first. Memories created with [`Memory::new`](crate::Memory::new) come
+    /// out of the pool as well, while other host-defined items aren't pooled.
+    ///
+    /// A memory creator configured with [`Config::with_host_memory`] takes
+    /// precedence over this for memories.
+    ///
+    /// The default is [`InstanceAllocationStrategy::OnDemand`].
+    pub fn allocation_strategy(&mut self, strategy: InstanceAllocationStrategy) -> &mut Self {
+        self.allocation_strategy = strategy;
+        self
+    }
+
     /// Configures the maximum size, in bytes, where a linear memory is
     /// considered static, above which it'll be considered dynamic.
     ///
@@ -609,6 +927,94 @@ impl Config {
         self
     }
 
+    /// Configures whether all memory accesses are explicitly bounds checked,
+    /// so that running wasm never relies on catching memory faults.
+    ///
+    /// By default out-of-bounds memory accesses are mostly caught by reserving
+    /// guard regions of unmapped memory around linear memories and handling
+    /// the resulting faults (`SIGSEGV` and `SIGBUS` on Unix). Embedders which
+    /// install their own handlers for those signals can't share them with
+    /// Wasmtime, and can enable this instead. All linear memories are then
+    /// dynamic memories without guard regions, regardless of
+    /// [`Config::static_memory_maximum_size`],
+    /// [`Config::static_memory_guard_size`] and
+    /// [`Config::dynamic_memory_guard_size`], and stores created with an
+    /// engine using this configuration don't install handlers for memory
+    /// faults. This makes memory accesses slower, and `memory.grow` may have
+    /// to relocate memories.
+    ///
+    /// Note that other traps, such as `unreachable`, are still raised by trap
+    /// instructions, which are caught by handling `SIGILL` (and `SIGFPE` on
+    /// x86) on Unix.
+    ///
+    /// Shared memories can never be relocated, so they can't be dynamic
+    /// memories: compiling modules which define or import one, and creating
+    /// one with [`Memory::new`](crate::Memory::new) or
+    /// [`SharedMemory::new`](crate::SharedMemory::new), fail with this
+    /// enabled. [`Memory::protect`](crate::Memory::protect) fails as well,
+    /// since it relies on memory faults.
+    ///
+    /// This is `false` by default.
+    pub fn explicit_bounds_checks(&mut self, enable: bool) -> &mut Self {
+        self.explicit_bounds_checks = enable;
+        self
+    }
+
+    /// Defines a host function named `module::name` for all stores using
+    /// engines created with this configuration, like [`Func::new`].
+    ///
+    /// Host functions defined here are bound to a [`Store`] the first time
+    /// they're used in it, by [`Linker`] when resolving imports or by
+    /// [`Store::get_host_func`]. The trampolines entering the host function
+    /// are only compiled once per engine, so binding it is cheap, which suits
+    /// embeddings creating a store for every instance they run. Items defined
+    /// in a [`Linker`] take precedence over these.
+    ///
+    /// Since engines are shared across threads, `func` must be `Send` and
+    /// `Sync`. A later definition with the same `module` and `name` replaces
+    /// an earlier one.
+    ///
+    /// [`Linker`]: crate::Linker
+    pub fn define_host_func(
+        &mut self,
+        module: &str,
+        name: &str,
+        ty: FuncType,
+        func: impl Fn(Caller<'_>, &[Val], &mut [Val]) -> Result<(), Trap> + Send + Sync + 'static,
+    ) -> &mut Self {
+        let func = Arc::new(func);
+        let bind = move |store: &Store| {
+            let func = func.clone();
+            Func::new(store, ty.clone(), move |caller, params, results| {
+                func(caller, params, results)
+            })
+        };
+        self.host_funcs
+            .insert((module.to_string(), name.to_string()), Arc::new(bind));
+        self
+    }
+
+    /// Defines a host function named `module::name` for all stores using
+    /// engines created with this configuration, like [`Func::wrap`].
+    ///
+    /// `func` is cloned into each [`Store`] it's used in. See
+    /// [`Config::define_host_func`] for more information.
+    pub fn wrap_host_func<Params, Results>(
+        &mut self,
+        module: &str,
+        name: &str,
+        func: impl IntoFunc<Params, Results> + Clone + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        Params: 'static,
+        Results: 'static,
+    {
+        let bind = move |store: &Store| IntoFunc::<Params, Results>::into_func(func.clone(), store);
+        self.host_funcs
+            .insert((module.to_string(), name.to_string()), Arc::new(bind));
+        self
+    }
+
     pub(crate) fn target_isa(&self) -> Box<dyn TargetIsa> {
         self.isa_flags
             .clone()
@@ -623,23 +1029,11 @@ impl Config {
 
     fn build_compiler(&self) -> Compiler {
         let isa = self.target_isa();
-        Compiler::new(isa, self.strategy, self.tunables.clone(), self.features)
-    }
-
-    /// Hashes/fingerprints compiler setting to ensure that compatible
-    /// compilation artifacts are used.
-    pub(crate) fn compiler_fingerprint<H>(&self, state: &mut H)
-    where
-        H: Hasher,
-    {
-        self.flags.hash(state);
-        self.tunables.hash(state);
-
-        let triple = Triple::host();
-        triple.hash(state);
-
-        // Catch accidental bugs of reusing across wasmtime versions.
-        env!("CARGO_PKG_VERSION").hash(state);
+        let mut compiler = Compiler::new(isa, self.strategy, self.tunables.clone(), self.features);
+        if self.function_cache {
+            compiler.enable_function_cache();
+        }
+        compiler
     }
 }
 
@@ -661,7 +1055,18 @@ impl fmt::Debug for Config {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Config")
             .field("debug_info", &self.tunables.debug_info)
+            .field("consume_fuel", &self.tunables.consume_fuel)
+            .field("epoch_interruption", &self.tunables.epoch_interruption)
+            .field("strip_names", &self.tunables.strip_names)
+            .field("target", &self.target_triple().to_string())
             .field("strategy", &self.strategy)
+            .field("allocation_strategy", &self.allocation_strategy)
+            .field("wasm_backtrace", &self.wasm_backtrace)
+            .field("explicit_bounds_checks", &self.explicit_bounds_checks)
+            .field("function_cache", &self.function_cache)
+            .field("coredump_on_trap", &self.coredump_on_trap)
+            .field("coredump_memories", &self.coredump_memories)
+            .field("host_funcs", &self.host_funcs.keys().collect::<Vec<_>>())
             .field("wasm_threads", &self.features.threads)
             .field("wasm_reference_types", &self.features.reference_types)
             .field("wasm_bulk_memory", &self.features.bulk_memory)
@@ -732,6 +1137,52 @@ pub enum ProfilingStrategy {
     VTune,
 }
 
+/// The resources held by a [`Store`], as returned by
+/// [`Store::resource_usage`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreResourceUsage {
+    /// The number of instances in the store. This includes the instances
+    /// created internally for host-defined functions, globals, memories and
+    /// tables.
+    pub instances: usize,
+    /// The total size, in bytes, of all linear memories.
+    pub memory_bytes: u64,
+    /// The total number of elements of all tables.
+    pub table_elements: u64,
+    /// The total size, in bytes, of the compiled code of the modules
+    /// instantiated in the store.
+    pub code_bytes: u64,
+}
+
+/// How instances and their tables and linear memories are allocated, used as
+/// an argument to [`Config::allocation_strategy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceAllocationStrategy {
+    /// Instances, tables and memories are allocated when they're created and
+    /// released when they're dropped.
+    OnDemand,
+
+    /// Instances, tables and memories are allocated from pools of slots, which
+    /// are reused rather than released. All limits apply across all stores of
+    /// an engine.
+    Pooling {
+        /// The maximum number of instances alive at any one time.
+        max_instances: u32,
+        /// The maximum size in bytes of an instance. This is the size of its
+        /// `VMContext`, which grows with the number of functions, globals,
+        /// tables and memories its module defines or imports.
+        max_instance_size: usize,
+        /// The maximum number of tables alive at any one time.
+        max_tables: u32,
+        /// The maximum number of elements of a table. Tables declaring a
+        /// larger minimum fail to be created, and tables can't grow past it.
+        max_table_elements: u32,
+        /// The maximum number of linear memories alive at any one time.
+        max_memories: u32,
+    },
+}
+
 // Engine
 
 /// An `Engine` which is a global context for compilation and management of wasm
@@ -764,6 +1215,15 @@ pub struct Engine {
 struct EngineInner {
     config: Config,
     compiler: Compiler,
+    epoch: AtomicU64,
+    instance_pool: Option<InstancePool>,
+    /// Shared by all stores using this engine, so that a given function type
+    /// has the same `VMSharedSignatureIndex` in every module and store.
+    /// Signatures are never unregistered, but they're deduplicated so this
+    /// only grows with the number of distinct function types seen.
+    signatures: RwLock<SignatureRegistry>,
+    /// The trampolines of `Func::new` functions, by their type.
+    host_trampolines: Mutex<HashMap<FuncType, Arc<HostTrampolines>>>,
 }
 
 impl Engine {
@@ -771,10 +1231,43 @@ impl Engine {
     /// configuration settings.
     pub fn new(config: &Config) -> Engine {
         debug_builtins::ensure_exported();
+        let instance_pool = match config.allocation_strategy {
+            InstanceAllocationStrategy::OnDemand => None,
+            InstanceAllocationStrategy::Pooling {
+                max_instances,
+                max_instance_size,
+                max_tables,
+                max_table_elements,
+                max_memories,
+            } => Some(InstancePool::new(&PoolingLimits {
+                max_instances,
+                max_instance_size,
+                max_tables,
+                max_table_elements,
+                max_memories,
+                max_memory_pages: config.tunables.static_memory_bound,
+                memory_offset_guard_size: config.tunables.static_memory_offset_guard_size as usize,
+            })),
+        };
+
+        // This is applied after creating the pool, whose memory slots can still hold
+        // memories as large as a static memory would have been; the dynamic
+        // memories placed in them just don't rely on that.
+        let mut config = config.clone();
+        if config.explicit_bounds_checks {
+            config.tunables.static_memory_bound = 0;
+            config.tunables.static_memory_offset_guard_size = 0;
+            config.tunables.dynamic_memory_offset_guard_size = 0;
+        }
+        let compiler = config.build_compiler();
         Engine {
             inner: Arc::new(EngineInner {
-                config: config.clone(),
-                compiler: config.build_compiler(),
+                config,
+                compiler,
+                epoch: AtomicU64::new(0),
+                instance_pool,
+                signatures: RwLock::new(SignatureRegistry::default()),
+                host_trampolines: Mutex::new(HashMap::new()),
             }),
         }
     }
@@ -788,15 +1281,83 @@ impl Engine {
         &self.inner.compiler
     }
 
+    pub(crate) fn signatures(&self) -> RwLockReadGuard<'_, SignatureRegistry> {
+        self.inner.signatures.read().unwrap()
+    }
+
+    pub(crate) fn signatures_mut(&self) -> RwLockWriteGuard<'_, SignatureRegistry> {
+        self.inner.signatures.write().unwrap()
+    }
+
+    /// Returns the trampolines for `Func::new` functions of type `ty`,
+    /// compiling them the first time they're needed.
+    pub(crate) fn host_trampolines(&self, ty: &FuncType) -> Result<Arc<HostTrampolines>> {
+        let mut trampolines = self.inner.host_trampolines.lock().unwrap();
+        if let Some(t) = trampolines.get(ty) {
+            return Ok(t.clone());
+        }
+        let t = Arc::new(HostTrampolines::new(self, ty)?);
+        trampolines.insert(ty.clone(), t.clone());
+        Ok(t)
+    }
+
     #[cfg(feature = "cache")]
     pub(crate) fn cache_config(&self) -> &CacheConfig {
         &self.config().cache_config
     }
 
+    /// Compiles `bytes` ahead of time, returning the compiled module in the
+    /// format produced by [`Module::serialize`].
+    ///
+    /// Unlike compiling with [`Module::new`], this doesn't load the compiled
+    /// code for execution, so it works for engines configured to compile for
+    /// another target with [`Config::target`]. The result can be loaded with
+    /// [`Module::deserialize`] by an engine with the same configuration on a
+    /// matching host.
+    ///
+    /// If the `wat` feature is enabled, `bytes` may also be in the text
+    /// format, see [`Module::new`].
+    ///
+    /// The result only depends on `bytes`, the configuration of this engine
+    /// and the version of Wasmtime, so precompiling the same module again
+    /// yields exactly the same bytes. This makes it possible to verify a
+    /// precompiled module by reproducing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` isn't a valid module or fails to compile.
+    pub fn precompile_module(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Module::precompile(self, bytes, &SerializeOptions::default())
+    }
+
+    /// Like [`Engine::precompile_module`], but serializes the module with
+    /// the given `options`, like [`Module::serialize_with`].
+    pub fn precompile_module_with(
+        &self,
+        bytes: &[u8],
+        options: &SerializeOptions,
+    ) -> Result<Vec<u8>> {
+        Module::precompile(self, bytes, options)
+    }
+
     /// Returns whether the engine `a` and `b` refer to the same configuration.
     pub fn same(a: &Engine, b: &Engine) -> bool {
         Arc::ptr_eq(&a.inner, &b.inner)
     }
+
+    /// Increments this engine's epoch, causing wasm in any of its stores
+    /// whose epoch deadline has now been reached to trap.
+    ///
+    /// This can be called from any thread, and is intended to be called
+    /// periodically from a timer thread. It only has an effect if
+    /// [`Config::epoch_interruption`] is enabled.
+    pub fn increment_epoch(&self) {
+        self.inner.epoch.fetch_add(1, SeqCst);
+    }
+
+    pub(crate) fn epoch_counter(&self) -> &AtomicU64 {
+        &self.inner.epoch
+    }
 }
 
 impl Default for Engine {
@@ -825,7 +1386,8 @@ impl Default for Engine {
 /// `Store` unsuitable for creating an unbounded number of instances in it
 /// because `Store` will never release this memory. It's instead recommended to
 /// have a long-lived [`Engine`] and instead create a `Store` for a more scoped
-/// portion of your application.
+/// portion of your application, or to release all of a store's instances at
+/// once with [`Store::reset`] when it's no longer referenced by any of them.
 ///
 /// # Stores and `Clone`
 ///
@@ -846,12 +1408,101 @@ pub struct Store {
 pub(crate) struct StoreInner {
     engine: Engine,
     interrupts: Arc<VMInterrupts>,
-    signatures: RefCell<SignatureRegistry>,
     instances: RefCell<Vec<InstanceHandle>>,
     signal_handler: RefCell<Option<Box<SignalHandler<'static>>>>,
     jit_code_ranges: RefCell<Vec<(usize, usize)>>,
     externref_activations_table: VMExternRefActivationsTable,
     stack_map_registry: StackMapRegistry,
+    /// Host data attached with `Store::set`, keyed by its type. Entries are
+    /// never removed or replaced, which is what allows `Store::get` to hand
+    /// out references into the boxes.
+    data: RefCell<HashMap<TypeId, Box<dyn Any>>>,
+    /// Total amount of fuel added with `Store::add_fuel`. Adding this to
+    /// `VMInterrupts::fuel_consumed`, which counts up from minus the fuel
+    /// remaining, gives the amount of fuel consumed so far.
+    fuel_adj: Cell<i64>,
+    /// Consulted by every instance in this store before growing a memory or
+    /// table.
+    limiter: Option<Rc<dyn ResourceLimiter>>,
+    /// The number of module instances, and of the memories and tables they
+    /// define, checked against the limiter's counts on instantiation.
+    instance_count: Cell<usize>,
+    memory_count: Cell<usize>,
+    table_count: Cell<usize>,
+    /// Installed with `Store::call_hook`. This is an `Rc` so that the hook can
+    /// be invoked without holding a borrow, allowing it to replace itself.
+    call_hook: RefCell<Option<Rc<CallHookFn>>>,
+    /// Installed with `Store::trap_hook`, and kept in an `Rc` for the same
+    /// reason as `call_hook`.
+    trap_hook: RefCell<Option<Rc<TrapHookFn>>>,
+    /// Installed with `Store::deadline_callback`, and kept in an `Rc` for the
+    /// same reason as `call_hook`.
+    deadline_callback: RefCell<Option<Rc<DeadlineCallbackFn>>>,
+    /// Host functions of the engine's `Config` bound to this store so far.
+    /// They're weak since the functions themselves keep the store alive.
+    host_funcs: RefCell<HashMap<(String, String), WeakFunc>>,
+    /// The number of times `Store::reset` deallocated the instances of this
+    /// store, which invalidates weak handles to them.
+    resets: Cell<usize>,
+}
+
+type CallHookFn = dyn Fn(CallHook) -> Result<(), Trap>;
+type TrapHookFn = dyn Fn(&Store, &Trap);
+type DeadlineCallbackFn = dyn Fn(Deadline) -> DeadlineAction;
+
+impl StoreInner {
+    pub(crate) fn invoke_call_hook(&self, kind: CallHook) -> Result<(), Trap> {
+        let hook = self.call_hook.borrow().clone();
+        match hook {
+            Some(hook) => hook(kind),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A transition between wasm and the host, passed to the hook installed with
+/// [`Store::call_hook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CallHook {
+    /// The host is about to call into wasm.
+    CallingWasm,
+    /// Wasm called by the host has returned or trapped.
+    ReturningFromWasm,
+    /// Wasm is about to call a host function.
+    CallingHost,
+    /// A host function called by wasm is about to return to wasm.
+    ReturningFromHost,
+}
+
+impl CallHook {
+    /// Returns whether this transition enters the host.
+    pub fn entering_host(&self) -> bool {
+        match self {
+            CallHook::ReturningFromWasm | CallHook::CallingHost => true,
+            CallHook::CallingWasm | CallHook::ReturningFromHost => false,
+        }
+    }
+
+    /// Returns whether this transition exits the host.
+    pub fn exiting_host(&self) -> bool {
+        !self.entering_host()
+    }
+}
+
+/// What to do when wasm reaches a [`Deadline`], as decided by the callback
+/// installed with [`Store::deadline_callback`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeadlineAction {
+    /// Trap as if no callback were installed: with an interrupt for
+    /// [`Deadline::Epoch`], and with an out-of-fuel trap for
+    /// [`Deadline::Fuel`].
+    Trap,
+    /// Extend the deadline and resume execution. For [`Deadline::Epoch`] the
+    /// new deadline is this many ticks after the engine's current epoch, as
+    /// with [`Store::set_epoch_deadline`], and for [`Deadline::Fuel`] this
+    /// much fuel is added, as with [`Store::add_fuel`].
+    Extend(u64),
 }
 
 struct HostInfoKey(VMExternRef);
@@ -876,23 +1527,66 @@ impl Hash for HostInfoKey {
 impl Store {
     /// Creates a new store to be associated with the given [`Engine`].
     pub fn new(engine: &Engine) -> Store {
+        Store::new_with_limiter(engine, None)
+    }
+
+    /// Creates a new store to be associated with the given [`Engine`], whose
+    /// memories and tables may only grow with the permission of `limiter`.
+    ///
+    /// The limiter is consulted for all growth of memories and tables created
+    /// in this store, whether requested by wasm through `memory.grow` and
+    /// `table.grow` or by the host through [`Memory::grow`](crate::Memory::grow)
+    /// and [`Table::grow`](crate::Table::grow). Denied growth fails as if the
+    /// declared maximum had been exceeded. Memories and tables are always
+    /// created with their declared minimum size regardless of the limiter.
+    ///
+    /// Instantiating a module fails if the store would then hold more
+    /// instances, or more memories or tables defined by those instances, than
+    /// the limiter allows. See [`StoreLimits`](crate::StoreLimits) for a
+    /// limiter with fixed limits.
+    ///
+    /// These limits cover the store as a whole. To budget instances
+    /// separately, instantiate each one in its own store.
+    pub fn new_with_limits(engine: &Engine, limiter: impl ResourceLimiter + 'static) -> Store {
+        Store::new_with_limiter(engine, Some(Rc::new(limiter)))
+    }
+
+    fn new_with_limiter(engine: &Engine, limiter: Option<Rc<dyn ResourceLimiter>>) -> Store {
         // Ensure that wasmtime_runtime's signal handlers are configured. Note
         // that at the `Store` level it means we should perform this
         // once-per-thread. Platforms like Unix, however, only require this
         // once-per-program. In any case this is safe to call many times and
         // each one that's not relevant just won't do anything.
-        wasmtime_runtime::init_traps();
+        if engine.config().explicit_bounds_checks {
+            wasmtime_runtime::init_traps_without_memory_faults();
+        } else {
+            wasmtime_runtime::init_traps();
+        }
+
+        let mut interrupts = VMInterrupts::default();
+        interrupts.epoch_ptr = engine.epoch_counter();
+        *interrupts.epoch_deadline.get_mut() = engine.epoch_counter().load(SeqCst);
 
         Store {
             inner: Rc::new(StoreInner {
                 engine: engine.clone(),
-                interrupts: Arc::new(Default::default()),
-                signatures: RefCell::new(Default::default()),
+                interrupts: Arc::new(interrupts),
                 instances: RefCell::new(Vec::new()),
                 signal_handler: RefCell::new(None),
                 jit_code_ranges: RefCell::new(Vec::new()),
                 externref_activations_table: VMExternRefActivationsTable::new(),
                 stack_map_registry: StackMapRegistry::default(),
+                data: RefCell::new(HashMap::new()),
+                fuel_adj: Cell::new(0),
+                limiter,
+                instance_count: Cell::new(0),
+                memory_count: Cell::new(0),
+                table_count: Cell::new(0),
+                call_hook: RefCell::new(None),
+                trap_hook: RefCell::new(None),
+                deadline_callback: RefCell::new(None),
+                host_funcs: RefCell::new(HashMap::new()),
+                resets: Cell::new(0),
             }),
         }
     }
@@ -906,30 +1600,71 @@ impl Store {
         &self.inner.engine
     }
 
+    /// Returns the host function named `module::name` defined in the
+    /// [`Config`] of this store's engine, or `None` if there's none.
+    ///
+    /// The function is bound to this store the first time it's requested,
+    /// and the same function is returned afterwards. See
+    /// [`Config::define_host_func`] and [`Config::wrap_host_func`].
+    pub fn get_host_func(&self, module: &str, name: &str) -> Option<Func> {
+        let key = (module.to_string(), name.to_string());
+        if let Some(func) = self.inner.host_funcs.borrow().get(&key) {
+            return func.upgrade();
+        }
+        let bind = self.engine().config().host_funcs.get(&key)?;
+        let func = bind(self);
+        self.inner
+            .host_funcs
+            .borrow_mut()
+            .insert(key, func.downgrade());
+        Some(func)
+    }
+
     /// Returns an optional reference to a ['RuntimeMemoryCreator']
     pub(crate) fn memory_creator(&self) -> Option<&dyn RuntimeMemoryCreator> {
-        self.engine()
-            .config()
-            .memory_creator
-            .as_ref()
-            .map(|x| x as _)
+        match &self.engine().config().memory_creator {
+            Some(creator) => Some(creator as &dyn RuntimeMemoryCreator),
+            None => self.instance_pool().map(|pool| pool.memory_creator() as _),
+        }
+    }
+
+    /// Returns the pool instances of wasm modules are allocated from, if the
+    /// engine was configured with one.
+    pub(crate) fn instance_pool(&self) -> Option<&InstancePool> {
+        self.engine().inner.instance_pool.as_ref()
     }
 
     pub(crate) fn lookup_signature(&self, sig_index: VMSharedSignatureIndex) -> wasm::WasmFuncType {
-        self.inner
-            .signatures
-            .borrow()
+        self.engine()
+            .signatures()
             .lookup_wasm(sig_index)
             .expect("failed to lookup signature")
     }
 
+    /// Same as `lookup_signature`, but avoids cloning the signature by
+    /// passing a reference to it to `f` instead.
+    ///
+    /// The engine's signature registry isn't locked while `f` runs, so `f` is
+    /// free to register new signatures.
+    pub(crate) fn with_signature<R>(
+        &self,
+        sig_index: VMSharedSignatureIndex,
+        f: impl FnOnce(&wasm::WasmFuncType) -> R,
+    ) -> R {
+        let sig = self
+            .engine()
+            .signatures()
+            .lookup_wasm_shared(sig_index)
+            .expect("failed to lookup signature");
+        f(&sig)
+    }
+
     pub(crate) fn lookup_wasm_and_native_signatures(
         &self,
         sig_index: VMSharedSignatureIndex,
     ) -> (wasm::WasmFuncType, ir::Signature) {
-        self.inner
-            .signatures
-            .borrow()
+        self.engine()
+            .signatures()
             .lookup_wasm_and_native_signatures(sig_index)
             .expect("failed to lookup signature")
     }
@@ -939,14 +1674,7 @@ impl Store {
         wasm_sig: wasm::WasmFuncType,
         native: ir::Signature,
     ) -> VMSharedSignatureIndex {
-        self.inner
-            .signatures
-            .borrow_mut()
-            .register(wasm_sig, native)
-    }
-
-    pub(crate) fn signatures_mut(&self) -> std::cell::RefMut<'_, SignatureRegistry> {
-        self.inner.signatures.borrow_mut()
+        self.engine().signatures_mut().register(wasm_sig, native)
     }
 
     /// Returns whether or not the given address falls within the JIT code
@@ -998,6 +1726,10 @@ impl Store {
         }
     }
 
+    pub(crate) fn instances(&self) -> std::cell::Ref<'_, Vec<InstanceHandle>> {
+        self.inner.instances.borrow()
+    }
+
     pub(crate) fn existing_instance_handle(&self, handle: InstanceHandle) -> StoreInstanceHandle {
         debug_assert!(self
             .inner
@@ -1020,6 +1752,10 @@ impl Store {
         Some(Self { inner })
     }
 
+    pub(crate) fn resets(&self) -> usize {
+        self.inner.resets.get()
+    }
+
     pub(crate) fn signal_handler(&self) -> std::cell::Ref<'_, Option<Box<SignalHandler<'static>>>> {
         self.inner.signal_handler.borrow()
     }
@@ -1034,6 +1770,72 @@ impl Store {
         &self.inner.interrupts
     }
 
+    pub(crate) fn limiter(&self) -> Option<Rc<dyn ResourceLimiter>> {
+        self.inner.limiter.clone()
+    }
+
+    /// Accounts for a new instance of `module`, failing if that would exceed
+    /// the number of instances, memories or tables allowed by the limiter.
+    ///
+    /// If the instance then fails to be created, this must be undone with
+    /// `release_resource_counts`.
+    pub(crate) fn bump_resource_counts(&self, module: &wasmtime_environ::Module) -> Result<()> {
+        let limiter = match &self.inner.limiter {
+            Some(limiter) => limiter,
+            None => return Ok(()),
+        };
+        let counts = self.resource_counts(module);
+        let maximums = [limiter.instances(), limiter.memories(), limiter.tables()];
+
+        // Check all the limits before bumping any count, so that a rejected
+        // instantiation isn't counted against the store.
+        for ((count, amt, desc), max) in counts.iter().zip(maximums.iter()) {
+            let new = count.get().saturating_add(*amt);
+            if new > *max {
+                bail!(
+                    "resource limit exceeded: {} count too high at {}",
+                    desc,
+                    new
+                );
+            }
+        }
+        for (count, amt, _) in counts.iter() {
+            count.set(count.get() + amt);
+        }
+        Ok(())
+    }
+
+    /// Undoes `bump_resource_counts` for an instance of `module` which failed
+    /// to be created.
+    pub(crate) fn release_resource_counts(&self, module: &wasmtime_environ::Module) {
+        if self.inner.limiter.is_none() {
+            return;
+        }
+        for (count, amt, _) in self.resource_counts(module).iter() {
+            count.set(count.get() - amt);
+        }
+    }
+
+    fn resource_counts(
+        &self,
+        module: &wasmtime_environ::Module,
+    ) -> [(&Cell<usize>, usize, &str); 3] {
+        let inner = &self.inner;
+        [
+            (&inner.instance_count, 1, "instance"),
+            (
+                &inner.memory_count,
+                module.memory_plans.len() - module.num_imported_memories,
+                "memory",
+            ),
+            (
+                &inner.table_count,
+                module.table_plans.len() - module.num_imported_tables,
+                "table",
+            ),
+        ]
+    }
+
     /// Returns whether the stores `a` and `b` refer to the same underlying
     /// `Store`.
     ///
@@ -1135,6 +1937,339 @@ impl Store {
         }
     }
 
+    /// Attaches a value of type `T` to this store, which can later be
+    /// retrieved with [`Store::get`].
+    ///
+    /// A store holds at most one value of each type, and once set it can't be
+    /// replaced or removed. This is intended for application state which host
+    /// functions need access to; they can reach it through
+    /// [`Caller::store`](crate::Caller::store). Use a type like `RefCell<T>`
+    /// for state that host functions need to mutate.
+    ///
+    /// # Errors
+    ///
+    /// Returns `data` back if a value of type `T` is already set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # use std::cell::Cell;
+    /// # fn main() -> anyhow::Result<()> {
+    /// struct Counter(Cell<u32>);
+    ///
+    /// let store = Store::default();
+    /// assert!(store.set(Counter(Cell::new(0))).is_ok());
+    ///
+    /// let bump = Func::wrap(&store, |caller: Caller<'_>| {
+    ///     let store = caller.store();
+    ///     let counter = store.get::<Counter>().unwrap();
+    ///     counter.0.set(counter.0.get() + 1);
+    /// });
+    /// bump.call(&[])?;
+    /// bump.call(&[])?;
+    /// assert_eq!(store.get::<Counter>().unwrap().0.get(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set<T: Any>(&self, data: T) -> Result<(), T> {
+        let mut map = self.inner.data.borrow_mut();
+        if map.contains_key(&TypeId::of::<T>()) {
+            return Err(data);
+        }
+        map.insert(TypeId::of::<T>(), Box::new(data));
+        Ok(())
+    }
+
+    /// Returns the value of type `T` attached to this store with
+    /// [`Store::set`], if any.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        let map = self.inner.data.borrow();
+        let data = map.get(&TypeId::of::<T>())?.downcast_ref::<T>()?;
+        // Safety: the box is never dropped or replaced while the store is
+        // alive, so its contents outlive the `RefCell` borrow above.
+        Some(unsafe { &*(data as *const T) })
+    }
+
+    /// Returns the amount of fuel consumed by this store's execution so far,
+    /// or `None` if [`Config::consume_fuel`] isn't enabled.
+    pub fn fuel_consumed(&self) -> Option<u64> {
+        if !self.engine().config().tunables.consume_fuel {
+            return None;
+        }
+        let consumed = unsafe { *self.inner.interrupts.fuel_consumed.get() };
+        Some(u64::try_from(self.inner.fuel_adj.get() + consumed).unwrap())
+    }
+
+    /// Adds `fuel` to this store for wasm to consume while executing.
+    ///
+    /// For this method to work fuel consumption must be enabled via
+    /// [`Config::consume_fuel`]. Wasm code traps once all of the fuel added to
+    /// its store has been consumed, see [`Config::consume_fuel`] for more
+    /// information.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if fuel consumption isn't enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::new(Config::new().consume_fuel(true));
+    /// let store = Store::new(&engine);
+    /// let module = Module::new(&engine, "(module (func (export \"run\") (loop br 0)))")?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// let run = instance.get_func("run").unwrap().typed::<(), ()>()?;
+    ///
+    /// store.add_fuel(10_000)?;
+    /// let trap = run.call(()).unwrap_err();
+    /// assert!(trap.is_out_of_fuel());
+    /// assert!(store.fuel_consumed().unwrap() >= 10_000);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_fuel(&self, fuel: u64) -> Result<()> {
+        ensure!(
+            self.engine().config().tunables.consume_fuel,
+            "fuel is not configured in this store"
+        );
+
+        // Fuel is stored as an `i64`, so anything larger than that is
+        // effectively infinite fuel.
+        let fuel = i64::try_from(fuel).unwrap_or(i64::max_value());
+        let adj = self.inner.fuel_adj.get();
+        let consumed_ptr = unsafe { &mut *self.inner.interrupts.fuel_consumed.get() };
+
+        match (consumed_ptr.checked_sub(fuel), adj.checked_add(fuel)) {
+            (Some(consumed), Some(adj)) => {
+                self.inner.fuel_adj.set(adj);
+                *consumed_ptr = consumed;
+            }
+
+            // Something overflowed, so preserve the amount of fuel consumed so
+            // far but otherwise treat the store as having infinite fuel.
+            _ => {
+                self.inner.fuel_adj.set(i64::max_value());
+                *consumed_ptr = (*consumed_ptr + adj) - i64::max_value();
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the epoch deadline of this store to `ticks_beyond_current` epochs
+    /// after the engine's current epoch.
+    ///
+    /// When [`Config::epoch_interruption`] is enabled, wasm executing in this
+    /// store traps with an interrupt once the epoch reaches the deadline, see
+    /// [`Engine::increment_epoch`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::new(Config::new().epoch_interruption(true));
+    /// let store = Store::new(&engine);
+    /// let module = Module::new(&engine, "(module (func (export \"run\") (loop br 0)))")?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// let run = instance.get_func("run").unwrap().typed::<(), ()>()?;
+    ///
+    /// store.set_epoch_deadline(1);
+    /// let timer = std::thread::spawn(move || {
+    ///     std::thread::sleep(std::time::Duration::from_millis(10));
+    ///     engine.increment_epoch();
+    /// });
+    /// let trap = run.call(()).unwrap_err();
+    /// assert!(trap.to_string().contains("wasm trap: interrupt"));
+    /// timer.join().unwrap();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_epoch_deadline(&self, ticks_beyond_current: u64) {
+        let current = self.engine().epoch_counter().load(SeqCst);
+        let deadline = current.saturating_add(ticks_beyond_current);
+        unsafe {
+            *self.inner.interrupts.epoch_deadline.get() = deadline;
+        }
+    }
+
+    /// Installs `hook` to be invoked on every transition between wasm and the
+    /// host in this store, replacing any previously installed hook.
+    ///
+    /// The hook is invoked with [`CallHook::CallingWasm`] and
+    /// [`CallHook::ReturningFromWasm`] around every call from the host into
+    /// wasm, including the start function run during instantiation, and with
+    /// [`CallHook::CallingHost`] and [`CallHook::ReturningFromHost`] around
+    /// every call from wasm to a host function. This can be used to, for
+    /// example, account for the time spent executing wasm, or to set up
+    /// thread-local state needed by host functions.
+    ///
+    /// If the hook returns an error the transition doesn't happen and the
+    /// error is raised as a trap instead: from the call into wasm when
+    /// entering wasm, and to the calling wasm when entering a host function.
+    /// An error while returning is raised in place of the call's result.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # use std::cell::Cell;
+    /// # use std::rc::Rc;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let depth = Rc::new(Cell::new(0));
+    /// let wasm_depth = depth.clone();
+    /// store.call_hook(move |hook| {
+    ///     match hook {
+    ///         CallHook::CallingWasm => wasm_depth.set(wasm_depth.get() + 1),
+    ///         CallHook::ReturningFromWasm => wasm_depth.set(wasm_depth.get() - 1),
+    ///         _ => {}
+    ///     }
+    ///     Ok(())
+    /// });
+    ///
+    /// let module = Module::new(store.engine(), r#"(func (export "run"))"#)?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// let run = instance.get_func("run").unwrap().typed::<(), ()>()?;
+    /// run.call(())?;
+    /// assert_eq!(depth.get(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_hook(&self, hook: impl Fn(CallHook) -> Result<(), Trap> + 'static) {
+        *self.inner.call_hook.borrow_mut() = Some(Rc::new(hook));
+    }
+
+    pub(crate) fn invoke_call_hook(&self, kind: CallHook) -> Result<(), Trap> {
+        self.inner.invoke_call_hook(kind)
+    }
+
+    /// Installs `hook` to be invoked with every trap raised by a call into
+    /// wasm in this store, replacing any previously installed hook.
+    ///
+    /// The hook sees each trap once, before it's returned from the call into
+    /// wasm it was raised in, along with the store it was raised in. This
+    /// includes traps raised by wasm itself, such as by `unreachable` or by
+    /// running out of fuel, as well as errors returned by host functions
+    /// called from wasm, but not errors raised before wasm is entered or after
+    /// it returns, such as by a [`Store::call_hook`] hook at those points.
+    /// Traps already have their
+    /// [coredump](crate::Trap::coredump) when the hook sees them, so this is
+    /// a single place to log all guest failures, rather than wrapping every
+    /// call into wasm.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # use std::cell::RefCell;
+    /// # use std::rc::Rc;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let log = Rc::new(RefCell::new(Vec::new()));
+    /// let trap_log = log.clone();
+    /// store.trap_hook(move |_store, trap| {
+    ///     trap_log.borrow_mut().push(trap.code());
+    /// });
+    ///
+    /// let module = Module::new(store.engine(), r#"(func (export "run") unreachable)"#)?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// let run = instance.get_func("run").unwrap().typed::<(), ()>()?;
+    /// assert!(run.call(()).is_err());
+    /// assert_eq!(*log.borrow(), [Some(TrapCode::Unreachable)]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn trap_hook(&self, hook: impl Fn(&Store, &Trap) + 'static) {
+        *self.inner.trap_hook.borrow_mut() = Some(Rc::new(hook));
+    }
+
+    /// Invokes the trap hook with `trap`, unless it already saw it when the
+    /// trap was raised by a nested call into wasm.
+    pub(crate) fn invoke_trap_hook(&self, trap: &Trap) {
+        let hook = self.inner.trap_hook.borrow().clone();
+        if let Some(hook) = hook {
+            if trap.observe() {
+                hook(self, trap);
+            }
+        }
+    }
+
+    /// Installs `callback` to decide what happens when wasm executing in this
+    /// store reaches its epoch deadline or runs out of fuel, replacing any
+    /// previously installed callback.
+    ///
+    /// Without a callback reaching either deadline traps. The callback can
+    /// instead extend the deadline and let execution resume, which allows
+    /// implementing policies such as a soft timeout, after which some more
+    /// time is granted (perhaps after logging a warning), followed by a hard
+    /// one.
+    ///
+    /// Note that a fuel deadline is only checked at function entries and loop
+    /// headers, so execution may overshoot it slightly before the callback is
+    /// invoked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # use std::cell::Cell;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::new(Config::new().consume_fuel(true));
+    /// let store = Store::new(&engine);
+    /// let module = Module::new(
+    ///     &engine,
+    ///     r#"
+    ///         (module
+    ///             (func (export "count_down") (param i32)
+    ///                 (loop
+    ///                     (br_if 0 (local.tee 0 (i32.sub (local.get 0) (i32.const 1)))))))
+    ///     "#,
+    /// )?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// let count_down = instance.get_func("count_down").unwrap().typed::<i32, ()>()?;
+    ///
+    /// // Grant a second budget once the first runs out, and trap after that.
+    /// let extended = Cell::new(false);
+    /// store.deadline_callback(move |_| {
+    ///     if extended.replace(true) {
+    ///         DeadlineAction::Trap
+    ///     } else {
+    ///         DeadlineAction::Extend(10_000)
+    ///     }
+    /// });
+    /// store.add_fuel(10_000)?;
+    /// count_down.call(3_000)?;
+    /// assert!(count_down.call(3_000).unwrap_err().is_out_of_fuel());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn deadline_callback(&self, callback: impl Fn(Deadline) -> DeadlineAction + 'static) {
+        *self.inner.deadline_callback.borrow_mut() = Some(Rc::new(callback));
+    }
+
+    /// Invoked when wasm reaches `deadline`, returning whether the deadline
+    /// was extended and execution should resume.
+    pub(crate) fn deadline_reached(&self, deadline: Deadline) -> bool {
+        let callback = self.inner.deadline_callback.borrow().clone();
+        let action = match callback {
+            Some(callback) => callback(deadline),
+            None => DeadlineAction::Trap,
+        };
+        match action {
+            DeadlineAction::Trap => false,
+            DeadlineAction::Extend(delta) => {
+                match deadline {
+                    Deadline::Epoch => self.set_epoch_deadline(delta),
+                    Deadline::Fuel => self.add_fuel(delta).expect("fuel is enabled if it ran out"),
+                }
+                true
+            }
+        }
+    }
+
     pub(crate) fn externref_activations_table(&self) -> &VMExternRefActivationsTable {
         &self.inner.externref_activations_table
     }
@@ -1143,7 +2278,129 @@ impl Store {
         &self.inner.stack_map_registry
     }
 
+    /// Returns the resources currently held by this store.
+    ///
+    /// This is a snapshot of the sizes of all the memories and tables created
+    /// in this store, whether by instantiating modules or by the host, and of
+    /// the compiled code of the modules instantiated in it. None of these are
+    /// released until the store itself is dropped or [reset](Store::reset),
+    /// so this can be used by long-running hosts to decide when to recycle a
+    /// store.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let module = Module::new(store.engine(), "(module (memory 2) (func))")?;
+    /// Instance::new(&store, &module, &[])?;
+    /// let usage = store.resource_usage();
+    /// assert_eq!(usage.instances, 1);
+    /// assert_eq!(usage.memory_bytes, 2 * 65536);
+    /// assert!(usage.code_bytes > 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn resource_usage(&self) -> StoreResourceUsage {
+        let mut usage = StoreResourceUsage::default();
+        for instance in self.inner.instances.borrow().iter() {
+            usage.instances += 1;
+            usage.memory_bytes += instance.defined_memory_pages() * u64::from(WASM_PAGE_SIZE);
+            usage.table_elements += instance.defined_table_elements();
+        }
+        usage.code_bytes = self
+            .inner
+            .jit_code_ranges
+            .borrow()
+            .iter()
+            .map(|(start, end)| (end - start) as u64)
+            .sum();
+        usage
+    }
+
+    /// Deallocates all instances in this store, so that it can be reused.
+    ///
+    /// This releases everything reported by [`Store::resource_usage`]: the
+    /// memories and tables of the instances, whether instantiated from
+    /// modules or created by the host, the store's references to the code
+    /// of the modules instantiated in it, and any `externref`s held by the
+    /// store. The counts checked against the store's
+    /// [`ResourceLimiter`](crate::ResourceLimiter) start from zero again.
+    /// Host data attached with [`Store::set`], fuel, and the hooks installed
+    /// in the store are left alone.
+    ///
+    /// # Errors
+    ///
+    /// Every instance, function, global, memory, table, and linker refers to
+    /// the store it belongs to, and the store can only be reset once none of
+    /// them are left. An error is returned if there are other references to
+    /// this store, including clones of it, in which case nothing is
+    /// deallocated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut store = Store::default();
+    /// let module = Module::new(store.engine(), "(module (memory 1))")?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// assert!(store.reset().is_err());
+    ///
+    /// drop(instance);
+    /// store.reset()?;
+    /// assert_eq!(store.resource_usage().instances, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reset(&mut self) -> Result<()> {
+        // Wasm can't be running either: calling it requires a `Func`, which
+        // would be another reference to the store.
+        let refs = Rc::strong_count(&self.inner) - 1;
+        if refs != 0 {
+            bail!(
+                "cannot reset a store which is still referenced by {} other handle{}",
+                refs,
+                if refs == 1 { "" } else { "s" },
+            );
+        }
+
+        let inner = &*self.inner;
+        inner.resets.set(inner.resets.get() + 1);
+        let instances = mem::take(&mut *inner.instances.borrow_mut());
+        for instance in instances {
+            unsafe {
+                instance.dealloc();
+            }
+        }
+        inner.jit_code_ranges.borrow_mut().clear();
+        inner.host_funcs.borrow_mut().clear();
+        inner.instance_count.set(0);
+        inner.memory_count.set(0);
+        inner.table_count.set(0);
+
+        // No wasm frames are on the stack, so this releases every reference in
+        // the activations table.
+        self.gc();
+        inner.stack_map_registry.clear();
+        Ok(())
+    }
+
     /// Perform garbage collection of `ExternRef`s.
+    ///
+    /// References passed into wasm, or read by wasm out of tables and
+    /// globals, are recorded in a per-store table of references which may be
+    /// held by wasm frames. Entries in that table are only released by a
+    /// garbage collection, which walks the stack to find the references that
+    /// are still in use by wasm frames and releases all the others.
+    ///
+    /// A collection happens automatically when that table fills up and when
+    /// an `externref` table grows, whether through `table.grow` or
+    /// [`Table::grow`](crate::Table::grow). This method forces one, for
+    /// example to promptly run the destructors of values which wasm no
+    /// longer references. References held by tables, globals, or by the host
+    /// are never released by a collection.
     pub fn gc(&self) {
         // For this crate's API, we ensure that `set_stack_canary` invariants
         // are upheld for all host-->Wasm calls, and we register every module
@@ -1206,6 +2463,7 @@ fn _assert_send_sync() {
     _assert::<Engine>();
     _assert::<Config>();
     _assert::<InterruptHandle>();
+    _assert::<crate::SharedMemory>();
 }
 
 #[cfg(test)]
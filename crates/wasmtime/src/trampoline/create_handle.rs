@@ -10,8 +10,8 @@ use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::wasm::DefinedFuncIndex;
 use wasmtime_environ::Module;
 use wasmtime_runtime::{
-    Imports, InstanceHandle, StackMapRegistry, VMExternRefActivationsTable, VMFunctionBody,
-    VMFunctionImport, VMSharedSignatureIndex, VMTrampoline,
+    Imports, InstanceHandle, RuntimeMemoryCreator, StackMapRegistry, VMExternRefActivationsTable,
+    VMFunctionBody, VMFunctionImport, VMSharedSignatureIndex, VMTrampoline,
 };
 
 pub(crate) fn create_handle(
@@ -21,6 +21,31 @@ pub(crate) fn create_handle(
     trampolines: HashMap<VMSharedSignatureIndex, VMTrampoline>,
     state: Box<dyn Any>,
     func_imports: &[VMFunctionImport],
+) -> Result<StoreInstanceHandle> {
+    create_handle_with_memory_creator(
+        module,
+        store,
+        finished_functions,
+        trampolines,
+        state,
+        func_imports,
+        store.memory_creator(),
+    )
+}
+
+/// Same as `create_handle`, but allocates the instance's memories with
+/// `memory_creator` instead of the one configured for the store's engine.
+///
+/// Host instances aren't allocated from the engine's instance pool, only
+/// their memories may be, if `memory_creator` is the pool's.
+pub(crate) fn create_handle_with_memory_creator(
+    module: Module,
+    store: &Store,
+    finished_functions: PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+    trampolines: HashMap<VMSharedSignatureIndex, VMTrampoline>,
+    state: Box<dyn Any>,
+    func_imports: &[VMFunctionImport],
+    memory_creator: Option<&dyn RuntimeMemoryCreator>,
 ) -> Result<StoreInstanceHandle> {
     let mut imports = Imports::default();
     imports.functions = func_imports;
@@ -39,12 +64,14 @@ pub(crate) fn create_handle(
             &finished_functions,
             trampolines,
             imports,
-            store.memory_creator(),
+            memory_creator,
+            None,
             signatures.into_boxed_slice(),
             state,
             store.interrupts(),
             store.externref_activations_table() as *const VMExternRefActivationsTable as *mut _,
             store.stack_map_registry() as *const StackMapRegistry as *mut _,
+            store.limiter(),
         )?;
         Ok(store.add_instance(handle))
     }
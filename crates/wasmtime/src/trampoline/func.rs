@@ -2,13 +2,14 @@
 
 use super::create_handle::create_handle;
 use crate::trampoline::StoreInstanceHandle;
-use crate::{FuncType, Store, Trap};
-use anyhow::Result;
+use crate::{Engine, FuncType, Store, Trap};
+use anyhow::{anyhow, Result};
 use std::any::Any;
 use std::cmp;
 use std::collections::HashMap;
 use std::mem;
 use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
 use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::isa::TargetIsa;
 use wasmtime_environ::{ir, CompiledFunction, EntityIndex, Module};
@@ -24,9 +25,65 @@ use wasmtime_runtime::{InstanceHandle, VMContext, VMFunctionBody, VMTrampoline};
 struct TrampolineState {
     func: Box<dyn Fn(*mut VMContext, *mut u128) -> Result<(), Trap>>,
     #[allow(dead_code)]
+    trampolines: Arc<HostTrampolines>,
+}
+
+/// The code of the trampolines of `Func::new` functions with a given type.
+///
+/// The trampolines find the host function to call through the `vmctx` they're
+/// called with, so they don't depend on it and are shared by all functions of
+/// that type created for an engine, see `Engine::host_trampolines`.
+pub(crate) struct HostTrampolines {
+    /// Called by wasm with the ABI of the function type, calls `stub_fn`.
+    func: *mut [VMFunctionBody],
+    /// Called by `Func::call` with the trampoline ABI, calls `func`.
+    trampoline: VMTrampoline,
+    #[allow(dead_code)]
     code_memory: CodeMemory,
 }
 
+// The pointers point into `code_memory`, which is read-only once published.
+unsafe impl Send for HostTrampolines {}
+unsafe impl Sync for HostTrampolines {}
+
+impl HostTrampolines {
+    /// Compiles the trampolines for functions of type `ft`.
+    pub(crate) fn new(engine: &Engine, ft: &FuncType) -> Result<HostTrampolines> {
+        // Note that we specifically enable reference types here in our ISA
+        // because `Func::new` is intended to be infallible, but our signature
+        // may use reference types which requires safepoints.
+        let isa = engine.config().target_isa_with_reference_types();
+        let sig = ft.get_wasmtime_signature(isa.pointer_type());
+
+        let mut fn_builder_ctx = FunctionBuilderContext::new();
+        let mut code_memory = CodeMemory::new();
+
+        // First up we manufacture a trampoline which has the ABI specified by
+        // `ft` and calls into `stub_fn`...
+        let func = make_trampoline(isa.as_ref(), &mut code_memory, &mut fn_builder_ctx, &sig);
+
+        // ... and then we also need a trampoline with the standard "trampoline
+        // ABI" which enters into the ABI specified by `ft`. Note that this is
+        // only used if `Func::call` is called on an object created by
+        // `Func::new`.
+        let trampoline = trampoline::make_trampoline(
+            &*isa,
+            &mut code_memory,
+            &mut fn_builder_ctx,
+            &sig,
+            mem::size_of::<u128>(),
+        )?;
+
+        // Publishing our code memory makes it executable.
+        code_memory.publish(isa.as_ref()).map_err(|e| anyhow!(e))?;
+        Ok(HostTrampolines {
+            func,
+            trampoline,
+            code_memory,
+        })
+    }
+}
+
 unsafe extern "C" fn stub_fn(
     vmctx: *mut VMContext,
     caller_vmctx: *mut VMContext,
@@ -208,22 +265,14 @@ pub fn create_handle_with_function(
     func: Box<dyn Fn(*mut VMContext, *mut u128) -> Result<(), Trap>>,
     store: &Store,
 ) -> Result<(StoreInstanceHandle, VMTrampoline)> {
-    // Note that we specifically enable reference types here in our ISA because
-    // `Func::new` is intended to be infallible, but our signature may use
-    // reference types which requires safepoints.
-    let isa = store.engine().config().target_isa_with_reference_types();
-
-    let pointer_type = isa.pointer_type();
+    let trampolines = store.engine().host_trampolines(ft)?;
+    let pointer_type = store.engine().compiler().isa().pointer_type();
     let sig = ft.get_wasmtime_signature(pointer_type);
 
-    let mut fn_builder_ctx = FunctionBuilderContext::new();
     let mut module = Module::new();
     let mut finished_functions = PrimaryMap::new();
-    let mut trampolines = HashMap::new();
-    let mut code_memory = CodeMemory::new();
+    let mut trampoline_map = HashMap::new();
 
-    // First up we manufacture a trampoline which has the ABI specified by `ft`
-    // and calls into `stub_fn`...
     let sig_id = module
         .signatures
         .push((ft.to_wasm_func_type(), sig.clone()));
@@ -231,32 +280,19 @@ pub fn create_handle_with_function(
     module
         .exports
         .insert(String::new(), EntityIndex::Function(func_id));
-    let trampoline = make_trampoline(isa.as_ref(), &mut code_memory, &mut fn_builder_ctx, &sig);
-    finished_functions.push(trampoline);
-
-    // ... and then we also need a trampoline with the standard "trampoline ABI"
-    // which enters into the ABI specified by `ft`. Note that this is only used
-    // if `Func::call` is called on an object created by `Func::new`.
-    let trampoline = trampoline::make_trampoline(
-        &*isa,
-        &mut code_memory,
-        &mut fn_builder_ctx,
-        &sig,
-        mem::size_of::<u128>(),
-    )?;
+    finished_functions.push(trampolines.func);
+    let trampoline = trampolines.trampoline;
     let sig_id = store.register_signature(ft.to_wasm_func_type(), sig);
-    trampolines.insert(sig_id, trampoline);
+    trampoline_map.insert(sig_id, trampoline);
 
-    // Next up we wrap everything up into an `InstanceHandle` by publishing our
-    // code memory (makes it executable) and ensuring all our various bits of
-    // state make it into the instance constructors.
-    code_memory.publish(isa.as_ref());
-    let trampoline_state = TrampolineState { func, code_memory };
+    // Finally we wrap everything up into an `InstanceHandle`, making sure the
+    // shared trampolines stay alive for as long as it does.
+    let trampoline_state = TrampolineState { func, trampolines };
     create_handle(
         module,
         store,
         finished_functions,
-        trampolines,
+        trampoline_map,
         Box::new(trampoline_state),
         &[],
     )
@@ -23,6 +23,7 @@ pub fn create_global(store: &Store, gt: &GlobalType, val: Val) -> Result<StoreIn
             Val::I64(i) => wasm::GlobalInit::I64Const(i),
             Val::F32(f) => wasm::GlobalInit::F32Const(f),
             Val::F64(f) => wasm::GlobalInit::F64Const(f),
+            Val::V128(x) => wasm::GlobalInit::V128Const((&x.to_le_bytes()[..]).into()),
             Val::ExternRef(None) | Val::FuncRef(None) => wasm::GlobalInit::RefNullConst,
             Val::ExternRef(Some(x)) => {
                 // There is no `GlobalInit` variant for using an existing
@@ -54,7 +55,6 @@ pub fn create_global(store: &Store, gt: &GlobalType, val: Val) -> Result<StoreIn
 
                 wasm::GlobalInit::RefFunc(func_index)
             }
-            _ => unimplemented!("create_global for {:?}", gt),
         },
     };
 
@@ -1,25 +1,148 @@
-use super::create_handle::create_handle;
+use super::create_handle::{create_handle, create_handle_with_memory_creator};
 use crate::externals::{LinearMemory, MemoryCreator};
 use crate::trampoline::StoreInstanceHandle;
-use crate::Store;
-use crate::{Limits, MemoryType};
-use anyhow::Result;
-use wasmtime_environ::entity::PrimaryMap;
-use wasmtime_environ::{wasm, EntityIndex, MemoryPlan, MemoryStyle, Module, WASM_PAGE_SIZE};
-use wasmtime_runtime::{RuntimeLinearMemory, RuntimeMemoryCreator, VMMemoryDefinition};
+use crate::{Engine, MemoryType, SharedMemory, Store};
+use anyhow::{anyhow, bail, Result};
+use wasmtime_environ::entity::{EntityRef, PrimaryMap};
+use wasmtime_environ::{
+    wasm, EntityIndex, MemoryPlan, MemoryStyle, Module, WASM_MAX_PAGES, WASM_PAGE_SIZE,
+};
+use wasmtime_runtime::{MmapMemory, RuntimeLinearMemory, RuntimeMemoryCreator, VMMemoryDefinition};
 
-use std::sync::Arc;
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
 
 pub fn create_handle_with_memory(
     store: &Store,
     memory: &MemoryType,
 ) -> Result<StoreInstanceHandle> {
+    create_handle(
+        memory_module(store, memory)?,
+        store,
+        PrimaryMap::new(),
+        Default::default(),
+        Box::new(()),
+        &[],
+    )
+}
+
+/// Creates an instance exporting a memory whose contents live in the
+/// `capacity` bytes starting at `base`, rather than in memory allocated by
+/// wasmtime.
+///
+/// This is `unsafe` since `base` must remain valid for reads and writes of
+/// `capacity` bytes for as long as the returned instance is alive.
+pub unsafe fn create_handle_with_host_buffer(
+    store: &Store,
+    memory: &MemoryType,
+    base: *mut u8,
+    capacity: usize,
+) -> Result<StoreInstanceHandle> {
+    let module = memory_module(store, memory)?;
+    let plan = &module.memory_plans[wasm::MemoryIndex::new(0)];
+
+    // Compiled code only bounds checks dynamic memories, and even then relies
+    // on the guard region to catch accesses at small offsets past the end.
+    // Neither is something we can provide for a host buffer, so refuse any
+    // plan which would let wasm access memory past `capacity`.
+    if plan.memory.shared {
+        bail!("shared memories cannot be backed by a host buffer");
+    }
+    match plan.style {
+        MemoryStyle::Dynamic if plan.offset_guard_size == 0 => {}
+        _ => bail!(
+            "host buffers can only back dynamically bounds-checked memories \
+             without guard pages, see `Config::static_memory_maximum_size` \
+             and `Config::dynamic_memory_guard_size`"
+        ),
+    }
+
+    let pages = u32::try_from(capacity / WASM_PAGE_SIZE as usize).unwrap_or(WASM_MAX_PAGES);
+    let maximum = match plan.memory.maximum {
+        Some(max) => pages.min(max),
+        None => pages.min(WASM_MAX_PAGES),
+    };
+    if maximum < plan.memory.minimum {
+        bail!(
+            "host buffer of {} bytes is too small for the minimum of {} pages",
+            capacity,
+            plan.memory.minimum
+        );
+    }
+
+    let creator = HostBufferCreator {
+        base: base as usize,
+        maximum,
+    };
+    create_handle_with_memory_creator(
+        module,
+        store,
+        PrimaryMap::new(),
+        Default::default(),
+        Box::new(()),
+        &[],
+        Some(&creator),
+    )
+}
+
+/// Creates an instance exporting a memory whose initial contents are mapped
+/// copy-on-write from `file`.
+///
+/// This is `unsafe` since the memory's contents are undefined if `file` is
+/// modified or truncated while the returned instance is alive.
+#[cfg(unix)]
+pub unsafe fn create_handle_with_file(
+    store: &Store,
+    memory: &MemoryType,
+    file: &std::fs::File,
+) -> Result<StoreInstanceHandle> {
+    create_handle_with_memory_creator(
+        memory_module(store, memory)?,
+        store,
+        PrimaryMap::new(),
+        Default::default(),
+        Box::new(()),
+        &[],
+        Some(&FileMemoryCreator { file }),
+    )
+}
+
+/// Creates an instance exporting `shared`, whose contents are shared with every
+/// other instance created from it.
+pub fn create_handle_with_shared_memory(
+    store: &Store,
+    shared: &SharedMemory,
+) -> Result<StoreInstanceHandle> {
+    if !Engine::same(store.engine(), shared.engine()) {
+        bail!("a shared memory can only be used in stores of the engine it was created with");
+    }
+    create_handle_with_memory_creator(
+        memory_module(store, shared.ty())?,
+        store,
+        PrimaryMap::new(),
+        Default::default(),
+        Box::new(shared.clone()),
+        &[],
+        Some(&SharedMemoryCreator {
+            memory: shared.linear_memory().clone(),
+        }),
+    )
+}
+
+fn memory_module(store: &Store, memory: &MemoryType) -> Result<Module> {
+    if memory.is_shared() {
+        store
+            .engine()
+            .config()
+            .check_memory_faults_allowed("shared memories")?;
+    }
     let mut module = Module::new();
 
     let memory = wasm::Memory {
         minimum: memory.limits().min(),
         maximum: memory.limits().max(),
-        shared: false, // TODO
+        shared: memory.is_shared(),
     };
 
     let memory_plan =
@@ -28,15 +151,131 @@ pub fn create_handle_with_memory(
     module
         .exports
         .insert(String::new(), EntityIndex::Memory(memory_id));
+    Ok(module)
+}
 
-    create_handle(
-        module,
-        store,
-        PrimaryMap::new(),
-        Default::default(),
-        Box::new(()),
-        &[],
-    )
+/// A memory creator handing out the single host buffer given to
+/// `create_handle_with_host_buffer`.
+///
+/// The base address is stored as a `usize` so this can be `Send` and `Sync`,
+/// as required of memory creators; it's only ever used on the thread which
+/// created the instance.
+struct HostBufferCreator {
+    base: usize,
+    maximum: u32,
+}
+
+impl RuntimeMemoryCreator for HostBufferCreator {
+    fn new_memory(&self, plan: &MemoryPlan) -> Result<Box<dyn RuntimeLinearMemory>, String> {
+        Ok(Box::new(HostBufferMemory {
+            base: self.base as *mut u8,
+            size: Cell::new(plan.memory.minimum),
+            maximum: self.maximum,
+        }))
+    }
+}
+
+/// A memory creator mapping the file given to `create_handle_with_file`.
+#[cfg(unix)]
+struct FileMemoryCreator<'a> {
+    file: &'a std::fs::File,
+}
+
+#[cfg(unix)]
+impl RuntimeMemoryCreator for FileMemoryCreator<'_> {
+    fn new_memory(&self, plan: &MemoryPlan) -> Result<Box<dyn RuntimeLinearMemory>, String> {
+        // Upheld by the caller of `create_handle_with_file`.
+        let memory = unsafe { wasmtime_runtime::MmapMemory::new_with_file(plan, self.file)? };
+        Ok(Box::new(memory))
+    }
+}
+
+/// The memory backing a `SharedMemory`, which every instance created from it
+/// refers to.
+///
+/// It's allocated with a static memory style, reserving at least the memory's
+/// maximum size, so growing it never relocates it.
+pub(crate) struct SharedLinearMemory(Mutex<MmapMemory>);
+
+impl SharedLinearMemory {
+    pub(crate) fn new(plan: &MemoryPlan) -> Result<SharedLinearMemory> {
+        match plan.style {
+            MemoryStyle::Static { .. } => {}
+            MemoryStyle::Dynamic => {
+                bail!("shared memories must be allocated up front to their maximum size")
+            }
+        }
+        let memory = MmapMemory::new(plan).map_err(|e| anyhow!(e))?;
+        Ok(SharedLinearMemory(Mutex::new(memory)))
+    }
+
+    pub(crate) fn size(&self) -> u32 {
+        self.0.lock().unwrap().size()
+    }
+
+    fn grow(&self, delta: u32) -> Option<u32> {
+        self.0.lock().unwrap().grow(delta)
+    }
+
+    fn vmmemory(&self) -> VMMemoryDefinition {
+        self.0.lock().unwrap().vmmemory()
+    }
+}
+
+/// A memory creator handing out the memory given to
+/// `create_handle_with_shared_memory`.
+struct SharedMemoryCreator {
+    memory: Arc<SharedLinearMemory>,
+}
+
+impl RuntimeMemoryCreator for SharedMemoryCreator {
+    fn new_memory(&self, _plan: &MemoryPlan) -> Result<Box<dyn RuntimeLinearMemory>, String> {
+        Ok(Box::new(SharedMemoryProxy(self.memory.clone())))
+    }
+}
+
+struct SharedMemoryProxy(Arc<SharedLinearMemory>);
+
+impl RuntimeLinearMemory for SharedMemoryProxy {
+    fn size(&self) -> u32 {
+        self.0.size()
+    }
+
+    fn grow(&self, delta: u32) -> Option<u32> {
+        self.0.grow(delta)
+    }
+
+    fn vmmemory(&self) -> VMMemoryDefinition {
+        self.0.vmmemory()
+    }
+}
+
+/// A linear memory living in a buffer owned by the embedder. It can grow up to
+/// the capacity of that buffer but is never relocated.
+struct HostBufferMemory {
+    base: *mut u8,
+    size: Cell<u32>,
+    maximum: u32,
+}
+
+impl RuntimeLinearMemory for HostBufferMemory {
+    fn size(&self) -> u32 {
+        self.size.get()
+    }
+
+    fn grow(&self, delta: u32) -> Option<u32> {
+        let prev = self.size.get();
+        let new = prev.checked_add(delta).filter(|new| *new <= self.maximum)?;
+        self.size.set(new);
+        Some(prev)
+    }
+
+    fn vmmemory(&self) -> VMMemoryDefinition {
+        VMMemoryDefinition {
+            base: self.base,
+            current_length: self.size.get() as usize * WASM_PAGE_SIZE as usize,
+        }
+    }
 }
 
 struct LinearMemoryProxy {
@@ -67,7 +306,7 @@ pub(crate) struct MemoryCreatorProxy {
 
 impl RuntimeMemoryCreator for MemoryCreatorProxy {
     fn new_memory(&self, plan: &MemoryPlan) -> Result<Box<dyn RuntimeLinearMemory>, String> {
-        let ty = MemoryType::new(Limits::new(plan.memory.minimum, plan.memory.maximum));
+        let ty = MemoryType::from_wasmtime_memory(&plan.memory);
         let reserved_size_in_bytes = match plan.style {
             MemoryStyle::Static { bound } => Some(bound as u64 * WASM_PAGE_SIZE as u64),
             MemoryStyle::Dynamic => None,
@@ -6,16 +6,21 @@ mod global;
 mod memory;
 mod table;
 
-pub(crate) use memory::MemoryCreatorProxy;
+pub(crate) use self::func::HostTrampolines;
+pub(crate) use memory::{MemoryCreatorProxy, SharedLinearMemory};
 
 use self::func::create_handle_with_function;
 use self::global::create_global;
-use self::memory::create_handle_with_memory;
+use self::memory::{
+    create_handle_with_host_buffer, create_handle_with_memory, create_handle_with_shared_memory,
+};
 use self::table::create_handle_with_table;
-use crate::{FuncType, GlobalType, MemoryType, Store, TableType, Trap, Val};
+use crate::runtime::StoreInner;
+use crate::{FuncType, GlobalType, MemoryType, SharedMemory, Store, TableType, Trap, Val};
 use anyhow::Result;
 use std::any::Any;
 use std::ops::Deref;
+use std::rc::Weak;
 use wasmtime_runtime::{InstanceHandle, VMContext, VMFunctionBody, VMTrampoline};
 
 /// A wrapper around `wasmtime_runtime::InstanceHandle` which pairs it with the
@@ -45,6 +50,50 @@ impl Deref for StoreInstanceHandle {
     }
 }
 
+impl StoreInstanceHandle {
+    pub fn downgrade(&self) -> WeakStoreInstanceHandle {
+        WeakStoreInstanceHandle {
+            store: self.store.weak(),
+            resets: self.store.resets(),
+            handle: unsafe { self.handle.clone() },
+        }
+    }
+}
+
+/// Same as `StoreInstanceHandle`, but doesn't keep the `Store` alive. The
+/// instance handle dangles once the store is dropped or reset, so it's only
+/// handed out again if the store can be upgraded and hasn't been reset since.
+pub struct WeakStoreInstanceHandle {
+    store: Weak<StoreInner>,
+    resets: usize,
+    handle: InstanceHandle,
+}
+
+impl WeakStoreInstanceHandle {
+    pub fn upgrade(&self) -> Option<StoreInstanceHandle> {
+        let store = Store::upgrade(&self.store)?;
+        if store.resets() != self.resets {
+            return None;
+        }
+        Some(StoreInstanceHandle {
+            store,
+            // Safe since the instance lives as long as the store we just
+            // upgraded.
+            handle: unsafe { self.handle.clone() },
+        })
+    }
+}
+
+impl Clone for WeakStoreInstanceHandle {
+    fn clone(&self) -> WeakStoreInstanceHandle {
+        WeakStoreInstanceHandle {
+            store: self.store.clone(),
+            resets: self.resets,
+            handle: unsafe { self.handle.clone() },
+        }
+    }
+}
+
 pub fn generate_func_export(
     ft: &FuncType,
     func: Box<dyn Fn(*mut VMContext, *mut u128) -> Result<(), Trap>>,
@@ -101,6 +150,47 @@ pub fn generate_memory_export(
     }
 }
 
+/// Note that this is `unsafe` since `base` must point to `capacity` bytes which
+/// stay valid for as long as the returned instance is alive.
+pub unsafe fn generate_host_buffer_memory_export(
+    store: &Store,
+    m: &MemoryType,
+    base: *mut u8,
+    capacity: usize,
+) -> Result<(StoreInstanceHandle, wasmtime_runtime::ExportMemory)> {
+    let instance = create_handle_with_host_buffer(store, m, base, capacity)?;
+    match instance.lookup("").expect("memory export") {
+        wasmtime_runtime::Export::Memory(m) => Ok((instance, m)),
+        _ => unreachable!(),
+    }
+}
+
+pub fn generate_shared_memory_export(
+    store: &Store,
+    shared: &SharedMemory,
+) -> Result<(StoreInstanceHandle, wasmtime_runtime::ExportMemory)> {
+    let instance = create_handle_with_shared_memory(store, shared)?;
+    match instance.lookup("").expect("memory export") {
+        wasmtime_runtime::Export::Memory(m) => Ok((instance, m)),
+        _ => unreachable!(),
+    }
+}
+
+/// Note that this is `unsafe` since the memory's contents are undefined if
+/// `file` is modified or truncated while the returned instance is alive.
+#[cfg(unix)]
+pub unsafe fn generate_file_memory_export(
+    store: &Store,
+    m: &MemoryType,
+    file: &std::fs::File,
+) -> Result<(StoreInstanceHandle, wasmtime_runtime::ExportMemory)> {
+    let instance = memory::create_handle_with_file(store, m, file)?;
+    match instance.lookup("").expect("memory export") {
+        wasmtime_runtime::Export::Memory(m) => Ok((instance, m)),
+        _ => unreachable!(),
+    }
+}
+
 pub fn generate_table_export(
     store: &Store,
     t: &TableType,
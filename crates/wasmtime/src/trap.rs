@@ -1,9 +1,13 @@
 use crate::frame_info::{GlobalFrameInfo, FRAME_INFO};
-use crate::FrameInfo;
+use crate::{FrameInfo, Store, WasmCoreDump};
 use backtrace::Backtrace;
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
 use std::sync::Arc;
-use wasmtime_environ::ir::TrapCode;
+use wasmtime_environ::entity::EntityRef;
+use wasmtime_environ::wasm::{EntityIndex, MemoryIndex};
+use wasmtime_environ::{ir, MemoryStyle, WASM_PAGE_SIZE};
+use wasmtime_runtime::Export;
 
 /// A struct representing an aborted instruction execution, with a message
 /// indicating the cause.
@@ -21,8 +25,14 @@ enum TrapReason {
     /// An `i32` exit status describing an explicit program exit.
     I32Exit(i32),
 
-    /// A structured error describing a trap.
-    Error(Box<dyn std::error::Error + Send + Sync>),
+    /// A structured error describing a trap, usually returned by the host.
+    Error(anyhow::Error),
+
+    /// A trap raised by a wasm instruction.
+    InstructionTrap(TrapCode),
+
+    /// Wasm ran out of the fuel given to its store.
+    OutOfFuel,
 }
 
 impl fmt::Display for TrapReason {
@@ -31,14 +41,173 @@ impl fmt::Display for TrapReason {
             TrapReason::Message(s) => write!(f, "{}", s),
             TrapReason::I32Exit(status) => write!(f, "Exited with i32 exit status {}", status),
             TrapReason::Error(e) => write!(f, "{}", e),
+            TrapReason::InstructionTrap(code) => write!(f, "wasm trap: {}", code),
+            TrapReason::OutOfFuel => write!(f, "all fuel consumed by WebAssembly"),
+        }
+    }
+}
+
+/// The kind of a trap raised by wasm, as returned by [`Trap::code`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TrapCode {
+    /// The call stack was exhausted.
+    StackOverflow,
+
+    /// An out-of-bounds memory access.
+    MemoryOutOfBounds,
+
+    /// A misaligned atomic memory access.
+    MemoryMisaligned,
+
+    /// An out-of-bounds access to a table.
+    TableOutOfBounds,
+
+    /// An indirect call to a null table entry.
+    IndirectCallToNull,
+
+    /// The function called indirectly doesn't have the expected signature.
+    IndirectCallTypeMismatch,
+
+    /// An integer arithmetic operation caused an overflow.
+    IntegerOverflow,
+
+    /// An integer division by zero.
+    IntegerDivideByZero,
+
+    /// A float couldn't be converted to an integer because it was NaN or out
+    /// of range.
+    BadConversionToInteger,
+
+    /// An `unreachable` instruction was executed.
+    Unreachable,
+
+    /// Execution was interrupted, see [`Store::interrupt_handle`].
+    ///
+    /// [`Store::interrupt_handle`]: crate::Store::interrupt_handle
+    Interrupt,
+
+    /// Wasm ran out of the fuel given to its store, see
+    /// [`Config::consume_fuel`].
+    ///
+    /// [`Config::consume_fuel`]: crate::Config::consume_fuel
+    OutOfFuel,
+}
+
+impl TrapCode {
+    fn from_ir(code: ir::TrapCode) -> TrapCode {
+        match code {
+            ir::TrapCode::StackOverflow => TrapCode::StackOverflow,
+            ir::TrapCode::HeapOutOfBounds => TrapCode::MemoryOutOfBounds,
+            ir::TrapCode::HeapMisaligned => TrapCode::MemoryMisaligned,
+            ir::TrapCode::TableOutOfBounds => TrapCode::TableOutOfBounds,
+            ir::TrapCode::IndirectCallToNull => TrapCode::IndirectCallToNull,
+            ir::TrapCode::BadSignature => TrapCode::IndirectCallTypeMismatch,
+            ir::TrapCode::IntegerOverflow => TrapCode::IntegerOverflow,
+            ir::TrapCode::IntegerDivisionByZero => TrapCode::IntegerDivideByZero,
+            ir::TrapCode::BadConversionToInteger => TrapCode::BadConversionToInteger,
+            ir::TrapCode::UnreachableCodeReached => TrapCode::Unreachable,
+            ir::TrapCode::Interrupt => TrapCode::Interrupt,
+            ir::TrapCode::User(_) => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for TrapCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let desc = match self {
+            TrapCode::StackOverflow => "call stack exhausted",
+            TrapCode::MemoryOutOfBounds => "out of bounds memory access",
+            TrapCode::MemoryMisaligned => "misaligned memory access",
+            TrapCode::TableOutOfBounds => "undefined element: out of bounds table access",
+            TrapCode::IndirectCallToNull => "uninitialized element",
+            TrapCode::IndirectCallTypeMismatch => "indirect call type mismatch",
+            TrapCode::IntegerOverflow => "integer overflow",
+            TrapCode::IntegerDivideByZero => "integer divide by zero",
+            TrapCode::BadConversionToInteger => "invalid conversion to integer",
+            TrapCode::Unreachable => "unreachable",
+            TrapCode::Interrupt => "interrupt",
+            TrapCode::OutOfFuel => "all fuel consumed",
+        };
+        write!(f, "{}", desc)
+    }
+}
+
+/// The out-of-bounds access of a [`TrapCode::MemoryOutOfBounds`] trap, as
+/// returned by [`Trap::memory_access`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MemoryAccess {
+    address: u64,
+    memory_size: u64,
+}
+
+impl MemoryAccess {
+    /// Returns the address in the linear memory which was accessed.
+    ///
+    /// This is the first byte of the access which faulted, so for an access
+    /// which straddles the end of the memory it's the first byte past the end
+    /// rather than the start of the access.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// Returns the size of the linear memory, in bytes, at the time of the
+    /// access.
+    pub fn memory_size(&self) -> u64 {
+        self.memory_size
+    }
+
+    /// Finds the memory of `store` whose reserved address space contains the
+    /// native address `addr`.
+    fn resolve(store: &Store, addr: usize) -> Option<MemoryAccess> {
+        for handle in store.instances().iter() {
+            let module = handle.module();
+            for i in module.num_imported_memories..module.memory_plans.len() {
+                let index = EntityIndex::Memory(MemoryIndex::new(i));
+                let export = match handle.lookup_by_declaration(&index) {
+                    Export::Memory(m) => m,
+                    _ => unreachable!(),
+                };
+                let definition = unsafe { &*export.definition };
+                let base = definition.base as usize;
+                let mapped = match export.memory.style {
+                    MemoryStyle::Static { bound } => u64::from(bound) * u64::from(WASM_PAGE_SIZE),
+                    MemoryStyle::Dynamic => definition.current_length as u64,
+                };
+                let reserved = mapped + export.memory.offset_guard_size;
+                if addr >= base && ((addr - base) as u64) < reserved {
+                    return Some(MemoryAccess {
+                        address: (addr - base) as u64,
+                        memory_size: definition.current_length as u64,
+                    });
+                }
+            }
         }
+        None
+    }
+}
+
+impl fmt::Display for MemoryAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "accessed address {:#x} of a memory of {:#x} bytes",
+            self.address, self.memory_size
+        )
     }
 }
 
 struct TrapInner {
     reason: TrapReason,
     wasm_trace: Vec<FrameInfo>,
+    /// For each frame of `wasm_trace`, the number of calls from the host into
+    /// wasm it's outside of, counted from the innermost one.
+    wasm_trace_entries: Vec<u32>,
     native_trace: Backtrace,
+    coredump: Option<WasmCoreDump>,
+    faulting_addr: Option<usize>,
+    memory_access: Option<MemoryAccess>,
+    observed: AtomicBool,
 }
 
 fn _assert_trap_is_sync_and_send(t: &Trap) -> (&dyn Sync, &dyn Send) {
@@ -55,7 +224,7 @@ impl Trap {
     pub fn new<I: Into<String>>(message: I) -> Self {
         let info = FRAME_INFO.read().unwrap();
         let reason = TrapReason::Message(message.into());
-        Trap::new_with_trace(&info, None, reason, Backtrace::new_unresolved())
+        Trap::new_with_trace(&info, None, reason, wasmtime_runtime::capture_backtrace())
     }
 
     /// Creates a new `Trap` representing an explicit program exit with a classic `i32`
@@ -65,7 +234,12 @@ impl Trap {
             inner: Arc::new(TrapInner {
                 reason: TrapReason::I32Exit(status),
                 wasm_trace: Vec::new(),
+                wasm_trace_entries: Vec::new(),
                 native_trace: Backtrace::from(Vec::new()),
+                coredump: None,
+                faulting_addr: None,
+                memory_access: None,
+                observed: AtomicBool::new(false),
             }),
         }
     }
@@ -78,15 +252,20 @@ impl Trap {
                 pc,
                 backtrace,
                 maybe_interrupted,
+                faulting_addr,
             } => {
                 let mut code = info
                     .lookup_trap_info(pc)
                     .map(|info| info.trap_code)
-                    .unwrap_or(TrapCode::StackOverflow);
-                if maybe_interrupted && code == TrapCode::StackOverflow {
-                    code = TrapCode::Interrupt;
+                    .unwrap_or(ir::TrapCode::StackOverflow);
+                if maybe_interrupted && code == ir::TrapCode::StackOverflow {
+                    code = ir::TrapCode::Interrupt;
+                }
+                let mut trap = Trap::new_wasm(&info, Some(pc), code, backtrace);
+                if code == ir::TrapCode::HeapOutOfBounds {
+                    Arc::get_mut(&mut trap.inner).unwrap().faulting_addr = faulting_addr;
                 }
-                Trap::new_wasm(&info, Some(pc), code, backtrace)
+                trap
             }
             wasmtime_runtime::Trap::Wasm {
                 trap_code,
@@ -96,32 +275,20 @@ impl Trap {
                 let reason = TrapReason::Message("out of memory".to_string());
                 Trap::new_with_trace(&info, None, reason, backtrace)
             }
+            wasmtime_runtime::Trap::OutOfFuel { backtrace } => {
+                Trap::new_with_trace(&info, None, TrapReason::OutOfFuel, backtrace)
+            }
         }
     }
 
     fn new_wasm(
         info: &GlobalFrameInfo,
         trap_pc: Option<usize>,
-        code: TrapCode,
+        code: ir::TrapCode,
         backtrace: Backtrace,
     ) -> Self {
-        use wasmtime_environ::ir::TrapCode::*;
-        let desc = match code {
-            StackOverflow => "call stack exhausted",
-            HeapOutOfBounds => "out of bounds memory access",
-            HeapMisaligned => "misaligned memory access",
-            TableOutOfBounds => "undefined element: out of bounds table access",
-            IndirectCallToNull => "uninitialized element",
-            BadSignature => "indirect call type mismatch",
-            IntegerOverflow => "integer overflow",
-            IntegerDivisionByZero => "integer divide by zero",
-            BadConversionToInteger => "invalid conversion to integer",
-            UnreachableCodeReached => "unreachable",
-            Interrupt => "interrupt",
-            User(_) => unreachable!(),
-        };
-        let msg = TrapReason::Message(format!("wasm trap: {}", desc));
-        Trap::new_with_trace(info, trap_pc, msg, backtrace)
+        let reason = TrapReason::InstructionTrap(TrapCode::from_ir(code));
+        Trap::new_with_trace(info, trap_pc, reason, backtrace)
     }
 
     fn new_with_trace(
@@ -131,6 +298,9 @@ impl Trap {
         native_trace: Backtrace,
     ) -> Self {
         let mut wasm_trace = Vec::new();
+        let mut wasm_trace_entries = Vec::new();
+        let mut entry = 0;
+        let mut in_wasm = false;
         for frame in native_trace.frames() {
             let pc = frame.ip() as usize;
             if pc == 0 {
@@ -149,13 +319,25 @@ impl Trap {
             let pc_to_lookup = if Some(pc) == trap_pc { pc } else { pc - 1 };
             if let Some(info) = info.lookup_frame_info(pc_to_lookup) {
                 wasm_trace.push(info);
+                wasm_trace_entries.push(entry);
+                in_wasm = true;
+            } else if in_wasm {
+                // Wasm only leaves wasm by calling the host, so the wasm
+                // frames further out belong to an earlier call into wasm.
+                entry += 1;
+                in_wasm = false;
             }
         }
         Trap {
             inner: Arc::new(TrapInner {
                 reason,
                 wasm_trace,
+                wasm_trace_entries,
                 native_trace,
+                coredump: None,
+                faulting_addr: None,
+                memory_access: None,
+                observed: AtomicBool::new(false),
             }),
         }
     }
@@ -169,6 +351,154 @@ impl Trap {
         }
     }
 
+    /// Returns whether this trap was raised because wasm consumed all of the
+    /// fuel given to its store, see [`Config::consume_fuel`].
+    ///
+    /// [`Config::consume_fuel`]: crate::Config::consume_fuel
+    pub fn is_out_of_fuel(&self) -> bool {
+        matches!(self.inner.reason, TrapReason::OutOfFuel)
+    }
+
+    /// Returns the kind of trap raised by wasm that this is, or `None` if it
+    /// was created by the host, for example with [`Trap::new`] or by
+    /// returning an error from a host function.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let module = Module::new(store.engine(), "(module (func (export \"f\") unreachable))")?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// let trap = instance.get_typed_func::<(), ()>("f")?.call(()).unwrap_err();
+    /// assert_eq!(trap.code(), Some(TrapCode::Unreachable));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn code(&self) -> Option<TrapCode> {
+        match self.inner.reason {
+            TrapReason::InstructionTrap(code) => Some(code),
+            TrapReason::OutOfFuel => Some(TrapCode::OutOfFuel),
+            _ => None,
+        }
+    }
+
+    /// Returns the error this trap was created from, if it's an `E`.
+    ///
+    /// Errors returned by host functions are carried through the wasm frames
+    /// which called them unchanged, so the original error is available from
+    /// the trap returned by the outermost call into wasm. For
+    /// [`Func::call`], which returns an `anyhow::Error`, that trap can be
+    /// retrieved with `err.downcast_ref::<Trap>()` first.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// #[derive(Debug)]
+    /// struct MyError(u32);
+    ///
+    /// impl std::fmt::Display for MyError {
+    ///     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    ///         write!(f, "my error {}", self.0)
+    ///     }
+    /// }
+    ///
+    /// impl std::error::Error for MyError {}
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let fail = Func::wrap(&store, || -> Result<(), Trap> {
+    ///     Err(Trap::from(anyhow::Error::new(MyError(42))))
+    /// });
+    /// let module = Module::new(
+    ///     store.engine(),
+    ///     r#"(module (import "" "" (func $fail)) (func (export "run") call $fail))"#,
+    /// )?;
+    /// let instance = Instance::new(&store, &module, &[fail.into()])?;
+    /// let err = instance.get_typed_func::<(), ()>("run")?.call(()).unwrap_err();
+    /// assert_eq!(err.downcast_ref::<MyError>().unwrap().0, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Func::call`]: crate::Func::call
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        match &self.inner.reason {
+            TrapReason::Error(e) => e
+                .downcast_ref::<E>()
+                .or_else(|| e.downcast_ref::<BoxedError>()?.0.downcast_ref::<E>()),
+            _ => None,
+        }
+    }
+
+    /// Returns the coredump captured when this trap was raised, if
+    /// [`Config::coredump_on_trap`] is enabled.
+    ///
+    /// Traps created by the host only have a coredump if they're returned by
+    /// a host function called from wasm.
+    ///
+    /// [`Config::coredump_on_trap`]: crate::Config::coredump_on_trap
+    pub fn coredump(&self) -> Option<&WasmCoreDump> {
+        self.inner.coredump.as_ref()
+    }
+
+    /// Captures a coredump of `store` for this trap, unless it already has
+    /// one or it didn't happen in wasm.
+    pub(crate) fn capture_coredump(&mut self, store: &Store) {
+        if self.inner.coredump.is_some() || self.inner.wasm_trace.is_empty() {
+            return;
+        }
+        // A host function may have held on to the trap it returned, in which
+        // case it can't be updated anymore.
+        if let Some(inner) = Arc::get_mut(&mut self.inner) {
+            inner.coredump = Some(WasmCoreDump::new(
+                store,
+                &inner.wasm_trace,
+                &inner.wasm_trace_entries,
+            ));
+        }
+    }
+
+    /// Returns the access which caused this trap, if it's a
+    /// [`TrapCode::MemoryOutOfBounds`] trap raised by a load or store which
+    /// faulted.
+    ///
+    /// Loads and stores are mostly kept in bounds by the guard regions around
+    /// linear memories, in which case the address they accessed is known. It
+    /// isn't known for accesses caught by explicit bounds checks instead,
+    /// which dynamic memories and [`Config::explicit_bounds_checks`] rely on,
+    /// nor for bulk memory instructions such as `memory.copy`, and this
+    /// returns `None` for those.
+    ///
+    /// [`Config::explicit_bounds_checks`]: crate::Config::explicit_bounds_checks
+    pub fn memory_access(&self) -> Option<MemoryAccess> {
+        self.inner.memory_access
+    }
+
+    /// Looks up which memory of `store` the faulting access of this trap was
+    /// in, so that it can be reported by `memory_access`.
+    pub(crate) fn resolve_memory_access(&mut self, store: &Store) {
+        let addr = match self.inner.faulting_addr {
+            Some(addr) if self.inner.memory_access.is_none() => addr,
+            _ => return,
+        };
+        let access = MemoryAccess::resolve(store, addr);
+        if let Some(inner) = Arc::get_mut(&mut self.inner) {
+            inner.memory_access = access;
+        }
+    }
+
+    /// Flags this trap as seen by a store's trap hook, returning whether it
+    /// hadn't been seen yet.
+    pub(crate) fn observe(&self) -> bool {
+        !self.inner.observed.swap(true, SeqCst)
+    }
+
     /// Returns a list of function frames in WebAssembly code that led to this
     /// trap happening.
     pub fn trace(&self) -> &[FrameInfo] {
@@ -182,6 +512,7 @@ impl fmt::Debug for Trap {
             .field("reason", &self.inner.reason)
             .field("wasm_trace", &self.inner.wasm_trace)
             .field("native_trace", &self.inner.native_trace)
+            .field("memory_access", &self.inner.memory_access)
             .finish()
     }
 }
@@ -189,6 +520,9 @@ impl fmt::Debug for Trap {
 impl fmt::Display for Trap {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.inner.reason)?;
+        if let Some(access) = &self.inner.memory_access {
+            write!(f, " ({})", access)?;
+        }
         let trace = self.trace();
         if trace.is_empty() {
             return Ok(());
@@ -205,6 +539,13 @@ impl fmt::Display for Trap {
                 None => write!(f, "<wasm function {}>", frame.func_index())?,
             }
             writeln!(f, "")?;
+            if let Some((file, line, column)) = frame.source_location() {
+                write!(f, "           at {}:{}", file, line)?;
+                if column != 0 {
+                    write!(f, ":{}", column)?;
+                }
+                writeln!(f, "")?;
+            }
         }
         Ok(())
     }
@@ -214,26 +555,55 @@ impl std::error::Error for Trap {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match &self.inner.reason {
             TrapReason::Error(e) => e.source(),
-            TrapReason::I32Exit(_) | TrapReason::Message(_) => None,
+            TrapReason::I32Exit(_)
+            | TrapReason::Message(_)
+            | TrapReason::InstructionTrap(_)
+            | TrapReason::OutOfFuel => None,
         }
     }
 }
 
 impl From<anyhow::Error> for Trap {
     fn from(e: anyhow::Error) -> Trap {
-        Box::<dyn std::error::Error + Send + Sync>::from(e).into()
+        // If the top-level error is already a trap, don't be redundant and just return it.
+        if let Some(trap) = e.downcast_ref::<Trap>() {
+            trap.clone()
+        } else {
+            let info = FRAME_INFO.read().unwrap();
+            let reason = TrapReason::Error(e);
+            Trap::new_with_trace(&info, None, reason, wasmtime_runtime::capture_backtrace())
+        }
     }
 }
 
 impl From<Box<dyn std::error::Error + Send + Sync>> for Trap {
     fn from(e: Box<dyn std::error::Error + Send + Sync>) -> Trap {
-        // If the top-level error is already a trap, don't be redundant and just return it.
         if let Some(trap) = e.downcast_ref::<Trap>() {
             trap.clone()
         } else {
-            let info = FRAME_INFO.read().unwrap();
-            let reason = TrapReason::Error(e.into());
-            Trap::new_with_trace(&info, None, reason, Backtrace::new_unresolved())
+            anyhow::Error::from(BoxedError(e)).into()
         }
     }
 }
+
+/// Adapts a boxed error to `anyhow::Error`, which doesn't accept one
+/// directly, while keeping it around for `Trap::downcast_ref`.
+struct BoxedError(Box<dyn std::error::Error + Send + Sync>);
+
+impl fmt::Debug for BoxedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for BoxedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl std::error::Error for BoxedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
@@ -46,6 +46,16 @@ impl Limits {
     }
 }
 
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.min)?;
+        if let Some(max) = self.max {
+            write!(f, " {}", max)?;
+        }
+        Ok(())
+    }
+}
+
 // Value Types
 
 /// A list of all possible value types in WebAssembly.
@@ -188,6 +198,17 @@ impl ExternType {
     }
 }
 
+impl fmt::Display for ExternType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExternType::Func(ty) => fmt::Display::fmt(ty, f),
+            ExternType::Global(ty) => fmt::Display::fmt(ty, f),
+            ExternType::Table(ty) => fmt::Display::fmt(ty, f),
+            ExternType::Memory(ty) => fmt::Display::fmt(ty, f),
+        }
+    }
+}
+
 impl From<FuncType> for ExternType {
     fn from(ty: FuncType) -> ExternType {
         ExternType::Func(ty)
@@ -295,6 +316,27 @@ impl FuncType {
     }
 }
 
+impl fmt::Display for FuncType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(func")?;
+        if !self.params.is_empty() {
+            write!(f, " (param")?;
+            for ty in self.params.iter() {
+                write!(f, " {}", ty)?;
+            }
+            write!(f, ")")?;
+        }
+        if !self.results.is_empty() {
+            write!(f, " (result")?;
+            for ty in self.results.iter() {
+                write!(f, " {}", ty)?;
+            }
+            write!(f, ")")?;
+        }
+        write!(f, ")")
+    }
+}
+
 // Global Types
 
 /// A WebAssembly global descriptor.
@@ -341,6 +383,15 @@ impl GlobalType {
     }
 }
 
+impl fmt::Display for GlobalType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mutability {
+            Mutability::Const => write!(f, "(global {})", self.content),
+            Mutability::Var => write!(f, "(global (mut {}))", self.content),
+        }
+    }
+}
+
 // Table Types
 
 /// A descriptor for a table in a WebAssembly module.
@@ -385,6 +436,12 @@ impl TableType {
     }
 }
 
+impl fmt::Display for TableType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(table {} {})", self.limits, self.element)
+    }
+}
+
 // Memory Types
 
 /// A descriptor for a WebAssembly memory type.
@@ -394,13 +451,29 @@ impl TableType {
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct MemoryType {
     limits: Limits,
+    shared: bool,
 }
 
 impl MemoryType {
     /// Creates a new descriptor for a WebAssembly memory given the specified
     /// limits of the memory.
     pub fn new(limits: Limits) -> MemoryType {
-        MemoryType { limits }
+        MemoryType {
+            limits,
+            shared: false,
+        }
+    }
+
+    /// Creates a new descriptor for a shared WebAssembly memory, as defined by
+    /// the threads proposal.
+    ///
+    /// Shared memories must always have a maximum size, and they are never
+    /// relocated when they grow.
+    pub fn shared(minimum: u32, maximum: u32) -> MemoryType {
+        MemoryType {
+            limits: Limits::new(minimum, Some(maximum)),
+            shared: true,
+        }
     }
 
     /// Returns the limits (in pages) that are configured for this memory.
@@ -408,8 +481,26 @@ impl MemoryType {
         &self.limits
     }
 
+    /// Returns whether this is a shared memory.
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
+
     pub(crate) fn from_wasmtime_memory(memory: &wasm::Memory) -> MemoryType {
-        MemoryType::new(Limits::new(memory.minimum, memory.maximum))
+        MemoryType {
+            limits: Limits::new(memory.minimum, memory.maximum),
+            shared: memory.shared,
+        }
+    }
+}
+
+impl fmt::Display for MemoryType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(memory {}", self.limits)?;
+        if self.shared {
+            write!(f, " shared")?;
+        }
+        write!(f, ")")
     }
 }
 
@@ -228,6 +228,12 @@ impl From<f64> for Val {
     }
 }
 
+impl From<u128> for Val {
+    fn from(val: u128) -> Val {
+        Val::V128(val)
+    }
+}
+
 impl From<ExternRef> for Val {
     fn from(val: ExternRef) -> Val {
         Val::ExternRef(Some(val))
@@ -252,20 +258,6 @@ impl From<Func> for Val {
     }
 }
 
-pub(crate) fn into_checked_anyfunc(
-    val: Val,
-    store: &Store,
-) -> Result<*mut wasmtime_runtime::VMCallerCheckedAnyfunc> {
-    if !val.comes_from_same_store(store) {
-        bail!("cross-`Store` values are not supported");
-    }
-    Ok(match val {
-        Val::FuncRef(None) => ptr::null_mut(),
-        Val::FuncRef(Some(f)) => f.caller_checked_anyfunc().as_ptr(),
-        _ => bail!("val is not funcref"),
-    })
-}
-
 pub(crate) unsafe fn from_checked_anyfunc(
     anyfunc: *mut wasmtime_runtime::VMCallerCheckedAnyfunc,
     store: &Store,
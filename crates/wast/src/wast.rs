@@ -89,7 +89,11 @@ impl WastContext {
         self.modules.push(module.clone());
         let instance = match self.linker.instantiate(&module) {
             Ok(i) => i,
-            Err(e) => return e.downcast::<Trap>().map(Outcome::Trap),
+            Err(e) => match e.downcast::<InstantiationError>() {
+                Ok(InstantiationError::Trap(trap)) => return Ok(Outcome::Trap(trap)),
+                Ok(e) => return Err(e.into()),
+                Err(e) => return Err(e),
+            },
         };
         Ok(Outcome::Ok(instance))
     }
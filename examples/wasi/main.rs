@@ -5,7 +5,7 @@
 
 use anyhow::Result;
 use wasmtime::*;
-use wasmtime_wasi::{Wasi, WasiCtx};
+use wasmtime_wasi::{add_wasi_to_linker, WasiCtx};
 
 fn main() -> Result<()> {
     tracing_subscriber::FmtSubscriber::builder()
@@ -16,11 +16,10 @@ fn main() -> Result<()> {
     let store = Store::default();
     let mut linker = Linker::new(&store);
 
-    // Create an instance of `Wasi` which contains a `WasiCtx`. Note that
+    // Define the WASI functions in the linker, backed by a `WasiCtx`. Note that
     // `WasiCtx` provides a number of ways to configure what the target program
     // will have access to.
-    let wasi = Wasi::new(&store, WasiCtx::new(std::env::args())?);
-    wasi.add_to_linker(&mut linker)?;
+    add_wasi_to_linker(&mut linker, WasiCtx::new(std::env::args())?)?;
 
     // Instantiate our module with the imports we've created, and run it.
     let module = Module::from_file(store.engine(), "target/wasm32-wasi/debug/wasi.wasm")?;
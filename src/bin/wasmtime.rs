@@ -6,7 +6,8 @@
 use anyhow::Result;
 use structopt::{clap::AppSettings, clap::ErrorKind, StructOpt};
 use wasmtime_cli::commands::{
-    ConfigCommand, RunCommand, WasmToObjCommand, WastCommand, WASM2OBJ_AFTER_HELP,
+    CompileCommand, ConfigCommand, RunCommand, WasmToObjCommand, WastCommand, COMPILE_AFTER_HELP,
+    WASM2OBJ_AFTER_HELP,
 };
 
 /// Wasmtime WebAssembly Runtime
@@ -36,6 +37,9 @@ use wasmtime_cli::commands::{
 )]
 enum WasmtimeApp {
     // !!! IMPORTANT: if subcommands are added or removed, update `parse_module` in `src/commands/run.rs`. !!!
+    /// Compiles a WebAssembly module ahead of time
+    #[structopt(after_help = COMPILE_AFTER_HELP)]
+    Compile(CompileCommand),
     /// Controls Wasmtime configuration settings
     Config(ConfigCommand),
     /// Runs a WebAssembly module
@@ -51,6 +55,7 @@ impl WasmtimeApp {
     /// Executes the command.
     pub fn execute(&self) -> Result<()> {
         match self {
+            Self::Compile(c) => c.execute(),
             Self::Config(c) => c.execute(),
             Self::Run(c) => c.execute(),
             Self::WasmToObj(c) => c.execute(),
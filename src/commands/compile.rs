@@ -0,0 +1,89 @@
+//! The module that implements the `wasmtime compile` command.
+
+use crate::{init_file_per_thread_logger, CommonOptions};
+use anyhow::{Context as _, Result};
+use std::path::PathBuf;
+use structopt::{clap::AppSettings, StructOpt};
+use wasmtime::{Engine, SerializeOptions};
+
+/// The after help text for the `compile` command.
+pub const COMPILE_AFTER_HELP: &str = "The precompiled module can be executed with \
+     `wasmtime run --allow-precompiled`,\n\
+     by a Wasmtime of the same version configured with the same settings.";
+
+/// Compiles a WebAssembly module ahead of time
+#[derive(StructOpt)]
+#[structopt(
+    name = "compile",
+    version = env!("CARGO_PKG_VERSION"),
+    setting = AppSettings::ColoredHelp,
+    after_help = COMPILE_AFTER_HELP,
+)]
+pub struct CompileCommand {
+    #[structopt(flatten)]
+    common: CommonOptions,
+
+    /// The target triple; default is the host triple
+    #[structopt(long, value_name = "TARGET")]
+    target: Option<String>,
+
+    /// The path of the output precompiled module; defaults to the path of the
+    /// module with a `.cwasm` extension
+    #[structopt(short = "o", long, value_name = "OUTPUT", parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Leave out the names of the module and its functions
+    #[structopt(long)]
+    strip_names: bool,
+
+    /// Leave out DWARF debug information, so the module can also be run
+    /// without `-g`
+    #[structopt(long)]
+    strip_debug_info: bool,
+
+    /// Compress the precompiled module
+    #[structopt(long)]
+    compress: bool,
+
+    /// The path of the WebAssembly module to compile
+    #[structopt(index = 1, value_name = "MODULE_PATH", parse(from_os_str))]
+    module: PathBuf,
+}
+
+impl CompileCommand {
+    /// Executes the command.
+    pub fn execute(&self) -> Result<()> {
+        if self.common.log_to_files {
+            let prefix = "compile.dbg.";
+            init_file_per_thread_logger(prefix);
+        } else {
+            pretty_env_logger::init();
+        }
+
+        let mut config = self.common.config()?;
+        if let Some(target) = &self.target {
+            config.target(target)?;
+        }
+        let engine = Engine::new(&config);
+
+        let input = std::fs::read(&self.module)
+            .with_context(|| format!("failed to read {}", self.module.display()))?;
+        let output = engine
+            .precompile_module_with(
+                &input,
+                SerializeOptions::new()
+                    .strip_names(self.strip_names)
+                    .strip_debug_info(self.strip_debug_info)
+                    .compress(self.compress),
+            )
+            .with_context(|| format!("failed to compile {}", self.module.display()))?;
+
+        let output_path = match &self.output {
+            Some(path) => path.clone(),
+            None => self.module.with_extension("cwasm"),
+        };
+        std::fs::write(&output_path, output)
+            .with_context(|| format!("failed to write {}", output_path.display()))?;
+        Ok(())
+    }
+}
@@ -7,20 +7,19 @@ use std::time::Duration;
 use std::{
     ffi::{OsStr, OsString},
     fs::File,
-    path::{Component, PathBuf},
+    path::{Component, Path, PathBuf},
     process,
 };
 use structopt::{clap::AppSettings, StructOpt};
 use wasi_common::{preopen_dir, WasiCtxBuilder};
-use wasmtime::{Engine, Func, Linker, Module, Store, Trap, Val, ValType};
-use wasmtime_wasi::Wasi;
+use wasmtime::{Engine, Func, InstantiationError, Linker, Module, Store, Trap, Val, ValType};
+use wasmtime_wasi::add_wasi_to_linker;
 
 fn parse_module(s: &OsStr) -> Result<PathBuf, OsString> {
     // Do not accept wasmtime subcommand names as the module name
     match s.to_str() {
-        Some("help") | Some("config") | Some("run") | Some("wasm2obj") | Some("wast") => {
-            Err("module name cannot be the same as a subcommand".into())
-        }
+        Some("help") | Some("compile") | Some("config") | Some("run") | Some("wasm2obj")
+        | Some("wast") => Err("module name cannot be the same as a subcommand".into()),
         _ => Ok(s.into()),
     }
 }
@@ -108,6 +107,13 @@ pub struct RunCommand {
     )]
     wasm_timeout: Option<Duration>,
 
+    /// Allow executing modules precompiled by `wasmtime compile`
+    ///
+    /// Precompiled modules contain native code which is executed without
+    /// being checked, so this must only be used with trusted modules.
+    #[structopt(long)]
+    allow_precompiled: bool,
+
     // NOTE: this must come last for trailing varargs
     /// The arguments to pass to the module
     #[structopt(value_name = "ARGS")]
@@ -140,8 +146,7 @@ impl RunCommand {
 
         // Load the preload wasm modules.
         for (name, path) in self.preloads.iter() {
-            // Read the wasm module binary either as `*.wat` or a raw binary
-            let module = Module::from_file(&engine, path)?;
+            let module = self.load_module(&engine, path)?;
 
             // Add the module's functions to the linker.
             linker.module(name, &module).context(format!(
@@ -160,7 +165,11 @@ impl RunCommand {
             Err(e) => {
                 // If the program exited because of a non-zero exit status, print
                 // a message and exit.
-                if let Some(trap) = e.downcast_ref::<Trap>() {
+                let trap = e.downcast_ref::<Trap>().or_else(|| {
+                    e.downcast_ref::<InstantiationError>()
+                        .and_then(InstantiationError::trap)
+                });
+                if let Some(trap) = trap {
                     // Print the error message in the usual way.
                     if let Some(status) = trap.i32_exit_status() {
                         // On Windows, exit status 3 indicates an abort (see below),
@@ -236,6 +245,24 @@ impl RunCommand {
         result
     }
 
+    /// Reads a module either as `*.wat`, a raw binary or, if allowed, a module
+    /// precompiled by `wasmtime compile`.
+    fn load_module(&self, engine: &Engine, path: &Path) -> Result<Module> {
+        if !self.allow_precompiled {
+            return Module::from_file(engine, path);
+        }
+        let bytes =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+        // Wasm binaries start with `\0asm` and text never starts with a NUL
+        // byte, while precompiled modules always do.
+        if bytes.starts_with(b"\0") && !bytes.starts_with(b"\0asm") {
+            Module::deserialize(engine, &bytes)
+        } else {
+            Module::new(engine, &bytes)
+        }
+    }
+
     fn load_main_module(&self, linker: &mut Linker) -> Result<()> {
         if let Some(timeout) = self.wasm_timeout {
             let handle = linker.store().interrupt_handle()?;
@@ -245,9 +272,8 @@ impl RunCommand {
             });
         }
 
-        // Read the wasm module binary either as `*.wat` or a raw binary.
         // Use "" as a default module name.
-        let module = Module::from_file(linker.store().engine(), &self.module)?;
+        let module = self.load_module(linker.store().engine(), &self.module)?;
         linker
             .module("", &module)
             .context(format!("failed to instantiate {:?}", self.module))?;
@@ -349,9 +375,7 @@ fn populate_with_wasi(
         cx.preopened_dir(file.try_clone()?, name);
     }
 
-    let cx = cx.build()?;
-    let wasi = Wasi::new(linker.store(), cx);
-    wasi.add_to_linker(linker)?;
+    add_wasi_to_linker(linker, cx.build()?)?;
 
     // Repeat the above, but this time for snapshot 0.
     let mut cx = wasi_common::old::snapshot_0::WasiCtxBuilder::new();
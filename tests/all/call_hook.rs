@@ -0,0 +1,165 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasmtime::*;
+
+fn record_hooks(store: &Store) -> Rc<RefCell<Vec<CallHook>>> {
+    let hooks = Rc::new(RefCell::new(Vec::new()));
+    let recorded = hooks.clone();
+    store.call_hook(move |hook| {
+        recorded.borrow_mut().push(hook);
+        Ok(())
+    });
+    hooks
+}
+
+#[test]
+fn hooks_around_wasm_and_host_calls() -> anyhow::Result<()> {
+    let store = Store::default();
+    let hooks = record_hooks(&store);
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "wrap" (func $wrap))
+                (import "" "new" (func $new))
+                (func (export "run")
+                    call $wrap
+                    call $new))
+        "#,
+    )?;
+    let wrap = Func::wrap(&store, || {});
+    let new = Func::new(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+        |_, _, _| Ok(()),
+    );
+    let instance = Instance::new(&store, &module, &[wrap.into(), new.into()])?;
+    let run = instance.get_func("run").unwrap();
+
+    run.call(&[])?;
+    assert_eq!(
+        *hooks.borrow(),
+        [
+            CallHook::CallingWasm,
+            CallHook::CallingHost,
+            CallHook::ReturningFromHost,
+            CallHook::CallingHost,
+            CallHook::ReturningFromHost,
+            CallHook::ReturningFromWasm,
+        ]
+    );
+
+    hooks.borrow_mut().clear();
+    run.typed::<(), ()>()?.call(())?;
+    assert_eq!(hooks.borrow().len(), 6);
+    Ok(())
+}
+
+#[test]
+fn hooks_when_wasm_traps() -> anyhow::Result<()> {
+    let store = Store::default();
+    let hooks = record_hooks(&store);
+    let module = Module::new(store.engine(), r#"(func (export "run") unreachable)"#)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let run = instance.get_func("run").unwrap();
+
+    assert!(run.call(&[]).is_err());
+    assert_eq!(
+        *hooks.borrow(),
+        [CallHook::CallingWasm, CallHook::ReturningFromWasm]
+    );
+    Ok(())
+}
+
+#[test]
+fn hook_errors_become_traps() -> anyhow::Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $host))
+                (func (export "run") call $host))
+        "#,
+    )?;
+    let called = Rc::new(Cell::new(false));
+    let host_called = called.clone();
+    let host = Func::wrap(&store, move || host_called.set(true));
+    let instance = Instance::new(&store, &module, &[host.into()])?;
+    let run = instance.get_func("run").unwrap().typed::<(), ()>()?;
+
+    store.call_hook(|hook| match hook {
+        CallHook::CallingHost => Err(Trap::new("host calls forbidden")),
+        _ => Ok(()),
+    });
+    let trap = run.call(()).unwrap_err();
+    assert!(trap.to_string().contains("host calls forbidden"));
+    assert!(!called.get());
+
+    store.call_hook(|hook| match hook {
+        CallHook::CallingWasm => Err(Trap::new("wasm calls forbidden")),
+        _ => Ok(()),
+    });
+    let trap = run.call(()).unwrap_err();
+    assert!(trap.to_string().contains("wasm calls forbidden"));
+    Ok(())
+}
+
+#[test]
+fn trap_hook_sees_each_trap_once() -> anyhow::Result<()> {
+    let store = Store::default();
+    let traps = Rc::new(RefCell::new(Vec::new()));
+    let recorded = traps.clone();
+    store.trap_hook(move |_, trap| {
+        recorded.borrow_mut().push(trap.code());
+    });
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "reenter" (func $reenter))
+                (import "" "fail" (func $fail))
+                (func (export "unreachable") unreachable)
+                (func (export "reenter") call $reenter)
+                (func (export "fail") call $fail))
+        "#,
+    )?;
+    let slot = Rc::new(RefCell::new(None::<Instance>));
+    let instance_slot = slot.clone();
+    let reenter = Func::wrap(&store, move || -> Result<(), Trap> {
+        let instance = instance_slot.borrow().clone().unwrap();
+        instance
+            .get_typed_func::<(), ()>("unreachable")
+            .map_err(Trap::from)?
+            .call(())
+    });
+    let fail = Func::wrap(&store, || -> Result<(), Trap> { Err(Trap::new("failed")) });
+    let instance = Instance::new(&store, &module, &[reenter.into(), fail.into()])?;
+    *slot.borrow_mut() = Some(instance.clone());
+
+    // The trap passes through two calls into wasm, but is only seen once.
+    let trap = instance
+        .get_typed_func::<(), ()>("reenter")?
+        .call(())
+        .unwrap_err();
+    assert_eq!(trap.code(), Some(TrapCode::Unreachable));
+    assert_eq!(*traps.borrow(), [Some(TrapCode::Unreachable)]);
+
+    traps.borrow_mut().clear();
+    let trap = instance
+        .get_typed_func::<(), ()>("fail")?
+        .call(())
+        .unwrap_err();
+    assert!(trap.to_string().contains("failed"));
+    assert_eq!(*traps.borrow(), [None]);
+    slot.borrow_mut().take();
+    Ok(())
+}
+
+#[test]
+fn entering_and_exiting_host() {
+    assert!(CallHook::CallingHost.entering_host());
+    assert!(CallHook::ReturningFromWasm.entering_host());
+    assert!(CallHook::CallingWasm.exiting_host());
+    assert!(CallHook::ReturningFromHost.exiting_host());
+}
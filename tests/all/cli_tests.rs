@@ -361,3 +361,34 @@ fn exit_with_saved_fprs() -> Result<()> {
     assert!(output.stdout.is_empty());
     Ok(())
 }
+
+// Compile a module ahead of time and run the precompiled module.
+#[test]
+fn compile_and_run_precompiled() -> Result<()> {
+    let wasm = build_wasm("tests/wasm/hello_wasi_snapshot1.wat")?;
+    let dir = tempfile::tempdir()?;
+    let cwasm = dir.path().join("hello.cwasm");
+    let cwasm = cwasm.to_str().unwrap();
+    run_wasmtime(&[
+        "compile",
+        wasm.path().to_str().unwrap(),
+        "-o",
+        cwasm,
+        "--disable-cache",
+    ])?;
+
+    // Precompiled modules are only run when explicitly allowed.
+    assert!(run_wasmtime(&["run", cwasm, "--disable-cache"]).is_err());
+    let stdout = run_wasmtime(&["run", "--allow-precompiled", cwasm, "--disable-cache"])?;
+    assert_eq!(stdout, "Hello, world!\n");
+
+    // Regular modules still run with `--allow-precompiled`.
+    let stdout = run_wasmtime(&[
+        "run",
+        "--allow-precompiled",
+        wasm.path().to_str().unwrap(),
+        "--disable-cache",
+    ])?;
+    assert_eq!(stdout, "Hello, world!\n");
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use wasmtime::*;
+
+const WAT: &str = r#"
+    (module $m
+        (memory 1)
+        (global $g (mut i32) (i32.const 7))
+        (global f64 (f64.const 1.5))
+        (data (i32.const 16) "hello")
+        (func $inner
+            i32.const 8
+            global.set $g
+            unreachable)
+        (func (export "run") call $inner))
+"#;
+
+fn run(config: &Config) -> Result<Trap> {
+    let store = Store::new(&Engine::new(config));
+    let module = Module::new(store.engine(), WAT)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    Ok(instance
+        .get_typed_func::<(), ()>("run")?
+        .call(())
+        .unwrap_err())
+}
+
+#[test]
+fn no_coredump_by_default() -> Result<()> {
+    assert!(run(&Config::new())?.coredump().is_none());
+    Ok(())
+}
+
+#[test]
+fn coredump_on_trap() -> Result<()> {
+    let trap = run(Config::new().coredump_on_trap(true))?;
+    let dump = trap.coredump().expect("expected a coredump");
+    assert_eq!(dump.frame_count(), 2);
+    assert_eq!(dump.memory_count(), 0);
+
+    let trap = run(Config::new().coredump_on_trap(true).coredump_memories(true))?;
+    let dump = trap.coredump().expect("expected a coredump");
+    assert_eq!(dump.memory_count(), 1);
+
+    // The coredump is a valid wasm module, whose data segments hold the
+    // contents of the memory.
+    let bytes = dump.serialize("test");
+    Module::new(&Engine::default(), &bytes)?;
+    let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+    for section in ["core", "coremodules", "coreinstances", "corestack"].iter() {
+        assert!(contains(section.as_bytes()), "missing section {}", section);
+    }
+    assert!(contains(b"hello"));
+    Ok(())
+}
+
+/// Returns the instance of each frame of a serialized coredump.
+fn frame_instances(bytes: &[u8]) -> Vec<u32> {
+    let header = b"corestack\x00\x04main";
+    let mut pos = bytes
+        .windows(header.len())
+        .position(|w| w == header)
+        .expect("missing corestack section")
+        + header.len();
+    let mut read_u32 = || {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = bytes[pos];
+            pos += 1;
+            result |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return result;
+            }
+            shift += 7;
+        }
+    };
+    let count = read_u32();
+    (0..count)
+        .map(|_| {
+            assert_eq!(read_u32(), 0);
+            let instance = read_u32();
+            // The function index, offset, and the empty locals and stack.
+            for _ in 0..4 {
+                read_u32();
+            }
+            instance
+        })
+        .collect()
+}
+
+#[test]
+fn frames_are_attributed_to_their_instance() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().coredump_on_trap(true)));
+
+    // Host-defined items live in instances of their own, which are left out.
+    let host = Func::wrap(&store, || {});
+    Memory::new(&store, MemoryType::new(Limits::new(1, None)))?;
+
+    let module = Module::new(store.engine(), WAT)?;
+    let first = Instance::new(&store, &module, &[])?;
+    let second = Instance::new(&store, &module, &[])?;
+    let importer = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "host" (func))
+                (import "" "run" (func $run))
+                (func (export "run") call $run))
+        "#,
+    )?;
+    let importer = Instance::new(
+        &store,
+        &importer,
+        &[host.into(), first.get_func("run").unwrap().into()],
+    )?;
+
+    let trap = first.get_typed_func::<(), ()>("run")?.call(()).unwrap_err();
+    let dump = trap.coredump().expect("expected a coredump");
+    assert_eq!(dump.instance_count(), 3);
+    assert_eq!(frame_instances(&dump.serialize("test")), [0, 0]);
+
+    let trap = second
+        .get_typed_func::<(), ()>("run")?
+        .call(())
+        .unwrap_err();
+    let dump = trap.coredump().expect("expected a coredump");
+    assert_eq!(frame_instances(&dump.serialize("test")), [1, 1]);
+
+    // Frames called through an import belong to the imported function's
+    // instance.
+    let trap = importer
+        .get_typed_func::<(), ()>("run")?
+        .call(())
+        .unwrap_err();
+    let dump = trap.coredump().expect("expected a coredump");
+    assert_eq!(frame_instances(&dump.serialize("test")), [0, 0, 2]);
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasmtime::*;
+
+fn epoch_store() -> Store {
+    let engine = Engine::new(Config::new().epoch_interruption(true));
+    Store::new(&engine)
+}
+
+#[test]
+fn traps_without_deadline() -> anyhow::Result<()> {
+    let store = epoch_store();
+    let module = Module::new(store.engine(), r#"(func (export "f"))"#)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let f = instance.get_func("f").unwrap().typed::<(), ()>()?;
+
+    let trap = f.call(()).unwrap_err();
+    assert!(trap.to_string().contains("wasm trap: interrupt"));
+
+    store.set_epoch_deadline(1);
+    f.call(())?;
+    store.engine().increment_epoch();
+    assert!(f.call(()).is_err());
+    Ok(())
+}
+
+#[test]
+fn deadline_is_per_store() -> anyhow::Result<()> {
+    let engine = Engine::new(Config::new().epoch_interruption(true));
+    let module = Module::new(&engine, r#"(func (export "f"))"#)?;
+    let a = Store::new(&engine);
+    let b = Store::new(&engine);
+    a.set_epoch_deadline(1);
+    b.set_epoch_deadline(2);
+    engine.increment_epoch();
+
+    let f = |store: &Store| -> anyhow::Result<()> {
+        let instance = Instance::new(store, &module, &[])?;
+        let f = instance.get_func("f").unwrap().typed::<(), ()>()?;
+        Ok(f.call(())?)
+    };
+    assert!(f(&a).is_err());
+    f(&b)?;
+    Ok(())
+}
+
+#[test]
+fn loops_interrupted_from_another_thread() -> anyhow::Result<()> {
+    let store = epoch_store();
+    let module = Module::new(store.engine(), r#"(func (export "loop") (loop br 0))"#)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let iloop = instance.get_func("loop").unwrap().typed::<(), ()>()?;
+
+    store.set_epoch_deadline(1);
+    let engine = store.engine().clone();
+    let thread = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        engine.increment_epoch();
+    });
+    let trap = iloop.call(()).unwrap_err();
+    thread.join().unwrap();
+    assert!(trap.to_string().contains("wasm trap: interrupt"));
+    Ok(())
+}
+
+#[test]
+fn callback_extends_then_traps() -> anyhow::Result<()> {
+    let store = epoch_store();
+    let module = Module::new(store.engine(), r#"(func (export "loop") (loop br 0))"#)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let iloop = instance.get_func("loop").unwrap().typed::<(), ()>()?;
+
+    // Extending by zero ticks resumes execution only until the next check, at
+    // the loop header, which then traps.
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let log = seen.clone();
+    store.deadline_callback(move |deadline| {
+        let mut log = log.borrow_mut();
+        log.push(deadline);
+        if log.len() == 1 {
+            DeadlineAction::Extend(0)
+        } else {
+            DeadlineAction::Trap
+        }
+    });
+
+    let trap = iloop.call(()).unwrap_err();
+    assert!(trap.to_string().contains("wasm trap: interrupt"));
+    assert_eq!(*seen.borrow(), [Deadline::Epoch, Deadline::Epoch]);
+    Ok(())
+}
@@ -67,7 +67,7 @@ fn cross_store() -> anyhow::Result<()> {
     let ty = GlobalType::new(ValType::I32, Mutability::Const);
     let global = Global::new(&store2, ty, Val::I32(0))?;
     let ty = MemoryType::new(Limits::new(1, None));
-    let memory = Memory::new(&store2, ty);
+    let memory = Memory::new(&store2, ty)?;
     let ty = TableType::new(ValType::FuncRef, Limits::new(1, None));
     let table = Table::new(&store2, ty, Val::FuncRef(None))?;
 
@@ -340,3 +340,620 @@ fn grow_externref_tables_via_api() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn read_write_memory_via_api() {
+    let store = Store::default();
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None))).unwrap();
+    let size = memory.data_size();
+
+    memory.write(0, b"hello").unwrap();
+    memory.write(size - 5, b"world").unwrap();
+    memory.write(size, &[]).unwrap();
+
+    let mut buf = [0; 5];
+    memory.read(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    memory.read(size - 5, &mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+
+    assert!(memory.write(size - 4, b"world").is_err());
+    assert!(memory.write(size + 1, &[]).is_err());
+    assert!(memory.write(usize::max_value(), b"x").is_err());
+    assert!(memory.read(size - 4, &mut buf).is_err());
+    assert!(memory.read(usize::max_value(), &mut buf).is_err());
+
+    // Failed writes must leave memory untouched.
+    memory.read(size - 5, &mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+
+    memory.grow(1).unwrap();
+    memory.write(size, b"grown").unwrap();
+    memory.read(size, &mut buf).unwrap();
+    assert_eq!(&buf, b"grown");
+}
+
+#[test]
+fn extern_accessors() -> anyhow::Result<()> {
+    use std::convert::TryFrom;
+
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (func (export "f"))
+                (global (export "g") i32 (i32.const 0))
+                (table (export "t") 1 funcref)
+                (memory (export "m") 1))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let f = instance.get_export("f").unwrap();
+    let g = instance.get_export("g").unwrap();
+    let t = instance.get_export("t").unwrap();
+    let m = instance.get_export("m").unwrap();
+
+    assert!(f.as_func().is_some());
+    assert!(f.as_global().is_none());
+    assert!(g.as_global().is_some());
+    assert!(g.as_table().is_none());
+    assert!(t.as_table().is_some());
+    assert!(t.as_memory().is_none());
+    assert!(m.as_memory().is_some());
+    assert!(m.as_func().is_none());
+
+    Func::try_from(f.clone())?;
+    Global::try_from(g.clone())?;
+    Table::try_from(t.clone())?;
+    Memory::try_from(m.clone())?;
+
+    let err = Func::try_from(m).err().unwrap();
+    assert_eq!(err.to_string(), "expected function, but found memory");
+    let err = Memory::try_from(f).err().unwrap();
+    assert_eq!(err.to_string(), "expected memory, but found function");
+    assert!(Global::try_from(t).is_err());
+    assert!(Table::try_from(g).is_err());
+    Ok(())
+}
+
+#[test]
+fn externref_tables_reference_counting() -> anyhow::Result<()> {
+    let mut cfg = Config::new();
+    cfg.wasm_reference_types(true);
+    let engine = Engine::new(&cfg);
+    let store = Store::new(&engine);
+
+    let r = ExternRef::new("hello".to_string());
+    assert_eq!(r.strong_count(), 1);
+
+    let table_ty = TableType::new(ValType::ExternRef, Limits::at_least(2));
+    let table = Table::new(&store, table_ty, Val::ExternRef(Some(r.clone())))?;
+    assert_eq!(r.strong_count(), 3);
+
+    table.grow(2, Val::ExternRef(Some(r.clone())))?;
+    assert_eq!(r.strong_count(), 5);
+
+    let elem = table.get(3).unwrap().unwrap_externref().unwrap();
+    assert!(elem.ptr_eq(&r));
+    assert_eq!(r.strong_count(), 6);
+    drop(elem);
+
+    table.set(0, Val::ExternRef(None))?;
+    assert_eq!(r.strong_count(), 4);
+
+    table.fill(1, Val::ExternRef(None), 3)?;
+    assert_eq!(r.strong_count(), 1);
+
+    table.fill(0, Val::ExternRef(Some(r.clone())), 4)?;
+    assert_eq!(r.strong_count(), 5);
+    drop(table);
+    drop(store);
+    assert_eq!(r.strong_count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn table_element_type_mismatch() -> anyhow::Result<()> {
+    let mut cfg = Config::new();
+    cfg.wasm_reference_types(true);
+    let engine = Engine::new(&cfg);
+    let store = Store::new(&engine);
+
+    let table_ty = TableType::new(ValType::ExternRef, Limits::at_least(2));
+    let table = Table::new(&store, table_ty, Val::ExternRef(None))?;
+    assert!(table.set(0, Val::FuncRef(None)).is_err());
+    assert!(table.fill(0, Val::FuncRef(None), 1).is_err());
+    assert!(table.grow(1, Val::FuncRef(None)).is_err());
+    assert!(table.set(0, Val::I32(0)).is_err());
+
+    let table_ty = TableType::new(ValType::FuncRef, Limits::at_least(2));
+    let table = Table::new(&store, table_ty, Val::FuncRef(None))?;
+    assert!(table.set(0, Val::ExternRef(None)).is_err());
+    assert!(table.fill(0, Val::ExternRef(None), 1).is_err());
+    assert!(table.grow(1, Val::ExternRef(None)).is_err());
+    Ok(())
+}
+
+#[test]
+fn shared_memory_wait_notify() -> anyhow::Result<()> {
+    use std::time::Duration;
+
+    let store = Store::default();
+    let shared = SharedMemory::new(store.engine(), MemoryType::shared(1, 4))?;
+    let memory = Memory::new_with_shared(&store, &shared)?;
+    assert!(memory.ty().is_shared());
+    assert_eq!(memory.ty().limits().max(), Some(4));
+
+    // Shared memories are never relocated when they grow.
+    let base = memory.data_ptr();
+    memory.grow(3)?;
+    assert_eq!(memory.data_ptr(), base);
+    assert!(memory.grow(1).is_err());
+    assert_eq!(shared.size(), 4);
+
+    memory.write(8, &1u32.to_le_bytes())?;
+    assert_eq!(memory.atomic_wait32(8, 0, None)?, WaitResult::Mismatch);
+    assert_eq!(
+        memory.atomic_wait32(8, 1, Some(Duration::from_millis(1)))?,
+        WaitResult::TimedOut
+    );
+    assert_eq!(
+        memory.atomic_wait64(8, 1, Some(Duration::from_millis(1)))?,
+        WaitResult::TimedOut
+    );
+    assert_eq!(memory.atomic_notify(8, 1)?, 0);
+
+    assert!(memory.atomic_wait32(9, 0, None).is_err());
+    assert!(memory.atomic_wait64(12, 0, None).is_err());
+    assert!(memory.atomic_notify(memory.data_size(), 1).is_err());
+
+    // No other thread can reach a memory which wasn't created from a
+    // `SharedMemory`, so waiting on it would never end.
+    let memory = Memory::new(&store, MemoryType::shared(1, 4))?;
+    assert!(memory.atomic_wait32(0, 0, None).is_err());
+
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)))?;
+    assert!(!memory.ty().is_shared());
+    assert!(memory.atomic_wait32(0, 0, None).is_err());
+    assert_eq!(memory.atomic_notify(0, 1)?, 0);
+
+    assert!(SharedMemory::new(store.engine(), MemoryType::new(Limits::new(1, Some(1)))).is_err());
+    assert!(Memory::new_with_shared(&Store::new(&Engine::default()), &shared).is_err());
+    Ok(())
+}
+
+#[test]
+fn wasm_atomic_wait_notify() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.wasm_threads(true);
+    let engine = Engine::new(&config);
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "" (memory 1 4 shared))
+                (func (export "wait32") (param i32 i32 i64) (result i32)
+                    (memory.atomic.wait32 (local.get 0) (local.get 1) (local.get 2)))
+                (func (export "wait64") (param i32 i64 i64) (result i32)
+                    (memory.atomic.wait64 (local.get 0) (local.get 1) (local.get 2)))
+                (func (export "notify") (param i32 i32) (result i32)
+                    (memory.atomic.notify (local.get 0) (local.get 1)))
+                (func (export "notify_at_8") (param i32 i32) (result i32)
+                    (memory.atomic.notify offset=8 (local.get 0) (local.get 1))))
+        "#,
+    )?;
+    let shared = SharedMemory::new(&engine, MemoryType::shared(1, 4))?;
+
+    let store = Store::new(&engine);
+    let memory = Memory::new_with_shared(&store, &shared)?;
+    let instance = Instance::new(&store, &module, &[memory.clone().into()])?;
+    let wait32 = instance.get_typed_func::<(i32, i32, i64), i32>("wait32")?;
+    let wait64 = instance.get_typed_func::<(i32, i64, i64), i32>("wait64")?;
+    let notify = instance.get_typed_func::<(i32, i32), i32>("notify")?;
+    let notify_at_8 = instance.get_typed_func::<(i32, i32), i32>("notify_at_8")?;
+
+    memory.write(8, &1u64.to_le_bytes())?;
+    assert_eq!(wait32.call((8, 0, -1))?, 1);
+    assert_eq!(wait32.call((8, 1, 1_000_000))?, 2);
+    assert_eq!(wait64.call((8, 0, -1))?, 1);
+    assert_eq!(wait64.call((8, 1, 1_000_000))?, 2);
+    assert_eq!(notify.call((8, 1))?, 0);
+
+    let trap = wait32.call((9, 1, 0)).unwrap_err();
+    assert_eq!(trap.code(), Some(TrapCode::MemoryMisaligned));
+    let trap = wait64.call((12, 1, 0)).unwrap_err();
+    assert_eq!(trap.code(), Some(TrapCode::MemoryMisaligned));
+    let trap = notify.call((memory.data_size() as i32, 1)).unwrap_err();
+    assert_eq!(trap.code(), Some(TrapCode::MemoryOutOfBounds));
+
+    // A thread waiting in wasm is woken up by wasm in another thread, through
+    // its own store.
+    let thread = std::thread::spawn({
+        let shared = shared.clone();
+        let module = module.clone();
+        move || -> anyhow::Result<i32> {
+            let store = Store::new(shared.engine());
+            let memory = Memory::new_with_shared(&store, &shared)?;
+            let instance = Instance::new(&store, &module, &[memory.into()])?;
+            let wait32 = instance.get_typed_func::<(i32, i32, i64), i32>("wait32")?;
+            Ok(wait32.call((16, 0, -1))?)
+        }
+    });
+    while notify_at_8.call((8, 1))? == 0 {
+        std::thread::yield_now();
+    }
+    assert_eq!(thread.join().unwrap()?, 0);
+
+    // Waiting on a memory which isn't shared traps, and nothing can be woken
+    // up on it.
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory 1)
+                (func (export "wait32") (result i32)
+                    (memory.atomic.wait32 (i32.const 0) (i32.const 0) (i64.const -1)))
+                (func (export "notify") (result i32)
+                    (memory.atomic.notify (i32.const 0) (i32.const 1))))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    assert!(instance
+        .get_typed_func::<(), i32>("wait32")?
+        .call(())
+        .is_err());
+    assert_eq!(instance.get_typed_func::<(), i32>("notify")?.call(())?, 0);
+    Ok(())
+}
+
+#[test]
+fn shared_memory_across_threads() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.wasm_threads(true);
+    let engine = Engine::new(&config);
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "" (memory 1 4 shared))
+                (func (export "store") (param i32 i32)
+                    (i32.atomic.store (local.get 0) (local.get 1)))
+                (func (export "grow") (result i32)
+                    (memory.grow (i32.const 1))))
+        "#,
+    )?;
+    let shared = SharedMemory::new(&engine, MemoryType::shared(1, 4))?;
+
+    // The thread waits until the value at 0 changes, then reports back
+    // through the value at 4.
+    let thread = std::thread::spawn({
+        let shared = shared.clone();
+        move || -> anyhow::Result<WaitResult> {
+            let store = Store::new(shared.engine());
+            let memory = Memory::new_with_shared(&store, &shared)?;
+            let result = memory.atomic_wait32(0, 0, None)?;
+            memory.write(4, &2u32.to_le_bytes())?;
+            memory.atomic_notify(4, 1)?;
+            Ok(result)
+        }
+    });
+
+    let store = Store::new(&engine);
+    let memory = Memory::new_with_shared(&store, &shared)?;
+    let instance = Instance::new(&store, &module, &[memory.clone().into()])?;
+    instance
+        .get_typed_func::<(i32, i32), ()>("store")?
+        .call((0, 1))?;
+
+    // The thread either sees the new value before it starts waiting, or it's
+    // woken up by one of these notifications.
+    let mut value = [0; 4];
+    while memory.atomic_notify(0, 1)? == 0 {
+        memory.read(4, &mut value)?;
+        if u32::from_le_bytes(value) == 2 {
+            break;
+        }
+        std::thread::yield_now();
+    }
+    let result = thread.join().unwrap()?;
+    assert!(result == WaitResult::Ok || result == WaitResult::Mismatch);
+    memory.read(4, &mut value)?;
+    assert_eq!(u32::from_le_bytes(value), 2);
+
+    // Growth through one store is visible in the others.
+    let other = Store::new(&engine);
+    let other_memory = Memory::new_with_shared(&other, &shared)?;
+    assert_eq!(instance.get_typed_func::<(), i32>("grow")?.call(())?, 1);
+    assert_eq!(other_memory.size(), 2);
+    assert_eq!(shared.size(), 2);
+    Ok(())
+}
+
+#[test]
+fn import_shared_memory() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.wasm_threads(true);
+    let engine = Engine::new(&config);
+    let store = Store::new(&engine);
+    let module = Module::new(&engine, r#"(module (import "" "" (memory 1 4 shared)))"#)?;
+
+    let shared = Memory::new(&store, MemoryType::shared(1, 4))?;
+    Instance::new(&store, &module, &[shared.into()])?;
+
+    let unshared = Memory::new(&store, MemoryType::new(Limits::new(1, Some(4))))?;
+    assert!(Instance::new(&store, &module, &[unshared.into()]).is_err());
+    Ok(())
+}
+
+#[test]
+fn host_buffer_memory() -> anyhow::Result<()> {
+    let page = 65536;
+    let mut buffer = vec![0u8; 2 * page + 100];
+    buffer[10] = 42;
+
+    // Memories relying on guard pages can't be backed by a host buffer.
+    let ty = MemoryType::new(Limits::new(1, None));
+    let store = Store::default();
+    assert!(unsafe {
+        Memory::new_with_host_buffer(&store, ty.clone(), buffer.as_mut_ptr(), buffer.len())
+    }
+    .is_err());
+
+    let mut config = Config::new();
+    config.static_memory_maximum_size(0);
+    config.dynamic_memory_guard_size(0);
+    let engine = Engine::new(&config);
+    let store = Store::new(&engine);
+    assert!(unsafe {
+        Memory::new_with_host_buffer(
+            &store,
+            MemoryType::new(Limits::new(3, None)),
+            buffer.as_mut_ptr(),
+            buffer.len(),
+        )
+    }
+    .is_err());
+    let memory =
+        unsafe { Memory::new_with_host_buffer(&store, ty, buffer.as_mut_ptr(), buffer.len())? };
+    assert_eq!(memory.data_ptr(), buffer.as_mut_ptr());
+    assert_eq!(memory.size(), 1);
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (import "" "") 1)
+                (func (export "load") (param i32) (result i32)
+                    local.get 0
+                    i32.load8_u)
+                (func (export "store") (param i32 i32)
+                    local.get 0
+                    local.get 1
+                    i32.store8)
+                (func (export "grow") (param i32) (result i32)
+                    local.get 0
+                    memory.grow))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[memory.clone().into()])?;
+    let load = instance.get_func("load").unwrap().typed::<i32, i32>()?;
+    let store8 = instance
+        .get_func("store")
+        .unwrap()
+        .typed::<(i32, i32), ()>()?;
+    let grow = instance.get_func("grow").unwrap().typed::<i32, i32>()?;
+
+    assert_eq!(load.call(10)?, 42);
+    store8.call((11, 7))?;
+    assert!(load.call(page as i32).is_err());
+
+    // Growth is limited by the capacity of the buffer, rounded down to pages.
+    assert_eq!(grow.call(1)?, 1);
+    assert_eq!(grow.call(1)?, -1);
+    assert_eq!(memory.data_ptr(), buffer.as_mut_ptr());
+    store8.call((page as i32 + 1, 9))?;
+    assert!(load.call(2 * page as i32).is_err());
+
+    drop((load, store8, grow, instance, memory, store));
+    assert_eq!(buffer[11], 7);
+    assert_eq!(buffer[page + 1], 9);
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn file_backed_memory() -> anyhow::Result<()> {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let mut file = tempfile::tempfile()?;
+    file.write_all(b"hello")?;
+
+    let store = Store::default();
+    let ty = MemoryType::new(Limits::new(1, None));
+    let a = unsafe { Memory::new_from_file(&store, ty.clone(), &file)? };
+    let b = unsafe { Memory::new_from_file(&store, ty, &file)? };
+
+    let mut buf = [0; 6];
+    a.read(0, &mut buf)?;
+    assert_eq!(&buf, b"hello\0");
+
+    // Writes are private to each memory and never reach the file.
+    a.write(0, b"j")?;
+    b.read(0, &mut buf)?;
+    assert_eq!(&buf, b"hello\0");
+    let mut contents = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut contents)?;
+    assert_eq!(contents, b"hello");
+
+    // Contents survive the memory growing.
+    a.grow(1)?;
+    a.read(0, &mut buf)?;
+    assert_eq!(&buf, b"jello\0");
+
+    // The file must fit in the memory's minimum size.
+    let ty = MemoryType::new(Limits::new(0, None));
+    assert!(unsafe { Memory::new_from_file(&store, ty, &file) }.is_err());
+    Ok(())
+}
+
+#[test]
+fn protect_memory() -> anyhow::Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory (export "mem") 2)
+                (func (export "load") (param i32) (result i32)
+                    local.get 0
+                    i32.load)
+                (func (export "store") (param i32)
+                    local.get 0
+                    i32.const 1
+                    i32.store))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let memory = instance.get_memory("mem").unwrap();
+    let load = instance.get_func("load").unwrap().typed::<i32, i32>()?;
+    let store_one = instance.get_func("store").unwrap().typed::<i32, ()>()?;
+    let page = 65536;
+
+    unsafe {
+        assert!(memory.protect(1..page, MemoryProtection::ReadOnly).is_err());
+        assert!(memory
+            .protect(0..3 * page, MemoryProtection::ReadOnly)
+            .is_err());
+        memory.protect(0..page, MemoryProtection::ReadOnly)?;
+    }
+    assert_eq!(load.call(0)?, 0);
+    let trap = store_one.call(0).unwrap_err();
+    assert!(trap.to_string().contains("out of bounds memory access"));
+    store_one.call(page as i32)?;
+
+    unsafe { memory.protect(0..page, MemoryProtection::NoAccess)? };
+    assert!(load.call(4).is_err());
+
+    unsafe { memory.protect(0..page, MemoryProtection::ReadWrite)? };
+    store_one.call(0)?;
+    assert_eq!(load.call(0)?, 1);
+
+    // Memories which may be relocated can't be protected.
+    let mut config = Config::new();
+    config.static_memory_maximum_size(0);
+    let store = Store::new(&Engine::new(&config));
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)))?;
+    assert!(unsafe { memory.protect(0..page, MemoryProtection::ReadOnly) }.is_err());
+    Ok(())
+}
+
+#[test]
+fn memory_new_with_data() -> anyhow::Result<()> {
+    let store = Store::default();
+    let ty = MemoryType::new(Limits::new(1, None));
+    let memory = Memory::new_with_data(&store, ty.clone(), 0x10, &[1, 2, 3, 4])?;
+
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory (import "" "") 1)
+                (func (export "load") (param i32) (result i32)
+                    local.get 0
+                    i32.load))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[memory.into()])?;
+    let load = instance.get_func("load").unwrap().typed::<i32, i32>()?;
+    assert_eq!(load.call(0x10)?, 0x04030201);
+    assert_eq!(load.call(0x14)?, 0);
+
+    let err = Memory::new_with_data(&store, ty, 65535, &[1, 2]).unwrap_err();
+    assert!(err.to_string().contains("minimum size"));
+    Ok(())
+}
+
+#[test]
+fn share_memory_between_instances() -> anyhow::Result<()> {
+    let store = Store::default();
+    let exporter = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory (export "m") 1)
+                (func (export "grow") (param i32) (result i32)
+                    local.get 0
+                    memory.grow))
+        "#,
+    )?;
+    let importer = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (memory 2))
+                (func (export "size") (result i32) memory.size)
+                (func (export "load") (param i32) (result i32)
+                    local.get 0
+                    i32.load8_u))
+        "#,
+    )?;
+
+    let a = Instance::new(&store, &exporter, &[])?;
+    let memory = a.get_memory("m").unwrap();
+    assert!(Instance::new(&store, &importer, &[memory.clone().into()]).is_err());
+
+    // Once grown the memory is large enough for the import.
+    assert_eq!(a.get_typed_func::<i32, i32>("grow")?.call(1)?, 1);
+    let b = Instance::new(&store, &importer, &[memory.clone().into()])?;
+    let c = Instance::new(&store, &importer, &[memory.clone().into()])?;
+    unsafe {
+        memory.data_unchecked_mut()[65536] = 7;
+    }
+    assert_eq!(b.get_typed_func::<i32, i32>("load")?.call(65536)?, 7);
+
+    // Growth through any of the instances is seen by all of them.
+    a.get_typed_func::<i32, i32>("grow")?.call(1)?;
+    assert_eq!(b.get_typed_func::<(), i32>("size")?.call(())?, 3);
+    assert_eq!(c.get_typed_func::<(), i32>("size")?.call(())?, 3);
+    assert_eq!(memory.size(), 3);
+    Ok(())
+}
+
+#[test]
+fn share_table_between_instances() -> anyhow::Result<()> {
+    let store = Store::default();
+    let ty = TableType::new(ValType::FuncRef, Limits::new(0, None));
+    let table = Table::new(&store, ty, Val::FuncRef(None))?;
+    table.grow(2, Val::FuncRef(None))?;
+
+    let writer = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (table 2 funcref))
+                (func $forty_two (result i32) i32.const 42)
+                (elem (i32.const 1) $forty_two))
+        "#,
+    )?;
+    let caller = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (table 1 funcref))
+                (type $t (func (result i32)))
+                (func (export "call") (param i32) (result i32)
+                    local.get 0
+                    call_indirect (type $t)))
+        "#,
+    )?;
+    Instance::new(&store, &writer, &[table.clone().into()])?;
+    let instance = Instance::new(&store, &caller, &[table.clone().into()])?;
+    let call = instance.get_typed_func::<i32, i32>("call")?;
+    assert_eq!(call.call(1)?, 42);
+    assert!(call.call(0).is_err());
+    assert!(matches!(table.get(1), Some(Val::FuncRef(Some(_)))));
+    Ok(())
+}
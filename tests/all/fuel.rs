@@ -0,0 +1,132 @@
+use wasmtime::*;
+
+fn fuel_store() -> Store {
+    let engine = Engine::new(Config::new().consume_fuel(true));
+    Store::new(&engine)
+}
+
+const COUNT: &str = r#"
+    (module
+        (func (export "count") (param $n i32)
+            (loop $l
+                local.get $n
+                i32.const 1
+                i32.sub
+                local.tee $n
+                br_if $l)))
+"#;
+
+#[test]
+fn no_fuel_configured() -> anyhow::Result<()> {
+    let store = Store::default();
+    assert!(store.add_fuel(1).is_err());
+    assert_eq!(store.fuel_consumed(), None);
+    Ok(())
+}
+
+#[test]
+fn traps_without_fuel() -> anyhow::Result<()> {
+    let store = fuel_store();
+    assert_eq!(store.fuel_consumed(), Some(0));
+    let module = Module::new(store.engine(), COUNT)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let count = instance.get_func("count").unwrap().typed::<i32, ()>()?;
+
+    let trap = count.call(1).unwrap_err();
+    assert!(trap.is_out_of_fuel());
+    assert_eq!(trap.code(), Some(TrapCode::OutOfFuel));
+    assert!(trap.to_string().contains("all fuel consumed"));
+    Ok(())
+}
+
+#[test]
+fn consumption_is_deterministic() -> anyhow::Result<()> {
+    let store = fuel_store();
+    let module = Module::new(store.engine(), COUNT)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let count = instance.get_func("count").unwrap().typed::<i32, ()>()?;
+
+    // Each iteration of the loop runs five instructions, and nothing else in
+    // the function costs any fuel.
+    store.add_fuel(1_000_000)?;
+    count.call(100)?;
+    assert_eq!(store.fuel_consumed(), Some(500));
+    count.call(100)?;
+    assert_eq!(store.fuel_consumed(), Some(1_000));
+    count.call(200)?;
+    assert_eq!(store.fuel_consumed(), Some(2_000));
+    Ok(())
+}
+
+#[test]
+fn runs_out_of_fuel_in_loop() -> anyhow::Result<()> {
+    let store = fuel_store();
+    let module = Module::new(store.engine(), COUNT)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let count = instance.get_func("count").unwrap().typed::<i32, ()>()?;
+
+    store.add_fuel(1_000)?;
+    let trap = count.call(1_000).unwrap_err();
+    assert!(trap.is_out_of_fuel());
+
+    // Adding more fuel allows execution to continue.
+    store.add_fuel(10_000)?;
+    count.call(1_000)?;
+    Ok(())
+}
+
+#[test]
+fn fuel_across_host_calls() -> anyhow::Result<()> {
+    let store = fuel_store();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $host))
+                (func (export "run")
+                    call $host
+                    call $host))
+        "#,
+    )?;
+    let host = Func::wrap(&store, |caller: Caller<'_>| {
+        let store = caller.store();
+        let before = store.fuel_consumed().unwrap();
+        store.add_fuel(5).unwrap();
+        assert_eq!(store.fuel_consumed().unwrap(), before);
+    });
+    let instance = Instance::new(&store, &module, &[host.into()])?;
+    let run = instance.get_func("run").unwrap().typed::<(), ()>()?;
+
+    // Host functions don't consume any fuel themselves, but adding fuel from
+    // within them is visible to their caller once they return.
+    store.add_fuel(1)?;
+    run.call(())?;
+    assert_eq!(store.fuel_consumed(), Some(2));
+    Ok(())
+}
+
+#[test]
+fn callback_adds_fuel() -> anyhow::Result<()> {
+    let store = fuel_store();
+    let module = Module::new(store.engine(), COUNT)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let count = instance.get_func("count").unwrap().typed::<i32, ()>()?;
+
+    let refills = std::rc::Rc::new(std::cell::Cell::new(0));
+    let left = refills.clone();
+    store.deadline_callback(move |deadline| {
+        assert_eq!(deadline, Deadline::Fuel);
+        if left.get() == 3 {
+            return DeadlineAction::Trap;
+        }
+        left.set(left.get() + 1);
+        DeadlineAction::Extend(1_000)
+    });
+
+    // Each iteration costs 5 units of fuel, so three refills cover this.
+    count.call(500)?;
+    assert_eq!(refills.get(), 3);
+    assert!(count.call(1_000).unwrap_err().is_out_of_fuel());
+    assert_eq!(store.fuel_consumed(), Some(3_000));
+    Ok(())
+}
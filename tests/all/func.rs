@@ -234,8 +234,8 @@ fn trap_import() -> Result<()> {
     )
     .err()
     .unwrap()
-    .downcast::<Trap>()?;
-    assert!(trap.to_string().contains("foo"));
+    .downcast::<InstantiationError>()?;
+    assert!(trap.trap().unwrap().to_string().contains("foo"));
     Ok(())
 }
 
@@ -410,8 +410,9 @@ fn caller_memory() -> anyhow::Result<()> {
     let f = Func::wrap(&store, |c: Caller<'_>| {
         assert!(c.get_export("m").is_some());
         assert!(c.get_export("f").is_some());
-        assert!(c.get_export("g").is_none());
-        assert!(c.get_export("t").is_none());
+        assert!(c.get_export("g").and_then(|e| e.into_global()).is_some());
+        assert!(c.get_export("t").and_then(|e| e.into_table()).is_some());
+        assert!(c.get_export("x").is_none());
     });
     let module = Module::new(
         store.engine(),
@@ -440,6 +441,9 @@ fn func_write_nothing() -> anyhow::Result<()> {
     assert!(err
         .to_string()
         .contains("function attempted to return an incompatible value"));
+    assert!(err
+        .to_string()
+        .contains("found externref but expected i32 for result 0 of `(func (result i32))`"));
     Ok(())
 }
 
@@ -522,3 +526,316 @@ fn externref_signature_no_reference_types() -> anyhow::Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn wrap_typed_roundtrip() -> anyhow::Result<()> {
+    let store = Store::default();
+    let f = Func::wrap(&store, |a: i32, b: u32, c: f32, d: i64, e: u64, f: f64| {
+        assert_eq!(a, -1);
+        assert_eq!(b, 1);
+        assert_eq!(c, 2.0);
+        assert_eq!(d, -3);
+        assert_eq!(e, 3);
+        assert_eq!(f, 4.0);
+        a.wrapping_add(b as i32)
+    });
+    assert_eq!(
+        f.ty().params(),
+        &[
+            ValType::I32,
+            ValType::I32,
+            ValType::F32,
+            ValType::I64,
+            ValType::I64,
+            ValType::F64
+        ]
+    );
+    assert_eq!(f.ty().results(), &[ValType::I32]);
+
+    let f = f.typed::<(i32, u32, f32, i64, u64, f64), i32>()?;
+    assert_eq!(f.call((-1, 1, 2.0, -3, 3, 4.0))?, 0);
+    Ok(())
+}
+
+#[test]
+fn typed_func_call() -> anyhow::Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (func (export "nop"))
+                (func (export "swap") (param i32 i64) (result i64 i32)
+                    local.get 1
+                    local.get 0)
+                (func (export "f64") (param f32) (result f64)
+                    local.get 0
+                    f64.promote_f32)
+                (func (export "trap") unreachable))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+
+    let nop = instance.get_func("nop").unwrap().typed::<(), ()>()?;
+    nop.call(())?;
+
+    let swap = instance
+        .get_func("swap")
+        .unwrap()
+        .typed::<(i32, i64), (i64, i32)>()?;
+    assert_eq!(swap.call((1, 2))?, (2, 1));
+
+    let f64 = instance.get_func("f64").unwrap().typed::<f32, f64>()?;
+    assert_eq!(f64.call(1.5)?, 1.5);
+
+    let trap = instance.get_func("trap").unwrap().typed::<(), ()>()?;
+    let err = trap.call(()).unwrap_err();
+    assert!(err.to_string().contains("unreachable"));
+    Ok(())
+}
+
+#[test]
+fn typed_func_type_mismatch() -> anyhow::Result<()> {
+    let store = Store::default();
+    let f = Func::wrap(&store, |a: i32, _b: i64| a);
+
+    assert!(f.typed::<(i32, i64), i32>().is_ok());
+    assert!(f.typed::<(i64, i32), i32>().is_err());
+    assert!(f.typed::<i32, i32>().is_err());
+    assert!(f.typed::<(i32, i64), ()>().is_err());
+    assert!(f.typed::<(i32, i64), (i32, i32)>().is_err());
+    assert!(f.typed::<(i32, i64, i32), i32>().is_err());
+    Ok(())
+}
+
+#[test]
+fn typed_func_cross_store_arg() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.wasm_reference_types(true);
+    let engine = Engine::new(&config);
+
+    let store1 = Store::new(&engine);
+    let store2 = Store::new(&engine);
+
+    let store1_func = Func::wrap(&store1, |_: Option<Func>| {});
+    let store2_func = Func::wrap(&store2, || {});
+
+    let f = store1_func.typed::<Option<Func>, ()>()?;
+    let result = f.call(Some(store2_func));
+    assert!(result.is_err());
+    assert!(result.unwrap_err().to_string().contains("cross-`Store`"));
+    Ok(())
+}
+
+#[test]
+fn call_into() -> anyhow::Result<()> {
+    let store = Store::default();
+    let f = Func::wrap(&store, |a: i32, b: i64| a as i64 + b);
+
+    let mut results = [Val::null()];
+    f.call_into(&[Val::I32(1), Val::I64(2)], &mut results)?;
+    assert_eq!(results[0].unwrap_i64(), 3);
+    f.call_into(&[Val::I32(-1), Val::I64(10)], &mut results)?;
+    assert_eq!(results[0].unwrap_i64(), 9);
+
+    let err = f
+        .call_into(&[Val::I32(1), Val::I64(2)], &mut [])
+        .unwrap_err();
+    assert!(err.to_string().contains("expected 1 results, got 0"));
+    let err = f.call_into(&[Val::I32(1)], &mut results).unwrap_err();
+    assert!(err.to_string().contains("expected 2 arguments, got 1"));
+    let err = f
+        .call_into(&[Val::I64(1), Val::I64(2)], &mut results)
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("argument type mismatch: found i64 but expected i32"));
+    Ok(())
+}
+
+#[test]
+fn call_unchecked() -> anyhow::Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (func (export "f") (param i32 i64 f32) (result f64 i32)
+                    local.get 2
+                    f64.promote_f32
+                    local.get 0
+                    local.get 1
+                    i32.wrap_i64
+                    i32.add)
+                (func (export "trap") unreachable))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+
+    let f = instance.get_func("f").unwrap();
+    let mut values = [3, 4, 1.5f32.to_bits() as u128];
+    unsafe {
+        f.call_unchecked(&mut values)?;
+    }
+    assert_eq!(f64::from_bits(values[0] as u64), 1.5);
+    assert_eq!(values[1] as i32, 7);
+
+    let trap = instance.get_func("trap").unwrap();
+    let err = unsafe { trap.call_unchecked(&mut []).unwrap_err() };
+    assert!(err.to_string().contains("unreachable"));
+    Ok(())
+}
+
+#[test]
+fn call_unchecked_externref() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.wasm_reference_types(true);
+    let store = Store::new(&Engine::new(&config));
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (global $g (mut externref) (ref.null extern))
+                (func (export "swap") (param externref) (result externref)
+                    global.get $g
+                    local.get 0
+                    global.set $g))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let swap = instance.get_func("swap").unwrap();
+
+    let a = ExternRef::new("a");
+    let mut values = [a.to_raw(&store) as usize as u128];
+    unsafe {
+        swap.call_unchecked(&mut values)?;
+    }
+    assert!(unsafe { ExternRef::from_raw(values[0] as usize as *mut u8) }.is_none());
+
+    let b = ExternRef::new("b");
+    let mut values = [b.to_raw(&store) as usize as u128];
+    unsafe {
+        swap.call_unchecked(&mut values)?;
+    }
+    drop(b);
+    let result = unsafe { ExternRef::from_raw(values[0] as usize as *mut u8) }.unwrap();
+    assert!(result.ptr_eq(&a));
+
+    // The global keeps the second argument alive even though the only other
+    // reference to it was dropped, and garbage collections don't free it.
+    store.gc();
+    let mut values = [0];
+    unsafe {
+        swap.call_unchecked(&mut values)?;
+    }
+    let result = unsafe { ExternRef::from_raw(values[0] as usize as *mut u8) }.unwrap();
+    assert_eq!(result.data().downcast_ref::<&str>(), Some(&"b"));
+    Ok(())
+}
+
+#[test]
+fn func_type_display() {
+    let ty = FuncType::new(Box::new([]), Box::new([]));
+    assert_eq!(ty.to_string(), "(func)");
+    let ty = FuncType::new(Box::new([ValType::I32, ValType::F64]), Box::new([]));
+    assert_eq!(ty.to_string(), "(func (param i32 f64))");
+    let ty = FuncType::new(
+        Box::new([ValType::ExternRef]),
+        Box::new([ValType::V128, ValType::FuncRef]),
+    );
+    assert_eq!(
+        ty.to_string(),
+        "(func (param externref) (result v128 funcref))"
+    );
+}
+
+#[test]
+fn wrap_multiple_results() -> anyhow::Result<()> {
+    let store = Store::default();
+    let swap = Func::wrap(&store, |a: i32, b: f64| (b, i64::from(a) * 2, a));
+    let fallible = Func::wrap(&store, |a: i32| -> Result<(i32, f32), Trap> {
+        if a < 0 {
+            Err(Trap::new("negative"))
+        } else {
+            Ok((a + 1, a as f32))
+        }
+    });
+    assert_eq!(
+        swap.ty().results(),
+        &[ValType::F64, ValType::I64, ValType::I32]
+    );
+
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "swap" (func $swap (param i32 f64) (result f64 i64 i32)))
+                (import "" "fallible" (func $fallible (param i32) (result i32 f32)))
+                (func (export "swap") (param i32 f64) (result f64 i64 i32)
+                    local.get 0
+                    local.get 1
+                    call $swap)
+                (func (export "fallible") (param i32) (result i32 f32)
+                    local.get 0
+                    call $fallible))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[swap.clone().into(), fallible.into()])?;
+
+    let results = swap.call(&[Val::I32(3), Val::F64(1.5f64.to_bits())])?;
+    assert_eq!(results[0].unwrap_f64(), 1.5);
+    assert_eq!(results[1].unwrap_i64(), 6);
+    assert_eq!(results[2].unwrap_i32(), 3);
+
+    let f = instance
+        .get_func("swap")
+        .unwrap()
+        .typed::<(i32, f64), (f64, i64, i32)>()?;
+    assert_eq!(f.call((5, 2.5))?, (2.5, 10, 5));
+
+    let f = instance
+        .get_func("fallible")
+        .unwrap()
+        .typed::<i32, (i32, f32)>()?;
+    assert_eq!(f.call(1)?, (2, 1.0));
+    let trap = f.call(-1).unwrap_err();
+    assert!(trap.to_string().contains("negative"));
+    Ok(())
+}
+
+#[test]
+fn store_data_in_host_func() -> anyhow::Result<()> {
+    type Log = std::cell::RefCell<Vec<i32>>;
+
+    let store = Store::default();
+    assert!(store.get::<Log>().is_none());
+    store.set(Log::default()).unwrap();
+    let err = store.set(Log::new(vec![1])).unwrap_err();
+    assert_eq!(err.into_inner(), [1]);
+
+    let log = Func::wrap(&store, |caller: Caller<'_>, x: i32| {
+        let store = caller.store();
+        store.get::<Log>().unwrap().borrow_mut().push(x);
+    });
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $log (param i32)))
+                (func (export "run")
+                    i32.const 1
+                    call $log
+                    i32.const 2
+                    call $log))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[log.into()])?;
+    let run = instance.get_func("run").unwrap().typed::<(), ()>()?;
+    run.call(())?;
+    assert_eq!(*store.get::<Log>().unwrap().borrow(), [1, 2]);
+
+    // Data is per-store, even when the engine is shared.
+    let other = Store::new(store.engine());
+    assert!(other.get::<Log>().is_none());
+    Ok(())
+}
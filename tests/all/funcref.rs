@@ -83,3 +83,53 @@ fn receive_null_funcref_from_wasm() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn funcref_through_host_funcs_globals_and_tables() -> anyhow::Result<()> {
+    let (store, module) = ref_types_module(
+        r#"
+            (module
+                (import "" "host" (func $host (param funcref) (result funcref)))
+                (global $g (export "g") (mut funcref) (ref.null func))
+                (table $t (export "t") 1 funcref)
+                (func (export "forty-two") (result i32) i32.const 42)
+                (func (export "roundtrip") (param funcref) (result funcref)
+                    (global.set $g (call $host (local.get 0)))
+                    (table.set $t (i32.const 0) (global.get $g))
+                    (table.get $t (i32.const 0))
+                )
+            )
+        "#,
+    )?;
+
+    let host = Func::wrap(&store, |f: Option<Func>| f);
+    let instance = Instance::new(&store, &module, &[host.into()])?;
+    let forty_two = instance.get_func("forty-two").unwrap();
+    let roundtrip = instance
+        .get_func("roundtrip")
+        .unwrap()
+        .typed::<Option<Func>, Option<Func>>()?;
+
+    let f = roundtrip.call(Some(forty_two))?.unwrap();
+    assert_eq!(f.typed::<(), i32>()?.call(())?, 42);
+
+    let g = instance.get_global("g").unwrap().get();
+    assert_eq!(
+        g.unwrap_funcref().unwrap().typed::<(), i32>()?.call(())?,
+        42
+    );
+    let t = instance.get_table("t").unwrap().get(0).unwrap();
+    assert_eq!(
+        t.unwrap_funcref().unwrap().typed::<(), i32>()?.call(())?,
+        42
+    );
+
+    assert!(roundtrip.call(None)?.is_none());
+    assert!(instance
+        .get_global("g")
+        .unwrap()
+        .get()
+        .unwrap_funcref()
+        .is_none());
+    Ok(())
+}
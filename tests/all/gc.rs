@@ -588,3 +588,43 @@ fn touch_own_externref_global_on_drop() -> anyhow::Result<()> {
         }
     }
 }
+
+#[test]
+#[cfg_attr(feature = "experimental_x64", ignore)] // TODO #2079 investigate.
+fn table_grow_releases_unreachable_refs() -> anyhow::Result<()> {
+    let (store, module) = ref_types_module(
+        r#"
+            (module
+                (table $t (export "t") 0 externref)
+                (func (export "drop_ref") (param externref))
+                (func (export "grow") (result i32)
+                    (table.grow $t (ref.null extern) (i32.const 1))
+                )
+            )
+        "#,
+    )?;
+
+    let instance = Instance::new(&store, &module, &[])?;
+    let drop_ref = instance.get_func("drop_ref").unwrap();
+    let grow = instance.get_func("grow").unwrap().typed::<(), i32>()?;
+    let table = instance.get_table("t").unwrap();
+
+    // Passing a reference into wasm leaves it in the activations table until
+    // the next GC, which growing an `externref` table performs.
+    let dropped = Rc::new(Cell::new(false));
+    let r = ExternRef::new(SetFlagOnDrop(dropped.clone()));
+    drop_ref.call(&[Val::ExternRef(Some(r))])?;
+    assert!(!dropped.get());
+    assert_eq!(grow.call(())?, 0);
+    assert!(dropped.get());
+
+    // Same when the host grows the table.
+    dropped.set(false);
+    let r = ExternRef::new(SetFlagOnDrop(dropped.clone()));
+    drop_ref.call(&[Val::ExternRef(Some(r))])?;
+    assert!(!dropped.get());
+    assert_eq!(table.grow(1, Val::ExternRef(None))?, 1);
+    assert!(dropped.get());
+
+    Ok(())
+}
@@ -52,6 +52,25 @@ fn mutability() -> anyhow::Result<()> {
     assert_eq!(g.get().i32(), Some(0));
     g.set(1.into())?;
     assert_eq!(g.get().i32(), Some(1));
+
+    // Type mismatches are rejected and leave the value alone.
+    let err = g.set(2i64.into()).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("global of type i32 cannot be set to i64"));
+    assert_eq!(g.get().i32(), Some(1));
+
+    // Immutable globals exported from wasm can't be set either.
+    let module = Module::new(
+        store.engine(),
+        r#"(module (global (export "g") i32 (i32.const 100)))"#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let g = instance.get_global("g").unwrap();
+    assert_eq!(g.mutability(), Mutability::Const);
+    let err = g.set(101.into()).unwrap_err();
+    assert!(err.to_string().contains("immutable global cannot be set"));
+    assert_eq!(g.get().i32(), Some(100));
     Ok(())
 }
 
@@ -91,3 +110,45 @@ fn use_after_drop() -> anyhow::Result<()> {
     assert_eq!(g.get().i32(), Some(101));
     Ok(())
 }
+
+#[test]
+fn v128() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.wasm_simd(true);
+    let engine = Engine::new(&config);
+    let store = Store::new(&engine);
+
+    let g = Global::new(
+        &store,
+        GlobalType::new(ValType::V128, Mutability::Var),
+        Val::V128(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10),
+    )?;
+    assert_eq!(
+        g.get().v128(),
+        Some(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10)
+    );
+    g.set(Val::V128(u128::max_value()))?;
+    assert_eq!(g.get().v128(), Some(u128::max_value()));
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (global (import "" "g") (mut v128))
+                (global (export "x") v128 (v128.const i64x2 1 2))
+                (func (export "get") (result v128) global.get 0)
+                (func (export "set") (param v128) local.get 0 global.set 0))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[g.clone().into()])?;
+
+    let x = instance.get_global("x").unwrap();
+    assert_eq!(x.get().v128(), Some((2 << 64) | 1));
+
+    let get = instance.get_func("get").unwrap();
+    assert_eq!(get.call(&[])?[0].v128(), Some(u128::max_value()));
+    instance.get_func("set").unwrap().call(&[Val::V128(42)])?;
+    assert_eq!(g.get().v128(), Some(42));
+    assert_eq!(get.call(&[])?[0].v128(), Some(42));
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::sync::Arc;
+use wasmtime::*;
+
+#[test]
+fn host_funcs_are_shared_by_stores() -> Result<()> {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut config = Config::new();
+    let counter = calls.clone();
+    config.define_host_func(
+        "host",
+        "add",
+        FuncType::new(
+            Box::new([ValType::I32, ValType::I32]),
+            Box::new([ValType::I32]),
+        ),
+        move |_, params, results| {
+            counter.fetch_add(1, SeqCst);
+            results[0] = Val::I32(params[0].unwrap_i32() + params[1].unwrap_i32());
+            Ok(())
+        },
+    );
+    config.wrap_host_func("host", "double", |x: i32| x * 2);
+
+    let engine = Engine::new(&config);
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "host" "add" (func $add (param i32 i32) (result i32)))
+                (import "host" "double" (func $double (param i32) (result i32)))
+                (func (export "run") (param i32) (result i32)
+                    local.get 0
+                    call $double
+                    local.get 0
+                    call $add))
+        "#,
+    )?;
+
+    for i in 0..3 {
+        let store = Store::new(&engine);
+        let linker = Linker::new(&store);
+        let instance = linker.instantiate(&module)?;
+        let run = instance.get_typed_func::<i32, i32>("run")?;
+        assert_eq!(run.call(i)?, 3 * i);
+    }
+    assert_eq!(calls.load(SeqCst), 3);
+    Ok(())
+}
+
+#[test]
+fn get_host_func() -> Result<()> {
+    let mut config = Config::new();
+    config.wrap_host_func("host", "one", || 1);
+    let store = Store::new(&Engine::new(&config));
+
+    assert!(store.get_host_func("host", "two").is_none());
+    let one = store.get_host_func("host", "one").unwrap();
+    assert_eq!(one.typed::<(), i32>()?.call(())?, 1);
+
+    // Requesting it again returns the function bound before.
+    let again = store.get_host_func("host", "one").unwrap();
+    assert_eq!(
+        one.typed::<(), i32>()?.call(())?,
+        again.typed::<(), i32>()?.call(())?
+    );
+    Ok(())
+}
+
+#[test]
+fn linker_definitions_take_precedence() -> Result<()> {
+    let mut config = Config::new();
+    config.wrap_host_func("host", "f", || 1);
+    let engine = Engine::new(&config);
+    let store = Store::new(&engine);
+    let mut linker = Linker::new(&store);
+    linker.func("host", "f", || 2)?;
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "host" "f" (func $f (result i32)))
+                (func (export "run") (result i32) call $f))
+        "#,
+    )?;
+    let instance = linker.instantiate(&module)?;
+    assert_eq!(instance.get_typed_func::<(), i32>("run")?.call(())?, 2);
+    Ok(())
+}
@@ -11,3 +11,151 @@ fn wrong_import_numbers() -> Result<()> {
     assert!(Instance::new(&store, &module, &[func.clone().into(), func.into()]).is_err());
     Ok(())
 }
+
+#[test]
+fn get_typed_func() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (func (export "f") (param i32) (result i32) local.get 0)
+                (memory (export "m") 1))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    assert_eq!(instance.get_typed_func::<i32, i32>("f")?.call(3)?, 3);
+
+    let err = instance.get_typed_func::<(), ()>("f").unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("type mismatch"),
+        "bad error: {:?}",
+        err
+    );
+    let err = instance.get_typed_func::<(), ()>("m").unwrap_err();
+    assert!(
+        err.to_string().contains("`m` is a memory"),
+        "bad error: {}",
+        err
+    );
+    let err = instance.get_typed_func::<(), ()>("g").unwrap_err();
+    assert!(err.to_string().contains("`g`"), "bad error: {}", err);
+    Ok(())
+}
+
+#[test]
+fn instantiation_errors() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(store.engine(), r#"(module (import "" "" (func)))"#)?;
+    let err = Instance::new(&store, &module, &[]).unwrap_err();
+    match err.downcast_ref::<InstantiationError>() {
+        Some(InstantiationError::Link(_)) => {}
+        _ => panic!("expected a link error: {:?}", err),
+    }
+    assert!(err.to_string().contains("wrong number of imports"));
+
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module $m
+                (func $start unreachable)
+                (start $start))
+        "#,
+    )?;
+    let err = Instance::new(&store, &module, &[]).unwrap_err();
+    let trap = match err.downcast_ref::<InstantiationError>() {
+        Some(InstantiationError::Trap(trap)) => trap,
+        _ => panic!("expected a trap: {:?}", err),
+    };
+    assert_eq!(trap.trace().len(), 1);
+    assert_eq!(trap.trace()[0].func_name(), Some("start"));
+    Ok(())
+}
+
+#[test]
+fn import_type_mismatch_names_types() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "m" "f" (func (param i32) (result i32)))
+                (import "m" "g" (global (mut i64))))
+        "#,
+    )?;
+    let func = Func::wrap(&store, |_: i64| {});
+    let global = Global::new(
+        &store,
+        GlobalType::new(ValType::I64, Mutability::Mut),
+        Val::I64(0),
+    )?;
+
+    let err = Instance::new(&store, &module, &[func.into(), global.clone().into()]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "incompatible import type for `m::f`: \
+         expected (func (param i32) (result i32)), found (func (param i64))",
+    );
+
+    let func = Func::wrap(&store, |x: i32| x);
+    let global = Global::new(
+        &store,
+        GlobalType::new(ValType::I64, Mutability::Const),
+        Val::I64(0),
+    )?;
+    let err = Instance::new(&store, &module, &[func.into(), global.into()]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "incompatible import type for `m::g`: \
+         expected (global (mut i64)), found (global i64)",
+    );
+    Ok(())
+}
+
+#[test]
+fn get_default() -> Result<()> {
+    let store = Store::default();
+    let default = |wat: &str| -> Result<Func> {
+        let module = Module::new(store.engine(), wat)?;
+        Instance::new(&store, &module, &[])?.get_default()
+    };
+
+    let func = default(
+        r#"
+            (module
+                (func (export "") (result i32) i32.const 1)
+                (func (export "_start") (result i32) i32.const 2))
+        "#,
+    )?;
+    assert_eq!(func.typed::<(), i32>()?.call(())?, 1);
+
+    let func = default(r#"(module (func (export "_start") (result i32) i32.const 2))"#)?;
+    assert_eq!(func.typed::<(), i32>()?.call(())?, 2);
+
+    let func = default("(module)")?;
+    func.typed::<(), ()>()?.call(())?;
+
+    assert!(default(r#"(module (memory (export "") 1))"#).is_err());
+    Ok(())
+}
+
+#[test]
+fn get_default_with_entry() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (func (export "_start") (result i32) i32.const 1)
+                (func (export "main") (result i32) i32.const 2))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+
+    let func = instance.get_default_with_entry("main")?;
+    assert_eq!(func.typed::<(), i32>()?.call(())?, 2);
+
+    let func = instance.get_default_with_entry("missing")?;
+    func.typed::<(), ()>()?.call(())?;
+    Ok(())
+}
@@ -29,3 +29,46 @@ fn test_invoke_func_via_table() -> Result<()> {
     assert_eq!(result[0].unwrap_i64(), 42);
     Ok(())
 }
+
+#[test]
+fn call_indirect_across_modules_and_stores() -> Result<()> {
+    let engine = Engine::default();
+    let callee = Module::new(
+        &engine,
+        r#"
+            (module
+                (func $f (param i32) (result i32) (i32.add (local.get 0) (i32.const 1)))
+                (table (export "table") 1 1 funcref)
+                (elem (i32.const 0) $f))
+        "#,
+    )?;
+    let caller = Module::new(
+        &engine,
+        r#"
+            (module
+                (type $unused (func (param f64)))
+                (type $t (func (param i32) (result i32)))
+                (import "" "table" (table 1 1 funcref))
+                (func (export "call") (param i32) (result i32)
+                    (call_indirect (type $t) (local.get 0) (i32.const 0))))
+        "#,
+    )?;
+
+    // Signatures are registered with the engine, so their indices don't
+    // depend on the order in which each store sees them.
+    for caller_first in [false, true].iter() {
+        let store = Store::new(&engine);
+        if *caller_first {
+            Func::wrap(&store, |_: f64| {});
+        }
+        let table = Instance::new(&store, &callee, &[])?
+            .get_table("table")
+            .unwrap();
+        let call = Instance::new(&store, &caller, &[table.into()])?
+            .get_func("call")
+            .unwrap()
+            .typed::<i32, i32>()?;
+        assert_eq!(call.call(41)?, 42);
+    }
+    Ok(())
+}
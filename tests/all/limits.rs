@@ -0,0 +1,281 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use wasmtime::*;
+
+/// The `(current, desired, maximum)` arguments of the last growth request.
+type LastRequest = Rc<Cell<Option<(u32, u32, Option<u32>)>>>;
+
+/// Allows growth up to a fixed number of pages or elements, recording the
+/// last request.
+struct Budget {
+    memory_pages: u32,
+    table_elements: u32,
+    last: LastRequest,
+}
+
+impl ResourceLimiter for Budget {
+    fn memory_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        self.last.set(Some((current, desired, maximum)));
+        desired <= self.memory_pages
+    }
+
+    fn table_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        self.last.set(Some((current, desired, maximum)));
+        desired <= self.table_elements
+    }
+}
+
+fn limited_store(last: &LastRequest) -> Store {
+    Store::new_with_limits(
+        &Engine::default(),
+        Budget {
+            memory_pages: 3,
+            table_elements: 5,
+            last: last.clone(),
+        },
+    )
+}
+
+#[test]
+fn memory_growth_is_limited() -> anyhow::Result<()> {
+    let last = Rc::new(Cell::new(None));
+    let store = limited_store(&last);
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory (export "m") 1 10)
+                (func (export "grow") (param i32) (result i32)
+                    local.get 0
+                    memory.grow))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let grow = instance.get_func("grow").unwrap().typed::<i32, i32>()?;
+
+    assert_eq!(grow.call(1)?, 1);
+    assert_eq!(last.get(), Some((1, 2, Some(10))));
+    assert_eq!(grow.call(2)?, -1);
+    assert_eq!(last.get(), Some((2, 4, Some(10))));
+
+    let memory = instance.get_memory("m").unwrap();
+    assert_eq!(memory.size(), 2);
+    assert_eq!(memory.grow(1)?, 2);
+    assert!(memory.grow(1).is_err());
+    assert_eq!(memory.size(), 3);
+    Ok(())
+}
+
+#[test]
+fn table_growth_is_limited() -> anyhow::Result<()> {
+    let last = Rc::new(Cell::new(None));
+    let store = limited_store(&last);
+    let ty = TableType::new(ValType::FuncRef, Limits::new(1, None));
+    let table = Table::new(&store, ty, Val::FuncRef(None))?;
+
+    assert_eq!(table.grow(4, Val::FuncRef(None))?, 1);
+    assert_eq!(last.get(), Some((1, 5, None)));
+    assert!(table.grow(1, Val::FuncRef(None)).is_err());
+    assert_eq!(last.get(), Some((5, 6, None)));
+    assert_eq!(table.size(), 5);
+    Ok(())
+}
+
+#[test]
+fn unlimited_without_limiter() -> anyhow::Result<()> {
+    let store = Store::default();
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)))?;
+    assert_eq!(memory.grow(10)?, 1);
+    Ok(())
+}
+
+#[test]
+fn resource_usage_tracks_growth() -> anyhow::Result<()> {
+    let store = Store::default();
+    assert_eq!(store.resource_usage(), Default::default());
+
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory (export "m") 1)
+                (table (export "t") 3 funcref)
+                (func (export "f")))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let usage = store.resource_usage();
+    assert_eq!(usage.instances, 1);
+    assert_eq!(usage.memory_bytes, 65536);
+    assert_eq!(usage.table_elements, 3);
+    assert!(usage.code_bytes > 0);
+
+    instance.get_memory("m").unwrap().grow(2)?;
+    instance
+        .get_table("t")
+        .unwrap()
+        .grow(1, Val::FuncRef(None))?;
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)))?;
+    let usage = store.resource_usage();
+    assert_eq!(usage.instances, 2);
+    assert_eq!(usage.memory_bytes, 4 * 65536);
+    assert_eq!(usage.table_elements, 4);
+
+    memory.grow(1)?;
+    assert_eq!(store.resource_usage().memory_bytes, 5 * 65536);
+    Ok(())
+}
+
+#[test]
+fn store_limits_cap_growth() -> anyhow::Result<()> {
+    let engine = Engine::default();
+    let limits = StoreLimitsBuilder::new()
+        .memory_pages(2)
+        .table_elements(3)
+        .build();
+    let store = Store::new_with_limits(&engine, limits);
+
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)))?;
+    assert_eq!(memory.grow(1)?, 1);
+    assert!(memory.grow(1).is_err());
+
+    let ty = TableType::new(ValType::FuncRef, Limits::new(1, None));
+    let table = Table::new(&store, ty, Val::FuncRef(None))?;
+    assert_eq!(table.grow(2, Val::FuncRef(None))?, 1);
+    assert!(table.grow(1, Val::FuncRef(None)).is_err());
+    Ok(())
+}
+
+#[test]
+fn store_limits_cap_counts() -> anyhow::Result<()> {
+    let engine = Engine::default();
+    let one_memory = Module::new(&engine, "(module (memory 0))")?;
+    let one_table = Module::new(&engine, "(module (table 0 funcref))")?;
+    let empty = Module::new(&engine, "(module)")?;
+
+    let limits = StoreLimitsBuilder::new().instances(2).build();
+    let store = Store::new_with_limits(&engine, limits);
+    Instance::new(&store, &empty, &[])?;
+    Instance::new(&store, &empty, &[])?;
+    let err = Instance::new(&store, &empty, &[]).unwrap_err();
+    assert!(
+        err.to_string().contains("instance count too high"),
+        "bad error: {}",
+        err
+    );
+    assert!(matches!(
+        err.downcast_ref::<InstantiationError>(),
+        Some(InstantiationError::Resource(_))
+    ));
+    // Host-defined items aren't instances of modules.
+    Memory::new(&store, MemoryType::new(Limits::new(0, None)))?;
+    Func::wrap(&store, || {});
+
+    let limits = StoreLimitsBuilder::new().memories(1).tables(1).build();
+    let store = Store::new_with_limits(&engine, limits);
+    Instance::new(&store, &one_memory, &[])?;
+    let err = Instance::new(&store, &one_memory, &[]).unwrap_err();
+    assert!(
+        err.to_string().contains("memory count too high"),
+        "bad error: {}",
+        err
+    );
+    Instance::new(&store, &one_table, &[])?;
+    let err = Instance::new(&store, &one_table, &[]).unwrap_err();
+    assert!(
+        err.to_string().contains("table count too high"),
+        "bad error: {}",
+        err
+    );
+    // Rejected instantiations don't count towards the limits.
+    Instance::new(&store, &empty, &[])?;
+    assert_eq!(store.resource_usage().instances, 3);
+    Ok(())
+}
+
+#[test]
+fn reset_releases_instances() -> anyhow::Result<()> {
+    struct SetFlagOnDrop(Rc<Cell<bool>>);
+
+    impl Drop for SetFlagOnDrop {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    let mut store = Store::default();
+    let module = Module::new(store.engine(), "(module (memory (export \"m\") 1))")?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let dropped = Rc::new(Cell::new(false));
+    let flag = SetFlagOnDrop(dropped.clone());
+    let func = Func::wrap(&store, move || {
+        let _ = &flag;
+    });
+    let weak = func.downgrade();
+
+    let err = store.reset().unwrap_err();
+    assert!(
+        err.to_string().contains("referenced by 2 other handles"),
+        "bad error: {}",
+        err
+    );
+    drop(instance);
+    drop(func);
+    assert!(weak.upgrade().is_some());
+    assert!(!dropped.get());
+
+    store.reset()?;
+    assert!(dropped.get());
+    assert!(weak.upgrade().is_none());
+    assert_eq!(store.resource_usage(), Default::default());
+
+    let instance = Instance::new(&store, &module, &[])?;
+    assert_eq!(instance.get_memory("m").unwrap().size(), 1);
+    assert_eq!(store.resource_usage().instances, 1);
+    Ok(())
+}
+
+#[test]
+fn reset_restarts_limit_counts() -> anyhow::Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, "(module (memory 0))")?;
+    let limits = StoreLimitsBuilder::new().instances(1).memories(1).build();
+    let mut store = Store::new_with_limits(&engine, limits);
+
+    Instance::new(&store, &module, &[])?;
+    assert!(Instance::new(&store, &module, &[]).is_err());
+    store.reset()?;
+    Instance::new(&store, &module, &[])?;
+    Ok(())
+}
+
+#[test]
+fn failed_instantiations_release_counts() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config
+        .static_memory_maximum_size(1 << 16)
+        .allocation_strategy(InstanceAllocationStrategy::Pooling {
+            max_instances: 10,
+            max_instance_size: 1 << 16,
+            max_tables: 10,
+            max_table_elements: 10,
+            max_memories: 1,
+        });
+    let engine = Engine::new(&config);
+    let module = Module::new(&engine, "(module (memory 0))")?;
+    let limits = StoreLimitsBuilder::new().instances(2).memories(2).build();
+    let store = Store::new_with_limits(&engine, limits);
+
+    Instance::new(&store, &module, &[])?;
+    // The pool is out of memories, so this fails once the limits were already
+    // checked.
+    let err = Instance::new(&store, &module, &[]).unwrap_err();
+    assert!(
+        err.to_string().contains("pooled memory slots are in use"),
+        "bad error: {}",
+        err
+    );
+    // Which didn't count towards the limit of two instances.
+    Instance::new(&store, &Module::new(&engine, "(module)")?, &[])?;
+    Ok(())
+}
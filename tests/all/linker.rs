@@ -19,6 +19,34 @@ fn link_undefined() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn link_errors_name_import() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    let module = Module::new(
+        store.engine(),
+        r#"(module (import "env" "f" (func (param i32))))"#,
+    )?;
+
+    let err = linker.instantiate(&module).unwrap_err().to_string();
+    assert!(
+        err.contains("`env::f` has not been defined"),
+        "bad error: {}",
+        err
+    );
+    assert!(err.contains("(func (param i32))"), "bad error: {}", err);
+
+    linker.func("env", "f", || {})?;
+    let err = linker.instantiate(&module).unwrap_err().to_string();
+    assert!(
+        err.contains("incompatible import type for `env::f`"),
+        "bad error: {}",
+        err
+    );
+    assert!(err.contains("  * (func)\n"), "bad error: {}", err);
+    Ok(())
+}
+
 #[test]
 fn link_twice_bad() -> Result<()> {
     let store = Store::default();
@@ -50,11 +78,11 @@ fn link_twice_bad() -> Result<()> {
 
     // memories
     let ty = MemoryType::new(Limits::new(1, None));
-    let memory = Memory::new(&store, ty);
+    let memory = Memory::new(&store, ty)?;
     linker.define("", "", memory.clone())?;
     assert!(linker.define("", "", memory.clone()).is_err());
     let ty = MemoryType::new(Limits::new(2, None));
-    let memory = Memory::new(&store, ty);
+    let memory = Memory::new(&store, ty)?;
     assert!(linker.define("", "", memory.clone()).is_err());
 
     // tables
@@ -160,3 +188,109 @@ fn module_interposition() -> Result<()> {
     assert_eq!(func()?, 112);
     Ok(())
 }
+
+#[test]
+fn instantiate_pre() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    let count = Global::new(
+        &store,
+        GlobalType::new(ValType::I32, Mutability::Var),
+        Val::I32(0),
+    )?;
+    linker.define("host", "count", count.clone())?;
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "host" "count" (global $count (mut i32)))
+                (func $start
+                    global.get $count
+                    i32.const 1
+                    i32.add
+                    global.set $count)
+                (start $start))
+        "#,
+    )?;
+
+    let pre = linker.instantiate_pre(&module)?;
+    // Later definitions don't affect the already resolved imports.
+    linker.allow_shadowing(true);
+    let other = Global::new(
+        &store,
+        GlobalType::new(ValType::I32, Mutability::Var),
+        Val::I32(0),
+    )?;
+    linker.define("host", "count", other.clone())?;
+
+    for _ in 0..3 {
+        pre.instantiate()?;
+    }
+    assert_eq!(count.get().i32(), Some(3));
+    assert_eq!(other.get().i32(), Some(0));
+
+    let empty = Linker::new(&store);
+    assert!(empty.instantiate_pre(&module).is_err());
+    Ok(())
+}
+
+#[test]
+fn define_unknown_imports_as_traps() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    linker.func("host", "known", || 1)?;
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "host" "known" (func $known (result i32)))
+                (import "host" "unknown" (func $unknown (param i32)))
+                (func (export "known") (result i32) call $known)
+                (func (export "unknown") i32.const 0 call $unknown))
+        "#,
+    )?;
+    linker.define_unknown_imports_as_traps(&module)?;
+    let instance = linker.instantiate(&module)?;
+
+    assert_eq!(instance.get_typed_func::<(), i32>("known")?.call(())?, 1);
+    let trap = instance
+        .get_typed_func::<(), ()>("unknown")?
+        .call(())
+        .unwrap_err();
+    assert!(
+        trap.to_string()
+            .contains("`host::unknown` has not been defined"),
+        "bad trap: {}",
+        trap
+    );
+    Ok(())
+}
+
+#[test]
+fn command_start_traps_are_returned_as_is() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    linker.func("host", "exit", || -> Result<(), Trap> {
+        Err(Trap::i32_exit(3))
+    })?;
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "host" "exit" (func $exit))
+                (start $exit)
+                (func (export "_start")))
+        "#,
+    )?;
+    linker.module("command", &module)?;
+
+    let err = linker
+        .get_one_by_name("command", "_start")?
+        .into_func()
+        .unwrap()
+        .call(&[])
+        .unwrap_err();
+    let trap = err.downcast::<Trap>()?;
+    assert_eq!(trap.i32_exit_status(), Some(3));
+    Ok(())
+}
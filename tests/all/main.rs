@@ -1,23 +1,32 @@
+mod call_hook;
 mod cli_tests;
+mod coredump;
 mod custom_signal_handler;
 mod debug;
+mod epoch;
 mod externals;
+mod fuel;
 mod func;
 mod fuzzing;
 mod globals;
+mod host_funcs;
 mod iloop;
 mod import_calling_export;
 mod import_indexes;
 mod instance;
 mod invoke_func_via_table;
+mod limits;
 mod linker;
 mod memory_creator;
+mod module;
 mod module_serialize;
 mod name;
+mod pooling;
 mod stack_overflow;
 mod table;
 mod traps;
 mod use_after_drop;
+mod wasi;
 mod wast;
 
 // TODO(#1886): Cranelift only supports reference types on x64.
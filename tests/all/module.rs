@@ -0,0 +1,386 @@
+use wasmtime::*;
+
+#[test]
+fn imports_and_exports() -> anyhow::Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "env" "f" (func $f (param i32) (result i32)))
+                (import "env" "g" (global (mut i64)))
+                (import "other" "m" (memory 1 2))
+                (table (export "t") 3 funcref)
+                (global (export "c") f32 (f32.const 1))
+                (func (export "run") (param i32) (result i32)
+                    local.get 0
+                    call $f)
+                (export "f" (func $f)))
+        "#,
+    )?;
+
+    let imports = module.imports();
+    assert_eq!(imports.len(), 3);
+    let imports = imports
+        .map(|i| (i.module().to_string(), i.name().to_string(), i.ty()))
+        .collect::<Vec<_>>();
+    assert_eq!(imports[0].0, "env");
+    assert_eq!(imports[0].1, "f");
+    assert_eq!(
+        imports[0].2.unwrap_func().to_string(),
+        "(func (param i32) (result i32))"
+    );
+    assert_eq!(imports[1].1, "g");
+    let g = imports[1].2.unwrap_global();
+    assert_eq!(*g.content(), ValType::I64);
+    assert_eq!(g.mutability(), Mutability::Var);
+    assert_eq!(imports[2].0, "other");
+    assert_eq!(imports[2].2.unwrap_memory().limits().max(), Some(2));
+
+    // Exports are listed in the order they appear in the module.
+    let exports = module
+        .exports()
+        .map(|e| (e.name().to_string(), e.ty()))
+        .collect::<Vec<_>>();
+    let names = exports.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["t", "c", "run", "f"]);
+    assert_eq!(*exports[0].1.unwrap_table().element(), ValType::FuncRef);
+    assert_eq!(exports[0].1.unwrap_table().limits().min(), 3);
+    assert_eq!(*exports[1].1.unwrap_global().content(), ValType::F32);
+    assert_eq!(exports[2].1.unwrap_func(), exports[3].1.unwrap_func());
+    assert!(module.get_export("missing").is_none());
+    Ok(())
+}
+
+#[test]
+fn segments() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.wasm_bulk_memory(true);
+    let engine = Engine::new(&config);
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "base" (global i32))
+                (memory 1)
+                (table 4 funcref)
+                (func $a)
+                (func $b)
+                (data (i32.const 16) "abc")
+                (data "passive")
+                (data (global.get 0) "xyz")
+                (elem (i32.const 1) $b $a)
+                (elem func $a))
+        "#,
+    )?;
+
+    let data = module.data_segments().collect::<Vec<_>>();
+    assert_eq!(data.len(), 3);
+    assert_eq!(
+        data[0].mode(),
+        SegmentMode::Active {
+            index: 0,
+            base: None,
+            offset: 16
+        }
+    );
+    assert_eq!(data[0].data(), b"abc");
+    assert_eq!(
+        data[1].mode(),
+        SegmentMode::Active {
+            index: 0,
+            base: Some(0),
+            offset: 0
+        }
+    );
+    assert_eq!(data[1].data(), b"xyz");
+    assert_eq!(data[2].mode(), SegmentMode::Passive { index: 1 });
+    assert_eq!(data[2].data(), b"passive");
+
+    let elems = module.element_segments().collect::<Vec<_>>();
+    assert_eq!(elems.len(), 2);
+    assert_eq!(
+        elems[0].mode(),
+        SegmentMode::Active {
+            index: 0,
+            base: None,
+            offset: 1
+        }
+    );
+    assert_eq!(elems[0].functions().collect::<Vec<_>>(), [1, 0]);
+    assert_eq!(elems[1].mode(), SegmentMode::Passive { index: 1 });
+    assert_eq!(elems[1].functions().collect::<Vec<_>>(), [0]);
+    Ok(())
+}
+
+#[test]
+fn instantiate_on_many_threads() -> anyhow::Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (global $g (mut i32) (i32.const 0))
+                (func (export "bump") (result i32)
+                    global.get $g
+                    i32.const 1
+                    i32.add
+                    global.set $g
+                    global.get $g))
+        "#,
+    )?;
+
+    // Each thread gets its own `Store`, but they all share the engine and the
+    // compiled module, and instance state isn't shared between stores.
+    let threads = (0..4)
+        .map(|_| {
+            let module = module.clone();
+            std::thread::spawn(move || -> anyhow::Result<i32> {
+                let store = Store::new(module.engine());
+                let instance = Instance::new(&store, &module, &[])?;
+                let bump = instance.get_func("bump").unwrap().typed::<(), i32>()?;
+                bump.call(())?;
+                Ok(bump.call(())?)
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap()?, 2);
+    }
+    Ok(())
+}
+
+#[test]
+fn features_gate_validation() -> anyhow::Result<()> {
+    fn check(wat: &str, enable: impl Fn(&mut Config, bool)) -> anyhow::Result<()> {
+        let binary = wat::parse_str(wat)?;
+        // Start from everything disabled which doesn't need to be on for
+        // parsing, since some proposals imply others.
+        let mut config = Config::new();
+        config
+            .wasm_reference_types(false)
+            .wasm_bulk_memory(false)
+            .wasm_multi_value(false);
+        enable(&mut config, false);
+        assert!(Module::validate(&Engine::new(&config), &binary).is_err());
+        enable(&mut config, true);
+        Module::validate(&Engine::new(&config), &binary)?;
+        Ok(())
+    }
+
+    check(
+        "(module (func (result i32 i32) i32.const 0 i32.const 0))",
+        |c, e| {
+            c.wasm_multi_value(e);
+        },
+    )?;
+    check("(module (func (param externref)))", |c, e| {
+        c.wasm_reference_types(e);
+    })?;
+    check("(module (func (param v128)))", |c, e| {
+        c.wasm_simd(e);
+    })?;
+    check("(module (memory 1 1 shared))", |c, e| {
+        c.wasm_threads(e);
+    })?;
+    check("(module (memory 1) (memory 1))", |c, e| {
+        c.wasm_multi_memory(e);
+    })?;
+    check(
+        "(module (memory 1) (func i32.const 0 i32.const 0 i32.const 0 memory.fill))",
+        |c, e| {
+            c.wasm_bulk_memory(e);
+        },
+    )?;
+    Ok(())
+}
+
+#[test]
+fn compile_with_cranelift_flags() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config
+        .cranelift_opt_level(OptLevel::SpeedAndSize)
+        .cranelift_flag("regalloc", "backtracking_checked")?
+        .cranelift_flag("enable_jump_tables", "false")?;
+    assert!(config.cranelift_flag("regalloc", "bogus").is_err());
+    assert!(config.cranelift_flag("is_pic", "true").is_err());
+
+    let store = Store::new(&Engine::new(&config));
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (func (export "f") (param i32) (result i32)
+                    (block (block (block
+                        (br_table 0 1 2 (local.get 0)))
+                        (return (i32.const 10)))
+                        (return (i32.const 20)))
+                    i32.const 30))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let f = instance.get_func("f").unwrap().typed::<i32, i32>()?;
+    assert_eq!(f.call(0)?, 10);
+    assert_eq!(f.call(1)?, 20);
+    assert_eq!(f.call(5)?, 30);
+    Ok(())
+}
+
+#[test]
+fn nan_canonicalization() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.cranelift_nan_canonicalization(true);
+    let store = Store::new(&Engine::new(&config));
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (func (export "f32") (param f32 f32) (result i32)
+                    (i32.reinterpret_f32 (f32.div (local.get 0) (local.get 1))))
+                (func (export "f64") (param f64 f64) (result i64)
+                    (i64.reinterpret_f64 (f64.div (local.get 0) (local.get 1)))))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let div32 = instance
+        .get_func("f32")
+        .unwrap()
+        .typed::<(f32, f32), i32>()?;
+    let div64 = instance
+        .get_func("f64")
+        .unwrap()
+        .typed::<(f64, f64), i64>()?;
+
+    // Whatever NaN the hardware produces, the result is the same canonical
+    // quiet NaN on every platform.
+    assert_eq!(div32.call((0.0, 0.0))? as u32, 0x7fc0_0000);
+    assert_eq!(div32.call((-0.0, 0.0))? as u32, 0x7fc0_0000);
+    assert_eq!(div64.call((0.0, 0.0))? as u64, 0x7ff8_0000_0000_0000);
+    assert_eq!(div32.call((1.0, 2.0))?, 0.5f32.to_bits() as i32);
+    Ok(())
+}
+
+#[test]
+fn stats() -> anyhow::Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "" (func))
+                (func $a call $b call $b)
+                (func $b))
+        "#,
+    )?;
+    let stats = module.stats();
+    assert_eq!(stats.functions.len(), 2);
+    let (a, b) = (&stats.functions[0], &stats.functions[1]);
+    assert_eq!((a.index, b.index), (1, 2));
+    assert!(a.code_size > b.code_size);
+    assert_eq!(a.relocations, 2);
+    assert_eq!(b.relocations, 0);
+    assert!(a.compile_time.is_some());
+    assert!(stats.code_memory_size >= a.code_size + b.code_size);
+
+    // Compile times aren't serialized.
+    let module = Module::deserialize(&engine, &module.serialize()?)?;
+    let stats = module.stats();
+    assert_eq!(stats.functions[0].relocations, 2);
+    assert!(stats.functions[0].compile_time.is_none());
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "disas")]
+fn function_code() -> anyhow::Result<()> {
+    let module = Module::new(
+        &Engine::default(),
+        r#"
+            (module
+                (import "" "" (func))
+                (func (param i32) (result i32)
+                    local.get 0
+                    i32.const 1
+                    i32.add))
+        "#,
+    )?;
+    assert!(module.function_code(0).is_none());
+    assert!(module.function_code(2).is_none());
+
+    let code = module.function_code(1).unwrap();
+    assert!(!code.bytes().is_empty());
+    let map = code.address_map().collect::<Vec<_>>();
+    assert!(!map.is_empty());
+    for (offset, len, _) in map.iter() {
+        assert!(offset + len <= code.bytes().len());
+    }
+    assert!(code.disassemble()?.contains("ret"));
+    Ok(())
+}
+
+#[test]
+fn function_cache() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.wasm_bulk_memory(true).function_cache(true);
+    let engine = Engine::new(&config);
+    let wat = |pad: &str, result: i32| {
+        format!(
+            r#"
+                (module
+                    (func (export "pad") {})
+                    (func (export "f") (result i32) i32.const {})
+                    (func (export "trap") unreachable))
+            "#,
+            pad, result
+        )
+    };
+    let trap_offset = |module: &Module| -> anyhow::Result<usize> {
+        let store = Store::new(module.engine());
+        let instance = Instance::new(&store, module, &[])?;
+        let trap = instance
+            .get_func("trap")
+            .unwrap()
+            .call(&[])
+            .unwrap_err()
+            .downcast::<Trap>()?;
+        Ok(trap.trace()[0].module_offset())
+    };
+
+    // `trap` is reused by the second module even though the change to `pad`
+    // moved it, which has to be reflected in its trap information.
+    let first = Module::new(&engine, wat("", 1))?;
+    let second = Module::new(&engine, wat("nop nop nop", 2))?;
+    let store = Store::new(&engine);
+    let instance = Instance::new(&store, &second, &[])?;
+    let f = instance.get_func("f").unwrap().typed::<(), i32>()?;
+    assert_eq!(f.call(())?, 2);
+    assert_eq!(trap_offset(&second)?, trap_offset(&first)? + 3);
+    let uncached = Module::new(&Engine::default(), wat("nop nop nop", 2))?;
+    assert_eq!(trap_offset(&second)?, trap_offset(&uncached)?);
+
+    // Reused functions are still validated against their new module.
+    Module::new(&engine, "(module (func data.drop 0) (data \"\"))")?;
+    assert!(Module::new(&engine, "(module (func data.drop 0))").is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn code_is_never_writable_and_executable() -> anyhow::Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"(module (import "" "" (func $f)) (func (export "run") call $f))"#,
+    )?;
+    let ty = FuncType::new(Box::new([]), Box::new([]));
+    let f = Func::new(&store, ty, |_, _, _| Ok(()));
+    let instance = Instance::new(&store, &module, &[f.into()])?;
+    instance.get_func("run").unwrap().call(&[])?;
+
+    let maps = std::fs::read_to_string("/proc/self/maps")?;
+    for line in maps.lines() {
+        let perms = line.split_whitespace().nth(1).unwrap();
+        assert!(!perms.starts_with("rwx"), "writable code: {}", line);
+    }
+    Ok(())
+}
@@ -42,7 +42,258 @@ fn test_module_serialize_fail() -> Result<()> {
     let store = Store::new(&Engine::new(&config));
     match deserialize_and_instantiate(&store, &buffer) {
         Ok(_) => bail!("expected failure at deserialization"),
-        Err(_) => (),
+        Err(e) => assert!(
+            e.to_string()
+                .contains("compiled with Cranelift setting `opt_level`"),
+            "bad error: {}",
+            e
+        ),
     }
     Ok(())
 }
+
+#[test]
+fn deserialize_rejects_garbage() -> Result<()> {
+    let engine = Engine::default();
+    let err = Module::deserialize(&engine, b"(module)").unwrap_err();
+    assert!(err.to_string().contains("not a serialized Wasmtime module"));
+
+    let buffer = serialize(&engine, "(module)")?;
+    assert!(Module::deserialize(&engine, &buffer[..buffer.len() / 2]).is_err());
+    Ok(())
+}
+
+#[test]
+fn deserialize_checks_wasm_features() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_simd(true);
+    let buffer = serialize(&Engine::new(&config), "(module)")?;
+
+    let err = Module::deserialize(&Engine::default(), &buffer).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("compiled with wasm feature `simd` set to true"),
+        "bad error: {}",
+        err
+    );
+    Module::deserialize(&Engine::new(&config), &buffer)?;
+    Ok(())
+}
+
+#[test]
+fn precompile_for_host_target() -> Result<()> {
+    let mut config = Config::new();
+    config.target(&target_lexicon::HOST.to_string())?;
+    let engine = Engine::new(&config);
+    let buffer =
+        engine.precompile_module(br#"(module (func (export "run") (result i32) i32.const 42))"#)?;
+
+    // Loadable by an engine with the same settings compiling for the host.
+    let store = Store::default();
+    let instance = deserialize_and_instantiate(&store, &buffer)?;
+    let run = instance.get_func("run").unwrap().typed::<(), i32>()?;
+    assert_eq!(run.call(())?, 42);
+    Ok(())
+}
+
+#[test]
+fn precompile_for_foreign_target() -> Result<()> {
+    let foreign = if cfg!(target_arch = "aarch64") {
+        "x86_64-unknown-linux-gnu"
+    } else {
+        "aarch64-unknown-linux-gnu"
+    };
+    let mut config = Config::new();
+    assert!(config.target("not-a-target").is_err());
+    if config.target(foreign).is_err() {
+        // Support for other architectures isn't compiled in.
+        return Ok(());
+    }
+    let engine = Engine::new(&config);
+    let wat = "(module (func (export \"run\") (result i32) i32.const 42))";
+
+    let err = Module::new(&engine, wat).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("cannot load code compiled for target"));
+    let buffer = engine.precompile_module(wat.as_bytes())?;
+    assert!(Module::deserialize(&engine, &buffer).is_err());
+    assert!(Module::deserialize(&Engine::default(), &buffer).is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn deserialize_file_shares_code_between_engines() -> Result<()> {
+    let wat = r#"
+        (module
+            (func (export "run") (result i32) i32.const 42)
+            (func (export "trap") unreachable))
+    "#;
+    let file = tempfile::NamedTempFile::new()?;
+    std::fs::write(file.path(), serialize(&Engine::default(), wat)?)?;
+
+    // Keep the first module alive so that the second engine reuses it.
+    let mut modules = Vec::new();
+    for _ in 0..2 {
+        let store = Store::new(&Engine::default());
+        let module = unsafe { Module::deserialize_file(store.engine(), file.path())? };
+        let instance = Instance::new(&store, &module, &[])?;
+        let run = instance.get_func("run").unwrap().typed::<(), i32>()?;
+        assert_eq!(run.call(())?, 42);
+
+        // Traps are symbolicated with the shared module's frame information.
+        let trap = instance
+            .get_func("trap")
+            .unwrap()
+            .typed::<(), ()>()?
+            .call(())
+            .unwrap_err();
+        assert_eq!(trap.trace().len(), 1);
+        assert_eq!(trap.trace()[0].func_index(), 1);
+
+        // The mapped module can be serialized again.
+        assert_eq!(module.serialize()?, std::fs::read(file.path())?);
+        modules.push(module);
+    }
+    drop(modules);
+
+    let mut config = Config::new();
+    config.cranelift_opt_level(OptLevel::None);
+    let err = unsafe { Module::deserialize_file(&Engine::new(&config), file.path()) }.unwrap_err();
+    assert!(
+        err.to_string().contains("`opt_level`"),
+        "bad error: {}",
+        err
+    );
+
+    std::fs::write(file.path(), b"(module)")?;
+    let err = unsafe { Module::deserialize_file(&Engine::default(), file.path()) }.unwrap_err();
+    assert!(err.to_string().contains("not a serialized Wasmtime module"));
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn deserialize_file_checks_shared_memories() -> Result<()> {
+    // Engines with explicit bounds checks can't run shared memories, but
+    // otherwise compile code just like engines without guard regions do.
+    let config = |explicit_bounds_checks| {
+        let mut config = Config::new();
+        config
+            .wasm_threads(true)
+            .explicit_bounds_checks(explicit_bounds_checks)
+            .static_memory_maximum_size(0)
+            .dynamic_memory_guard_size(0)
+            .static_memory_guard_size(0);
+        Engine::new(&config)
+    };
+    let file = tempfile::NamedTempFile::new()?;
+    let serialized = config(true).precompile_module(b"(module (memory 1 1 shared))")?;
+    std::fs::write(file.path(), serialized)?;
+
+    let err = unsafe { Module::deserialize_file(&config(true), file.path()) }.unwrap_err();
+    assert!(
+        err.to_string().contains("explicit_bounds_checks"),
+        "bad error: {}",
+        err
+    );
+
+    // The module loaded by another engine is shared, but still rejected.
+    let module = unsafe { Module::deserialize_file(&config(false), file.path())? };
+    let err = unsafe { Module::deserialize_file(&config(true), file.path()) }.unwrap_err();
+    assert!(
+        err.to_string().contains("explicit_bounds_checks"),
+        "bad error: {}",
+        err
+    );
+    drop(module);
+    Ok(())
+}
+
+#[test]
+fn serialization_is_deterministic() -> Result<()> {
+    let mut wat = String::from("(module $m (memory 1) (table 1 funcref)\n");
+    for i in 0..100 {
+        wat.push_str(&format!(
+            "(func $f{0} (export \"f{0}\") (result i32) i32.const {0})\n\
+             (data \"{0}\")\n\
+             (elem func $f{0})\n",
+            i
+        ));
+    }
+    wat.push_str(")");
+
+    let mut config = Config::new();
+    config.wasm_bulk_memory(true);
+    let first = Engine::new(&config).precompile_module(wat.as_bytes())?;
+    let second = Engine::new(&config).precompile_module(wat.as_bytes())?;
+    assert!(first == second);
+
+    let module = Module::new(&Engine::new(&config), &wat)?;
+    assert!(module.serialize()? == first);
+    Ok(())
+}
+
+#[test]
+fn serialize_stripped_and_compressed() -> Result<()> {
+    let mut wat = String::from("(module $m\n");
+    for i in 0..100 {
+        wat.push_str(&format!(
+            "(func $f{0} (export \"f{0}\") (result i32) i32.const {0})\n",
+            i
+        ));
+    }
+    wat.push_str(")");
+
+    let engine = Engine::default();
+    let module = Module::new(&engine, &wat)?;
+    let full = module.serialize()?;
+    let stripped = module.serialize_with(SerializeOptions::new().strip_names(true))?;
+    let compressed = module.serialize_with(SerializeOptions::new().compress(true))?;
+    assert!(stripped.len() < full.len());
+    assert!(compressed.len() < full.len());
+
+    let module = Module::deserialize(&engine, &stripped)?;
+    assert_eq!(module.name(), None);
+    assert_eq!(module.func_name(7), None);
+
+    let module = Module::deserialize(&engine, &compressed)?;
+    assert_eq!(module.name(), Some("m"));
+    assert_eq!(module.func_name(7), Some("f7"));
+    let store = Store::new(&engine);
+    let instance = Instance::new(&store, &module, &[])?;
+    let f7 = instance.get_func("f7").unwrap().typed::<(), i32>()?;
+    assert_eq!(f7.call(())?, 7);
+
+    // Compressed modules are serialized again uncompressed.
+    assert!(module.serialize()? == full);
+
+    #[cfg(unix)]
+    {
+        let file = tempfile::NamedTempFile::new()?;
+        std::fs::write(file.path(), &compressed)?;
+        let module = unsafe { Module::deserialize_file(&engine, file.path())? };
+        assert_eq!(module.func_name(7), Some("f7"));
+    }
+    Ok(())
+}
+
+#[test]
+fn serialize_without_debug_info() -> Result<()> {
+    let wat = "(module (func (export \"run\") (result i32) i32.const 42))";
+    let mut config = Config::new();
+    config.debug_info(true);
+    let module = Module::new(&Engine::new(&config), wat)?;
+    let full = module.serialize()?;
+    let stripped = module.serialize_with(SerializeOptions::new().strip_debug_info(true))?;
+    assert!(stripped.len() < full.len());
+
+    // Only an engine without debug info can load the stripped module.
+    assert!(Module::deserialize(&Engine::default(), &full).is_err());
+    let store = Store::default();
+    let instance = deserialize_and_instantiate(&store, &stripped)?;
+    let run = instance.get_func("run").unwrap().typed::<(), i32>()?;
+    assert_eq!(run.call(())?, 42);
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use wasmtime::*;
+
+fn pooling_engine(max_instances: u32, max_tables: u32, max_memories: u32) -> Engine {
+    let mut config = Config::new();
+    config
+        .static_memory_maximum_size(1 << 20)
+        .allocation_strategy(InstanceAllocationStrategy::Pooling {
+            max_instances,
+            max_instance_size: 1 << 16,
+            max_tables,
+            max_table_elements: 100,
+            max_memories,
+        });
+    Engine::new(&config)
+}
+
+#[test]
+fn memories_are_reused_and_zeroed() -> anyhow::Result<()> {
+    let engine = pooling_engine(10, 10, 1);
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m") 1 16)
+                (func (export "load") (result i32)
+                    i32.const 0
+                    i32.load)
+                (func (export "store")
+                    i32.const 0
+                    i32.const 42
+                    i32.store))
+        "#,
+    )?;
+
+    // With a single slot every instantiation reuses the same memory, which
+    // must still look freshly allocated.
+    for _ in 0..3 {
+        let store = Store::new(&engine);
+        let instance = Instance::new(&store, &module, &[])?;
+        let load = instance.get_func("load").unwrap().typed::<(), i32>()?;
+        let store_fn = instance.get_func("store").unwrap().typed::<(), ()>()?;
+        assert_eq!(load.call(())?, 0);
+        store_fn.call(())?;
+        assert_eq!(load.call(())?, 42);
+
+        let memory = instance.get_memory("m").unwrap();
+        assert_eq!(memory.grow(2)?, 1);
+    }
+    Ok(())
+}
+
+#[test]
+fn limited_number_of_memories() -> anyhow::Result<()> {
+    let engine = pooling_engine(10, 10, 2);
+    let module = Module::new(&engine, "(module (memory 1))")?;
+    let store = Store::new(&engine);
+    let a = Instance::new(&store, &module, &[])?;
+    let _b = Instance::new(&store, &module, &[])?;
+    let err = Instance::new(&store, &module, &[]).unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("pooled memory slots are in use"),
+        "{:?}",
+        err
+    );
+
+    // Host-created memories come out of the same pool.
+    assert!(Memory::new(&store, MemoryType::new(Limits::new(1, None))).is_err());
+
+    // Slots are only returned once the store holding on to them goes away.
+    drop((a, store));
+    let store = Store::new(&engine);
+    Instance::new(&store, &module, &[])?;
+    Ok(())
+}
+
+#[test]
+fn memories_cannot_outgrow_slots() -> anyhow::Result<()> {
+    let engine = pooling_engine(10, 10, 2);
+    let store = Store::new(&engine);
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m") 1)
+                (func (export "grow") (param i32) (result i32)
+                    local.get 0
+                    memory.grow))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let grow = instance.get_func("grow").unwrap().typed::<i32, i32>()?;
+    assert_eq!(grow.call(15)?, 1);
+    assert_eq!(grow.call(1)?, -1);
+
+    let module = Module::new(&engine, "(module (memory 17))")?;
+    assert!(Instance::new(&Store::new(&engine), &module, &[]).is_err());
+    Ok(())
+}
+
+#[test]
+fn limited_number_of_instances() -> anyhow::Result<()> {
+    let engine = pooling_engine(2, 10, 10);
+    let module = Module::new(&engine, "(module)")?;
+    let store = Store::new(&engine);
+    Instance::new(&store, &module, &[])?;
+    Instance::new(&store, &module, &[])?;
+    let err = Instance::new(&store, &module, &[]).unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("pooled instance slots are in use"),
+        "{:?}",
+        err
+    );
+
+    // Host-defined functions aren't allocated from the pool.
+    Func::wrap(&store, || {});
+
+    drop(store);
+    let store = Store::new(&engine);
+    Instance::new(&store, &module, &[])?;
+    Ok(())
+}
+
+#[test]
+fn instances_cannot_outgrow_slots() -> anyhow::Result<()> {
+    let engine = pooling_engine(10, 10, 10);
+    let globals = "(global i64 (i64.const 0))".repeat(10_000);
+    let module = Module::new(&engine, &format!("(module {})", globals))?;
+    let err = Instance::new(&Store::new(&engine), &module, &[]).unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("doesn't fit in a pooled instance slot"),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn tables_are_reused_and_cleared() -> anyhow::Result<()> {
+    let engine = pooling_engine(10, 1, 10);
+    let module = Module::new(&engine, "(module (table (export \"t\") 1 funcref))")?;
+
+    // With a single slot every instantiation reuses the same table, which
+    // must still look freshly allocated.
+    for _ in 0..3 {
+        let store = Store::new(&engine);
+        let instance = Instance::new(&store, &module, &[])?;
+        let table = instance.get_table("t").unwrap();
+        assert_eq!(table.size(), 1);
+        assert!(table.get(0).unwrap().unwrap_funcref().is_none());
+
+        let func = Func::wrap(&store, || {});
+        table.set(0, Val::FuncRef(Some(func.clone())))?;
+        assert_eq!(table.grow(9, Val::FuncRef(Some(func)))?, 1);
+    }
+    Ok(())
+}
+
+#[test]
+fn limited_number_of_tables() -> anyhow::Result<()> {
+    let engine = pooling_engine(10, 2, 10);
+    let module = Module::new(&engine, "(module (table 1 funcref))")?;
+    let store = Store::new(&engine);
+    Instance::new(&store, &module, &[])?;
+    Instance::new(&store, &module, &[])?;
+    let err = Instance::new(&store, &module, &[]).unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("pooled tables are in use"),
+        "{:?}",
+        err
+    );
+
+    // Host-created tables aren't allocated from the pool.
+    let ty = TableType::new(ValType::FuncRef, Limits::new(1, None));
+    Table::new(&store, ty, Val::FuncRef(None))?;
+
+    drop(store);
+    let store = Store::new(&engine);
+    Instance::new(&store, &module, &[])?;
+    Ok(())
+}
+
+#[test]
+fn tables_cannot_outgrow_slots() -> anyhow::Result<()> {
+    let engine = pooling_engine(10, 10, 10);
+    let store = Store::new(&engine);
+    let module = Module::new(&engine, "(module (table (export \"t\") 1 funcref))")?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let table = instance.get_table("t").unwrap();
+    assert_eq!(table.grow(99, Val::FuncRef(None))?, 1);
+    assert!(table.grow(1, Val::FuncRef(None)).is_err());
+
+    let module = Module::new(&engine, "(module (table 101 funcref))")?;
+    assert!(Instance::new(&store, &module, &[]).is_err());
+    Ok(())
+}
@@ -58,3 +58,39 @@ fn host_always_has_some_stack() -> anyhow::Result<()> {
         consume_some_stack(space.as_mut_ptr() as usize, stack.saturating_sub(1024))
     }
 }
+
+#[test]
+fn max_wasm_stack_bounds_recursion() -> anyhow::Result<()> {
+    // Returns how deep `$recursive` got before the stack was exhausted.
+    fn max_depth(max_wasm_stack: usize) -> anyhow::Result<i32> {
+        let mut config = Config::new();
+        config.max_wasm_stack(max_wasm_stack);
+        let store = Store::new(&Engine::new(&config));
+        let module = Module::new(
+            store.engine(),
+            r#"
+                (module
+                    (global $depth (export "depth") (mut i32) (i32.const 0))
+                    (func $recursive (export "foo")
+                        (global.set $depth (i32.add (global.get $depth) (i32.const 1)))
+                        call $recursive)
+                )
+            "#,
+        )?;
+        let instance = Instance::new(&store, &module, &[])?;
+        let foo = instance.get_func("foo").unwrap().typed::<(), ()>()?;
+        let trap = foo.call(()).unwrap_err();
+        assert!(
+            trap.to_string().contains("call stack exhausted"),
+            "{}",
+            trap
+        );
+        Ok(instance.get_global("depth").unwrap().get().unwrap_i32())
+    }
+
+    let small = max_depth(64 * 1024)?;
+    let large = max_depth(256 * 1024)?;
+    assert!(small > 0);
+    assert!(large > 2 * small, "{} vs {}", large, small);
+    Ok(())
+}
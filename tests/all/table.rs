@@ -11,3 +11,117 @@ fn get_none() {
     }
     assert!(table.get(1).is_none());
 }
+
+#[test]
+fn copy_out_of_bounds() -> anyhow::Result<()> {
+    let store = Store::default();
+    let ty = TableType::new(ValType::FuncRef, Limits::new(10, None));
+    let a = Table::new(&store, ty.clone(), Val::FuncRef(None))?;
+    let b = Table::new(&store, ty, Val::FuncRef(Some(Func::wrap(&store, || {}))))?;
+
+    Table::copy(&a, 2, &b, 0, 3)?;
+    assert!(a.get(1).unwrap().unwrap_funcref().is_none());
+    for i in 2..5 {
+        assert!(a.get(i).unwrap().unwrap_funcref().is_some());
+    }
+    assert!(a.get(5).unwrap().unwrap_funcref().is_none());
+
+    assert!(Table::copy(&a, 8, &b, 0, 3).is_err());
+    assert!(Table::copy(&a, 0, &b, 8, 3).is_err());
+    assert!(Table::copy(&a, 0, &b, u32::max_value(), 2).is_err());
+    assert!(a.fill(8, Val::FuncRef(None), 3).is_err());
+    Ok(())
+}
+
+#[test]
+fn init_from_passive_segment() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.wasm_bulk_memory(true);
+    let engine = Engine::new(&config);
+    let store = Store::new(&engine);
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (func $a (result i32) i32.const 1)
+                (func $b (result i32) i32.const 2)
+                (elem func $a $b)
+                (func (export "drop") elem.drop 0))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let ty = TableType::new(ValType::FuncRef, Limits::new(4, None));
+    let table = Table::new(&store, ty, Val::FuncRef(None))?;
+
+    table.init(1, &instance, 0, 0, 2)?;
+    assert!(table.get(0).unwrap().unwrap_funcref().is_none());
+    for (i, expected) in (1..3).zip(1..) {
+        let f = table.get(i).unwrap().unwrap_funcref().unwrap().clone();
+        assert_eq!(f.typed::<(), i32>()?.call(())?, expected);
+    }
+    assert!(table.get(3).unwrap().unwrap_funcref().is_none());
+
+    // Out of bounds of either the table or the segment.
+    assert!(table.init(3, &instance, 0, 0, 2).is_err());
+    assert!(table.init(0, &instance, 0, 1, 2).is_err());
+
+    // Dropped segments behave as if they were empty.
+    instance.get_func("drop").unwrap().typed::<(), ()>()?.call(())?;
+    table.init(0, &instance, 0, 0, 0)?;
+    assert!(table.init(0, &instance, 0, 0, 1).is_err());
+
+    let ty = TableType::new(ValType::ExternRef, Limits::new(4, None));
+    let table = Table::new(&store, ty, Val::ExternRef(None))?;
+    assert!(table.init(0, &instance, 0, 0, 0).is_err());
+    Ok(())
+}
+
+#[test]
+fn grow_returns_previous_size() -> anyhow::Result<()> {
+    let store = Store::default();
+    let ty = TableType::new(ValType::FuncRef, Limits::new(1, Some(3)));
+    let table = Table::new(&store, ty, Val::FuncRef(None))?;
+    assert_eq!(table.grow(0, Val::FuncRef(None))?, 1);
+    assert_eq!(table.grow(2, Val::FuncRef(None))?, 1);
+    assert_eq!(table.size(), 3);
+
+    let err = table.grow(1, Val::FuncRef(None)).unwrap_err();
+    assert!(err.to_string().contains("maximum of 3 elements exceeded"));
+    let err = table
+        .grow(u32::max_value(), Val::FuncRef(None))
+        .unwrap_err();
+    assert!(err.to_string().contains("overflow"));
+    let err = table.grow(0, Val::ExternRef(None)).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("does not match table element type"));
+    assert_eq!(table.size(), 3);
+    Ok(())
+}
+
+#[test]
+fn new_fills_with_init() -> anyhow::Result<()> {
+    let store = Store::default();
+    let f = Func::wrap(&store, || 7);
+    let ty = TableType::new(ValType::FuncRef, Limits::new(3, None));
+    let table = Table::new(&store, ty, Val::FuncRef(Some(f)))?;
+    for i in 0..3 {
+        let f = table.get(i).unwrap().unwrap_funcref().unwrap().clone();
+        assert_eq!(f.typed::<(), i32>()?.call(())?, 7);
+    }
+
+    let r = ExternRef::new("hello");
+    let ty = TableType::new(ValType::ExternRef, Limits::new(2, None));
+    let table = Table::new(&store, ty, Val::ExternRef(Some(r.clone())))?;
+    for i in 0..2 {
+        assert!(table.get(i).unwrap().unwrap_externref().unwrap().ptr_eq(&r));
+    }
+    assert_eq!(r.strong_count(), 3);
+
+    let ty = TableType::new(ValType::FuncRef, Limits::new(1, None));
+    let err = Table::new(&store, ty, Val::ExternRef(None)).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("does not match table element type"));
+    Ok(())
+}
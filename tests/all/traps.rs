@@ -143,6 +143,40 @@ fn test_trap_stack_overflow() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1642)
+#[cfg_attr(all(target_os = "windows", feature = "experimental_x64"), ignore)] // FIXME(#2079)
+fn stack_overflow_on_small_host_stack() -> Result<()> {
+    // The thread's stack is smaller than the default `max_wasm_stack`, so wasm
+    // has to trap before it reaches the end of the stack rather than at the
+    // configured limit.
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (func $run (export "run") (param i64 i64 i64 i64)
+                    (call $run (local.get 0) (local.get 1) (local.get 2) (local.get 3))))
+        "#,
+    )?;
+    let codes = std::thread::Builder::new()
+        .stack_size(512 * 1024)
+        .spawn(move || -> Result<_> {
+            let store = Store::new(&engine);
+            let instance = Instance::new(&store, &module, &[])?;
+            let run = instance.get_typed_func::<(i64, i64, i64, i64), ()>("run")?;
+            let mut codes = Vec::new();
+            for _ in 0..2 {
+                codes.push(run.call((1, 2, 3, 4)).unwrap_err().code());
+            }
+            Ok(codes)
+        })?
+        .join()
+        .unwrap()?;
+    assert_eq!(codes, [Some(TrapCode::StackOverflow); 2]);
+    Ok(())
+}
+
 #[test]
 #[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1642)
 #[cfg_attr(all(target_os = "windows", feature = "experimental_x64"), ignore)] // FIXME(#2079)
@@ -243,7 +277,8 @@ fn trap_start_function_import() -> Result<()> {
         .err()
         .unwrap();
     assert!(err
-        .downcast_ref::<Trap>()
+        .downcast_ref::<InstantiationError>()
+        .and_then(InstantiationError::trap)
         .unwrap()
         .to_string()
         .contains("user trap"));
@@ -349,17 +384,27 @@ fn mismatched_arguments() -> Result<()> {
     let func = instance.get_func("foo").unwrap();
     assert_eq!(
         func.call(&[]).unwrap_err().to_string(),
-        "expected 1 arguments, got 0"
+        "expected 1 arguments, got 0 \
+         (function type is `(func (param i32))` but was called with `()`)"
     );
     assert_eq!(
         func.call(&[Val::F32(0)]).unwrap_err().to_string(),
-        "argument type mismatch: found f32 but expected i32",
+        "argument type mismatch: found f32 but expected i32 \
+         (function type is `(func (param i32))` but was called with `(f32)`)",
     );
     assert_eq!(
         func.call(&[Val::I32(0), Val::I32(1)])
             .unwrap_err()
             .to_string(),
-        "expected 1 arguments, got 2"
+        "expected 1 arguments, got 2 \
+         (function type is `(func (param i32))` but was called with `(i32 i32)`)"
+    );
+    assert_eq!(
+        func.call_into(&[Val::I32(0)], &mut [Val::I32(0)])
+            .unwrap_err()
+            .to_string(),
+        "expected 0 results, got 1 \
+         (function type is `(func (param i32))` but room for 1 results was provided)"
     );
     Ok(())
 }
@@ -388,9 +433,11 @@ fn call_signature_mismatch() -> Result<()> {
     let err = Instance::new(&store, &module, &[])
         .err()
         .unwrap()
-        .downcast::<Trap>()
+        .downcast::<InstantiationError>()
         .unwrap();
     assert!(err
+        .trap()
+        .unwrap()
         .to_string()
         .contains("wasm trap: indirect call type mismatch"));
     Ok(())
@@ -414,7 +461,10 @@ fn start_trap_pretty() -> Result<()> {
     let module = Module::new(store.engine(), wat)?;
     let e = match Instance::new(&store, &module, &[]) {
         Ok(_) => panic!("expected failure"),
-        Err(e) => e.downcast::<Trap>()?,
+        Err(e) => match e.downcast::<InstantiationError>()? {
+            InstantiationError::Trap(trap) => trap,
+            e => panic!("expected a trap: {}", e),
+        },
     };
 
     assert_eq!(
@@ -452,3 +502,372 @@ fn present_after_module_drop() -> Result<()> {
         assert_eq!(t.trace()[0].func_index(), 0);
     }
 }
+
+#[test]
+fn explicit_bounds_checks() -> Result<()> {
+    let mut config = Config::new();
+    config.explicit_bounds_checks(true);
+    let engine = Engine::new(&config);
+    let store = Store::new(&engine);
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m") 1 3)
+                (func (export "load") (param i32) (result i32)
+                    (i32.load offset=4 (local.get 0))))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let load = instance.get_func("load").unwrap().typed::<i32, i32>()?;
+    let memory = instance.get_memory("m").unwrap();
+
+    // Accesses just past the end of memory and far beyond it all trap, not
+    // just those a guard region would have caught.
+    assert_eq!(load.call(65528)?, 0);
+    for addr in [65529, 65536, -4].iter() {
+        let trap = load.call(*addr).unwrap_err();
+        assert!(
+            trap.to_string().contains("out of bounds memory access"),
+            "bad trap: {}",
+            trap
+        );
+        assert_eq!(trap.memory_access(), None);
+    }
+
+    memory.grow(1)?;
+    assert_eq!(load.call(65529)?, 0);
+    assert!(load.call(2 * 65536 - 7).is_err());
+
+    // Shared memories and memory protection rely on memory faults.
+    let mut config = Config::new();
+    config.explicit_bounds_checks(true).wasm_threads(true);
+    let engine = Engine::new(&config);
+    let store = Store::new(&engine);
+    assert!(Module::new(&engine, "(module (memory 1 1 shared))").is_err());
+    assert!(Module::new(&engine, r#"(module (import "" "" (memory 1 1 shared)))"#).is_err());
+    assert!(Memory::new(&store, MemoryType::shared(1, 1)).is_err());
+    assert!(SharedMemory::new(&engine, MemoryType::shared(1, 1)).is_err());
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(1, Some(1))))?;
+    assert!(unsafe { memory.protect(0..65536, MemoryProtection::NoAccess) }.is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1642)
+#[cfg_attr(all(target_os = "windows", feature = "experimental_x64"), ignore)] // FIXME(#2079)
+fn memory_access_details() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory (export "m") 1 3)
+                (func (export "load") (param i32) (result i32)
+                    (i32.load offset=4 (local.get 0))))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let load = instance.get_typed_func::<i32, i32>("load")?;
+    let memory = instance.get_memory("m").unwrap();
+    let access = |addr: i32| {
+        let access = load.call(addr).unwrap_err().memory_access().unwrap();
+        (access.address(), access.memory_size())
+    };
+
+    assert_eq!(access(65532), (65536, 65536));
+    assert_eq!(access(0x123450), (0x123454, 65536));
+
+    memory.grow(1)?;
+    assert_eq!(access(2 * 65536 - 4), (2 * 65536, 2 * 65536));
+
+    let trap = load.call(2 * 65536).unwrap_err();
+    assert!(
+        trap.to_string()
+            .contains("accessed address 0x20004 of a memory of 0x20000 bytes"),
+        "bad trap: {}",
+        trap
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1642)
+#[cfg_attr(all(target_os = "windows", feature = "experimental_x64"), ignore)] // FIXME(#2079)
+fn strip_names() -> Result<()> {
+    let wat = r#"
+        (module $hello_mod
+            (func $hello (export "run") (unreachable))
+        )
+    "#;
+    let mut config = Config::new();
+    config.strip_names(true);
+    let store = Store::new(&Engine::new(&config));
+    let module = Module::new(store.engine(), wat)?;
+    assert_eq!(module.name(), None);
+    assert_eq!(module.func_name(0), None);
+
+    let instance = Instance::new(&store, &module, &[])?;
+    let trap = instance
+        .get_func("run")
+        .unwrap()
+        .typed::<(), ()>()?
+        .call(())
+        .unwrap_err();
+    assert_eq!(trap.trace()[0].module_name(), None);
+    assert_eq!(trap.trace()[0].func_name(), None);
+    assert!(!trap.to_string().contains("hello"), "{}", trap);
+    Ok(())
+}
+
+#[test]
+fn wasm_backtrace_disabled() -> Result<()> {
+    let wat = r#"
+        (module
+            (import "" "" (func $host))
+            (func $hello (export "run") (unreachable))
+            (func (export "host") (call $host))
+        )
+    "#;
+    let mut config = Config::new();
+    config.wasm_backtrace(false).coredump_on_trap(true);
+    let store = Store::new(&Engine::new(&config));
+    let module = Module::new(store.engine(), wat)?;
+    let host = Func::wrap(&store, || -> Result<(), Trap> { Err(Trap::new("host")) });
+    let instance = Instance::new(&store, &module, &[host.into()])?;
+
+    let trap = instance
+        .get_typed_func::<(), ()>("run")?
+        .call(())
+        .unwrap_err();
+    assert_eq!(trap.code(), Some(TrapCode::Unreachable));
+    assert!(trap.trace().is_empty());
+    assert!(trap.coredump().is_none());
+
+    let trap = instance
+        .get_typed_func::<(), ()>("host")?
+        .call(())
+        .unwrap_err();
+    assert!(trap.to_string().contains("host"), "{}", trap);
+    assert!(trap.trace().is_empty());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1642)
+#[cfg_attr(all(target_os = "windows", feature = "experimental_x64"), ignore)] // FIXME(#2079)
+fn trap_source_locations() -> Result<()> {
+    let wasm = std::fs::read("tests/all/debug/testsuite/fib-wasm.wasm")?;
+    let trap = |debug_info: bool| -> Result<Trap> {
+        let mut config = Config::new();
+        config.debug_info(debug_info).consume_fuel(true);
+        let store = Store::new(&Engine::new(&config));
+        let module = Module::new(store.engine(), &wasm)?;
+        let instance = Instance::new(&store, &module, &[])?;
+        let fib = instance.get_typed_func::<i32, i32>("fib")?;
+        // Run out of fuel somewhere in the middle of the loop.
+        store.add_fuel(100)?;
+        Ok(fib.call(1_000_000).unwrap_err())
+    };
+
+    let with_debug_info = trap(true)?;
+    let (file, line, _) = with_debug_info.trace()[0].source_location().unwrap();
+    assert!(file.ends_with("fib-wasm.c"), "bad file: {}", file);
+    assert!((8..=16).contains(&line), "bad line: {}", line);
+    assert!(
+        with_debug_info
+            .to_string()
+            .contains(&format!("at {}:{}", file, line)),
+        "bad trap: {}",
+        with_debug_info
+    );
+
+    let without = trap(false)?;
+    assert_eq!(without.trace()[0].source_location(), None);
+    Ok(())
+}
+
+#[test]
+fn trap_codes() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory 1)
+                (table 1 funcref)
+                (type $t (func))
+                (func $i64 (param i64))
+                (elem (i32.const 0) $i64)
+                (func (export "unreachable") unreachable)
+                (func (export "load") i32.const 65536 i32.load drop)
+                (func (export "div") i32.const 1 i32.const 0 i32.div_s drop)
+                (func (export "overflow") i32.const 0x80000000 i32.const -1 i32.div_s drop)
+                (func (export "trunc") f32.const nan i32.trunc_f32_s drop)
+                (func (export "table") i32.const 1 call_indirect (type $t))
+                (func (export "signature") i32.const 0 call_indirect (type $t))
+                (func $recurse (export "recurse") call $recurse))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let code = |name: &str| -> Result<Option<TrapCode>> {
+        let trap = instance
+            .get_typed_func::<(), ()>(name)?
+            .call(())
+            .unwrap_err();
+        Ok(trap.code())
+    };
+    assert_eq!(code("unreachable")?, Some(TrapCode::Unreachable));
+    assert_eq!(code("load")?, Some(TrapCode::MemoryOutOfBounds));
+    assert_eq!(code("div")?, Some(TrapCode::IntegerDivideByZero));
+    assert_eq!(code("overflow")?, Some(TrapCode::IntegerOverflow));
+    assert_eq!(code("trunc")?, Some(TrapCode::BadConversionToInteger));
+    assert_eq!(code("table")?, Some(TrapCode::TableOutOfBounds));
+    assert_eq!(code("signature")?, Some(TrapCode::IndirectCallTypeMismatch));
+    assert_eq!(code("recurse")?, Some(TrapCode::StackOverflow));
+
+    assert_eq!(Trap::new("host").code(), None);
+    assert_eq!(Trap::i32_exit(1).code(), None);
+    Ok(())
+}
+
+#[test]
+fn host_errors_are_preserved() -> Result<()> {
+    #[derive(Debug)]
+    struct MyError(u32);
+
+    impl std::fmt::Display for MyError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "my error {}", self.0)
+        }
+    }
+
+    impl std::error::Error for MyError {}
+
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "boxed" (func $boxed))
+                (import "" "anyhow" (func $anyhow))
+                (func (export "boxed") call $boxed)
+                (func (export "anyhow") call $anyhow))
+        "#,
+    )?;
+    let ty = FuncType::new(Box::new([]), Box::new([]));
+    let boxed = Func::new(&store, ty, |_, _, _| {
+        Err(Trap::from(
+            Box::new(MyError(1)) as Box<dyn std::error::Error + Send + Sync>
+        ))
+    });
+    let anyhow = Func::wrap(&store, || -> Result<(), Trap> {
+        Err(anyhow::Error::new(MyError(2))
+            .context("while doing something")
+            .into())
+    });
+    let instance = Instance::new(&store, &module, &[boxed.into(), anyhow.into()])?;
+
+    let err = instance.get_func("boxed").unwrap().call(&[]).unwrap_err();
+    let trap = err.downcast_ref::<Trap>().unwrap();
+    assert_eq!(trap.downcast_ref::<MyError>().unwrap().0, 1);
+    assert!(
+        trap.to_string().contains("my error 1"),
+        "bad error: {}",
+        trap
+    );
+    assert!(trap.code().is_none());
+
+    let trap = instance
+        .get_typed_func::<(), ()>("anyhow")?
+        .call(())
+        .unwrap_err();
+    assert_eq!(trap.downcast_ref::<MyError>().unwrap().0, 2);
+    assert!(
+        trap.to_string().contains("while doing something"),
+        "bad error: {}",
+        trap
+    );
+    assert!(trap.downcast_ref::<std::io::Error>().is_none());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1642)
+#[cfg_attr(all(target_os = "windows", feature = "experimental_x64"), ignore)] // FIXME(#2079)
+fn rust_panic_in_libcall() -> Result<()> {
+    struct PanickingLimiter;
+
+    impl ResourceLimiter for PanickingLimiter {
+        fn memory_growing(&self, _current: u32, _desired: u32, _max: Option<u32>) -> bool {
+            panic!("memory limiter panic")
+        }
+
+        fn table_growing(&self, _current: u32, _desired: u32, _max: Option<u32>) -> bool {
+            panic!("table limiter panic")
+        }
+    }
+
+    struct PanicOnDrop;
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            panic!("externref drop panic")
+        }
+    }
+
+    let mut config = Config::new();
+    config.wasm_reference_types(true);
+    let store = Store::new_with_limits(&Engine::new(&config), PanickingLimiter);
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory 1)
+                (table 1 funcref)
+                (global $g (mut externref) (ref.null extern))
+                (func (export "memory.grow") (result i32)
+                    i32.const 1
+                    memory.grow)
+                (func (export "table.grow") (result i32)
+                    ref.null func
+                    i32.const 1
+                    table.grow 0)
+                (func (export "global.set") (param externref)
+                    local.get 0
+                    global.set $g)
+                (func (export "ok") (result i32) i32.const 42))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+
+    for (name, msg) in [
+        ("memory.grow", "memory limiter panic"),
+        ("table.grow", "table limiter panic"),
+    ]
+    .iter()
+    {
+        let func = instance.get_typed_func::<(), i32>(name)?;
+        let err = panic::catch_unwind(AssertUnwindSafe(|| drop(func.call(())))).unwrap_err();
+        assert_eq!(err.downcast_ref::<&'static str>(), Some(msg));
+    }
+
+    let set = instance.get_func("global.set").unwrap();
+    let value = Val::ExternRef(Some(ExternRef::new(PanicOnDrop)));
+    set.call(&[value])?;
+    // Release the references held on behalf of wasm frames, so that the
+    // global holds the last one.
+    store.gc();
+    let err = panic::catch_unwind(AssertUnwindSafe(|| {
+        drop(set.call(&[Val::ExternRef(None)]));
+    }))
+    .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<&'static str>(),
+        Some(&"externref drop panic")
+    );
+
+    // The store is still usable afterwards.
+    assert_eq!(instance.get_typed_func::<(), i32>("ok")?.call(())?, 42);
+    Ok(())
+}
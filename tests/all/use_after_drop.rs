@@ -1,4 +1,5 @@
 use anyhow::Result;
+use std::rc::Rc;
 use wasmtime::*;
 
 #[test]
@@ -19,3 +20,46 @@ fn use_func_after_drop() -> Result<()> {
     func()?;
     Ok(())
 }
+
+#[test]
+fn weak_handles_dont_keep_store_alive() -> Result<()> {
+    let state = Rc::new(());
+    let store = Store::default();
+    let func = {
+        let state = state.clone();
+        Func::wrap(&store, move || {
+            let _ = &state;
+        })
+    };
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)))?;
+    let global = Global::new(
+        &store,
+        GlobalType::new(ValType::I32, Mutability::Var),
+        Val::I32(1),
+    )?;
+    let ty = TableType::new(ValType::FuncRef, Limits::new(1, None));
+    let table = Table::new(&store, ty, Val::FuncRef(None))?;
+
+    let weak_func = func.downgrade();
+    let weak_memory = memory.downgrade();
+    let weak_global = global.downgrade();
+    let weak_table = table.downgrade();
+    drop((func, memory, global, table));
+
+    // Upgrading works for as long as the store is alive.
+    weak_func.upgrade().unwrap().call(&[])?;
+    assert_eq!(weak_memory.upgrade().unwrap().size(), 1);
+    assert_eq!(weak_global.upgrade().unwrap().get().unwrap_i32(), 1);
+    assert_eq!(weak_table.upgrade().unwrap().size(), 1);
+
+    // Dropping the store frees its instances, and with them the function's
+    // closure, even though the weak handles are still around.
+    assert_eq!(Rc::strong_count(&state), 2);
+    drop(store);
+    assert_eq!(Rc::strong_count(&state), 1);
+    assert!(weak_func.upgrade().is_none());
+    assert!(weak_memory.upgrade().is_none());
+    assert!(weak_global.upgrade().is_none());
+    assert!(weak_table.upgrade().is_none());
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use anyhow::Result;
+use wasmtime::*;
+use wasmtime_wasi::virtfs::pipe::{ReadPipe, WritePipe};
+use wasmtime_wasi::{add_wasi_to_linker, WasiCtxBuilder};
+
+#[test]
+fn add_wasi_to_linker_defines_preview1() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    let ctx = WasiCtxBuilder::new().args(&["prog", "arg"]).build()?;
+    add_wasi_to_linker(&mut linker, ctx)?;
+
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "wasi_snapshot_preview1" "args_sizes_get"
+                    (func $args_sizes_get (param i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "proc_exit"
+                    (func $proc_exit (param i32)))
+                (memory (export "memory") 1)
+                (func (export "_start")
+                    (drop (call $args_sizes_get (i32.const 0) (i32.const 4)))
+                    ;; exit with the number of arguments
+                    (call $proc_exit (i32.load (i32.const 0)))))
+        "#,
+    )?;
+    linker.module("", &module)?;
+    let trap = linker.get_default("")?.get0::<()>()?().unwrap_err();
+    assert_eq!(trap.i32_exit_status(), Some(2));
+    Ok(())
+}
+
+#[test]
+fn preopened_dir_path_is_confined() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    std::fs::create_dir_all(dir.path().join("sandbox/sub"))?;
+    std::fs::write(dir.path().join("sandbox/inside"), b"")?;
+    std::fs::write(dir.path().join("outside"), b"")?;
+
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    let ctx = WasiCtxBuilder::new()
+        .preopened_dir_path(dir.path().join("sandbox"), "/sandbox")
+        .build()?;
+    add_wasi_to_linker(&mut linker, ctx)?;
+
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "wasi_snapshot_preview1" "path_open"
+                    (func $path_open
+                        (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                ;; opens the path at `ptr` relative to the preopen, following
+                ;; symlinks, and returns the errno
+                (func (export "open") (param $ptr i32) (param $len i32) (result i32)
+                    (call $path_open
+                        (i32.const 3) (i32.const 1) (local.get $ptr) (local.get $len)
+                        (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0)
+                        (i32.const 0))))
+        "#,
+    )?;
+    let instance = linker.instantiate(&module)?;
+    let memory = instance.get_memory("memory").unwrap();
+    let open = instance.get_func("open").unwrap().get2::<i32, i32, i32>()?;
+    let errno = |path: &str| -> Result<i32> {
+        memory.write(16, path.as_bytes())?;
+        Ok(open(16, path.len() as i32)?)
+    };
+
+    const NOTCAPABLE: i32 = 76;
+    assert_eq!(errno("inside")?, 0);
+    assert_eq!(errno("./sub/../inside")?, 0);
+    assert_eq!(errno("../outside")?, NOTCAPABLE);
+    assert_eq!(errno("sub/../../outside")?, NOTCAPABLE);
+    let absolute = dir.path().join("outside");
+    assert_eq!(errno(absolute.to_str().unwrap())?, NOTCAPABLE);
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&absolute, dir.path().join("sandbox/link"))?;
+        assert_eq!(errno("link")?, NOTCAPABLE);
+    }
+    Ok(())
+}
+
+#[test]
+fn stdio_pipes() -> Result<()> {
+    let stdout = WritePipe::new_in_memory();
+    let stderr = WritePipe::new_in_memory();
+
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    let ctx = WasiCtxBuilder::new()
+        .stdin(ReadPipe::from("hello"))
+        .stdout(stdout.clone())
+        .stderr(stderr.clone())
+        .build()?;
+    add_wasi_to_linker(&mut linker, ctx)?;
+
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "wasi_snapshot_preview1" "fd_read"
+                    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 0) "\64\00\00\00\40\00\00\00")
+                (data (i32.const 16) "\c8\00\00\00\04\00\00\00")
+                (data (i32.const 200) "oops")
+                (func (export "_start")
+                    ;; echo stdin to stdout
+                    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+                    (i32.store (i32.const 4) (i32.load (i32.const 8)))
+                    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+                    (drop (call $fd_write (i32.const 2) (i32.const 16) (i32.const 1) (i32.const 8)))))
+        "#,
+    )?;
+    linker.module("", &module)?;
+    linker.get_default("")?.get0::<()>()?()?;
+
+    assert_eq!(stdout.contents(), b"hello");
+    assert_eq!(stderr.contents(), b"oops");
+    Ok(())
+}
+
+#[test]
+fn inherit_env_filtered() -> Result<()> {
+    // Setting variables here would race with other tests reading the
+    // environment, so this filters one which is always set instead. Its name
+    // is `Path` on Windows, so it's looked up without regard to case.
+    let (path_key, path) = std::env::vars()
+        .find(|(key, _)| key.eq_ignore_ascii_case("PATH"))
+        .expect("`PATH` should be set");
+
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    let ctx = WasiCtxBuilder::new()
+        .inherit_env_filtered(|key, _| key == path_key.as_str())
+        .env("KEY", "VAL")
+        .build()?;
+    add_wasi_to_linker(&mut linker, ctx)?;
+
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "wasi_snapshot_preview1" "environ_sizes_get"
+                    (func $environ_sizes_get (param i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "environ_get"
+                    (func $environ_get (param i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                ;; stores the number of variables at 0, the size of their
+                ;; buffer at 4, and the buffer itself at 64
+                (func (export "environ") (result i32)
+                    (drop (call $environ_sizes_get (i32.const 0) (i32.const 4)))
+                    (call $environ_get (i32.const 16) (i32.const 64))))
+        "#,
+    )?;
+    let instance = linker.instantiate(&module)?;
+    let memory = instance.get_memory("memory").unwrap();
+    assert_eq!(instance.get_func("environ").unwrap().get0::<i32>()?()?, 0);
+
+    let mut sizes = [0; 8];
+    memory.read(0, &mut sizes)?;
+    assert_eq!(sizes[..4], 2u32.to_le_bytes());
+    let mut buf = vec![0; u32::from_le_bytes([sizes[4], sizes[5], sizes[6], sizes[7]]) as usize];
+    memory.read(64, &mut buf)?;
+    let mut vars = buf
+        .split(|b| *b == 0)
+        .filter(|var| !var.is_empty())
+        .map(|var| String::from_utf8_lossy(var).into_owned())
+        .collect::<Vec<_>>();
+    vars.sort();
+    assert_eq!(
+        vars,
+        ["KEY=VAL".to_string(), format!("{}={}", path_key, path)]
+    );
+    Ok(())
+}